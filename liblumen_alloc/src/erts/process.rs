@@ -322,10 +322,23 @@ impl Process {
         heap.stack_slot(1)
     }
 
+    /// Returns the term in the given stack slot, where `1` is the top of the
+    /// stack
+    #[inline]
+    pub fn stack_slot(&self, slot: usize) -> Option<Term> {
+        let mut heap = self.heap.lock();
+        heap.stack_slot(slot)
+    }
+
     pub fn stack_used(&self) -> usize {
         self.heap.lock().stack_used()
     }
 
+    /// The `pid` of the process that `spawn`ed this process
+    pub fn parent_pid(&self) -> Option<Pid> {
+        self.parent_pid
+    }
+
     // Links
 
     pub fn link(&self, other: &Process) {
@@ -846,6 +859,12 @@ impl Process {
             .map(|frame| frame.module_function_arity())
     }
 
+    /// The `Code` of the top `Frame`, which is the `Code` that will be called when the process
+    /// is next run.
+    pub fn current_code(&self) -> Option<Code> {
+        self.code_stack.lock().get(0).map(|frame| frame.code())
+    }
+
     pub fn place_frame(&self, frame: Frame, placement: Placement) {
         match placement {
             Placement::Replace => self.replace_frame(frame),
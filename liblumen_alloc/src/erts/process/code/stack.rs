@@ -1,6 +1,7 @@
 pub mod frame;
 
 use core::fmt::{self, Debug, Display};
+use core::slice;
 
 use alloc::collections::vec_deque::{Iter, VecDeque};
 use alloc::sync::Arc;
@@ -62,6 +63,13 @@ impl Debug for Stack {
 
 pub struct Trace(Vec<Arc<ModuleFunctionArity>>);
 
+impl Trace {
+    /// Iterates over the frames' `ModuleFunctionArity`s, starting with the top of the stack
+    pub fn iter(&self) -> slice::Iter<Arc<ModuleFunctionArity>> {
+        self.0.iter()
+    }
+}
+
 impl Debug for Trace {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for module_function_arity in self.0.iter() {
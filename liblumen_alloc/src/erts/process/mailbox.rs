@@ -7,7 +7,18 @@ use crate::borrow::CloneToProcess;
 use crate::erts::exception::system::Alloc;
 use crate::erts::message::{self, Message};
 use crate::erts::process::Process;
-use crate::erts::term::Term;
+use crate::erts::term::{Reference, Term};
+
+/// The timeout of a receive in progress in the eir interpreter or in loaded BEAM code
+#[derive(Clone, Copy, Debug)]
+pub enum RecvTimeout {
+    /// `after infinity`, or no `after` clause at all
+    Infinity,
+    /// `after 0`: the receive times out as soon as no message matches
+    Immediate,
+    /// A timer was started which sends the process a message on expiry
+    Timer(Reference),
+}
 
 #[derive(Debug)]
 pub struct Mailbox {
@@ -15,12 +26,22 @@ pub struct Mailbox {
     seen: isize,
 
     cursor: usize,
+    recv_timeout: RecvTimeout,
 }
 
 impl Mailbox {
-    // Start receive implementation for the eir interpreter
-    pub fn recv_start(&self) {
+    // Start receive implementation for the eir interpreter and loaded BEAM code
+    pub fn recv_start(&mut self, timeout: RecvTimeout) {
         debug_assert!(self.cursor == 0);
+        self.recv_timeout = timeout;
+    }
+    pub fn recv_timeout(&self) -> RecvTimeout {
+        self.recv_timeout
+    }
+    /// Sets the timeout of a receive that has already looked at some of the messages, as the
+    /// BEAM `wait_timeout` instruction only starts the timer when no message matched.
+    pub fn recv_set_timeout(&mut self, timeout: RecvTimeout) {
+        self.recv_timeout = timeout;
     }
     /// Important to remember that this might return a term in a heap
     /// fragment, and that it needs to be copied over to the process
@@ -44,8 +65,20 @@ impl Mailbox {
     pub fn recv_finish(&mut self, proc: &Process) {
         self.remove(self.cursor - 1, proc);
         self.cursor = 0;
+        self.recv_timeout = RecvTimeout::Infinity;
+    }
+    /// Ends the receive without matching a message, because it timed out.
+    ///
+    /// If the receive had a timer, its message is the one under the cursor,
+    /// and is removed from the mailbox.
+    pub fn recv_finish_timeout(&mut self, proc: &Process) {
+        if let RecvTimeout::Timer(_) = self.recv_timeout {
+            self.remove(self.cursor, proc);
+        }
+        self.cursor = 0;
+        self.recv_timeout = RecvTimeout::Infinity;
     }
-    // End receive implementation for the eir interpreter
+    // End receive implementation for the eir interpreter and loaded BEAM code
 
     pub fn flush<F>(&mut self, predicate: F, process: &Process) -> bool
     where
@@ -173,6 +206,7 @@ impl Default for Mailbox {
             messages: Default::default(),
             seen: -1,
             cursor: 0,
+            recv_timeout: RecvTimeout::Infinity,
         }
     }
 }
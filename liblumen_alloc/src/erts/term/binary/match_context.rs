@@ -73,17 +73,20 @@ impl MatchBuffer {
         let bin_ptr = original.boxed_val();
         let bin = unsafe { *bin_ptr };
 
-        let (base, full_byte_bit_len, byte_offset, bit_offset, partial_byte_bit_len) =
+        let (original, base, full_byte_bit_len, byte_offset, bit_offset, partial_byte_bit_len) =
             if bin.is_procbin() {
                 let pb = unsafe { &*(bin_ptr as *mut ProcBin) };
-                (pb.bytes(), pb.full_byte_len() * 8, 0, 0, 0)
+                (original, pb.bytes(), pb.full_byte_len() * 8, 0, 0, 0)
             } else if bin.is_heapbin() {
                 let hb = unsafe { &*(bin_ptr as *mut HeapBin) };
-                (hb.bytes(), hb.full_byte_len() * 8, 0, 0, 0)
+                (original, hb.bytes(), hb.full_byte_len() * 8, 0, 0, 0)
             } else {
                 assert!(bin.is_subbinary_header());
                 let sb = unsafe { &*(bin_ptr as *mut SubBinary) };
+                // Match against the binary the sub-binary refers to, as the
+                // offsets below are relative to it
                 (
+                    sb.original(),
                     sb.bytes(),
                     sb.full_byte_len() * 8,
                     sb.byte_offset(),
@@ -168,7 +168,7 @@ impl SubBinary {
     /// See erts_bs_get_binary_2 in erl_bits.c:460
     #[inline]
     pub fn from_match(ctx: &mut MatchContext, bit_len: usize) -> Self {
-        assert!(ctx.buffer.bit_len - ctx.buffer.bit_offset < bit_len);
+        assert!(ctx.buffer.bit_len - ctx.buffer.bit_offset >= bit_len);
 
         let original = ctx.buffer.original;
         let subbinary_byte_offset = byte_offset(ctx.buffer.bit_offset);
@@ -22,6 +22,7 @@
 //! * [org.elixir_lang.beam.Beam in IntelliJ Elixir](https://github.
 //!   com/KronicDeth/intellij-elixir/blob/master/src/org/elixir_lang/beam/Beam.kt) in Kotlin
 
+pub mod dump;
 pub mod reader;
 
 pub use self::reader::chunk;
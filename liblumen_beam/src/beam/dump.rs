@@ -0,0 +1,367 @@
+//! Renders the chunks of a BEAM file for people to read, either as text or as JSON.
+//!
+//! References to other chunks, such as atom indices in the import table or literal indices in the
+//! code, are resolved so each chunk can be read on its own.
+//!
+//! # Examples
+//!
+//!     use liblumen_beam::beam::dump::{dump, Format, Options};
+//!     use liblumen_beam::beam::reader::StandardBeamFile;
+//!
+//!     let beam = StandardBeamFile::from_file("tests/testdata/reader/test.beam").unwrap();
+//!     let options = Options {
+//!         format: Format::Text,
+//!         chunks: Some(vec![*b"ImpT"]),
+//!     };
+//!     let mut out = Vec::new();
+//!     dump(&beam, &options, &mut out).unwrap();
+//!     assert!(String::from_utf8(out).unwrap().contains("io:format/2"));
+//!
+#[cfg(test)]
+mod test;
+
+use std::io::{self, Cursor, Write};
+
+use serde_json::{json, Map, Value};
+
+use crate::beam::chunk::code::Disassembler;
+use crate::beam::chunk::{AtomChunk, Chunk, Id, LitTChunk, StandardChunk};
+use crate::beam::reader::parts::AtomId;
+use crate::beam::reader::StandardBeamFile;
+use crate::serialization::etf;
+use crate::syntax::ast::format::raw_abstract_v1::AbstractCode;
+
+/// How chunks are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A heading per chunk followed by its contents, one entry per line.
+    Text,
+    /// A single JSON object with the file's chunks in a `"chunks"` array.
+    Json,
+}
+
+/// Options for [dump](dump).
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub format: Format,
+    /// Only dump the chunks with these identifiers.  `Atom` also matches `AtU8`.
+    pub chunks: Option<Vec<Id>>,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            format: Format::Text,
+            chunks: None,
+        }
+    }
+}
+
+/// Writes the chunks of `beam` selected by `options` to `out`.
+pub fn dump<W: Write>(beam: &StandardBeamFile, options: &Options, mut out: W) -> io::Result<()> {
+    let atoms = match beam.atoms() {
+        Some(StandardChunk::Atom(atoms)) => Some(atoms),
+        _ => None,
+    };
+    let literals = match beam.get_chunk(b"LitT") {
+        Some(StandardChunk::LitT(literals)) => Some(literals),
+        _ => None,
+    };
+    let context = Context { atoms, literals };
+
+    let sections: Vec<Section> = beam
+        .chunks()
+        .into_iter()
+        .filter(|chunk| is_selected(chunk.id(), options))
+        .map(|chunk| context.section(chunk))
+        .collect();
+
+    match options.format {
+        Format::Text => {
+            for (i, section) in sections.iter().enumerate() {
+                if i > 0 {
+                    writeln!(out)?;
+                }
+                writeln!(out, "== {} ==", section.id)?;
+                for line in &section.lines {
+                    writeln!(out, "{}", line)?;
+                }
+            }
+            Ok(())
+        }
+        Format::Json => {
+            let chunks: Vec<Value> = sections.into_iter().map(|section| section.json).collect();
+            let json = json!({ "chunks": chunks });
+            writeln!(out, "{}", json)
+        }
+    }
+}
+
+/// Parses a chunk identifier given on the command line, such as `ImpT`.
+pub fn parse_id(id: &str) -> Option<Id> {
+    let bytes = id.as_bytes();
+    if bytes.len() != 4 {
+        return None;
+    }
+
+    let mut chunk_id = [0; 4];
+    chunk_id.copy_from_slice(bytes);
+    Some(chunk_id)
+}
+
+fn is_selected(id: &Id, options: &Options) -> bool {
+    match options.chunks {
+        None => true,
+        Some(ref ids) => ids
+            .iter()
+            .any(|selected| selected == id || (selected == b"Atom" && id == b"AtU8")),
+    }
+}
+
+// A chunk rendered both ways, so that the text and JSON output never disagree
+struct Section {
+    id: String,
+    lines: Vec<String>,
+    json: Value,
+}
+
+struct Context<'a> {
+    atoms: Option<&'a AtomChunk>,
+    literals: Option<&'a LitTChunk>,
+}
+impl<'a> Context<'a> {
+    fn section(&self, chunk: &StandardChunk) -> Section {
+        let id = String::from_utf8_lossy(chunk.id()).into_owned();
+        let mut lines = Vec::new();
+        let mut fields = Map::new();
+        fields.insert("id".to_string(), Value::from(id.as_str()));
+
+        match *chunk {
+            StandardChunk::Atom(ref c) => {
+                let mut atoms = Vec::new();
+                for (i, atom) in c.atoms.iter().enumerate() {
+                    lines.push(format!(
+                        "{:>5} {}",
+                        i + 1,
+                        etf::Atom::from(atom.name.as_str())
+                    ));
+                    atoms.push(Value::from(atom.name.as_str()));
+                }
+                fields.insert("atoms".to_string(), Value::Array(atoms));
+            }
+            StandardChunk::Code(ref c) => {
+                lines.push(format!(
+                    "version {}, max opcode {}, {} labels, {} functions",
+                    c.version, c.opcode_max, c.label_count, c.function_count
+                ));
+                fields.insert("version".to_string(), Value::from(c.version));
+                fields.insert("opcode_max".to_string(), Value::from(c.opcode_max));
+                fields.insert("label_count".to_string(), Value::from(c.label_count));
+                fields.insert("function_count".to_string(), Value::from(c.function_count));
+
+                match self.disassemble(c) {
+                    Ok(instructions) => {
+                        for instruction in instructions.iter() {
+                            // Indent everything but labels, like `erlc -S`
+                            if instruction.starts_with("{label,") {
+                                lines.push(format!("  {}", instruction));
+                            } else {
+                                lines.push(format!("    {}", instruction));
+                            }
+                        }
+                        let instructions = instructions.into_iter().map(Value::String).collect();
+                        fields.insert("instructions".to_string(), Value::Array(instructions));
+                    }
+                    Err(error) => {
+                        lines.push(format!("unable to decode code: {}", error));
+                        fields.insert("error".to_string(), Value::String(error));
+                    }
+                }
+            }
+            StandardChunk::StrT(ref c) => {
+                let strings = String::from_utf8_lossy(&c.strings).into_owned();
+                lines.push(format!("{:?}", strings));
+                fields.insert("strings".to_string(), Value::String(strings));
+            }
+            StandardChunk::ImpT(ref c) => {
+                let mut imports = Vec::new();
+                for (i, import) in c.imports.iter().enumerate() {
+                    let module = self.atom(import.module);
+                    let function = self.atom(import.function);
+                    lines.push(format!("{:>5} {}:{}/{}", i, module, function, import.arity));
+                    imports.push(json!({
+                        "module": module,
+                        "function": function,
+                        "arity": import.arity,
+                    }));
+                }
+                fields.insert("imports".to_string(), Value::Array(imports));
+            }
+            StandardChunk::ExpT(ref c) => {
+                let exports = c
+                    .exports
+                    .iter()
+                    .map(|e| self.function(&mut lines, e.function, e.arity, e.label))
+                    .collect();
+                fields.insert("exports".to_string(), Value::Array(exports));
+            }
+            StandardChunk::LocT(ref c) => {
+                let locals = c
+                    .locals
+                    .iter()
+                    .map(|l| self.function(&mut lines, l.function, l.arity, l.label))
+                    .collect();
+                fields.insert("locals".to_string(), Value::Array(locals));
+            }
+            StandardChunk::FunT(ref c) => {
+                let mut functions = Vec::new();
+                for f in c.functions.iter() {
+                    let name = self.atom(f.function);
+                    lines.push(format!(
+                        "{}/{} label {}, index {}, {} free, uniq {}",
+                        name, f.arity, f.label, f.index, f.num_free, f.old_uniq
+                    ));
+                    functions.push(json!({
+                        "function": name,
+                        "arity": f.arity,
+                        "label": f.label,
+                        "index": f.index,
+                        "num_free": f.num_free,
+                        "old_uniq": f.old_uniq,
+                    }));
+                }
+                fields.insert("functions".to_string(), Value::Array(functions));
+            }
+            StandardChunk::LitT(ref c) => {
+                let mut literals = Vec::new();
+                for (i, literal) in c.literals.iter().enumerate() {
+                    let literal = decode_term(literal);
+                    lines.push(format!("{:>5} {}", i, literal));
+                    literals.push(Value::String(literal));
+                }
+                fields.insert("literals".to_string(), Value::Array(literals));
+            }
+            StandardChunk::Attr(ref c) => {
+                self.terms(&mut lines, &mut fields, "attributes", &c.term)
+            }
+            StandardChunk::CInf(ref c) => {
+                self.terms(&mut lines, &mut fields, "compile_info", &c.term)
+            }
+            StandardChunk::Abst(ref c) => {
+                if c.term.is_empty() {
+                    lines.push("no abstract code".to_string());
+                    fields.insert("forms".to_string(), Value::Array(Vec::new()));
+                } else {
+                    self.forms(
+                        &mut lines,
+                        &mut fields,
+                        AbstractCode::from_abst(&c.term),
+                        &c.term,
+                    )
+                }
+            }
+            StandardChunk::Dbgi(ref c) => self.forms(
+                &mut lines,
+                &mut fields,
+                AbstractCode::from_dbgi(&c.term),
+                &c.term,
+            ),
+            StandardChunk::Docs(ref c) => {
+                let docs = decode_term(&c.term);
+                lines.push(docs.clone());
+                fields.insert("term".to_string(), Value::String(docs));
+            }
+            StandardChunk::Unknown(ref c) => {
+                lines.push(format!("{} bytes", c.data.len()));
+                fields.insert("size".to_string(), Value::from(c.data.len()));
+            }
+        }
+
+        Section {
+            id,
+            lines,
+            json: Value::Object(fields),
+        }
+    }
+
+    fn disassemble(&self, code: &crate::beam::chunk::CodeChunk) -> Result<Vec<String>, String> {
+        let atoms = self.atoms.ok_or_else(|| "no atom chunk".to_string())?;
+        let disassembler = Disassembler::new(atoms, self.literals).map_err(|e| e.to_string())?;
+        let instructions = code.instructions().map_err(|e| e.to_string())?;
+
+        Ok(instructions
+            .iter()
+            .map(|instruction| disassembler.display(instruction).to_string())
+            .collect())
+    }
+
+    // An export or local function table entry
+    fn function(&self, lines: &mut Vec<String>, function: AtomId, arity: u32, label: u32) -> Value {
+        let name = self.atom(function);
+        lines.push(format!("{}/{} label {}", name, arity, label));
+        json!({
+            "function": name,
+            "arity": arity,
+            "label": label,
+        })
+    }
+
+    // A chunk holding a list of terms, such as `Attr`, shown one element per line
+    fn terms(
+        &self,
+        lines: &mut Vec<String>,
+        fields: &mut Map<String, Value>,
+        name: &str,
+        data: &[u8],
+    ) {
+        let terms = match etf::Term::decode(Cursor::new(data)) {
+            Ok(etf::Term::List(list)) => list.elements.iter().map(|t| t.to_string()).collect(),
+            Ok(term) => vec![term.to_string()],
+            Err(error) => vec![format!("unable to decode term: {}", error)],
+        };
+        lines.extend(terms.iter().cloned());
+        fields.insert(
+            name.to_string(),
+            Value::Array(terms.into_iter().map(Value::String).collect()),
+        );
+    }
+
+    // Abstract code, printed as Erlang source when it can be, otherwise as the raw term
+    fn forms(
+        &self,
+        lines: &mut Vec<String>,
+        fields: &mut Map<String, Value>,
+        code: crate::syntax::ast::FromBeamResult<AbstractCode>,
+        data: &[u8],
+    ) {
+        match code.and_then(|code| code.to_forms()) {
+            Ok(forms) => {
+                let mut json = Vec::new();
+                for form in forms.iter() {
+                    let source = form.to_string();
+                    lines.extend(source.lines().map(str::to_string));
+                    json.push(Value::String(source));
+                }
+                fields.insert("forms".to_string(), Value::Array(json));
+            }
+            Err(_) => {
+                let term = decode_term(data);
+                lines.push(term.clone());
+                fields.insert("term".to_string(), Value::String(term));
+            }
+        }
+    }
+
+    fn atom(&self, id: AtomId) -> String {
+        self.atoms
+            .and_then(|atoms| atoms.atoms.get((id as usize).wrapping_sub(1)))
+            .map(|atom| atom.name.clone())
+            .unwrap_or_else(|| format!("#{}", id))
+    }
+}
+
+fn decode_term(data: &[u8]) -> String {
+    match etf::Term::decode(Cursor::new(data)) {
+        Ok(term) => term.to_string(),
+        Err(error) => format!("unable to decode term: {}", error),
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+use crate::beam::dump::{dump, parse_id, Format, Options};
+use crate::beam::reader::StandardBeamFile;
+
+#[test]
+fn json_test() {
+    let json = dump_json("test.beam", None);
+
+    let ids: Vec<&str> = json["chunks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|chunk| chunk["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        vec![
+            "Atom", "Code", "StrT", "ImpT", "ExpT", "FunT", "LitT", "LocT", "Attr", "CInf", "Abst",
+            "Line"
+        ],
+        ids
+    );
+
+    let imports = &chunk(&json, "ImpT")["imports"];
+    assert!(imports
+        .as_array()
+        .unwrap()
+        .contains(&json!({"module": "io", "function": "format", "arity": 2})));
+
+    let exports = &chunk(&json, "ExpT")["exports"];
+    assert!(exports
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|export| export["function"] == "hello" && export["arity"] == 1));
+
+    let code = chunk(&json, "Code");
+    assert!(code["instructions"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("{func_info,{atom,'test'},{atom,'hello'},1}")));
+}
+
+#[test]
+fn chunk_test() {
+    let json = dump_json("test.beam", Some(vec![*b"ExpT", *b"ImpT"]));
+
+    // In the order of the file, not of the options
+    let ids: Vec<&str> = json["chunks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|chunk| chunk["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(vec!["ImpT", "ExpT"], ids);
+
+    let text = dump_text("test.beam", Some(vec![*b"ImpT"]));
+    assert!(text.starts_with("== ImpT ==\n"));
+    assert!(text.contains("io:format/2"));
+    assert!(!text.contains("== ExpT =="));
+
+    // A chunk that is not in the file
+    assert_eq!(
+        json!({"chunks": []}),
+        dump_json("test.beam", Some(vec![*b"Docs"]))
+    );
+}
+
+#[test]
+fn atom_alias_test() {
+    // Elixir.Unicode.beam has an `AtU8` chunk, which `Atom` selects
+    for id in &[*b"Atom", *b"AtU8"] {
+        let json = dump_json("Elixir.Unicode.beam", Some(vec![*id]));
+        let chunks = json["chunks"].as_array().unwrap();
+
+        assert_eq!(1, chunks.len());
+        assert_eq!("AtU8", chunks[0]["id"]);
+        assert!(chunks[0]["atoms"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("åtom")));
+    }
+
+    // `AtU8` does not select the latin-1 `Atom` chunk of test.beam
+    let json = dump_json("test.beam", Some(vec![*b"AtU8"]));
+    assert_eq!(json!({"chunks": []}), json);
+
+    // The atom indices of other chunks are resolved through `AtU8`
+    let text = dump_text("Elixir.Unicode.beam", Some(vec![*b"ImpT"]));
+    assert!(text.contains("erlang:get_module_info/2"));
+}
+
+#[test]
+fn parse_id_test() {
+    assert_eq!(Some(*b"ImpT"), parse_id("ImpT"));
+    assert_eq!(None, parse_id("Imp"));
+    assert_eq!(None, parse_id("ImpTs"));
+}
+
+fn dump_json(name: &str, chunks: Option<Vec<[u8; 4]>>) -> Value {
+    let out = dump_to_string(name, Format::Json, chunks);
+    assert!(out.ends_with('\n'));
+    serde_json::from_str(&out).unwrap()
+}
+
+fn dump_text(name: &str, chunks: Option<Vec<[u8; 4]>>) -> String {
+    dump_to_string(name, Format::Text, chunks)
+}
+
+fn dump_to_string(name: &str, format: Format, chunks: Option<Vec<[u8; 4]>>) -> String {
+    let beam = StandardBeamFile::from_file(test_file(name)).unwrap();
+    let mut out = Vec::new();
+    dump(&beam, &Options { format, chunks }, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn chunk<'a>(json: &'a Value, id: &str) -> &'a Value {
+    json["chunks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|chunk| chunk["id"] == id)
+        .unwrap()
+}
+
+fn test_file(name: &str) -> PathBuf {
+    let mut path = PathBuf::from("tests/testdata/reader");
+    path.push(name);
+    path
+}
@@ -48,7 +48,22 @@ pub enum ReadError {
     InvalidString(std::str::Utf8Error),
     UnexpectedMagicNumber([u8; 4]),
     UnexpectedFormType([u8; 4]),
-    UnexpectedChunk { id: chunk::Id, expected: chunk::Id },
+    UnexpectedChunk {
+        id: chunk::Id,
+        expected: chunk::Id,
+    },
+    UnknownOpcode {
+        opcode: u8,
+        offset: usize,
+    },
+    InvalidOperand {
+        offset: usize,
+    },
+    WrongOperandCount {
+        opcode: u8,
+        expected: usize,
+        actual: usize,
+    },
 }
 
 impl std::fmt::Display for ReadError {
@@ -76,6 +91,19 @@ impl std::fmt::Display for ReadError {
                 bytes_to_str(id),
                 bytes_to_str(expected)
             ),
+            UnknownOpcode { opcode, offset } => {
+                write!(f, "Unknown opcode {} at byte {} of code", opcode, offset)
+            }
+            InvalidOperand { offset } => write!(f, "Invalid operand at byte {} of code", offset),
+            WrongOperandCount {
+                opcode,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Opcode {} takes {} operands, but {} were given",
+                opcode, expected, actual
+            ),
         }
     }
 }
@@ -89,6 +117,9 @@ impl std::error::Error for ReadError {
             UnexpectedMagicNumber(_) => "Unexpected magic number",
             UnexpectedFormType(_) => "Unexpected form type",
             UnexpectedChunk { .. } => "Unexpected chunk",
+            UnknownOpcode { .. } => "Unknown opcode",
+            InvalidOperand { .. } => "Invalid operand",
+            WrongOperandCount { .. } => "Wrong number of operands",
         }
     }
     fn cause(&self) -> Option<&dyn std::error::Error> {
@@ -11,6 +11,7 @@
 //!   com/KronicDeth/intellij-elixir/blob/master/src/org/elixir_lang/beam/chunk/Chunk.java) in Java.
 //!
 mod aux;
+pub mod code;
 
 use std::io::Cursor;
 use std::io::Read;
@@ -241,6 +242,22 @@ pub struct CodeChunk {
     /// The byte code.
     pub bytecode: Vec<u8>,
 }
+impl CodeChunk {
+    /// Decodes the byte code into instructions.
+    pub fn instructions(&self) -> Result<Vec<code::Instruction>> {
+        code::decode_instructions(&self.bytecode)
+    }
+
+    /// Replaces the byte code with the encoded `instructions`.
+    ///
+    /// The header fields, such as `label_count` and `opcode_max`, are left as is.
+    pub fn set_instructions(&mut self, instructions: &[code::Instruction]) -> Result<()> {
+        let mut bytecode = Vec::new();
+        code::encode_instructions(instructions, &mut bytecode)?;
+        self.bytecode = bytecode;
+        Ok(())
+    }
+}
 impl Chunk for CodeChunk {
     fn id(&self) -> &Id {
         b"Code"
@@ -251,8 +268,8 @@ impl Chunk for CodeChunk {
     ///   Elixir](https://github.com/KronicDeth/intellij-elixir/blob/
     ///   2f5c826040681e258e98c3e2f02b25985cd0766b/src/org/elixir_lang/beam/chunk/Code.kt#L171-L216)
     ///   in Kotlin
-    /// NOTE: This implementation decodes the operations as it loads the data instead of storing it
-    /// as raw bytes.
+    /// NOTE: The operations are kept as raw bytes; use [instructions](CodeChunk::instructions) to
+    /// decode them.
     fn decode_data<R: Read>(id: &Id, mut reader: R) -> Result<Self>
     where
         Self: Sized,
@@ -0,0 +1,721 @@
+//! Decoding and encoding of the instructions in the [CodeChunk](super::CodeChunk).
+//!
+//! Each instruction is a one byte opcode followed by as many operands as the opcode's arity.
+//! Operands use the compact term encoding: the low 3 bits of the first byte are a tag saying what
+//! kind of operand it is, and the value is packed into the rest of the byte, the following byte, or
+//! a run of following bytes, depending on its size.
+//!
+//! # References
+//!
+//! - [BEAM Wisdom - BEAM File Format - Compact Term
+//!   Encoding](http://beam-wisdoms.clau.se/en/latest/indepth-beam-file.html#beam-compact-term-encoding)
+//! - [`genop.tab`](https://github.com/erlang/otp/blob/master/lib/compiler/src/genop.tab) for the
+//!   opcodes and their arities
+//! - [`beam_asm:encode/2`](https://github.com/erlang/otp/blob/master/lib/compiler/src/beam_asm.erl)
+//!   for how the compiler encodes operands
+//!
+//! # Alternative Implementations
+//!
+//! - [`org.elixir_lang.beam.chunk.code.Operation` in IntelliJ
+//!   Elixir](https://github.com/KronicDeth/intellij-elixir/blob/
+//!   2f5c826040681e258e98c3e2f02b25985cd0766b/src/org/elixir_lang/beam/chunk/code/Operation.kt)
+//!   in Kotlin
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Write;
+
+use byteorder::BigEndian;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+use num::bigint::BigInt;
+use num::traits::{Signed, ToPrimitive};
+
+use crate::beam::reader::parts::AtomId;
+use crate::beam::reader::{ReadError, Result};
+use crate::serialization::etf;
+
+use super::{AtomChunk, LitTChunk};
+
+macro_rules! opcodes {
+    ($($opcode:ident = $value:literal => ($name:literal, $arity:literal),)*) => {
+        /// A generic BEAM instruction opcode.
+        ///
+        /// Opcodes marked as obsolete in `genop.tab` are included so that old BEAM files can still
+        /// be decoded.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Opcode {
+            $($opcode = $value,)*
+        }
+        impl Opcode {
+            /// Returns the opcode with the number `value`, if there is one.
+            pub fn from_u8(value: u8) -> Option<Self> {
+                match value {
+                    $($value => Some(Opcode::$opcode),)*
+                    _ => None,
+                }
+            }
+
+            /// The name of the opcode in BEAM assembly.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Opcode::$opcode => $name,)*
+                }
+            }
+
+            /// The number of operands that follow the opcode.
+            pub fn arity(self) -> usize {
+                match self {
+                    $(Opcode::$opcode => $arity,)*
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    Label = 1 => ("label", 1),
+    FuncInfo = 2 => ("func_info", 3),
+    IntCodeEnd = 3 => ("int_code_end", 0),
+    Call = 4 => ("call", 2),
+    CallLast = 5 => ("call_last", 3),
+    CallOnly = 6 => ("call_only", 2),
+    CallExt = 7 => ("call_ext", 2),
+    CallExtLast = 8 => ("call_ext_last", 3),
+    Bif0 = 9 => ("bif0", 2),
+    Bif1 = 10 => ("bif1", 4),
+    Bif2 = 11 => ("bif2", 5),
+    Allocate = 12 => ("allocate", 2),
+    AllocateHeap = 13 => ("allocate_heap", 3),
+    AllocateZero = 14 => ("allocate_zero", 2),
+    AllocateHeapZero = 15 => ("allocate_heap_zero", 3),
+    TestHeap = 16 => ("test_heap", 2),
+    Init = 17 => ("init", 1),
+    Deallocate = 18 => ("deallocate", 1),
+    Return = 19 => ("return", 0),
+    Send = 20 => ("send", 0),
+    RemoveMessage = 21 => ("remove_message", 0),
+    Timeout = 22 => ("timeout", 0),
+    LoopRec = 23 => ("loop_rec", 2),
+    LoopRecEnd = 24 => ("loop_rec_end", 1),
+    Wait = 25 => ("wait", 1),
+    WaitTimeout = 26 => ("wait_timeout", 2),
+    MPlus = 27 => ("m_plus", 4),
+    MMinus = 28 => ("m_minus", 4),
+    MTimes = 29 => ("m_times", 4),
+    MDiv = 30 => ("m_div", 4),
+    IntDiv = 31 => ("int_div", 4),
+    IntRem = 32 => ("int_rem", 4),
+    IntBand = 33 => ("int_band", 4),
+    IntBor = 34 => ("int_bor", 4),
+    IntBxor = 35 => ("int_bxor", 4),
+    IntBsl = 36 => ("int_bsl", 4),
+    IntBsr = 37 => ("int_bsr", 4),
+    IntBnot = 38 => ("int_bnot", 3),
+    IsLt = 39 => ("is_lt", 3),
+    IsGe = 40 => ("is_ge", 3),
+    IsEq = 41 => ("is_eq", 3),
+    IsNe = 42 => ("is_ne", 3),
+    IsEqExact = 43 => ("is_eq_exact", 3),
+    IsNeExact = 44 => ("is_ne_exact", 3),
+    IsInteger = 45 => ("is_integer", 2),
+    IsFloat = 46 => ("is_float", 2),
+    IsNumber = 47 => ("is_number", 2),
+    IsAtom = 48 => ("is_atom", 2),
+    IsPid = 49 => ("is_pid", 2),
+    IsReference = 50 => ("is_reference", 2),
+    IsPort = 51 => ("is_port", 2),
+    IsNil = 52 => ("is_nil", 2),
+    IsBinary = 53 => ("is_binary", 2),
+    IsConstant = 54 => ("is_constant", 2),
+    IsList = 55 => ("is_list", 2),
+    IsNonemptyList = 56 => ("is_nonempty_list", 2),
+    IsTuple = 57 => ("is_tuple", 2),
+    TestArity = 58 => ("test_arity", 3),
+    SelectVal = 59 => ("select_val", 3),
+    SelectTupleArity = 60 => ("select_tuple_arity", 3),
+    Jump = 61 => ("jump", 1),
+    Catch = 62 => ("catch", 2),
+    CatchEnd = 63 => ("catch_end", 1),
+    Move = 64 => ("move", 2),
+    GetList = 65 => ("get_list", 3),
+    GetTupleElement = 66 => ("get_tuple_element", 3),
+    SetTupleElement = 67 => ("set_tuple_element", 3),
+    PutString = 68 => ("put_string", 3),
+    PutList = 69 => ("put_list", 3),
+    PutTuple = 70 => ("put_tuple", 2),
+    Put = 71 => ("put", 1),
+    Badmatch = 72 => ("badmatch", 1),
+    IfEnd = 73 => ("if_end", 0),
+    CaseEnd = 74 => ("case_end", 1),
+    CallFun = 75 => ("call_fun", 1),
+    MakeFun = 76 => ("make_fun", 3),
+    IsFunction = 77 => ("is_function", 2),
+    CallExtOnly = 78 => ("call_ext_only", 2),
+    BsStartMatch = 79 => ("bs_start_match", 2),
+    BsGetInteger = 80 => ("bs_get_integer", 5),
+    BsGetFloat = 81 => ("bs_get_float", 5),
+    BsGetBinary = 82 => ("bs_get_binary", 5),
+    BsSkipBits = 83 => ("bs_skip_bits", 4),
+    BsTestTail = 84 => ("bs_test_tail", 2),
+    BsSave = 85 => ("bs_save", 1),
+    BsRestore = 86 => ("bs_restore", 1),
+    BsInit = 87 => ("bs_init", 2),
+    BsFinal = 88 => ("bs_final", 2),
+    BsPutInteger = 89 => ("bs_put_integer", 5),
+    BsPutBinary = 90 => ("bs_put_binary", 5),
+    BsPutFloat = 91 => ("bs_put_float", 5),
+    BsPutString = 92 => ("bs_put_string", 2),
+    BsNeedBuf = 93 => ("bs_need_buf", 1),
+    Fclearerror = 94 => ("fclearerror", 0),
+    Fcheckerror = 95 => ("fcheckerror", 1),
+    Fmove = 96 => ("fmove", 2),
+    Fconv = 97 => ("fconv", 2),
+    Fadd = 98 => ("fadd", 4),
+    Fsub = 99 => ("fsub", 4),
+    Fmul = 100 => ("fmul", 4),
+    Fdiv = 101 => ("fdiv", 4),
+    Fnegate = 102 => ("fnegate", 3),
+    MakeFun2 = 103 => ("make_fun2", 1),
+    Try = 104 => ("try", 2),
+    TryEnd = 105 => ("try_end", 1),
+    TryCase = 106 => ("try_case", 1),
+    TryCaseEnd = 107 => ("try_case_end", 1),
+    Raise = 108 => ("raise", 2),
+    BsInit2 = 109 => ("bs_init2", 6),
+    BsBitsToBytes = 110 => ("bs_bits_to_bytes", 3),
+    BsAdd = 111 => ("bs_add", 5),
+    Apply = 112 => ("apply", 1),
+    ApplyLast = 113 => ("apply_last", 2),
+    IsBoolean = 114 => ("is_boolean", 2),
+    IsFunction2 = 115 => ("is_function2", 3),
+    BsStartMatch2 = 116 => ("bs_start_match2", 5),
+    BsGetInteger2 = 117 => ("bs_get_integer2", 7),
+    BsGetFloat2 = 118 => ("bs_get_float2", 7),
+    BsGetBinary2 = 119 => ("bs_get_binary2", 7),
+    BsSkipBits2 = 120 => ("bs_skip_bits2", 5),
+    BsTestTail2 = 121 => ("bs_test_tail2", 3),
+    BsSave2 = 122 => ("bs_save2", 2),
+    BsRestore2 = 123 => ("bs_restore2", 2),
+    GcBif1 = 124 => ("gc_bif1", 5),
+    GcBif2 = 125 => ("gc_bif2", 6),
+    BsFinal2 = 126 => ("bs_final2", 2),
+    BsBitsToBytes2 = 127 => ("bs_bits_to_bytes2", 2),
+    PutLiteral = 128 => ("put_literal", 2),
+    IsBitstr = 129 => ("is_bitstr", 2),
+    BsContextToBinary = 130 => ("bs_context_to_binary", 1),
+    BsTestUnit = 131 => ("bs_test_unit", 3),
+    BsMatchString = 132 => ("bs_match_string", 4),
+    BsInitWritable = 133 => ("bs_init_writable", 0),
+    BsAppend = 134 => ("bs_append", 8),
+    BsPrivateAppend = 135 => ("bs_private_append", 6),
+    Trim = 136 => ("trim", 2),
+    BsInitBits = 137 => ("bs_init_bits", 6),
+    BsGetUtf8 = 138 => ("bs_get_utf8", 5),
+    BsSkipUtf8 = 139 => ("bs_skip_utf8", 4),
+    BsGetUtf16 = 140 => ("bs_get_utf16", 5),
+    BsSkipUtf16 = 141 => ("bs_skip_utf16", 4),
+    BsGetUtf32 = 142 => ("bs_get_utf32", 5),
+    BsSkipUtf32 = 143 => ("bs_skip_utf32", 4),
+    BsUtf8Size = 144 => ("bs_utf8_size", 3),
+    BsPutUtf8 = 145 => ("bs_put_utf8", 3),
+    BsUtf16Size = 146 => ("bs_utf16_size", 3),
+    BsPutUtf16 = 147 => ("bs_put_utf16", 3),
+    BsPutUtf32 = 148 => ("bs_put_utf32", 3),
+    OnLoad = 149 => ("on_load", 0),
+    RecvMark = 150 => ("recv_mark", 1),
+    RecvSet = 151 => ("recv_set", 1),
+    GcBif3 = 152 => ("gc_bif3", 7),
+    Line = 153 => ("line", 1),
+    PutMapAssoc = 154 => ("put_map_assoc", 5),
+    PutMapExact = 155 => ("put_map_exact", 5),
+    IsMap = 156 => ("is_map", 2),
+    HasMapFields = 157 => ("has_map_fields", 3),
+    GetMapElements = 158 => ("get_map_elements", 3),
+    IsTaggedTuple = 159 => ("is_tagged_tuple", 4),
+    BuildStacktrace = 160 => ("build_stacktrace", 0),
+    RawRaise = 161 => ("raw_raise", 0),
+    GetHd = 162 => ("get_hd", 2),
+    GetTl = 163 => ("get_tl", 2),
+    PutTuple2 = 164 => ("put_tuple2", 2),
+    BsGetTail = 165 => ("bs_get_tail", 3),
+    BsStartMatch3 = 166 => ("bs_start_match3", 4),
+    BsGetPosition = 167 => ("bs_get_position", 3),
+    BsSetPosition = 168 => ("bs_set_position", 2),
+    Swap = 169 => ("swap", 2),
+    BsStartMatch4 = 170 => ("bs_start_match4", 4),
+    MakeFun3 = 171 => ("make_fun3", 3),
+    InitYregs = 172 => ("init_yregs", 1),
+    RecvMarkerBind = 173 => ("recv_marker_bind", 2),
+    RecvMarkerClear = 174 => ("recv_marker_clear", 1),
+    RecvMarkerReserve = 175 => ("recv_marker_reserve", 1),
+    RecvMarkerUse = 176 => ("recv_marker_use", 1),
+    BsCreateBin = 177 => ("bs_create_bin", 6),
+    CallFun2 = 178 => ("call_fun2", 3),
+    NifStart = 179 => ("nif_start", 0),
+    Badrecord = 180 => ("badrecord", 1),
+    UpdateRecord = 181 => ("update_record", 5),
+    BsMatch = 182 => ("bs_match", 3),
+    ExecutableLine = 183 => ("executable_line", 2),
+}
+
+/// The kinds of heap space in an [Operand::AllocationList](Operand::AllocationList).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Allocation {
+    Words(u64),
+    Floats(u64),
+    Funs(u64),
+}
+
+/// An operand of an [Instruction](Instruction).
+///
+/// Atoms and literals are left as indices into the [AtomChunk](super::AtomChunk) and
+/// [LitTChunk](super::LitTChunk), so that instructions can be encoded again without those chunks.
+/// Use a [Disassembler](Disassembler) to look them up.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// An untagged unsigned integer, such as an arity, a number of live registers or an index
+    /// into a table in another chunk.
+    Unsigned(u64),
+    Integer(BigInt),
+    Atom(AtomId),
+    /// The empty list, which is encoded as the atom with index `0`.
+    Nil,
+    X(u64),
+    Y(u64),
+    Label(u64),
+    Character(u64),
+    /// A float encoded inline, which only old compilers emit.  Newer compilers put floats in the
+    /// [LitTChunk](super::LitTChunk).
+    Float(f64),
+    /// A list of operands, such as the value and label pairs of `select_val`.
+    List(Vec<Operand>),
+    FloatRegister(u64),
+    AllocationList(Vec<Allocation>),
+    /// An index into the [LitTChunk](super::LitTChunk).
+    Literal(u64),
+    /// A register annotated with the index of its type in the `"Type"` chunk.
+    TypedRegister(Box<Operand>, u64),
+}
+
+/// A decoded generic BEAM instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub operands: Vec<Operand>,
+}
+
+/// Decodes the instructions in `bytecode`, which is the code after the header of the
+/// [CodeChunk](super::CodeChunk).
+pub fn decode_instructions(bytecode: &[u8]) -> Result<Vec<Instruction>> {
+    let mut reader = Cursor::new(bytecode);
+    let mut instructions = Vec::new();
+
+    while (reader.position() as usize) < bytecode.len() {
+        let offset = reader.position() as usize;
+        let value = reader.read_u8()?;
+        let opcode = Opcode::from_u8(value).ok_or(ReadError::UnknownOpcode {
+            opcode: value,
+            offset,
+        })?;
+
+        let mut operands = Vec::with_capacity(opcode.arity());
+        for _ in 0..opcode.arity() {
+            operands.push(decode_operand(&mut reader)?);
+        }
+
+        instructions.push(Instruction { opcode, operands });
+    }
+
+    Ok(instructions)
+}
+
+/// Encodes `instructions` the same way the Erlang compiler does, so that decoding and encoding the
+/// instructions of a compiled module reproduces its bytecode.
+pub fn encode_instructions<W: Write>(instructions: &[Instruction], mut writer: W) -> Result<()> {
+    for instruction in instructions {
+        if instruction.operands.len() != instruction.opcode.arity() {
+            return Err(ReadError::WrongOperandCount {
+                opcode: instruction.opcode as u8,
+                expected: instruction.opcode.arity(),
+                actual: instruction.operands.len(),
+            });
+        }
+
+        writer.write_u8(instruction.opcode as u8)?;
+        for operand in &instruction.operands {
+            encode_operand(operand, &mut writer)?;
+        }
+    }
+
+    Ok(())
+}
+
+const TAG_U: u8 = 0;
+const TAG_I: u8 = 1;
+const TAG_A: u8 = 2;
+const TAG_X: u8 = 3;
+const TAG_Y: u8 = 4;
+const TAG_F: u8 = 5;
+const TAG_H: u8 = 6;
+const TAG_Z: u8 = 7;
+
+const EXT_FLOAT: u64 = 0;
+const EXT_LIST: u64 = 1;
+const EXT_FLOAT_REGISTER: u64 = 2;
+const EXT_ALLOCATION_LIST: u64 = 3;
+const EXT_LITERAL: u64 = 4;
+const EXT_TYPED_REGISTER: u64 = 5;
+
+// The value packed after the tag of an operand
+enum Value {
+    Small(u64),
+    // Big-endian bytes, which are two's complement for integers
+    Bytes(Vec<u8>),
+}
+
+fn decode_operand(reader: &mut Cursor<&[u8]>) -> Result<Operand> {
+    let offset = reader.position() as usize;
+    let byte = reader.read_u8()?;
+    let value = decode_value(byte, offset, reader)?;
+    let invalid = ReadError::InvalidOperand { offset };
+
+    let operand = match byte & 0b111 {
+        TAG_U => Operand::Unsigned(unsigned(value, offset)?),
+        TAG_I => Operand::Integer(match value {
+            Value::Small(n) => BigInt::from(n),
+            Value::Bytes(bytes) => BigInt::from_signed_bytes_be(&bytes),
+        }),
+        TAG_A => match unsigned(value, offset)? {
+            0 => Operand::Nil,
+            id => Operand::Atom(AtomId::try_from(id).map_err(|_| invalid)?),
+        },
+        TAG_X => Operand::X(unsigned(value, offset)?),
+        TAG_Y => Operand::Y(unsigned(value, offset)?),
+        TAG_F => Operand::Label(unsigned(value, offset)?),
+        TAG_H => Operand::Character(unsigned(value, offset)?),
+        _ => match unsigned(value, offset)? {
+            EXT_FLOAT => Operand::Float(reader.read_f64::<BigEndian>()?),
+            EXT_LIST => {
+                let len = decode_unsigned(reader)?;
+                let mut operands = Vec::new();
+                for _ in 0..len {
+                    operands.push(decode_operand(reader)?);
+                }
+                Operand::List(operands)
+            }
+            EXT_FLOAT_REGISTER => Operand::FloatRegister(decode_unsigned(reader)?),
+            EXT_ALLOCATION_LIST => {
+                let len = decode_unsigned(reader)?;
+                let mut allocations = Vec::new();
+                for _ in 0..len {
+                    let kind_offset = reader.position() as usize;
+                    let kind = decode_unsigned(reader)?;
+                    let count = decode_unsigned(reader)?;
+                    allocations.push(match kind {
+                        0 => Allocation::Words(count),
+                        1 => Allocation::Floats(count),
+                        2 => Allocation::Funs(count),
+                        _ => {
+                            return Err(ReadError::InvalidOperand {
+                                offset: kind_offset,
+                            })
+                        }
+                    });
+                }
+                Operand::AllocationList(allocations)
+            }
+            EXT_LITERAL => Operand::Literal(decode_unsigned(reader)?),
+            EXT_TYPED_REGISTER => {
+                let register = decode_operand(reader)?;
+                match register {
+                    Operand::X(_) | Operand::Y(_) => (),
+                    _ => return Err(invalid),
+                }
+                Operand::TypedRegister(Box::new(register), decode_unsigned(reader)?)
+            }
+            _ => return Err(invalid),
+        },
+    };
+
+    Ok(operand)
+}
+
+fn decode_value(byte: u8, offset: usize, reader: &mut Cursor<&[u8]>) -> Result<Value> {
+    if byte & 0b1000 == 0 {
+        // 4 bit value in the high bits
+        Ok(Value::Small(u64::from(byte >> 4)))
+    } else if byte & 0b1_0000 == 0 {
+        // 11 bit value in the high 3 bits and the next byte
+        let low = reader.read_u8()?;
+        Ok(Value::Small((u64::from(byte >> 5) << 8) | u64::from(low)))
+    } else {
+        // The length of the value in bytes is in the high 3 bits, or follows as an unsigned
+        // operand if it does not fit
+        let len = match byte >> 5 {
+            7 => decode_unsigned(reader)?.saturating_add(9),
+            len => u64::from(len) + 2,
+        };
+        // The length is untrusted, so it is checked before anything is allocated for it
+        let remaining = reader.get_ref().len() as u64 - reader.position();
+        if len > remaining {
+            return Err(ReadError::InvalidOperand { offset });
+        }
+        let mut bytes = vec![0; len as usize];
+        reader.read_exact(&mut bytes)?;
+        Ok(Value::Bytes(bytes))
+    }
+}
+
+fn decode_unsigned(reader: &mut Cursor<&[u8]>) -> Result<u64> {
+    let offset = reader.position() as usize;
+    match decode_operand(reader)? {
+        Operand::Unsigned(n) => Ok(n),
+        _ => Err(ReadError::InvalidOperand { offset }),
+    }
+}
+
+fn unsigned(value: Value, offset: usize) -> Result<u64> {
+    match value {
+        Value::Small(n) => Ok(n),
+        Value::Bytes(bytes) => {
+            let significant: Vec<u8> = bytes.into_iter().skip_while(|b| *b == 0).collect();
+            if significant.len() > 8 {
+                return Err(ReadError::InvalidOperand { offset });
+            }
+            Ok(significant
+                .iter()
+                .fold(0, |acc, b| (acc << 8) | u64::from(*b)))
+        }
+    }
+}
+
+fn encode_operand<W: Write>(operand: &Operand, writer: &mut W) -> Result<()> {
+    match *operand {
+        Operand::Unsigned(n) => encode_unsigned(TAG_U, n, writer),
+        Operand::Integer(ref n) => encode_integer(TAG_I, n, writer),
+        Operand::Atom(id) => encode_unsigned(TAG_A, u64::from(id), writer),
+        Operand::Nil => encode_unsigned(TAG_A, 0, writer),
+        Operand::X(n) => encode_unsigned(TAG_X, n, writer),
+        Operand::Y(n) => encode_unsigned(TAG_Y, n, writer),
+        Operand::Label(n) => encode_unsigned(TAG_F, n, writer),
+        Operand::Character(n) => encode_unsigned(TAG_H, n, writer),
+        Operand::Float(f) => {
+            encode_unsigned(TAG_Z, EXT_FLOAT, writer)?;
+            writer.write_f64::<BigEndian>(f)?;
+            Ok(())
+        }
+        Operand::List(ref operands) => {
+            encode_unsigned(TAG_Z, EXT_LIST, writer)?;
+            encode_unsigned(TAG_U, operands.len() as u64, writer)?;
+            for operand in operands {
+                encode_operand(operand, writer)?;
+            }
+            Ok(())
+        }
+        Operand::FloatRegister(n) => {
+            encode_unsigned(TAG_Z, EXT_FLOAT_REGISTER, writer)?;
+            encode_unsigned(TAG_U, n, writer)
+        }
+        Operand::AllocationList(ref allocations) => {
+            encode_unsigned(TAG_Z, EXT_ALLOCATION_LIST, writer)?;
+            encode_unsigned(TAG_U, allocations.len() as u64, writer)?;
+            for allocation in allocations {
+                let (kind, count) = match *allocation {
+                    Allocation::Words(count) => (0, count),
+                    Allocation::Floats(count) => (1, count),
+                    Allocation::Funs(count) => (2, count),
+                };
+                encode_unsigned(TAG_U, kind, writer)?;
+                encode_unsigned(TAG_U, count, writer)?;
+            }
+            Ok(())
+        }
+        Operand::Literal(index) => {
+            encode_unsigned(TAG_Z, EXT_LITERAL, writer)?;
+            encode_unsigned(TAG_U, index, writer)
+        }
+        Operand::TypedRegister(ref register, type_index) => {
+            encode_unsigned(TAG_Z, EXT_TYPED_REGISTER, writer)?;
+            encode_operand(register, writer)?;
+            encode_unsigned(TAG_U, type_index, writer)
+        }
+    }
+}
+
+fn encode_unsigned<W: Write>(tag: u8, n: u64, writer: &mut W) -> Result<()> {
+    encode_integer(tag, &BigInt::from(n), writer)
+}
+
+// Mirrors `beam_asm:encode/2`
+fn encode_integer<W: Write>(tag: u8, n: &BigInt, writer: &mut W) -> Result<()> {
+    match n.to_u64() {
+        Some(small) if small < 0x10 => {
+            writer.write_u8(((small as u8) << 4) | tag)?;
+        }
+        Some(small) if small < 0x800 => {
+            writer.write_u8((((small >> 3) as u8) & 0b1110_0000) | tag | 0b1000)?;
+            writer.write_u8(small as u8)?;
+        }
+        _ => {
+            // Minimal two's complement, so non-negative values get a leading zero byte if their
+            // top bit is set, and negative values always take at least two bytes
+            let mut bytes = n.to_signed_bytes_be();
+            if n.is_negative() && bytes.len() < 2 {
+                bytes.insert(0, 0xff);
+            }
+
+            if bytes.len() <= 8 {
+                writer.write_u8((((bytes.len() - 2) as u8) << 5) | 0b1_1000 | tag)?;
+            } else {
+                writer.write_u8(0b1111_1000 | tag)?;
+                encode_unsigned(TAG_U, (bytes.len() - 9) as u64, writer)?;
+            }
+            writer.write_all(&bytes)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Looks up the atoms and literals that [Operand](Operand)s refer to, so that instructions can be
+/// shown as BEAM assembly.
+///
+/// ```
+/// use liblumen_beam::beam::chunk::StandardChunk;
+/// use liblumen_beam::beam::chunk::code::Disassembler;
+/// use liblumen_beam::beam::reader::StandardBeamFile;
+///
+/// let beam = StandardBeamFile::from_file("tests/testdata/reader/test.beam").unwrap();
+/// let (mut atoms, mut code) = (None, None);
+/// for chunk in beam.chunks() {
+///     match chunk {
+///         StandardChunk::Atom(c) => atoms = Some(c),
+///         StandardChunk::Code(c) => code = Some(c),
+///         _ => (),
+///     }
+/// }
+///
+/// let disassembler = Disassembler::new(atoms.unwrap(), None).unwrap();
+/// let instructions = code.unwrap().instructions().unwrap();
+/// assert_eq!(
+///     "{func_info,{atom,'test'},{atom,'hello'},1}",
+///     disassembler.display(&instructions[2]).to_string()
+/// );
+/// ```
+pub struct Disassembler<'a> {
+    atoms: &'a AtomChunk,
+    literals: Vec<etf::Term>,
+}
+impl<'a> Disassembler<'a> {
+    /// Decodes the literals in `literals`, if the module has any.
+    pub fn new(
+        atoms: &'a AtomChunk,
+        literals: Option<&LitTChunk>,
+    ) -> std::result::Result<Self, etf::DecodeError> {
+        let literals = match literals {
+            Some(chunk) => chunk
+                .literals
+                .iter()
+                .map(|literal| etf::Term::decode(Cursor::new(literal)))
+                .collect::<std::result::Result<_, _>>()?,
+            None => Vec::new(),
+        };
+
+        Ok(Disassembler { atoms, literals })
+    }
+
+    /// The name of the atom with the one-based index `id`.
+    pub fn atom(&self, id: AtomId) -> Option<&'a str> {
+        let atoms = self.atoms;
+        (id as usize)
+            .checked_sub(1)
+            .and_then(|index| atoms.atoms.get(index))
+            .map(|atom| atom.name.as_str())
+    }
+
+    /// The literal term with the zero-based `index`.
+    pub fn literal(&self, index: u64) -> Option<&etf::Term> {
+        self.literals.get(index as usize)
+    }
+
+    /// Formats `instruction` like `erlc -S` does, such as `{move,{x,0},{y,1}}`.
+    pub fn display<'b>(&'b self, instruction: &'b Instruction) -> impl fmt::Display + 'b {
+        DisplayInstruction {
+            disassembler: self,
+            instruction,
+        }
+    }
+
+    fn fmt_operand(&self, operand: &Operand, f: &mut fmt::Formatter) -> fmt::Result {
+        match *operand {
+            Operand::Unsigned(n) => write!(f, "{}", n),
+            Operand::Integer(ref n) => write!(f, "{{integer,{}}}", n),
+            Operand::Atom(id) => match self.atom(id) {
+                Some(name) => write!(f, "{{atom,{}}}", etf::Atom::from(name)),
+                None => write!(f, "{{atom,#{}}}", id),
+            },
+            Operand::Nil => write!(f, "nil"),
+            Operand::X(n) => write!(f, "{{x,{}}}", n),
+            Operand::Y(n) => write!(f, "{{y,{}}}", n),
+            Operand::Label(n) => write!(f, "{{f,{}}}", n),
+            Operand::Character(n) => write!(f, "{{char,{}}}", n),
+            Operand::Float(n) => write!(f, "{{float,{:?}}}", n),
+            Operand::List(ref operands) => {
+                write!(f, "{{list,[")?;
+                for (i, operand) in operands.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    self.fmt_operand(operand, f)?;
+                }
+                write!(f, "]}}")
+            }
+            Operand::FloatRegister(n) => write!(f, "{{fr,{}}}", n),
+            Operand::AllocationList(ref allocations) => {
+                write!(f, "{{alloc,[")?;
+                for (i, allocation) in allocations.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    match *allocation {
+                        Allocation::Words(n) => write!(f, "{{words,{}}}", n)?,
+                        Allocation::Floats(n) => write!(f, "{{floats,{}}}", n)?,
+                        Allocation::Funs(n) => write!(f, "{{funs,{}}}", n)?,
+                    }
+                }
+                write!(f, "]}}")
+            }
+            Operand::Literal(index) => match self.literal(index) {
+                Some(term) => write!(f, "{{literal,{}}}", term),
+                None => write!(f, "{{literal,#{}}}", index),
+            },
+            Operand::TypedRegister(ref register, type_index) => {
+                write!(f, "{{tr,")?;
+                self.fmt_operand(register, f)?;
+                write!(f, ",{}}}", type_index)
+            }
+        }
+    }
+}
+
+struct DisplayInstruction<'a> {
+    disassembler: &'a Disassembler<'a>,
+    instruction: &'a Instruction,
+}
+impl<'a> fmt::Display for DisplayInstruction<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.instruction.opcode.name();
+        if self.instruction.operands.is_empty() {
+            return write!(f, "{}", name);
+        }
+
+        write!(f, "{{{}", name)?;
+        for operand in &self.instruction.operands {
+            write!(f, ",")?;
+            self.disassembler.fmt_operand(operand, f)?;
+        }
+        write!(f, "}}")
+    }
+}
@@ -4,11 +4,13 @@ use std::io::Write;
 use std::path::PathBuf;
 
 use crate::beam::reader::chunk;
+use crate::beam::reader::chunk::code;
 use crate::beam::reader::chunk::Chunk;
 use crate::beam::reader::chunk::StandardChunk;
 use crate::beam::reader::parts;
 use crate::beam::reader::BeamFile;
 use crate::beam::reader::RawBeamFile;
+use crate::beam::reader::ReadError;
 use crate::beam::reader::Result;
 use crate::beam::reader::StandardBeamFile;
 
@@ -181,6 +183,135 @@ fn standard_chunks() {
     assert_eq!(307, find_chunk!(beam, Abst).term.len());
 }
 
+#[test]
+fn code_instructions() {
+    use self::StandardChunk::*;
+
+    let beam = StandardBeamFile::from_file(test_file("test.beam")).unwrap();
+    let (mut atoms, mut code, mut literals) = (None, None, None);
+    for chunk in beam.chunks() {
+        match chunk {
+            Atom(c) => atoms = Some(c),
+            Code(c) => code = Some(c),
+            LitT(c) => literals = Some(c),
+            _ => (),
+        }
+    }
+    let code = code.unwrap();
+    let instructions = code.instructions().unwrap();
+    assert_eq!(36, instructions.len());
+    assert_eq!(
+        Some(&code::Opcode::IntCodeEnd),
+        instructions.last().map(|i| &i.opcode)
+    );
+
+    let disassembler = code::Disassembler::new(atoms.unwrap(), literals).unwrap();
+    let disassembly = instructions[29..33]
+        .iter()
+        .map(|i| disassembler.display(i).to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            "{label,8}",
+            "{test_heap,2,1}",
+            "{put_list,{x,0},nil,{x,1}}",
+            "{move,{literal,[72,101,108,108,111,32,126,112,33]},{x,0}}",
+        ],
+        disassembly
+    );
+
+    // Re-encoding reproduces the compiler's output
+    for name in &["test.beam", "Elixir.Unicode.beam"] {
+        let beam = StandardBeamFile::from_file(test_file(name)).unwrap();
+        for chunk in beam.chunks() {
+            if let Code(code) = chunk {
+                let mut encoded = Vec::new();
+                code::encode_instructions(&code.instructions().unwrap(), &mut encoded).unwrap();
+                assert_eq!(code.bytecode, encoded);
+            }
+        }
+    }
+}
+
+#[test]
+fn code_operands() {
+    use self::code::{Allocation, Instruction, Opcode, Operand};
+    use num::bigint::BigInt;
+
+    let integer = |n: i64| Operand::Integer(BigInt::from(n));
+    let instructions = vec![
+        Instruction {
+            opcode: Opcode::Move,
+            operands: vec![integer(-1), Operand::X(1023)],
+        },
+        Instruction {
+            opcode: Opcode::Move,
+            operands: vec![integer(0x7fff_ffff_ffff), Operand::Y(0x800)],
+        },
+        Instruction {
+            opcode: Opcode::Move,
+            operands: vec![
+                Operand::Integer(BigInt::from(std::u64::MAX) * BigInt::from(1 << 20)),
+                Operand::TypedRegister(Box::new(Operand::X(0)), 3),
+            ],
+        },
+        Instruction {
+            opcode: Opcode::TestHeap,
+            operands: vec![
+                Operand::AllocationList(vec![Allocation::Words(2), Allocation::Floats(1)]),
+                Operand::Unsigned(u64::from(std::u32::MAX)),
+            ],
+        },
+        Instruction {
+            opcode: Opcode::Fmove,
+            operands: vec![Operand::Float(1.5), Operand::FloatRegister(0)],
+        },
+    ];
+
+    let mut encoded = Vec::new();
+    code::encode_instructions(&instructions, &mut encoded).unwrap();
+    // `move {integer,-1} {x,1023}` as `beam_asm` encodes it
+    assert_eq!(
+        vec![64, 0b0001_1001, 0xff, 0xff, 0b0110_1011, 0xff],
+        encoded[..6].to_vec()
+    );
+    assert_eq!(instructions, code::decode_instructions(&encoded).unwrap());
+
+    match code::decode_instructions(&[0]) {
+        Err(ReadError::UnknownOpcode {
+            opcode: 0,
+            offset: 0,
+        }) => (),
+        result => panic!("expected an unknown opcode, got {:?}", result),
+    }
+
+    // An integer which is longer than the rest of the code
+    match code::decode_instructions(&[64, 0b1101_1001, 1, 3]) {
+        Err(ReadError::InvalidOperand { offset: 1 }) => (),
+        result => panic!("expected an invalid operand, got {:?}", result),
+    }
+    // An integer whose length does not fit in a byte, and is too large to allocate
+    let mut huge = vec![64, 0b1111_1001, 0b1101_1000];
+    huge.extend_from_slice(&[0xff; 8]);
+    match code::decode_instructions(&huge) {
+        Err(ReadError::InvalidOperand { offset: 1 }) => (),
+        result => panic!("expected an invalid operand, got {:?}", result),
+    }
+
+    let missing_operand = Instruction {
+        opcode: Opcode::Move,
+        operands: vec![Operand::X(0)],
+    };
+    match code::encode_instructions(&[missing_operand], &mut Vec::new()) {
+        Err(ReadError::WrongOperandCount {
+            opcode: 64,
+            expected: 2,
+            actual: 1,
+        }) => (),
+        result => panic!("expected a wrong operand count, got {:?}", result),
+    }
+}
+
 enum EncodeTestChunk {
     Idempotent(chunk::StandardChunk),
     Other(chunk::RawChunk),
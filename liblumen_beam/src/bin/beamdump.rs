@@ -0,0 +1,74 @@
+use std::io::{self, Write};
+use std::process;
+
+use clap::{App, Arg};
+
+use liblumen_beam::beam::dump::{dump, parse_id, Format, Options};
+use liblumen_beam::beam::reader::StandardBeamFile;
+
+fn main() {
+    let matches = App::new("beamdump")
+        .about("Prints the chunks of BEAM files")
+        .arg(Arg::from_usage(
+            "<BEAM_FILES>... 'the .beam files to print'",
+        ))
+        .arg(Arg::from_usage(
+            "--json 'print each file as a JSON object on its own line'",
+        ))
+        .arg(
+            Arg::from_usage(
+                "-c, --chunk [ID]... 'only print chunks with this identifier, such as ImpT'",
+            )
+            .number_of_values(1),
+        )
+        .get_matches();
+
+    let mut options = Options::default();
+    if matches.is_present("json") {
+        options.format = Format::Json;
+    }
+    if let Some(ids) = matches.values_of("chunk") {
+        let mut chunks = Vec::new();
+        for id in ids {
+            match parse_id(id) {
+                Some(id) => chunks.push(id),
+                None => {
+                    eprintln!("invalid chunk identifier `{}`: expected 4 characters", id);
+                    process::exit(2);
+                }
+            }
+        }
+        options.chunks = Some(chunks);
+    }
+
+    let paths: Vec<&str> = matches.values_of("BEAM_FILES").unwrap().collect();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut failed = false;
+
+    for (i, path) in paths.iter().enumerate() {
+        let beam = match StandardBeamFile::from_file(path) {
+            Ok(beam) => beam,
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                failed = true;
+                continue;
+            }
+        };
+
+        let mut result = Ok(());
+        if paths.len() > 1 && options.format == Format::Text {
+            let separator = if i > 0 { "\n" } else { "" };
+            result = writeln!(out, "{}{}:", separator, path);
+        }
+
+        if let Err(error) = result.and_then(|_| dump(&beam, &options, &mut out)) {
+            eprintln!("{}: {}", path, error);
+            process::exit(1);
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
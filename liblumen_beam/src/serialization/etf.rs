@@ -26,6 +26,7 @@
 //!
 mod codec;
 pub mod convert;
+pub mod distribution;
 pub mod pattern;
 
 #[cfg(test)]
@@ -364,10 +365,11 @@ pub struct Pid {
     pub node: Atom,
     pub id: u32,
     pub serial: u32,
-    pub creation: u8,
+    /// 32 bits in `NEW_PID_EXT`, which OTP 23 and later send; `PID_EXT` only has room for 8.
+    pub creation: u32,
 }
 impl Pid {
-    pub fn new<T>(node: T, id: u32, serial: u32, creation: u8) -> Self
+    pub fn new<T>(node: T, id: u32, serial: u32, creation: u32) -> Self
     where
         Atom: From<T>,
     {
@@ -400,8 +402,10 @@ impl<'a> From<(&'a str, u32, u32)> for Pid {
 #[derive(Debug, PartialEq, Clone)]
 pub struct Port {
     pub node: Atom,
-    pub id: u32,
-    pub creation: u8,
+    /// 64 bits in `V4_PORT_EXT`; the other port tags only have room for 32.
+    pub id: u64,
+    /// 32 bits in `NEW_PORT_EXT` and `V4_PORT_EXT`; `PORT_EXT` only has room for 8.
+    pub creation: u32,
 }
 impl std::fmt::Display for Port {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -412,7 +416,7 @@ impl<'a> From<(&'a str, u32)> for Port {
     fn from((node, id): (&'a str, u32)) -> Self {
         Port {
             node: Atom::from(node),
-            id,
+            id: u64::from(id),
             creation: 0,
         }
     }
@@ -423,7 +427,8 @@ impl<'a> From<(&'a str, u32)> for Port {
 pub struct Reference {
     pub node: Atom,
     pub id: Vec<u32>,
-    pub creation: u8,
+    /// 32 bits in `NEWER_REFERENCE_EXT`; `NEW_REFERENCE_EXT` only has room for 8.
+    pub creation: u32,
 }
 impl std::fmt::Display for Reference {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -14,6 +14,7 @@ use failure::Fail;
 
 use self::convert::TryAsRef;
 use self::convert::TryInto;
+use self::distribution::{AtomCache, AtomCacheRef, Message};
 use super::*;
 
 /// Errors which can occur when decoding a term
@@ -36,6 +37,24 @@ pub enum DecodeError {
         value: i32,
         range: std::ops::Range<i32>,
     },
+
+    #[fail(display = "a distribution header is not a term, decode it as a distribution message")]
+    DistributionHeader,
+
+    #[fail(display = "a LOCAL_EXT term can only be decoded by the node that encoded it")]
+    LocalTerm,
+
+    #[fail(display = "atom cache ref {} is not in the distribution header", index)]
+    UnknownAtomCacheRef { index: u8 },
+
+    #[fail(display = "atom cache index {} has no cached atom", index)]
+    UnknownAtomCacheIndex { index: usize },
+
+    #[fail(
+        display = "fragment {} of sequence {} was not expected",
+        fragment_id, sequence_id
+    )]
+    UnexpectedFragment { sequence_id: u64, fragment_id: u64 },
 }
 impl std::convert::From<std::io::Error> for DecodeError {
     fn from(err: std::io::Error) -> DecodeError {
@@ -70,13 +89,18 @@ impl std::convert::From<std::io::Error> for EncodeError {
 pub type DecodeResult = Result<Term, DecodeError>;
 pub type EncodeResult = Result<(), EncodeError>;
 
-const VERSION: u8 = 131;
+pub const VERSION: u8 = 131;
 
-const DISTRIBUTION_HEADER: u8 = 68;
+pub const DISTRIBUTION_HEADER: u8 = 68;
+pub const DISTRIBUTION_FRAGMENT_HEADER: u8 = 69;
+pub const DISTRIBUTION_FRAGMENT_CONTINUATION: u8 = 70;
 const NEW_FLOAT_EXT: u8 = 70;
 const BIT_BINARY_EXT: u8 = 77;
 const COMPRESSED_TERM: u8 = 80;
 const ATOM_CACHE_REF: u8 = 82;
+const NEW_PID_EXT: u8 = 88;
+const NEW_PORT_EXT: u8 = 89;
+const NEWER_REFERENCE_EXT: u8 = 90;
 const SMALL_INTEGER_EXT: u8 = 97;
 const INTEGER_EXT: u8 = 98;
 const FLOAT_EXT: u8 = 99;
@@ -100,16 +124,27 @@ const MAP_EXT: u8 = 116;
 const FUN_EXT: u8 = 117;
 const ATOM_UTF8_EXT: u8 = 118;
 const SMALL_ATOM_UTF8_EXT: u8 = 119;
+const V4_PORT_EXT: u8 = 120;
+const LOCAL_EXT: u8 = 121;
+
+// In an atom cache ref's half byte of the distribution header flags
+const NEW_CACHE_ENTRY_FLAG: u8 = 0b1000;
+const SEGMENT_INDEX_MASK: u8 = 0b0111;
+// In the half byte after the atom cache refs' flags
+const LONG_ATOMS_FLAG: u8 = 0b0001;
 
 pub struct Decoder<R> {
     reader: R,
     buf: Vec<u8>,
+    /// The atoms `ATOM_CACHE_REF` refers to, from the distribution header before the terms
+    atom_cache_refs: Vec<Atom>,
 }
 impl<R: std::io::Read> Decoder<R> {
     pub fn new(reader: R) -> Self {
         Decoder {
             reader,
             buf: Vec::new(),
+            atom_cache_refs: Vec::new(),
         }
     }
     pub fn decode(mut self) -> DecodeResult {
@@ -120,10 +155,74 @@ impl<R: std::io::Read> Decoder<R> {
         let tag = self.reader.read_u8()?;
         match tag {
             COMPRESSED_TERM => self.decode_compressed_term(),
-            DISTRIBUTION_HEADER => unimplemented!(),
+            DISTRIBUTION_HEADER | DISTRIBUTION_FRAGMENT_HEADER => {
+                Err(DecodeError::DistributionHeader)
+            }
             _ => self.decode_term_with_tag(tag),
         }
     }
+    /// Decodes the atom cache refs of a distribution header, which follow its tag (and the ids of
+    /// a fragmented header), adding the new entries to `atom_cache`.
+    pub fn decode_atom_cache_refs(
+        &mut self,
+        atom_cache: &mut AtomCache,
+    ) -> Result<Vec<Atom>, DecodeError> {
+        let count = self.reader.read_u8()? as usize;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        // A half byte for each ref, then a half byte of flags for the whole header
+        let mut flags = vec![0; count / 2 + 1];
+        self.reader.read_exact(&mut flags)?;
+        let half_byte = |i: usize| (flags[i / 2] >> ((i % 2) * 4)) & 0x0F;
+        let long_atoms = half_byte(count) & LONG_ATOMS_FLAG != 0;
+
+        let mut atoms = Vec::with_capacity(count);
+        for i in 0..count {
+            let flags = half_byte(i);
+            let segment_index = (flags & SEGMENT_INDEX_MASK) as usize;
+            let internal_segment_index = self.reader.read_u8()? as usize;
+            let index = segment_index * 256 + internal_segment_index;
+            if flags & NEW_CACHE_ENTRY_FLAG != 0 {
+                let len = if long_atoms {
+                    self.reader.read_u16::<BigEndian>()?
+                } else {
+                    self.reader.read_u8()? as u16
+                };
+                self.buf.resize(len as usize, 0);
+                self.reader.read_exact(&mut self.buf)?;
+                // Nodes which do not support UTF-8 atoms send them as Latin-1
+                let atom = match std::str::from_utf8(&self.buf) {
+                    Ok(name) => Atom::from(name),
+                    Err(_) => Atom::from(self.buf.iter().map(|&b| b as char).collect::<String>()),
+                };
+                atom_cache.insert(index, atom.clone());
+                atoms.push(atom);
+            } else {
+                match atom_cache.get(index) {
+                    Some(atom) => atoms.push(atom.clone()),
+                    None => return Err(DecodeError::UnknownAtomCacheIndex { index }),
+                }
+            }
+        }
+        Ok(atoms)
+    }
+    /// Decodes the control message and, if the reader has more bytes, the payload that follow a
+    /// distribution header.  The terms have no version byte.
+    pub fn decode_distribution_message(
+        mut self,
+        atom_cache_refs: Vec<Atom>,
+    ) -> Result<Message, DecodeError> {
+        self.atom_cache_refs = atom_cache_refs;
+        let control = self.decode_term()?;
+        let payload = match self.reader.read_u8() {
+            Ok(tag) => Some(self.decode_term_with_tag(tag)?),
+            Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => None,
+            Err(e) => return Err(DecodeError::from(e)),
+        };
+        Ok(Message { control, payload })
+    }
     fn decode_term(&mut self) -> DecodeResult {
         let tag = self.reader.read_u8()?;
         self.decode_term_with_tag(tag)
@@ -132,7 +231,10 @@ impl<R: std::io::Read> Decoder<R> {
         match tag {
             NEW_FLOAT_EXT => self.decode_new_float_ext(),
             BIT_BINARY_EXT => self.decode_bit_binary_ext(),
-            ATOM_CACHE_REF => unimplemented!(),
+            ATOM_CACHE_REF => self.decode_atom_cache_ref(),
+            NEW_PID_EXT => self.decode_new_pid_ext(),
+            NEW_PORT_EXT => self.decode_new_port_ext(),
+            NEWER_REFERENCE_EXT => self.decode_newer_reference_ext(),
             SMALL_INTEGER_EXT => self.decode_small_integer_ext(),
             INTEGER_EXT => self.decode_integer_ext(),
             FLOAT_EXT => self.decode_float_ext(),
@@ -156,13 +258,27 @@ impl<R: std::io::Read> Decoder<R> {
             FUN_EXT => self.decode_fun_ext(),
             ATOM_UTF8_EXT => self.decode_atom_utf8_ext(),
             SMALL_ATOM_UTF8_EXT => self.decode_small_atom_utf8_ext(),
+            V4_PORT_EXT => self.decode_v4_port_ext(),
+            // The format after the tag is private to the node that encoded the term
+            LOCAL_EXT => Err(DecodeError::LocalTerm),
             _ => Err(DecodeError::UnknownTag { tag }),
         }
     }
+    fn decode_atom_cache_ref(&mut self) -> DecodeResult {
+        let index = self.reader.read_u8()?;
+        match self.atom_cache_refs.get(index as usize) {
+            Some(atom) => Ok(Term::from(atom.clone())),
+            None => Err(DecodeError::UnknownAtomCacheRef { index }),
+        }
+    }
     fn decode_compressed_term(&mut self) -> DecodeResult {
         let _uncompressed_size = self.reader.read_u32::<BigEndian>()? as usize;
         let zlib_decoder = zlib::Decoder::new(&mut self.reader)?;
-        let mut decoder = Decoder::new(zlib_decoder);
+        let mut decoder = Decoder {
+            reader: zlib_decoder,
+            buf: Vec::new(),
+            atom_cache_refs: self.atom_cache_refs.clone(),
+        };
         decoder.decode_term()
     }
     fn decode_nil_ext(&mut self) -> DecodeResult {
@@ -242,7 +358,16 @@ impl<R: std::io::Read> Decoder<R> {
             node,
             id: self.reader.read_u32::<BigEndian>()?,
             serial: self.reader.read_u32::<BigEndian>()?,
-            creation: self.reader.read_u8()?,
+            creation: u32::from(self.reader.read_u8()?),
+        }))
+    }
+    fn decode_new_pid_ext(&mut self) -> DecodeResult {
+        let node = self.decode_term().and_then(aux::term_into_atom)?;
+        Ok(Term::from(Pid {
+            node,
+            id: self.reader.read_u32::<BigEndian>()?,
+            serial: self.reader.read_u32::<BigEndian>()?,
+            creation: self.reader.read_u32::<BigEndian>()?,
         }))
     }
     fn decode_port_ext(&mut self) -> DecodeResult {
@@ -254,8 +379,24 @@ impl<R: std::io::Read> Decoder<R> {
         })?;
         Ok(Term::from(Port {
             node,
-            id: self.reader.read_u32::<BigEndian>()?,
-            creation: self.reader.read_u8()?,
+            id: u64::from(self.reader.read_u32::<BigEndian>()?),
+            creation: u32::from(self.reader.read_u8()?),
+        }))
+    }
+    fn decode_new_port_ext(&mut self) -> DecodeResult {
+        let node = self.decode_term().and_then(aux::term_into_atom)?;
+        Ok(Term::from(Port {
+            node,
+            id: u64::from(self.reader.read_u32::<BigEndian>()?),
+            creation: self.reader.read_u32::<BigEndian>()?,
+        }))
+    }
+    fn decode_v4_port_ext(&mut self) -> DecodeResult {
+        let node = self.decode_term().and_then(aux::term_into_atom)?;
+        Ok(Term::from(Port {
+            node,
+            id: self.reader.read_u64::<BigEndian>()?,
+            creation: self.reader.read_u32::<BigEndian>()?,
         }))
     }
     fn decode_reference_ext(&mut self) -> DecodeResult {
@@ -263,13 +404,23 @@ impl<R: std::io::Read> Decoder<R> {
         Ok(Term::from(Reference {
             node,
             id: vec![self.reader.read_u32::<BigEndian>()?],
-            creation: self.reader.read_u8()?,
+            creation: u32::from(self.reader.read_u8()?),
         }))
     }
     fn decode_new_reference_ext(&mut self) -> DecodeResult {
         let id_count = self.reader.read_u16::<BigEndian>()? as usize;
         let node = self.decode_term().and_then(aux::term_into_atom)?;
-        let creation = self.reader.read_u8()?;
+        let creation = u32::from(self.reader.read_u8()?);
+        let mut id = Vec::with_capacity(id_count);
+        for _ in 0..id_count {
+            id.push(self.reader.read_u32::<BigEndian>()?);
+        }
+        Ok(Term::from(Reference { node, id, creation }))
+    }
+    fn decode_newer_reference_ext(&mut self) -> DecodeResult {
+        let id_count = self.reader.read_u16::<BigEndian>()? as usize;
+        let node = self.decode_term().and_then(aux::term_into_atom)?;
+        let creation = self.reader.read_u32::<BigEndian>()?;
         let mut id = Vec::with_capacity(id_count);
         for _ in 0..id_count {
             id.push(self.reader.read_u32::<BigEndian>()?);
@@ -405,15 +556,72 @@ impl<R: std::io::Read> Decoder<R> {
 
 pub struct Encoder<W> {
     writer: W,
+    /// The atoms to encode as `ATOM_CACHE_REF`, from the distribution header before the terms
+    atom_cache_refs: Vec<Atom>,
 }
 impl<W: std::io::Write> Encoder<W> {
     pub fn new(writer: W) -> Self {
-        Encoder { writer }
+        Encoder {
+            writer,
+            atom_cache_refs: Vec::new(),
+        }
     }
     pub fn encode(mut self, term: &Term) -> EncodeResult {
         self.writer.write_u8(VERSION)?;
         self.encode_term(term)
     }
+    /// Encodes the atom cache refs of a distribution header, after its tag (and the ids of a
+    /// fragmented header).
+    pub fn encode_atom_cache_refs(&mut self, refs: &[AtomCacheRef]) -> EncodeResult {
+        self.writer.write_u8(refs.len() as u8)?;
+        if refs.is_empty() {
+            return Ok(());
+        }
+
+        let long_atoms = refs
+            .iter()
+            .any(|r| r.is_new && r.atom.name.len() > std::u8::MAX as usize);
+        let mut flags = vec![0; refs.len() / 2 + 1];
+        for (i, r) in refs.iter().enumerate() {
+            let mut half_byte = (r.index / 256) as u8 & SEGMENT_INDEX_MASK;
+            if r.is_new {
+                half_byte |= NEW_CACHE_ENTRY_FLAG;
+            }
+            flags[i / 2] |= half_byte << ((i % 2) * 4);
+        }
+        if long_atoms {
+            flags[refs.len() / 2] |= LONG_ATOMS_FLAG << ((refs.len() % 2) * 4);
+        }
+        self.writer.write_all(&flags)?;
+
+        for r in refs {
+            self.writer.write_u8((r.index % 256) as u8)?;
+            if r.is_new {
+                if long_atoms {
+                    self.writer
+                        .write_u16::<BigEndian>(r.atom.name.len() as u16)?;
+                } else {
+                    self.writer.write_u8(r.atom.name.len() as u8)?;
+                }
+                self.writer.write_all(r.atom.name.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+    /// Encodes the control message and payload that follow a distribution header, without version
+    /// bytes.
+    pub fn encode_distribution_message(
+        mut self,
+        message: &Message,
+        refs: &[AtomCacheRef],
+    ) -> EncodeResult {
+        self.atom_cache_refs = refs.iter().map(|r| r.atom.clone()).collect();
+        self.encode_term(&message.control)?;
+        if let Some(ref payload) = message.payload {
+            self.encode_term(payload)?;
+        }
+        Ok(())
+    }
     fn encode_term(&mut self, term: &Term) -> EncodeResult {
         match *term {
             Term::Atom(ref x) => self.encode_atom(x),
@@ -439,8 +647,13 @@ impl<W: std::io::Write> Encoder<W> {
     }
     fn encode_list(&mut self, x: &List) -> EncodeResult {
         let to_byte = |e: &Term| {
-            e.try_as_ref()
-                .and_then(|&FixInteger { value: i }| if i < 0x100 { Some(i as u8) } else { None })
+            e.try_as_ref().and_then(|&FixInteger { value: i }| {
+                if 0 <= i && i < 0x100 {
+                    Some(i as u8)
+                } else {
+                    None
+                }
+            })
         };
         if !x.elements.is_empty()
             && x.elements.len() <= std::u16::MAX as usize
@@ -521,6 +734,11 @@ impl<W: std::io::Write> Encoder<W> {
         Ok(())
     }
     fn encode_atom(&mut self, x: &Atom) -> EncodeResult {
+        if let Some(index) = self.atom_cache_refs.iter().position(|a| a == x) {
+            self.writer.write_u8(ATOM_CACHE_REF)?;
+            self.writer.write_u8(index as u8)?;
+            return Ok(());
+        }
         if x.name.len() > 0xFFFF {
             return Err(EncodeError::TooLongAtomName(x.clone()));
         }
@@ -561,28 +779,58 @@ impl<W: std::io::Write> Encoder<W> {
         Ok(())
     }
     fn encode_pid(&mut self, x: &Pid) -> EncodeResult {
-        self.writer.write_u8(PID_EXT)?;
+        let is_small_creation = x.creation <= std::u8::MAX as u32;
+        if is_small_creation {
+            self.writer.write_u8(PID_EXT)?;
+        } else {
+            self.writer.write_u8(NEW_PID_EXT)?;
+        }
         self.encode_atom(&x.node)?;
         self.writer.write_u32::<BigEndian>(x.id)?;
         self.writer.write_u32::<BigEndian>(x.serial)?;
-        self.writer.write_u8(x.creation)?;
+        if is_small_creation {
+            self.writer.write_u8(x.creation as u8)?;
+        } else {
+            self.writer.write_u32::<BigEndian>(x.creation)?;
+        }
         Ok(())
     }
     fn encode_port(&mut self, x: &Port) -> EncodeResult {
-        self.writer.write_u8(PORT_EXT)?;
-        self.encode_atom(&x.node)?;
-        self.writer.write_u32::<BigEndian>(x.id)?;
-        self.writer.write_u8(x.creation)?;
+        if x.id > std::u32::MAX as u64 {
+            self.writer.write_u8(V4_PORT_EXT)?;
+            self.encode_atom(&x.node)?;
+            self.writer.write_u64::<BigEndian>(x.id)?;
+            self.writer.write_u32::<BigEndian>(x.creation)?;
+        } else if x.creation > std::u8::MAX as u32 {
+            self.writer.write_u8(NEW_PORT_EXT)?;
+            self.encode_atom(&x.node)?;
+            self.writer.write_u32::<BigEndian>(x.id as u32)?;
+            self.writer.write_u32::<BigEndian>(x.creation)?;
+        } else {
+            self.writer.write_u8(PORT_EXT)?;
+            self.encode_atom(&x.node)?;
+            self.writer.write_u32::<BigEndian>(x.id as u32)?;
+            self.writer.write_u8(x.creation as u8)?;
+        }
         Ok(())
     }
     fn encode_reference(&mut self, x: &Reference) -> EncodeResult {
-        self.writer.write_u8(NEW_REFERENCE_EXT)?;
+        let is_small_creation = x.creation <= std::u8::MAX as u32;
+        if is_small_creation {
+            self.writer.write_u8(NEW_REFERENCE_EXT)?;
+        } else {
+            self.writer.write_u8(NEWER_REFERENCE_EXT)?;
+        }
         if x.id.len() > std::u16::MAX as usize {
             return Err(EncodeError::TooLargeReferenceId(x.clone()));
         }
         self.writer.write_u16::<BigEndian>(x.id.len() as u16)?;
         self.encode_atom(&x.node)?;
-        self.writer.write_u8(x.creation)?;
+        if is_small_creation {
+            self.writer.write_u8(x.creation as u8)?;
+        } else {
+            self.writer.write_u32::<BigEndian>(x.creation)?;
+        }
         for n in &x.id {
             self.writer.write_u32::<BigEndian>(*n)?;
         }
@@ -628,7 +876,10 @@ impl<W: std::io::Write> Encoder<W> {
 
                 let mut buf = Vec::new();
                 {
-                    let mut tmp = Encoder::new(&mut buf);
+                    let mut tmp = Encoder {
+                        writer: &mut buf,
+                        atom_cache_refs: self.atom_cache_refs.clone(),
+                    };
                     tmp.writer.write_u8(arity)?;
                     tmp.writer.write_all(uniq)?;
                     tmp.writer.write_u32::<BigEndian>(index)?;
@@ -0,0 +1,415 @@
+//! Messages between nodes, which follow a distribution header.
+//!
+//! A distribution header lists the atoms that the control message and payload after it refer to
+//! with `ATOM_CACHE_REF`.  Atoms are cached by each end of a connection: the first header that
+//! uses an atom sends its name with the index of the cache entry, and later headers only send the
+//! index.  The `AtomCache` of a sender must therefore be the mirror of its receiver's, so use one
+//! `AtomCache` per direction of each connection.
+//!
+//! # Examples
+//!
+//!     use liblumen_beam::serialization::etf::distribution::{AtomCache, Message};
+//!     use liblumen_beam::serialization::etf::{Atom, FixInteger, Term, Tuple};
+//!
+//!     let message = Message::new(
+//!         Term::from(Tuple::from(vec![
+//!             Term::from(FixInteger::from(6)),
+//!             Term::from(Atom::from("net_kernel")),
+//!         ])),
+//!         Some(Term::from(Atom::from("hello"))),
+//!     );
+//!
+//!     let mut buf = Vec::new();
+//!     message.encode(&mut buf, &mut AtomCache::new()).unwrap();
+//!
+//!     let decoded = Message::decode(&buf[..], &mut AtomCache::new()).unwrap();
+//!     assert_eq!(message, decoded);
+//!
+//! # Reference
+//!
+//! - [Distribution Header](http://erlang.org/doc/apps/erts/erl_ext_dist.html#distribution-header)
+//!
+#[cfg(test)]
+mod test;
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use byteorder::BigEndian;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+
+use super::codec::{self, Decoder, Encoder};
+use super::*;
+
+/// The number of atoms in an `AtomCache`: 8 segments of 256 atoms.
+pub const ATOM_CACHE_SIZE: usize = 2048;
+
+/// The count of atom cache refs in a distribution header is a byte.
+const MAX_ATOM_CACHE_REFS: usize = 255;
+
+/// The atoms cached by one direction of a connection between two nodes.
+#[derive(Debug, Clone)]
+pub struct AtomCache {
+    atoms: Vec<Option<Atom>>,
+}
+impl AtomCache {
+    pub fn new() -> Self {
+        AtomCache {
+            atoms: vec![None; ATOM_CACHE_SIZE],
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Atom> {
+        self.atoms.get(index).and_then(|atom| atom.as_ref())
+    }
+
+    pub fn insert(&mut self, index: usize, atom: Atom) {
+        self.atoms[index] = Some(atom);
+    }
+
+    /// The refs for the atoms in `message`, which make cache entries for the atoms that are not
+    /// cached yet once they are `commit`ted.
+    ///
+    /// Atoms after the first 255, or whose entry is taken by another atom of the message, are not
+    /// cached and are encoded in full.
+    fn refs(&self, message: &Message) -> Vec<AtomCacheRef> {
+        let mut atoms = Vec::new();
+        collect_atoms(&message.control, &mut atoms);
+        if let Some(ref payload) = message.payload {
+            collect_atoms(payload, &mut atoms);
+        }
+
+        let mut refs: Vec<AtomCacheRef> = Vec::new();
+        for atom in atoms {
+            if refs.len() == MAX_ATOM_CACHE_REFS {
+                break;
+            }
+            if atom.name.len() > std::u16::MAX as usize {
+                continue;
+            }
+
+            let index = cache_index(atom);
+            if refs.iter().any(|r| r.index == index) {
+                continue;
+            }
+
+            refs.push(AtomCacheRef {
+                index,
+                atom: atom.clone(),
+                is_new: self.get(index) != Some(atom),
+            });
+        }
+        refs
+    }
+
+    /// Makes the new cache entries of `refs`, once the message they are for is encoded.
+    ///
+    /// A message that fails to encode is never sent, so its new entries must not be made: the
+    /// receiver would not have them, and later messages would refer to them by index only.
+    fn commit(&mut self, refs: &[AtomCacheRef]) {
+        for atom_cache_ref in refs.iter().filter(|r| r.is_new) {
+            self.insert(atom_cache_ref.index, atom_cache_ref.atom.clone());
+        }
+    }
+}
+impl Default for AtomCache {
+    fn default() -> Self {
+        AtomCache::new()
+    }
+}
+
+/// An atom in a distribution header.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AtomCacheRef {
+    /// The index of the atom in the `AtomCache`
+    pub index: usize,
+    pub atom: Atom,
+    /// Whether the header makes a new cache entry, so that the name of the atom is sent
+    pub is_new: bool,
+}
+
+/// A message between nodes.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Message {
+    /// The tuple whose first element is the operation, such as `SEND` or `LINK`
+    pub control: Term,
+    /// The term sent by operations such as `SEND` and `REG_SEND`
+    pub payload: Option<Term>,
+}
+impl Message {
+    pub fn new(control: Term, payload: Option<Term>) -> Self {
+        Message { control, payload }
+    }
+
+    /// Decodes a message with a distribution header that is not fragmented.
+    ///
+    /// The reader must end with the message, as the packets of the distribution protocol do,
+    /// because the payload is optional.
+    pub fn decode<R: Read>(mut reader: R, atom_cache: &mut AtomCache) -> Result<Self, DecodeError> {
+        read_version(&mut reader)?;
+        let tag = reader.read_u8()?;
+        if tag != codec::DISTRIBUTION_HEADER {
+            return Err(DecodeError::UnknownTag { tag });
+        }
+        let mut decoder = Decoder::new(reader);
+        let refs = decoder.decode_atom_cache_refs(atom_cache)?;
+        decoder.decode_distribution_message(refs)
+    }
+
+    /// Encodes the message with a distribution header that is not fragmented.
+    ///
+    /// The message is written, and `atom_cache` updated, only if all of it encodes.
+    pub fn encode<W: Write>(&self, mut writer: W, atom_cache: &mut AtomCache) -> EncodeResult {
+        let refs = atom_cache.refs(self);
+        let mut buf = Vec::new();
+        buf.write_u8(codec::VERSION)?;
+        buf.write_u8(codec::DISTRIBUTION_HEADER)?;
+        let mut encoder = Encoder::new(&mut buf);
+        encoder.encode_atom_cache_refs(&refs)?;
+        encoder.encode_distribution_message(self, &refs)?;
+        writer.write_all(&buf)?;
+        atom_cache.commit(&refs);
+        Ok(())
+    }
+
+    /// Encodes the message as fragments whose control message and payload bytes are at most
+    /// `fragment_size` each, for the `sequence_id` that is unique to the message on its
+    /// connection.
+    ///
+    /// The first fragment has the distribution header.  A message is always at least one
+    /// fragment.  Like `encode`, `atom_cache` is only updated if all of the message encodes.
+    pub fn encode_fragments(
+        &self,
+        sequence_id: u64,
+        fragment_size: usize,
+        atom_cache: &mut AtomCache,
+    ) -> Result<Vec<Vec<u8>>, EncodeError> {
+        assert!(fragment_size > 0);
+
+        let refs = atom_cache.refs(self);
+        let mut body = Vec::new();
+        Encoder::new(&mut body).encode_distribution_message(self, &refs)?;
+
+        let chunks = body.chunks(fragment_size).collect::<Vec<_>>();
+        let fragment_count = std::cmp::max(chunks.len(), 1) as u64;
+        let mut fragments = Vec::with_capacity(fragment_count as usize);
+
+        let mut first = Vec::new();
+        first.write_u8(codec::VERSION)?;
+        first.write_u8(codec::DISTRIBUTION_FRAGMENT_HEADER)?;
+        first.write_u64::<BigEndian>(sequence_id)?;
+        first.write_u64::<BigEndian>(fragment_count)?;
+        Encoder::new(&mut first).encode_atom_cache_refs(&refs)?;
+        first.write_all(chunks.first().cloned().unwrap_or(&[]))?;
+        fragments.push(first);
+
+        // Fragment ids count down, so the last fragment is `1`
+        for (chunk, fragment_id) in chunks.iter().skip(1).zip((1..fragment_count).rev()) {
+            let mut continuation = Vec::new();
+            continuation.write_u8(codec::VERSION)?;
+            continuation.write_u8(codec::DISTRIBUTION_FRAGMENT_CONTINUATION)?;
+            continuation.write_u64::<BigEndian>(sequence_id)?;
+            continuation.write_u64::<BigEndian>(fragment_id)?;
+            continuation.write_all(chunk)?;
+            fragments.push(continuation);
+        }
+
+        atom_cache.commit(&refs);
+        Ok(fragments)
+    }
+}
+
+/// Reassembles the messages of a connection from their fragments.
+///
+/// The fragments of different messages can be interleaved, but the fragments of each message
+/// must arrive in order.
+#[derive(Debug, Default)]
+pub struct Defragmenter {
+    partial_by_sequence_id: HashMap<u64, Partial>,
+}
+impl Defragmenter {
+    pub fn new() -> Self {
+        Defragmenter::default()
+    }
+
+    /// Decodes a packet with a distribution header, fragmented or not, or a continuation of a
+    /// fragmented one.
+    ///
+    /// Returns the message when the packet completes it.
+    pub fn push<R: Read>(
+        &mut self,
+        mut reader: R,
+        atom_cache: &mut AtomCache,
+    ) -> Result<Option<Message>, DecodeError> {
+        read_version(&mut reader)?;
+        let tag = reader.read_u8()?;
+        match tag {
+            codec::DISTRIBUTION_HEADER => {
+                let mut decoder = Decoder::new(reader);
+                let refs = decoder.decode_atom_cache_refs(atom_cache)?;
+                decoder.decode_distribution_message(refs).map(Some)
+            }
+            codec::DISTRIBUTION_FRAGMENT_HEADER => {
+                let sequence_id = reader.read_u64::<BigEndian>()?;
+                let fragment_id = reader.read_u64::<BigEndian>()?;
+                if fragment_id == 0 || self.partial_by_sequence_id.contains_key(&sequence_id) {
+                    return Err(DecodeError::UnexpectedFragment {
+                        sequence_id,
+                        fragment_id,
+                    });
+                }
+
+                let atom_cache_refs =
+                    Decoder::new(&mut reader).decode_atom_cache_refs(atom_cache)?;
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes)?;
+                let partial = Partial {
+                    atom_cache_refs,
+                    next_fragment_id: fragment_id - 1,
+                    bytes,
+                };
+
+                if partial.next_fragment_id == 0 {
+                    partial.decode().map(Some)
+                } else {
+                    self.partial_by_sequence_id.insert(sequence_id, partial);
+                    Ok(None)
+                }
+            }
+            codec::DISTRIBUTION_FRAGMENT_CONTINUATION => {
+                let sequence_id = reader.read_u64::<BigEndian>()?;
+                let fragment_id = reader.read_u64::<BigEndian>()?;
+                let is_next = self
+                    .partial_by_sequence_id
+                    .get(&sequence_id)
+                    .map(|partial| partial.next_fragment_id == fragment_id)
+                    .unwrap_or(false);
+                if !is_next {
+                    return Err(DecodeError::UnexpectedFragment {
+                        sequence_id,
+                        fragment_id,
+                    });
+                }
+
+                let mut partial = self.partial_by_sequence_id.remove(&sequence_id).unwrap();
+                reader.read_to_end(&mut partial.bytes)?;
+                partial.next_fragment_id -= 1;
+
+                if partial.next_fragment_id == 0 {
+                    partial.decode().map(Some)
+                } else {
+                    self.partial_by_sequence_id.insert(sequence_id, partial);
+                    Ok(None)
+                }
+            }
+            _ => Err(DecodeError::UnknownTag { tag }),
+        }
+    }
+}
+
+/// The fragments of a message received so far.
+#[derive(Debug)]
+struct Partial {
+    atom_cache_refs: Vec<Atom>,
+    next_fragment_id: u64,
+    bytes: Vec<u8>,
+}
+impl Partial {
+    fn decode(self) -> Result<Message, DecodeError> {
+        Decoder::new(&self.bytes[..]).decode_distribution_message(self.atom_cache_refs)
+    }
+}
+
+fn read_version<R: Read>(reader: &mut R) -> Result<(), DecodeError> {
+    let version = reader.read_u8()?;
+    if version != codec::VERSION {
+        return Err(DecodeError::UnsupportedVersion { version });
+    }
+    Ok(())
+}
+
+/// Adds the atoms in `term` that are not in `atoms` yet, in the order they are encoded.
+fn collect_atoms<'a>(term: &'a Term, atoms: &mut Vec<&'a Atom>) {
+    fn add<'a>(atom: &'a Atom, atoms: &mut Vec<&'a Atom>) {
+        if !atoms.contains(&atom) {
+            atoms.push(atom);
+        }
+    }
+
+    match *term {
+        Term::Atom(ref x) => add(x, atoms),
+        Term::Pid(ref x) => add(&x.node, atoms),
+        Term::Port(ref x) => add(&x.node, atoms),
+        Term::Reference(ref x) => add(&x.node, atoms),
+        Term::ExternalFun(ref x) => {
+            add(&x.module, atoms);
+            add(&x.function, atoms);
+        }
+        Term::InternalFun(InternalFun::Old {
+            ref module,
+            ref pid,
+            ref free_vars,
+            ..
+        }) => {
+            add(&pid.node, atoms);
+            add(module, atoms);
+            for v in free_vars {
+                collect_atoms(v, atoms);
+            }
+        }
+        Term::InternalFun(InternalFun::New {
+            ref module,
+            ref pid,
+            ref free_vars,
+            ..
+        }) => {
+            add(module, atoms);
+            add(&pid.node, atoms);
+            for v in free_vars {
+                collect_atoms(v, atoms);
+            }
+        }
+        Term::List(ref x) => {
+            for e in &x.elements {
+                collect_atoms(e, atoms);
+            }
+        }
+        Term::ImproperList(ref x) => {
+            for e in &x.elements {
+                collect_atoms(e, atoms);
+            }
+            collect_atoms(&x.last, atoms);
+        }
+        Term::Tuple(ref x) => {
+            for e in &x.elements {
+                collect_atoms(e, atoms);
+            }
+        }
+        Term::Map(ref x) => {
+            for &(ref k, ref v) in &x.entries {
+                collect_atoms(k, atoms);
+                collect_atoms(v, atoms);
+            }
+        }
+        Term::FixInteger(_)
+        | Term::BigInteger(_)
+        | Term::Float(_)
+        | Term::Binary(_)
+        | Term::BitBinary(_) => (),
+    }
+}
+
+/// The index of the cache entry for `atom`, from the `hashpjw` hash of its name.
+fn cache_index(atom: &Atom) -> usize {
+    let mut hash: u32 = 0;
+    for &byte in atom.name.as_bytes() {
+        hash = (hash << 4).wrapping_add(byte as u32);
+        let high = hash & 0xF000_0000;
+        if high != 0 {
+            hash ^= high >> 24;
+            hash ^= high;
+        }
+    }
+    hash as usize % ATOM_CACHE_SIZE
+}
@@ -0,0 +1,302 @@
+use std::io::Cursor;
+
+use proptest::option;
+use proptest::prelude::*;
+
+use crate::serialization::etf::distribution::*;
+use crate::serialization::etf::test::strategy;
+use crate::serialization::etf::*;
+
+#[test]
+fn distribution_header_test() {
+    // These vectors follow the encoding an OTP 22+ node uses with `DFLAG_DIST_HDR_ATOM_CACHE`
+    // (every atom goes through the cache, at `hashpjw(Name) rem 2048`, and pids are
+    // `NEW_PID_EXT` with a 32 bit creation). They were built by hand from the external term
+    // format documentation, not captured from a running node.
+    let mut atom_cache = AtomCache::new();
+    let from = Pid::new("a@localhost", 38, 0, 1_584_229_420);
+
+    // REG_SEND from <'a@localhost'.38.0> to `net_kernel`
+    let message = Message::new(
+        reg_send(from, "net_kernel"),
+        Some(Term::from(Atom::from("hello"))),
+    );
+    let bytes = [
+        131, 68, 4, 141, 184, 0, // 4 new entries in segments 5, 0, 0 and 3, short atoms
+        244, 11, 97, 64, 108, 111, 99, 97, 108, 104, 111, 115, 116, // 'a@localhost'
+        0, 0, // ''
+        28, 10, 110, 101, 116, 95, 107, 101, 114, 110, 101, 108, // 'net_kernel'
+        47, 5, 104, 101, 108, 108, 111, // 'hello'
+        104, 4, 97, 6, 88, 82, 0, 0, 0, 0, 38, 0, 0, 0, 0, 94, 109, 108, 44, 82, 1, 82,
+        2, // control
+        82, 3, // payload
+    ];
+    assert_eq!(
+        message,
+        Message::decode(Cursor::new(&bytes[..]), &mut atom_cache).unwrap()
+    );
+    assert_eq!(Some(&Atom::from("a@localhost")), atom_cache.get(1524));
+    assert_eq!(Some(&Atom::from("")), atom_cache.get(0));
+    assert_eq!(Some(&Atom::from("net_kernel")), atom_cache.get(28));
+    assert_eq!(Some(&Atom::from("hello")), atom_cache.get(815));
+
+    let mut encoded = Vec::new();
+    message.encode(&mut encoded, &mut AtomCache::new()).unwrap();
+    assert_eq!(&bytes[..], &encoded[..]);
+
+    // The same atoms, referring to the cache entries
+    assert_eq!(
+        Message::new(
+            reg_send(Pid::new("a@localhost", 39, 0, 1_584_229_420), "net_kernel"),
+            Some(Term::from(FixInteger::from(42)))
+        ),
+        Message::decode(
+            Cursor::new(&[
+                131, 68, 3, 5, 0, // 3 cached entries in segments 5, 0 and 0
+                244, 0, 28, // refs
+                104, 4, 97, 6, 88, 82, 0, 0, 0, 0, 39, 0, 0, 0, 0, 94, 109, 108, 44, 82, 1, 82,
+                2, // control
+                97, 42 // payload
+            ]),
+            &mut atom_cache
+        )
+        .unwrap()
+    );
+
+    // Long atoms, which a node only flags once an atom is longer than 255 bytes
+    let long = "é".repeat(150);
+    let mut bytes = vec![131, 68, 1, 30, 57, 1, 44];
+    bytes.extend(long.as_bytes());
+    bytes.extend(&[82, 0]);
+    assert_eq!(
+        Message::new(Term::from(Atom::from(long.as_str())), None),
+        Message::decode(Cursor::new(&bytes), &mut AtomCache::new()).unwrap()
+    );
+
+    // Nodes without UTF-8 atom support send Latin-1 names
+    assert_eq!(
+        Message::new(Term::from(Atom::from("é")), None),
+        Message::decode(
+            Cursor::new(&[131, 68, 1, 8, 7, 1, 233, 82, 0]),
+            &mut AtomCache::new()
+        )
+        .unwrap()
+    );
+
+    // A distribution header is not a term
+    match Term::decode(Cursor::new(&[131, 68, 0, 106])) {
+        Err(DecodeError::DistributionHeader) => (),
+        other => panic!("{:?} is not a distribution header error", other),
+    }
+}
+
+#[test]
+fn atom_cache_ref_test() {
+    // Cache entry that was never made
+    match Message::decode(
+        Cursor::new(&[131, 68, 1, 0, 7, 82, 0]),
+        &mut AtomCache::new(),
+    ) {
+        Err(DecodeError::UnknownAtomCacheIndex { index: 7 }) => (),
+        other => panic!("{:?} is not an unknown atom cache index error", other),
+    }
+
+    // Ref that is not in the header
+    match Message::decode(Cursor::new(&[131, 68, 0, 82, 0]), &mut AtomCache::new()) {
+        Err(DecodeError::UnknownAtomCacheRef { index: 0 }) => (),
+        other => panic!("{:?} is not an unknown atom cache ref error", other),
+    }
+}
+
+#[test]
+fn fragment_test() {
+    let mut atom_cache = AtomCache::new();
+    let mut defragmenter = Defragmenter::new();
+
+    // `{foo, 1}` in 2 fragments, with the atom cache refs in the first
+    assert_eq!(
+        None,
+        defragmenter
+            .push(
+                Cursor::new(&[
+                    131, 69, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 1, 8, 3, 3, 102, 111,
+                    111, 104, 2, 82
+                ]),
+                &mut atom_cache
+            )
+            .unwrap()
+    );
+    assert_eq!(
+        Some(Message::new(
+            Term::from(Tuple::from(vec![
+                Term::from(Atom::from("foo")),
+                Term::from(FixInteger::from(1))
+            ])),
+            None
+        )),
+        defragmenter
+            .push(
+                Cursor::new(&[131, 70, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 97, 1]),
+                &mut atom_cache
+            )
+            .unwrap()
+    );
+
+    // The sequence is complete, so there are no more fragments
+    match defragmenter.push(
+        Cursor::new(&[
+            131, 70, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 97, 1,
+        ]),
+        &mut atom_cache,
+    ) {
+        Err(DecodeError::UnexpectedFragment {
+            sequence_id: 1,
+            fragment_id: 1,
+        }) => (),
+        other => panic!("{:?} is not an unexpected fragment error", other),
+    }
+}
+
+#[test]
+fn encode_test() {
+    let message = Message::new(
+        reg_send(Pid::new("a@localhost", 38, 0, 1), "net_kernel"),
+        Some(Term::from(List::from(vec![
+            Term::from(Atom::from("hello")),
+            Term::from(Atom::from("é".repeat(150))),
+            Term::from(Reference::from(("a@localhost", vec![1, 2, 3]))),
+        ]))),
+    );
+    let mut sender_atom_cache = AtomCache::new();
+    let mut receiver_atom_cache = AtomCache::new();
+
+    let mut first = Vec::new();
+    message.encode(&mut first, &mut sender_atom_cache).unwrap();
+    assert_eq!(
+        message,
+        Message::decode(Cursor::new(&first), &mut receiver_atom_cache).unwrap()
+    );
+
+    // The second time, the atoms are cached
+    let mut second = Vec::new();
+    message.encode(&mut second, &mut sender_atom_cache).unwrap();
+    assert!(second.len() < first.len());
+    assert_eq!(
+        message,
+        Message::decode(Cursor::new(&second), &mut receiver_atom_cache).unwrap()
+    );
+}
+
+#[test]
+fn encode_fragments_test() {
+    let message = Message::new(
+        reg_send(Pid::new("a@localhost", 38, 0, 1), "net_kernel"),
+        Some(Term::from(Binary::from(vec![7; 100]))),
+    );
+    let other = Message::new(Term::from(Atom::from("other")), None);
+    let mut sender_atom_cache = AtomCache::new();
+    let mut receiver_atom_cache = AtomCache::new();
+    let mut defragmenter = Defragmenter::new();
+
+    let fragments = message
+        .encode_fragments(1, 16, &mut sender_atom_cache)
+        .unwrap();
+    assert!(fragments.len() > 2);
+
+    // Another message can arrive between the fragments
+    let other_fragments = other
+        .encode_fragments(2, 16, &mut sender_atom_cache)
+        .unwrap();
+    assert_eq!(1, other_fragments.len());
+
+    let (last, rest) = fragments.split_last().unwrap();
+    for (i, fragment) in rest.iter().enumerate() {
+        assert_eq!(
+            None,
+            defragmenter
+                .push(Cursor::new(fragment), &mut receiver_atom_cache)
+                .unwrap()
+        );
+
+        if i == 0 {
+            assert_eq!(
+                Some(other.clone()),
+                defragmenter
+                    .push(Cursor::new(&other_fragments[0]), &mut receiver_atom_cache)
+                    .unwrap()
+            );
+        }
+    }
+    assert_eq!(
+        Some(message),
+        defragmenter
+            .push(Cursor::new(last), &mut receiver_atom_cache)
+            .unwrap()
+    );
+}
+
+#[test]
+fn encode_error_test() {
+    // The reference is too large for NEWER_REFERENCE_EXT, after `hello` got a cache ref
+    let invalid = Message::new(
+        Term::from(Tuple::from(vec![
+            Term::from(Atom::from("hello")),
+            Term::from(Reference::from(("a@localhost", vec![0; 0x1_0000]))),
+        ])),
+        None,
+    );
+    let valid = Message::new(Term::from(Atom::from("hello")), None);
+    let mut sender_atom_cache = AtomCache::new();
+
+    let mut buf = Vec::new();
+    match invalid.encode(&mut buf, &mut sender_atom_cache) {
+        Err(EncodeError::TooLargeReferenceId(_)) => (),
+        other => panic!("{:?} is not a too large reference error", other),
+    }
+    assert!(buf.is_empty());
+    assert!(invalid
+        .encode_fragments(1, 16, &mut sender_atom_cache)
+        .is_err());
+    assert_eq!(None, sender_atom_cache.get(815));
+
+    // So the next message still sends the name of `hello`
+    valid.encode(&mut buf, &mut sender_atom_cache).unwrap();
+    assert_eq!(
+        valid,
+        Message::decode(Cursor::new(&buf), &mut AtomCache::new()).unwrap()
+    );
+    assert_eq!(Some(&Atom::from("hello")), sender_atom_cache.get(815));
+}
+
+proptest! {
+    #[test]
+    fn decode_of_encode_is_message(
+        control in strategy::term(),
+        payload in option::of(strategy::term())
+    ) {
+        let message = Message::new(control, payload);
+        let mut sender_atom_cache = AtomCache::new();
+        let mut receiver_atom_cache = AtomCache::new();
+
+        // Once with new cache entries and once with the cached atoms
+        for _ in 0..2 {
+            let mut buf = Vec::new();
+            message.encode(&mut buf, &mut sender_atom_cache).unwrap();
+
+            prop_assert_eq!(
+                &message,
+                &Message::decode(Cursor::new(&buf), &mut receiver_atom_cache).unwrap()
+            );
+        }
+    }
+}
+
+// `{6, From, '', To}`
+fn reg_send(from: Pid, to: &str) -> Term {
+    Term::from(Tuple::from(vec![
+        Term::from(FixInteger::from(6)),
+        Term::from(from),
+        Term::from(Atom::from("")),
+        Term::from(Atom::from(to)),
+    ]))
+}
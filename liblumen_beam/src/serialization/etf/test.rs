@@ -1,5 +1,9 @@
+pub mod strategy;
+
 use std::io::Cursor;
 
+use proptest::prelude::*;
+
 use crate::serialization::etf::convert::TryInto;
 use crate::serialization::etf::*;
 
@@ -154,6 +158,23 @@ fn pid_test() {
         ],
         encode(Term::from(Pid::from(("nonode@nohost", 49, 0))))
     );
+
+    // 32-bit creation
+    assert_eq!(
+        Ok(Pid::new("nonode@nohost", 49, 0, 1_600_000_000)),
+        decode(&[
+            131, 88, 100, 0, 13, 110, 111, 110, 111, 100, 101, 64, 110, 111, 104, 111, 115, 116, 0,
+            0, 0, 49, 0, 0, 0, 0, 95, 94, 16, 0
+        ])
+        .try_into()
+    ); // NEW_PID_EXT
+    assert_eq!(
+        vec![
+            131, 88, 100, 0, 13, 110, 111, 110, 111, 100, 101, 64, 110, 111, 104, 111, 115, 116, 0,
+            0, 0, 49, 0, 0, 0, 0, 95, 94, 16, 0
+        ],
+        encode(Term::from(Pid::new("nonode@nohost", 49, 0, 1_600_000_000)))
+    );
 }
 
 #[test]
@@ -182,6 +203,50 @@ fn port_test() {
         ],
         encode(Term::from(Port::from(("nonode@nohost", 366))))
     );
+
+    // 32-bit creation
+    let port = Port {
+        node: Atom::from("nonode@nohost"),
+        id: 366,
+        creation: 1_600_000_000,
+    };
+    assert_eq!(
+        Ok(port.clone()),
+        decode(&[
+            131, 89, 100, 0, 13, 110, 111, 110, 111, 100, 101, 64, 110, 111, 104, 111, 115, 116, 0,
+            0, 1, 110, 95, 94, 16, 0
+        ])
+        .try_into()
+    ); // NEW_PORT_EXT
+    assert_eq!(
+        vec![
+            131, 89, 100, 0, 13, 110, 111, 110, 111, 100, 101, 64, 110, 111, 104, 111, 115, 116, 0,
+            0, 1, 110, 95, 94, 16, 0
+        ],
+        encode(Term::from(port))
+    );
+
+    // 64-bit id
+    let port = Port {
+        node: Atom::from("nonode@nohost"),
+        id: 1 << 32,
+        creation: 1_600_000_000,
+    };
+    assert_eq!(
+        Ok(port.clone()),
+        decode(&[
+            131, 120, 100, 0, 13, 110, 111, 110, 111, 100, 101, 64, 110, 111, 104, 111, 115, 116,
+            0, 0, 0, 1, 0, 0, 0, 0, 95, 94, 16, 0
+        ])
+        .try_into()
+    ); // V4_PORT_EXT
+    assert_eq!(
+        vec![
+            131, 120, 100, 0, 13, 110, 111, 110, 111, 100, 101, 64, 110, 111, 104, 111, 115, 116,
+            0, 0, 0, 1, 0, 0, 0, 0, 95, 94, 16, 0
+        ],
+        encode(Term::from(port))
+    );
 }
 
 #[test]
@@ -212,6 +277,28 @@ fn reference_test() {
         vec![131, 114, 0, 1, 100, 0, 3, 102, 111, 111, 0, 0, 0, 0, 123],
         encode(Term::from(Reference::from(("foo", 123))))
     );
+
+    // 32-bit creation
+    let reference = Reference {
+        node: Atom::from("nonode@nohost"),
+        id: vec![138016, 262145, 0],
+        creation: 1_600_000_000,
+    };
+    assert_eq!(
+        Ok(reference.clone()),
+        decode(&[
+            131, 90, 0, 3, 100, 0, 13, 110, 111, 110, 111, 100, 101, 64, 110, 111, 104, 111, 115,
+            116, 95, 94, 16, 0, 0, 2, 27, 32, 0, 4, 0, 1, 0, 0, 0, 0
+        ])
+        .try_into()
+    ); // NEWER_REFERENCE_EXT
+    assert_eq!(
+        vec![
+            131, 90, 0, 3, 100, 0, 13, 110, 111, 110, 111, 100, 101, 64, 110, 111, 104, 111, 115,
+            116, 95, 94, 16, 0, 0, 2, 27, 32, 0, 4, 0, 1, 0, 0, 0, 0
+        ],
+        encode(Term::from(reference))
+    );
 }
 
 #[test]
@@ -471,6 +558,22 @@ fn compressed_term_test() {
     );
 }
 
+#[test]
+fn local_test() {
+    // Decode
+    match Term::decode(Cursor::new(&[131, 121, 0, 0, 0, 0, 97, 1])) {
+        Err(DecodeError::LocalTerm) => (),
+        other => panic!("{:?} is not a local term error", other),
+    }
+}
+
+proptest! {
+    #[test]
+    fn decode_of_encode_is_term(term in strategy::term()) {
+        prop_assert_eq!(decode(&encode(term.clone())), term);
+    }
+}
+
 fn encode(term: Term) -> Vec<u8> {
     let mut buf = Vec::new();
     term.encode(&mut buf).unwrap();
@@ -0,0 +1,160 @@
+use num::bigint::{BigInt, Sign};
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::serialization::etf::*;
+
+/// Any `Term` that decodes to itself after encoding
+pub fn term() -> BoxedStrategy<Term> {
+    leaf()
+        .prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                vec(inner.clone(), 0..8).prop_map(|elements| Term::from(List::from(elements))),
+                // The last term of an improper list is not a list, or it would decode as part of
+                // the list
+                (vec(inner.clone(), 1..8), leaf())
+                    .prop_map(|(elements, last)| Term::from(ImproperList::from((elements, last)))),
+                vec(inner.clone(), 0..8).prop_map(|elements| Term::from(Tuple::from(elements))),
+                vec((inner.clone(), inner.clone()), 0..8)
+                    .prop_map(|entries| Term::from(Map::from(entries))),
+                internal_fun(inner),
+            ]
+        })
+        .boxed()
+}
+
+/// The `Term`s that have no other terms in them
+fn leaf() -> BoxedStrategy<Term> {
+    prop_oneof![
+        atom().prop_map(Term::from),
+        any::<i32>().prop_map(|value| Term::from(FixInteger::from(value))),
+        big_integer().prop_map(Term::from),
+        float().prop_map(Term::from),
+        pid().prop_map(Term::from),
+        port().prop_map(Term::from),
+        reference().prop_map(Term::from),
+        external_fun().prop_map(Term::from),
+        vec(any::<u8>(), 0..32).prop_map(|bytes| Term::from(Binary::from(bytes))),
+        bit_binary().prop_map(Term::from),
+    ]
+    .boxed()
+}
+
+fn atom() -> BoxedStrategy<Atom> {
+    "\\PC{0,32}".prop_map(Atom::from).boxed()
+}
+
+fn big_integer() -> BoxedStrategy<BigInteger> {
+    (any::<bool>(), vec(any::<u8>(), 0..32))
+        .prop_map(|(is_negative, bytes)| {
+            let sign = if is_negative { Sign::Minus } else { Sign::Plus };
+
+            BigInteger {
+                value: BigInt::from_bytes_le(sign, &bytes),
+            }
+        })
+        .boxed()
+}
+
+fn bit_binary() -> BoxedStrategy<BitBinary> {
+    (vec(any::<u8>(), 1..32), 1u8..9)
+        .prop_map(|(mut bytes, tail_bits_size)| {
+            // Only the `tail_bits_size` low bits of the last byte are in the bit string
+            let last = bytes.len() - 1;
+            bytes[last] &= ((1u16 << tail_bits_size) - 1) as u8;
+
+            BitBinary::from((bytes, tail_bits_size))
+        })
+        .boxed()
+}
+
+/// Creations that fit in the old tags and ones that need the new tags
+fn creation() -> BoxedStrategy<u32> {
+    prop_oneof![any::<u8>().prop_map(u32::from), any::<u32>()].boxed()
+}
+
+fn external_fun() -> BoxedStrategy<ExternalFun> {
+    (atom(), atom(), any::<u8>())
+        .prop_map(|(module, function, arity)| ExternalFun {
+            module,
+            function,
+            arity,
+        })
+        .boxed()
+}
+
+fn float() -> BoxedStrategy<Float> {
+    any::<f64>()
+        .prop_filter("NaN is not equal to itself", |value| !value.is_nan())
+        .prop_map(Float::from)
+        .boxed()
+}
+
+fn internal_fun(free_var: BoxedStrategy<Term>) -> BoxedStrategy<Term> {
+    prop_oneof![
+        (
+            atom(),
+            pid(),
+            vec(free_var.clone(), 0..4),
+            any::<i32>(),
+            any::<i32>()
+        )
+            .prop_map(|(module, pid, free_vars, index, uniq)| Term::from(
+                InternalFun::Old {
+                    module,
+                    pid,
+                    free_vars,
+                    index,
+                    uniq,
+                }
+            )),
+        (
+            atom(),
+            any::<u8>(),
+            pid(),
+            vec(free_var, 0..4),
+            any::<u32>(),
+            any::<[u8; 16]>(),
+            any::<i32>(),
+            any::<i32>()
+        )
+            .prop_map(
+                |(module, arity, pid, free_vars, index, uniq, old_index, old_uniq)| Term::from(
+                    InternalFun::New {
+                        module,
+                        arity,
+                        pid,
+                        free_vars,
+                        index,
+                        uniq,
+                        old_index,
+                        old_uniq,
+                    }
+                )
+            ),
+    ]
+    .boxed()
+}
+
+fn pid() -> BoxedStrategy<Pid> {
+    (atom(), any::<u32>(), any::<u32>(), creation())
+        .prop_map(|(node, id, serial, creation)| Pid::new(node, id, serial, creation))
+        .boxed()
+}
+
+fn port() -> BoxedStrategy<Port> {
+    (
+        atom(),
+        prop_oneof![any::<u32>().prop_map(u64::from), any::<u64>()],
+        creation(),
+    )
+        .prop_map(|(node, id, creation)| Port { node, id, creation })
+        .boxed()
+}
+
+fn reference() -> BoxedStrategy<Reference> {
+    (atom(), vec(any::<u32>(), 1..5), creation())
+        .prop_map(|(node, id, creation)| Reference { node, id, creation })
+        .boxed()
+}
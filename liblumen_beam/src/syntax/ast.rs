@@ -16,6 +16,7 @@
 pub mod ast;
 pub mod error;
 pub mod format;
+pub mod printer;
 
 #[cfg(test)]
 mod test;
@@ -40,22 +40,75 @@ pub struct AbstractCode {
     pub code: etf::Term,
 }
 impl AbstractCode {
+    /// Loads the abstract code from either the `Abst` chunk, or for modules compiled by OTP 20+,
+    /// the `erl_abstract_code` backend of the `Dbgi` chunk.
     pub fn from_beam_file<P: AsRef<Path>>(path: P) -> FromBeamResult<Self> {
         let beam = crate::beam::reader::RawBeamFile::from_file(path)?;
-        let chunk = beam
-            .chunks()
-            .into_iter()
-            .find(|c| c.id() == b"Abst")
+        let chunks = beam.chunks();
+        if let Some(chunk) = chunks
+            .iter()
+            .find(|c| c.id() == b"Abst" && !c.data.is_empty())
+        {
+            return Self::from_abst(&chunk.data);
+        }
+        let chunk = chunks
+            .iter()
+            .find(|c| c.id() == b"Dbgi")
             .ok_or(FromBeamError::NoDebugInfo)?;
-        let code = etf::Term::decode(std::io::Cursor::new(&chunk.data))?;
+        Self::from_dbgi(&chunk.data)
+    }
+
+    /// Loads the abstract code from the data of an `Abst` chunk.
+    pub fn from_abst(data: &[u8]) -> FromBeamResult<Self> {
+        let code = etf::Term::decode(std::io::Cursor::new(data))?;
+        Ok(AbstractCode { code })
+    }
+
+    /// Loads the abstract code from the data of a `Dbgi` chunk, which only has abstract code if
+    /// it uses the `erl_abstract_code` backend.
+    pub fn from_dbgi(data: &[u8]) -> FromBeamResult<Self> {
+        let debug_info = etf::Term::decode(std::io::Cursor::new(data))?;
+        let (_, _, (forms, _)) = debug_info
+            .as_match(("debug_info_v1", "erl_abstract_code", (any(), any())))
+            .map_err(|_| FromBeamError::NoDebugInfo)?;
+        let code = etf::Term::from(etf::Tuple::from(vec![
+            etf::Term::from(etf::Atom::from("raw_abstract_v1")),
+            forms.clone(),
+        ]));
         Ok(AbstractCode { code })
     }
     pub fn to_forms(&self) -> FromBeamResult<Vec<form::Form>> {
         let (_, forms) = self
             .code
             .as_match(("raw_abstract_v1", VarList(to!(form::Form))))?;
-        Ok(forms)
+        Ok(merge_record_types(forms))
+    }
+}
+
+/// Modules compiled before OTP 19 declare the types of record fields in a separate
+/// `-type({{record, Name}, Fields, []})` attribute after the untyped `-record`, which is
+/// merged back into the record declaration as later releases write it
+fn merge_record_types(forms: Vec<form::Form>) -> Vec<form::Form> {
+    let mut merged: Vec<form::Form> = Vec::with_capacity(forms.len());
+    for form in forms {
+        if let form::Form::Attr(ref attr) = form {
+            let pattern = (("record", atom()), VarList(to!(form::RecordFieldDecl)), Nil);
+            if let ("type", Ok(((_, name), fields, _))) =
+                (attr.name.as_str(), attr.value.as_match(pattern))
+            {
+                let record = merged.iter_mut().rev().find_map(|form| match *form {
+                    form::Form::Record(ref mut x) if x.name == name => Some(x),
+                    _ => None,
+                });
+                if let Some(record) = record {
+                    record.fields = fields;
+                    continue;
+                }
+            }
+        }
+        merged.push(form);
     }
+    merged
 }
 
 trait FromTerm<'a> {
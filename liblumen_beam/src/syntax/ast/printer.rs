@@ -0,0 +1,1092 @@
+//! Prints Abstract Syntax Trees back out as Erlang source code.
+//!
+//! The output is not intended to reproduce the original formatting of the module, only to be
+//! valid Erlang which parses back into an equivalent syntax tree.  Operator precedence is
+//! never relied upon: nested operator expressions are always parenthesized.
+//!
+//! `ModuleDecl::to_source_with_original_lines` additionally pads the output with blank lines
+//! so that forms, clauses and the expressions of bodies start on their original lines, so
+//! that line numbers reported for the printed source refer to the original source.  It fails
+//! if the module has forms which cannot be printed as source, where `Display` writes them as
+//! comments.
+//!
+//! # Examples
+//!
+//!     use liblumen_beam::syntax::ast::AST;
+//!
+//!     let ast = AST::from_beam_file("tests/testdata/ast/test.beam").unwrap();
+//!     let source = ast.module.to_string();
+//!     assert!(source.starts_with("-file("));
+//!
+use std::fmt::{self, Display, Write};
+
+use super::ast::clause::Clause;
+use super::ast::common;
+use super::ast::expr::{self, Expression, Qualifier};
+use super::ast::form::{self, Form};
+use super::ast::guard::{Guard, OrGuard};
+use super::ast::literal;
+use super::ast::pat::Pattern;
+use super::ast::ty::{self, Type};
+use super::ast::{LineNum, ModuleDecl, Node};
+
+const INDENT: &str = "    ";
+
+/// Reserved words which must be quoted when used as atoms
+const RESERVED_WORDS: &[&str] = &[
+    "after", "and", "andalso", "band", "begin", "bnot", "bor", "bsl", "bsr", "bxor", "case",
+    "catch", "cond", "div", "end", "fun", "if", "let", "not", "of", "or", "orelse", "receive",
+    "rem", "try", "when", "xor",
+];
+
+/// A form which has no representation in Erlang source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnrepresentableForm {
+    pub line: LineNum,
+    pub name: String,
+}
+impl Display for UnrepresentableForm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the -{} attribute on line {} could not be decoded, so it cannot be printed as source",
+            self.name, self.line
+        )
+    }
+}
+
+impl ModuleDecl {
+    /// Prints the module as Erlang source in which forms, clauses and the expressions of
+    /// bodies start on the line they had in the original source, where that line has not
+    /// already been passed, e.g. by the forms of an included file
+    ///
+    /// Returns the forms which cannot be printed, if there are any
+    pub fn to_source_with_original_lines(&self) -> Result<String, Vec<UnrepresentableForm>> {
+        let mut source = String::new();
+        let mut printer = Printer::new(&mut source);
+        printer.preserve_lines = true;
+        for form in self.forms.iter() {
+            // Writing to a `String` never fails
+            printer.form(form).unwrap();
+        }
+        if printer.unrepresentable.is_empty() {
+            Ok(source)
+        } else {
+            Err(printer.unrepresentable)
+        }
+    }
+}
+impl Display for ModuleDecl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut printer = Printer::new(f);
+        for form in self.forms.iter() {
+            printer.form(form)?;
+        }
+        Ok(())
+    }
+}
+impl Display for Form {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer::new(f).form(self)
+    }
+}
+impl Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer::new(f).expr(self)
+    }
+}
+impl Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer::new(f).pat(self)
+    }
+}
+impl Display for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer::new(f).guard(self)
+    }
+}
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer::new(f).ty(self)
+    }
+}
+
+/// Writes an atom, quoting it if necessary
+pub fn write_atom<W: Write>(out: &mut W, name: &str) -> fmt::Result {
+    let mut chars = name.chars();
+    let is_bare = match chars.next() {
+        Some(c) if c.is_ascii_lowercase() => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@')
+        }
+        _ => false,
+    };
+    if is_bare && !RESERVED_WORDS.contains(&name) {
+        return out.write_str(name);
+    }
+    out.write_char('\'')?;
+    for c in name.chars() {
+        write_escaped_char(out, c, '\'')?;
+    }
+    out.write_char('\'')
+}
+
+/// Writes a double-quoted string literal
+pub fn write_string<W: Write>(out: &mut W, value: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in value.chars() {
+        write_escaped_char(out, c, '"')?;
+    }
+    out.write_char('"')
+}
+
+fn write_escaped_char<W: Write>(out: &mut W, c: char, quote: char) -> fmt::Result {
+    match c {
+        '\\' => out.write_str("\\\\"),
+        '\n' => out.write_str("\\n"),
+        '\r' => out.write_str("\\r"),
+        '\t' => out.write_str("\\t"),
+        '\u{8}' => out.write_str("\\b"),
+        '\u{c}' => out.write_str("\\f"),
+        '\u{b}' => out.write_str("\\v"),
+        '\u{1b}' => out.write_str("\\e"),
+        '\u{7f}' => out.write_str("\\d"),
+        c if c == quote => write!(out, "\\{}", c),
+        c if (c as u32) < 0x20 => write!(out, "\\x{{{:X}}}", c as u32),
+        c => out.write_char(c),
+    }
+}
+
+fn write_char_literal<W: Write>(out: &mut W, c: char) -> fmt::Result {
+    out.write_char('$')?;
+    match c {
+        ' ' => out.write_str("\\s"),
+        c => write_escaped_char(out, c, '\''),
+    }
+}
+
+fn write_float<W: Write>(out: &mut W, value: f64) -> fmt::Result {
+    // Erlang requires a fractional part before any exponent, e.g. `1.0e100` rather than `1e100`
+    let s = format!("{:?}", value);
+    match s.find('e') {
+        Some(i) if !s[..i].contains('.') => write!(out, "{}.0{}", &s[..i], &s[i..]),
+        _ => out.write_str(&s),
+    }
+}
+
+/// Operators which are words rather than symbols need surrounding whitespace
+fn is_word_operator(op: &str) -> bool {
+    op.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+/// Dispatches the printing of a node to the printer, allowing the generic nodes in
+/// `common` to be printed regardless of the context (expression, pattern, guard) they occur in
+trait Print {
+    fn print<W: Write>(&self, p: &mut Printer<W>) -> fmt::Result;
+
+    /// Whether this node must be parenthesized when used as an operand
+    fn is_compound(&self) -> bool;
+
+    /// Returns the cons cell this node represents, if it is one
+    fn as_cons(&self) -> Option<&common::Cons<Self>>
+    where
+        Self: Sized;
+
+    /// Whether this node is the empty list
+    fn is_nil(&self) -> bool;
+}
+impl Print for Expression {
+    fn print<W: Write>(&self, p: &mut Printer<W>) -> fmt::Result {
+        p.expr(self)
+    }
+    fn is_compound(&self) -> bool {
+        match *self {
+            Expression::Match(_)
+            | Expression::UnaryOp(_)
+            | Expression::BinaryOp(_)
+            | Expression::Catch(_) => true,
+            _ => false,
+        }
+    }
+    fn as_cons(&self) -> Option<&common::Cons<Self>> {
+        match *self {
+            Expression::Cons(ref x) => Some(x),
+            _ => None,
+        }
+    }
+    fn is_nil(&self) -> bool {
+        match *self {
+            Expression::Nil(_) => true,
+            _ => false,
+        }
+    }
+}
+impl Print for Pattern {
+    fn print<W: Write>(&self, p: &mut Printer<W>) -> fmt::Result {
+        p.pat(self)
+    }
+    fn is_compound(&self) -> bool {
+        match *self {
+            Pattern::Match(_) | Pattern::UnaryOp(_) | Pattern::BinaryOp(_) => true,
+            _ => false,
+        }
+    }
+    fn as_cons(&self) -> Option<&common::Cons<Self>> {
+        match *self {
+            Pattern::Cons(ref x) => Some(x),
+            _ => None,
+        }
+    }
+    fn is_nil(&self) -> bool {
+        match *self {
+            Pattern::Nil(_) => true,
+            _ => false,
+        }
+    }
+}
+impl Print for Guard {
+    fn print<W: Write>(&self, p: &mut Printer<W>) -> fmt::Result {
+        p.guard(self)
+    }
+    fn is_compound(&self) -> bool {
+        match *self {
+            Guard::UnaryOp(_) | Guard::BinaryOp(_) => true,
+            _ => false,
+        }
+    }
+    fn as_cons(&self) -> Option<&common::Cons<Self>> {
+        match *self {
+            Guard::Cons(ref x) => Some(x),
+            _ => None,
+        }
+    }
+    fn is_nil(&self) -> bool {
+        match *self {
+            Guard::Nil(_) => true,
+            _ => false,
+        }
+    }
+}
+impl Print for Type {
+    fn print<W: Write>(&self, p: &mut Printer<W>) -> fmt::Result {
+        p.ty(self)
+    }
+    fn is_compound(&self) -> bool {
+        match *self {
+            Type::UnaryOp(_)
+            | Type::BinaryOp(_)
+            | Type::Annotated(_)
+            | Type::Range(_)
+            | Type::Union(_) => true,
+            _ => false,
+        }
+    }
+    fn as_cons(&self) -> Option<&common::Cons<Self>> {
+        None
+    }
+    fn is_nil(&self) -> bool {
+        match *self {
+            Type::Nil(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Indicates how the patterns of a clause should be printed
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ClauseKind {
+    /// `name(Pattern, ..) when Guard -> Body`
+    Function,
+    /// `Pattern when Guard -> Body`, as in `case` and `receive`
+    Case,
+    /// `Class:Reason:Stacktrace when Guard -> Body`
+    Catch,
+    /// `Guard -> Body`
+    If,
+}
+
+/// Counts the lines written through it
+struct Lines<W> {
+    out: W,
+    line: LineNum,
+}
+impl<W: Write> Write for Lines<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.line += s.matches('\n').count() as LineNum;
+        self.out.write_str(s)
+    }
+}
+
+struct Printer<W> {
+    out: Lines<W>,
+    indent: usize,
+    /// Whether to pad the output so that nodes start on their original lines
+    preserve_lines: bool,
+    /// The forms which were written as comments, as they cannot be printed as source
+    unrepresentable: Vec<UnrepresentableForm>,
+}
+impl<W: Write> Printer<W> {
+    fn new(out: W) -> Self {
+        Printer {
+            out: Lines { out, line: 1 },
+            indent: 0,
+            preserve_lines: false,
+            unrepresentable: Vec::new(),
+        }
+    }
+
+    fn newline(&mut self) -> fmt::Result {
+        self.out.write_char('\n')?;
+        for _ in 0..self.indent {
+            self.out.write_str(INDENT)?;
+        }
+        Ok(())
+    }
+
+    /// Starts a new line, which is the given line if lines are preserved and it has not
+    /// been passed yet. If it is the line being written, the output continues on it
+    fn newline_at(&mut self, line: LineNum) -> fmt::Result {
+        if self.preserve_lines && self.out.line == line {
+            return self.out.write_char(' ');
+        }
+        self.pad_to(line - 1)?;
+        self.newline()
+    }
+
+    /// Writes blank lines until the given line is reached, if lines are preserved
+    fn pad_to(&mut self, line: LineNum) -> fmt::Result {
+        if self.preserve_lines {
+            while self.out.line < line {
+                self.out.write_char('\n')?;
+            }
+        }
+        Ok(())
+    }
+
+    fn atom(&mut self, name: &str) -> fmt::Result {
+        write_atom(&mut self.out, name)
+    }
+
+    fn seq<T, F>(&mut self, items: &[T], sep: &str, mut f: F) -> fmt::Result
+    where
+        F: FnMut(&mut Self, &T) -> fmt::Result,
+    {
+        for (i, item) in items.iter().enumerate() {
+            if i != 0 {
+                self.out.write_str(sep)?;
+            }
+            f(self, item)?;
+        }
+        Ok(())
+    }
+
+    fn operand<T: Print>(&mut self, node: &T) -> fmt::Result {
+        if node.is_compound() {
+            self.out.write_char('(')?;
+            node.print(self)?;
+            self.out.write_char(')')
+        } else {
+            node.print(self)
+        }
+    }
+
+    fn body(&mut self, body: &[Expression]) -> fmt::Result {
+        self.indent += 1;
+        for (i, expr) in body.iter().enumerate() {
+            if i != 0 {
+                self.out.write_char(',')?;
+            }
+            self.newline_at(expr.line())?;
+            self.expr(expr)?;
+        }
+        self.indent -= 1;
+        Ok(())
+    }
+
+    //
+    // Forms
+    //
+
+    fn form(&mut self, form: &Form) -> fmt::Result {
+        self.pad_to(form.line())?;
+        match *form {
+            Form::Module(ref x) => {
+                self.out.write_str("-module(")?;
+                self.atom(&x.name)?;
+                self.out.write_str(").\n")
+            }
+            Form::Behaviour(ref x) => {
+                let attr = if x.is_british {
+                    "behaviour"
+                } else {
+                    "behavior"
+                };
+                write!(self.out, "-{}(", attr)?;
+                self.atom(&x.name)?;
+                self.out.write_str(").\n")
+            }
+            Form::Export(ref x) => {
+                self.out.write_str("-export([")?;
+                self.seq(&x.funs, ", ", |p, e| p.name_arity(&e.fun, e.arity))?;
+                self.out.write_str("]).\n")
+            }
+            Form::Import(ref x) => {
+                self.out.write_str("-import(")?;
+                self.atom(&x.module)?;
+                self.out.write_str(", [")?;
+                self.seq(&x.funs, ", ", |p, i| p.name_arity(&i.fun, i.arity))?;
+                self.out.write_str("]).\n")
+            }
+            Form::ExportType(ref x) => {
+                self.out.write_str("-export_type([")?;
+                self.seq(&x.types, ", ", |p, t| p.name_arity(&t.typ, t.arity))?;
+                self.out.write_str("]).\n")
+            }
+            Form::Compile(ref x) => writeln!(self.out, "-compile({}).", x.options),
+            Form::File(ref x) => {
+                self.out.write_str("-file(")?;
+                write_string(&mut self.out, &x.original_file)?;
+                writeln!(self.out, ", {}).", x.original_line)
+            }
+            Form::Record(ref x) => self.record_decl(x),
+            Form::Type(ref x) => self.type_decl(x),
+            Form::Spec(ref x) => self.fun_spec(x),
+            Form::Attr(ref x) if is_typed_attribute(&x.name) => {
+                // A typing attribute in a form we could not decode cannot be printed back
+                // as valid source
+                self.unrepresentable.push(UnrepresentableForm {
+                    line: x.line,
+                    name: x.name.clone(),
+                });
+                writeln!(self.out, "%% -{}({}).", x.name, x.value)
+            }
+            Form::Attr(ref x) => {
+                self.out.write_char('-')?;
+                self.atom(&x.name)?;
+                writeln!(self.out, "({}).", x.value)
+            }
+            Form::Fun(ref x) => self.fun_decl(x),
+            Form::Eof(_) => Ok(()),
+        }
+    }
+
+    fn name_arity(&mut self, name: &str, arity: u32) -> fmt::Result {
+        self.atom(name)?;
+        write!(self.out, "/{}", arity)
+    }
+
+    fn record_decl(&mut self, decl: &form::RecordDecl) -> fmt::Result {
+        self.out.write_str("-record(")?;
+        self.atom(&decl.name)?;
+        self.out.write_str(", {")?;
+        self.indent += 1;
+        for (i, field) in decl.fields.iter().enumerate() {
+            if i != 0 {
+                self.out.write_char(',')?;
+            }
+            self.newline()?;
+            self.atom(&field.name)?;
+            self.out.write_str(" = ")?;
+            self.expr(&field.default_value)?;
+            if !is_any_type(&field.ty) {
+                self.out.write_str(" :: ")?;
+                self.ty(&field.ty)?;
+            }
+        }
+        self.indent -= 1;
+        self.out.write_str("\n}).\n")
+    }
+
+    fn type_decl(&mut self, decl: &form::TypeDecl) -> fmt::Result {
+        let attr = if decl.is_opaque { "opaque" } else { "type" };
+        write!(self.out, "-{} ", attr)?;
+        self.atom(&decl.name)?;
+        self.out.write_char('(')?;
+        self.seq(&decl.vars, ", ", |p, v| p.var(v))?;
+        self.out.write_str(") :: ")?;
+        self.ty(&decl.ty)?;
+        self.out.write_str(".\n")
+    }
+
+    fn fun_spec(&mut self, spec: &form::FunSpec) -> fmt::Result {
+        let attr = if spec.is_callback { "callback" } else { "spec" };
+        write!(self.out, "-{} ", attr)?;
+        if let Some(ref module) = spec.module {
+            self.atom(module)?;
+            self.out.write_char(':')?;
+        }
+        self.atom(&spec.name)?;
+        self.indent += 1;
+        for (i, fun) in spec.types.iter().enumerate() {
+            if i != 0 {
+                self.out.write_char(';')?;
+                self.newline()?;
+            }
+            self.fun_type_signature(fun)?;
+        }
+        self.indent -= 1;
+        self.out.write_str(".\n")
+    }
+
+    fn fun_decl(&mut self, decl: &form::FunDecl) -> fmt::Result {
+        for (i, clause) in decl.clauses.iter().enumerate() {
+            if i != 0 {
+                self.out.write_str(";\n")?;
+                self.pad_to(clause.line)?;
+            }
+            self.atom(&decl.name)?;
+            self.clause(clause, ClauseKind::Function)?;
+        }
+        self.out.write_str(".\n")
+    }
+
+    //
+    // Clauses
+    //
+
+    fn clauses(&mut self, clauses: &[Clause], kind: ClauseKind) -> fmt::Result {
+        self.indent += 1;
+        for (i, clause) in clauses.iter().enumerate() {
+            if i != 0 {
+                self.out.write_char(';')?;
+            }
+            self.newline_at(clause.line)?;
+            self.clause(clause, kind)?;
+        }
+        self.indent -= 1;
+        Ok(())
+    }
+
+    fn clause(&mut self, clause: &Clause, kind: ClauseKind) -> fmt::Result {
+        match kind {
+            ClauseKind::Function => {
+                self.out.write_char('(')?;
+                self.seq(&clause.patterns, ", ", |p, x| p.pat(x))?;
+                self.out.write_char(')')?;
+            }
+            ClauseKind::Case => self.seq(&clause.patterns, ", ", |p, x| p.pat(x))?,
+            ClauseKind::Catch => self.catch_pattern(&clause.patterns)?,
+            ClauseKind::If => (),
+        }
+        if !clause.guards.is_empty() {
+            if kind != ClauseKind::If {
+                self.out.write_str(" when ")?;
+            }
+            self.guards(&clause.guards)?;
+        }
+        self.out.write_str(" ->")?;
+        self.body(&clause.body)
+    }
+
+    fn catch_pattern(&mut self, patterns: &[Pattern]) -> fmt::Result {
+        if let [Pattern::Tuple(ref tuple)] = patterns {
+            if let [ref class, ref reason, ref stack] = tuple.elements[..] {
+                self.pat(class)?;
+                self.out.write_char(':')?;
+                self.pat(reason)?;
+                if let Pattern::Var(ref v) = *stack {
+                    if v.is_anonymous() {
+                        return Ok(());
+                    }
+                }
+                self.out.write_char(':')?;
+                return self.pat(stack);
+            }
+        }
+        self.seq(patterns, ", ", |p, x| p.pat(x))
+    }
+
+    fn guards(&mut self, guards: &[OrGuard]) -> fmt::Result {
+        self.seq(guards, "; ", |p, g| {
+            p.seq(&g.and_guards, ", ", |p, x| p.guard(x))
+        })
+    }
+
+    //
+    // Nodes common to expressions, patterns and guards
+    //
+
+    fn var(&mut self, var: &common::Var) -> fmt::Result {
+        self.out.write_str(&var.name)
+    }
+
+    fn integer(&mut self, x: &literal::Integer) -> fmt::Result {
+        write!(self.out, "{}", x.value)
+    }
+
+    fn float(&mut self, x: &literal::Float) -> fmt::Result {
+        write_float(&mut self.out, x.value)
+    }
+
+    fn string(&mut self, x: &literal::Str) -> fmt::Result {
+        write_string(&mut self.out, &x.value)
+    }
+
+    fn char(&mut self, x: &literal::Char) -> fmt::Result {
+        write_char_literal(&mut self.out, x.value)
+    }
+
+    fn match_<L: Print, R: Print>(&mut self, x: &common::Match<L, R>) -> fmt::Result {
+        self.operand(&x.left)?;
+        self.out.write_str(" = ")?;
+        x.right.print(self)
+    }
+
+    fn tuple<T: Print>(&mut self, x: &common::Tuple<T>) -> fmt::Result {
+        self.out.write_char('{')?;
+        self.seq(&x.elements, ", ", |p, e| e.print(p))?;
+        self.out.write_char('}')
+    }
+
+    fn cons<T: Print>(&mut self, x: &common::Cons<T>) -> fmt::Result {
+        self.out.write_char('[')?;
+        x.head.print(self)?;
+        let mut tail = &x.tail;
+        while let Some(cons) = tail.as_cons() {
+            self.out.write_str(", ")?;
+            cons.head.print(self)?;
+            tail = &cons.tail;
+        }
+        if !tail.is_nil() {
+            self.out.write_str(" | ")?;
+            tail.print(self)?;
+        }
+        self.out.write_char(']')
+    }
+
+    fn binary<T: Print>(&mut self, x: &common::Binary<T>) -> fmt::Result {
+        self.out.write_str("<<")?;
+        self.seq(&x.elements, ", ", |p, e| p.bin_element(e))?;
+        self.out.write_str(">>")
+    }
+
+    fn bin_element<T: Print>(&mut self, x: &common::BinElement<T>) -> fmt::Result {
+        self.operand(&x.element)?;
+        if let Some(ref size) = x.size {
+            self.out.write_char(':')?;
+            self.operand(size)?;
+        }
+        if let Some(ref tsl) = x.tsl {
+            self.out.write_char('/')?;
+            self.seq(tsl, "-", |p, spec| {
+                p.out.write_str(&spec.name)?;
+                match spec.value {
+                    Some(value) => write!(p.out, ":{}", value),
+                    None => Ok(()),
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    fn unary_op<T: Print>(&mut self, x: &common::UnaryOp<T>) -> fmt::Result {
+        self.out.write_str(&x.operator)?;
+        if is_word_operator(&x.operator) {
+            self.out.write_char(' ')?;
+        }
+        self.operand(&x.operand)
+    }
+
+    fn binary_op<T: Print>(&mut self, x: &common::BinaryOp<T>) -> fmt::Result {
+        self.operand(&x.left_operand)?;
+        write!(self.out, " {} ", x.operator)?;
+        self.operand(&x.right_operand)
+    }
+
+    fn record<T: Print>(&mut self, x: &common::Record<T>) -> fmt::Result {
+        if let Some(ref base) = x.base {
+            self.operand(base)?;
+        }
+        self.out.write_char('#')?;
+        self.atom(&x.name)?;
+        self.out.write_char('{')?;
+        self.seq(&x.fields, ", ", |p, field| {
+            match field.name {
+                Some(ref name) => p.atom(name)?,
+                None => p.out.write_char('_')?,
+            }
+            p.out.write_str(" = ")?;
+            field.value.print(p)
+        })?;
+        self.out.write_char('}')
+    }
+
+    fn record_index<T: Print>(&mut self, x: &common::RecordIndex<T>) -> fmt::Result {
+        if let Some(ref base) = x.base {
+            self.operand(base)?;
+        }
+        self.out.write_char('#')?;
+        self.atom(&x.record)?;
+        self.out.write_char('.')?;
+        self.atom(&x.field)
+    }
+
+    fn map<T: Print>(&mut self, x: &common::Map<T>) -> fmt::Result {
+        if let Some(ref base) = x.base {
+            self.operand(base)?;
+        }
+        self.out.write_str("#{")?;
+        self.seq(&x.pairs, ", ", |p, pair| {
+            pair.key.print(p)?;
+            p.out
+                .write_str(if pair.is_assoc { " => " } else { " := " })?;
+            pair.value.print(p)
+        })?;
+        self.out.write_char('}')
+    }
+
+    fn local_call<T: Print>(&mut self, x: &common::LocalCall<T>) -> fmt::Result {
+        self.callee(&x.function)?;
+        self.args(&x.args)
+    }
+
+    fn remote_call<T: Print>(&mut self, x: &common::RemoteCall<T>) -> fmt::Result {
+        self.callee(&x.module)?;
+        self.out.write_char(':')?;
+        self.callee(&x.function)?;
+        self.args(&x.args)
+    }
+
+    fn callee<T: Print>(&mut self, callee: &T) -> fmt::Result {
+        // Anything other than an atom or variable must be parenthesized to be called
+        let mut buf = String::new();
+        let mut printer = Printer::new(&mut buf);
+        printer.indent = self.indent;
+        printer.preserve_lines = self.preserve_lines;
+        printer.out.line = self.out.line;
+        callee.print(&mut printer)?;
+        let is_simple = buf
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@')
+            || (buf.starts_with('\'') && buf.ends_with('\''));
+        if is_simple {
+            self.out.write_str(&buf)
+        } else {
+            write!(self.out, "({})", buf)
+        }
+    }
+
+    fn args<T: Print>(&mut self, args: &[T]) -> fmt::Result {
+        self.out.write_char('(')?;
+        self.seq(args, ", ", |p, a| a.print(p))?;
+        self.out.write_char(')')
+    }
+
+    //
+    // Expressions
+    //
+
+    fn expr(&mut self, expr: &Expression) -> fmt::Result {
+        match *expr {
+            Expression::Integer(ref x) => self.integer(x),
+            Expression::Float(ref x) => self.float(x),
+            Expression::String(ref x) => self.string(x),
+            Expression::Char(ref x) => self.char(x),
+            Expression::Atom(ref x) => self.atom(&x.value),
+            Expression::Match(ref x) => self.match_(x),
+            Expression::Var(ref x) => self.var(x),
+            Expression::Tuple(ref x) => self.tuple(x),
+            Expression::Nil(_) => self.out.write_str("[]"),
+            Expression::Cons(ref x) => self.cons(x),
+            Expression::Binary(ref x) => self.binary(x),
+            Expression::UnaryOp(ref x) => self.unary_op(x),
+            Expression::BinaryOp(ref x) => self.binary_op(x),
+            Expression::Record(ref x) => self.record(x),
+            Expression::RecordIndex(ref x) => self.record_index(x),
+            Expression::Map(ref x) => self.map(x),
+            Expression::Catch(ref x) => {
+                self.out.write_str("catch ")?;
+                self.expr(&x.expr)
+            }
+            Expression::LocalCall(ref x) => self.local_call(x),
+            Expression::RemoteCall(ref x) => self.remote_call(x),
+            Expression::Comprehension(ref x) => self.comprehension(x),
+            Expression::Block(ref x) => {
+                self.out.write_str("begin")?;
+                self.body(&x.body)?;
+                self.newline()?;
+                self.out.write_str("end")
+            }
+            Expression::If(ref x) => {
+                self.out.write_str("if")?;
+                self.clauses(&x.clauses, ClauseKind::If)?;
+                self.newline()?;
+                self.out.write_str("end")
+            }
+            Expression::Case(ref x) => {
+                self.out.write_str("case ")?;
+                self.expr(&x.expr)?;
+                self.out.write_str(" of")?;
+                self.clauses(&x.clauses, ClauseKind::Case)?;
+                self.newline()?;
+                self.out.write_str("end")
+            }
+            Expression::Try(ref x) => self.try_(x),
+            Expression::Receive(ref x) => self.receive(x),
+            Expression::InternalFun(ref x) => {
+                self.out.write_str("fun ")?;
+                self.name_arity(&x.function, x.arity)
+            }
+            Expression::ExternalFun(ref x) => {
+                self.out.write_str("fun ")?;
+                self.callee(&x.module)?;
+                self.out.write_char(':')?;
+                self.callee(&x.function)?;
+                self.out.write_char('/')?;
+                self.callee(&x.arity)
+            }
+            Expression::AnonymousFun(ref x) => self.anonymous_fun(x),
+        }
+    }
+
+    fn comprehension(&mut self, x: &expr::Comprehension) -> fmt::Result {
+        let (open, close) = if x.is_list { ("[", "]") } else { ("<<", ">>") };
+        self.out.write_str(open)?;
+        self.expr(&x.expr)?;
+        self.out.write_str(" || ")?;
+        self.seq(&x.qualifiers, ", ", |p, q| match *q {
+            Qualifier::Generator(ref g) => {
+                p.pat(&g.pattern)?;
+                p.out.write_str(" <- ")?;
+                p.expr(&g.expr)
+            }
+            Qualifier::BitStringGenerator(ref g) => {
+                p.pat(&g.pattern)?;
+                p.out.write_str(" <= ")?;
+                p.expr(&g.expr)
+            }
+            Qualifier::Filter(ref e) => p.expr(e),
+        })?;
+        self.out.write_str(close)
+    }
+
+    fn try_(&mut self, x: &expr::Try) -> fmt::Result {
+        self.out.write_str("try")?;
+        self.body(&x.body)?;
+        if !x.case_clauses.is_empty() {
+            self.newline()?;
+            self.out.write_str("of")?;
+            self.clauses(&x.case_clauses, ClauseKind::Case)?;
+        }
+        if !x.catch_clauses.is_empty() {
+            self.newline()?;
+            self.out.write_str("catch")?;
+            self.clauses(&x.catch_clauses, ClauseKind::Catch)?;
+        }
+        if !x.after.is_empty() {
+            self.newline()?;
+            self.out.write_str("after")?;
+            self.body(&x.after)?;
+        }
+        self.newline()?;
+        self.out.write_str("end")
+    }
+
+    fn receive(&mut self, x: &expr::Receive) -> fmt::Result {
+        self.out.write_str("receive")?;
+        self.clauses(&x.clauses, ClauseKind::Case)?;
+        if let Some(ref timeout) = x.timeout {
+            self.newline()?;
+            self.out.write_str("after")?;
+            self.indent += 1;
+            self.newline()?;
+            self.expr(timeout)?;
+            self.out.write_str(" ->")?;
+            self.body(&x.after)?;
+            self.indent -= 1;
+        }
+        self.newline()?;
+        self.out.write_str("end")
+    }
+
+    fn anonymous_fun(&mut self, x: &expr::AnonymousFun) -> fmt::Result {
+        self.out.write_str("fun")?;
+        self.indent += 1;
+        for (i, clause) in x.clauses.iter().enumerate() {
+            if i != 0 {
+                self.out.write_char(';')?;
+            }
+            self.newline_at(clause.line)?;
+            if let Some(ref name) = x.name {
+                self.out.write_str(name)?;
+            }
+            self.clause(clause, ClauseKind::Function)?;
+        }
+        self.indent -= 1;
+        self.newline()?;
+        self.out.write_str("end")
+    }
+
+    //
+    // Patterns
+    //
+
+    fn pat(&mut self, pat: &Pattern) -> fmt::Result {
+        match *pat {
+            Pattern::Integer(ref x) => self.integer(x),
+            Pattern::Float(ref x) => self.float(x),
+            Pattern::String(ref x) => self.string(x),
+            Pattern::Char(ref x) => self.char(x),
+            Pattern::Atom(ref x) => self.atom(&x.value),
+            Pattern::Var(ref x) => self.var(x),
+            Pattern::Match(ref x) => self.match_(x),
+            Pattern::Tuple(ref x) => self.tuple(x),
+            Pattern::Nil(_) => self.out.write_str("[]"),
+            Pattern::Cons(ref x) => self.cons(x),
+            Pattern::Binary(ref x) => self.binary(x),
+            Pattern::UnaryOp(ref x) => self.unary_op(x),
+            Pattern::BinaryOp(ref x) => self.binary_op(x),
+            Pattern::Record(ref x) => self.record(x),
+            Pattern::RecordIndex(ref x) => self.record_index(x),
+            Pattern::Map(ref x) => self.map(x),
+        }
+    }
+
+    //
+    // Guards
+    //
+
+    fn guard(&mut self, guard: &Guard) -> fmt::Result {
+        match *guard {
+            Guard::Integer(ref x) => self.integer(x),
+            Guard::Float(ref x) => self.float(x),
+            Guard::String(ref x) => self.string(x),
+            Guard::Char(ref x) => self.char(x),
+            Guard::Atom(ref x) => self.atom(&x.value),
+            Guard::Var(ref x) => self.var(x),
+            Guard::Tuple(ref x) => self.tuple(x),
+            Guard::Nil(_) => self.out.write_str("[]"),
+            Guard::Cons(ref x) => self.cons(x),
+            Guard::Binary(ref x) => self.binary(x),
+            Guard::UnaryOp(ref x) => self.unary_op(x),
+            Guard::BinaryOp(ref x) => self.binary_op(x),
+            Guard::Record(ref x) => self.record(x),
+            Guard::RecordIndex(ref x) => self.record_index(x),
+            Guard::LocalCall(ref x) => self.local_call(x),
+            Guard::RemoteCall(ref x) => self.remote_call(x),
+        }
+    }
+
+    //
+    // Types
+    //
+
+    fn ty(&mut self, ty: &Type) -> fmt::Result {
+        match *ty {
+            Type::Atom(ref x) => self.atom(&x.value),
+            Type::Integer(ref x) => self.integer(x),
+            Type::Var(ref x) => self.var(x),
+            Type::Annotated(ref x) => {
+                self.var(&x.name)?;
+                self.out.write_str(" :: ")?;
+                self.ty(&x.ty)
+            }
+            Type::UnaryOp(ref x) => self.unary_op(x),
+            Type::BinaryOp(ref x) => self.binary_op(x),
+            Type::BitString(ref x) => self.bitstring_type(x),
+            Type::Nil(_) => self.out.write_str("[]"),
+            Type::AnyFun(ref x) => match x.return_type {
+                None => self.out.write_str("fun()"),
+                Some(ref ret) => {
+                    self.out.write_str("fun((...) -> ")?;
+                    self.ty(ret)?;
+                    self.out.write_str(")")
+                }
+            },
+            Type::Function(ref x) => {
+                self.out.write_str("fun(")?;
+                self.fun_type_signature(x)?;
+                self.out.write_char(')')
+            }
+            Type::Range(ref x) => {
+                self.operand(&x.low)?;
+                self.out.write_str("..")?;
+                self.operand(&x.high)
+            }
+            Type::Map(ref x) => {
+                self.out.write_str("#{")?;
+                self.seq(&x.pairs, ", ", |p, pair| {
+                    p.ty(&pair.key)?;
+                    p.out.write_str(" => ")?;
+                    p.ty(&pair.value)
+                })?;
+                self.out.write_char('}')
+            }
+            Type::BuiltIn(ref x) => {
+                self.atom(&x.name)?;
+                self.args(&x.args)
+            }
+            Type::Record(ref x) => {
+                self.out.write_char('#')?;
+                self.atom(&x.name)?;
+                self.out.write_char('{')?;
+                self.seq(&x.fields, ", ", |p, field| {
+                    p.atom(&field.name)?;
+                    p.out.write_str(" :: ")?;
+                    p.ty(&field.ty)
+                })?;
+                self.out.write_char('}')
+            }
+            Type::Remote(ref x) => {
+                self.atom(&x.module)?;
+                self.out.write_char(':')?;
+                self.atom(&x.function)?;
+                self.args(&x.args)
+            }
+            Type::AnyTuple(_) => self.out.write_str("tuple()"),
+            Type::Tuple(ref x) => {
+                self.out.write_char('{')?;
+                self.seq(&x.elements, ", ", |p, t| p.ty(t))?;
+                self.out.write_char('}')
+            }
+            Type::Union(ref x) => self.seq(&x.types, " | ", |p, t| p.operand(t)),
+            Type::User(ref x) => {
+                self.atom(&x.name)?;
+                self.args(&x.args)
+            }
+        }
+    }
+
+    fn bitstring_type(&mut self, x: &ty::BitString) -> fmt::Result {
+        self.out.write_str("<<")?;
+        if x.bytes != 0 {
+            write!(self.out, "_:{}", x.bytes)?;
+        }
+        if x.tail_bits != 0 {
+            if x.bytes != 0 {
+                self.out.write_str(", ")?;
+            }
+            write!(self.out, "_:_*{}", x.tail_bits)?;
+        }
+        self.out.write_str(">>")
+    }
+
+    /// Prints `(Args) -> Return when Constraints`, as used by specs and `fun(..)` types
+    fn fun_type_signature(&mut self, fun: &ty::Fun) -> fmt::Result {
+        self.args(&fun.args)?;
+        self.out.write_str(" -> ")?;
+        self.ty(&fun.return_type)?;
+        if !fun.constraints.is_empty() {
+            self.out.write_str(" when ")?;
+            self.seq(&fun.constraints, ", ", |p, c| {
+                p.var(&c.var)?;
+                p.out.write_str(" :: ")?;
+                p.ty(&c.subtype)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+fn is_any_type(ty: &Type) -> bool {
+    match *ty {
+        Type::BuiltIn(ref x) => x.name == "any" && x.args.is_empty(),
+        _ => false,
+    }
+}
+
+fn is_typed_attribute(name: &str) -> bool {
+    match name {
+        "type" | "opaque" | "spec" | "callback" | "record" => true,
+        _ => false,
+    }
+}
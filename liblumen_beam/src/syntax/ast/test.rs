@@ -1,3 +1,7 @@
+use crate::serialization::etf;
+use crate::syntax::ast::ast::form::{Form, WildAttr};
+use crate::syntax::ast::ast::{ModuleDecl, Node};
+use crate::syntax::ast::printer::UnrepresentableForm;
 use crate::syntax::ast::*;
 
 #[test]
@@ -9,3 +13,88 @@ fn it_works() {
         })
         .unwrap();
 }
+
+#[test]
+fn prints_erlang_source() {
+    let ast = AST::from_beam_file("tests/testdata/ast/test.beam").unwrap();
+    let source = ast.module.to_string();
+    assert!(source.contains("-module(test).\n"));
+    assert!(source.contains("-export([sum/1, op/1]).\n"));
+    assert!(source.contains("-import(lists, [usort/1]).\n"));
+    assert!(source.contains("{123, -123, 12.3, foo, [1, 2, 3], <<\"123\">>"));
+    assert!(source.contains("guard(X) when is_integer(X); is_atom(X) ->\n    X;\n"));
+    assert!(source.contains("(Num + 1) band 4294967295."));
+}
+
+#[test]
+fn prints_each_form_kind() {
+    let ast = AST::from_beam_file("tests/testdata/ast/test.beam").unwrap();
+    let source = ast.module.to_string();
+    for form in &[
+        "-file(\"test.erl\", 1).\n",
+        "-module(test).\n",
+        "-compile('debug_info').\n",
+        "-foo_attribute('bar').\n",
+        "-behaviour(test).\n",
+        "-behavior(test2).\n",
+        "-export([literals/0]).\n",
+        "-export_type([my_list/1]).\n",
+        "-import(lists, [usort/1]).\n",
+        "-callback hello(Name :: binary()) -> ok | {error, Reason :: term()}.\n",
+        "-opaque my_list(E) :: my_cons(E, my_list(E)) | nil.\n",
+        "-type my_cons(H, T) :: {H, T}.\n",
+        "-spec foo:bar(_) -> baz.\n",
+        // With the field types of the separate `-type({{record, my_record}, ..})` written
+        // before OTP 19
+        "-record(my_record, {\n    a = undefined,\n    b = 10 :: integer(),\n",
+        "to_my_list([]) ->\n    nil;\nto_my_list([H | T]) ->\n    cons(H, to_my_list(T)).\n",
+    ] {
+        assert!(source.contains(form), "{:?} is not in:\n{}", form, source);
+    }
+}
+
+#[test]
+fn prints_forms_on_their_original_lines() {
+    let ast = AST::from_beam_file("tests/testdata/ast/test.beam").unwrap();
+    let source = ast.module.to_source_with_original_lines().unwrap();
+    let lines = source.lines().collect::<Vec<_>>();
+    let line = |form: &Form| lines[form.line() as usize - 1];
+    for form in ast.module.forms.iter() {
+        match *form {
+            Form::Fun(ref x) => {
+                assert!(line(form).starts_with(&format!("{}(", x.name)));
+                for clause in x.clauses.iter() {
+                    assert!(lines[clause.line as usize - 1].starts_with(&x.name));
+                }
+            }
+            Form::Eof(_) => (),
+            _ => assert!(line(form).starts_with('-'), "{:?}", form),
+        }
+    }
+
+    // Clauses written on one line stay on one line
+    assert!(source.contains("\nguard(X) when is_integer(X); is_atom(X) -> X;\n"));
+    // The output is the same apart from the blank lines
+    let without_whitespace = |s: String| s.replace(char::is_whitespace, "");
+    assert_eq!(
+        without_whitespace(source),
+        without_whitespace(ast.module.to_string())
+    );
+}
+
+#[test]
+fn undecodable_typed_attributes_are_not_printed_as_source() {
+    let value = etf::Term::from(etf::Atom::from("foo"));
+    let module = ModuleDecl {
+        forms: vec![Form::Attr(WildAttr::new(3, "spec".to_string(), value))],
+    };
+
+    assert_eq!(
+        Err(vec![UnrepresentableForm {
+            line: 3,
+            name: "spec".to_string()
+        }]),
+        module.to_source_with_original_lines()
+    );
+    assert!(module.to_string().contains("%% -spec('foo').\n"));
+}
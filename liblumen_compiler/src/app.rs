@@ -0,0 +1,225 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// An OTP application, laid out in the conventional way:
+///
+/// ```text
+/// <root>/
+///     src/<name>.app.src
+///     src/*.erl
+///     include/*.hrl
+///     priv/
+/// ```
+#[derive(Debug, Clone)]
+pub struct Application {
+    name: String,
+    root: PathBuf,
+    app_src: PathBuf,
+}
+impl Application {
+    /// Returns the application rooted at the given directory, if it contains
+    /// a `src/<name>.app.src` resource file
+    pub fn detect(root: &Path) -> io::Result<Option<Self>> {
+        let src_dir = root.join("src");
+        if !src_dir.is_dir() {
+            return Ok(None);
+        }
+        for entry in fs::read_dir(&src_dir)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if name.ends_with(".app.src") => &name[..name.len() - ".app.src".len()],
+                _ => continue,
+            };
+            return Ok(Some(Application {
+                name: name.to_string(),
+                root: root.to_path_buf(),
+                app_src: path.clone(),
+            }));
+        }
+        Ok(None)
+    }
+
+    /// The directory containing the application's sources
+    pub fn src_dir(&self) -> PathBuf {
+        self.root.join("src")
+    }
+
+    /// The directory containing the application's public headers
+    pub fn include_dir(&self) -> PathBuf {
+        self.root.join("include")
+    }
+
+    /// Generates the `<name>.app` resource file in the output directory from
+    /// the `.app.src` file, filling in the given list of modules
+    pub fn write_app_file(&self, output_dir: &Path, modules: &[String]) -> io::Result<PathBuf> {
+        let app_src = fs::read_to_string(&self.app_src)?;
+        let app = set_modules(&app_src, modules).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "invalid application resource file {}",
+                    self.app_src.display()
+                ),
+            )
+        })?;
+        let path = output_dir.join(format!("{}.app", self.name));
+        fs::write(&path, app)?;
+        Ok(path)
+    }
+}
+
+// Sets the `modules` key of an application resource file, i.e.
+// `{application, Name, [{modules, [...]}, ...]}.`, replacing any existing value.
+//
+// Returns `None` if the resource file is not of the expected form
+fn set_modules(app_src: &str, modules: &[String]) -> Option<String> {
+    let modules = format!("{{modules, [{}]}}", modules.join(", "));
+
+    let tuple = find_token(app_src, 0, '{')?;
+    let tuple_end = matching(app_src, tuple)?;
+    let props = find_token(app_src, tuple + 1, '[')?;
+    let props_end = matching(app_src, props)?;
+    if props_end > tuple_end {
+        return None;
+    }
+
+    // Look for an existing `{modules, ...}` entry among the properties
+    let mut i = props + 1;
+    while let Some(start) = find_token(&app_src[..props_end], i, '{') {
+        let end = matching(app_src, start)?;
+        let key = app_src[start + 1..end].trim_start();
+        if key.starts_with("modules") && !is_atom_char(key["modules".len()..].chars().next()) {
+            return Some(format!(
+                "{}{}{}",
+                &app_src[..start],
+                modules,
+                &app_src[end + 1..]
+            ));
+        }
+        i = end + 1;
+    }
+
+    let empty = app_src[props + 1..props_end].trim().is_empty();
+    let separator = if empty { "" } else { ",\n  " };
+    Some(format!(
+        "{}{}{}{}",
+        &app_src[..props + 1],
+        modules,
+        separator,
+        &app_src[props + 1..]
+    ))
+}
+
+fn is_atom_char(c: Option<char>) -> bool {
+    c.map(|c| c.is_alphanumeric() || c == '_' || c == '@')
+        .unwrap_or(false)
+}
+
+// Finds the next occurrence of `token` at or after `from` which is not inside
+// a string, quoted atom, character literal or comment
+fn find_token(source: &str, from: usize, token: char) -> Option<usize> {
+    Scanner::new(source, from)
+        .find(|&(_, c)| c == token)
+        .map(|(i, _)| i)
+}
+
+// Finds the bracket closing the one at `open`
+fn matching(source: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in Scanner::new(source, open) {
+        match c {
+            '{' | '[' | '(' => depth += 1,
+            '}' | ']' | ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+// Iterates over the significant characters of Erlang source, i.e. those
+// outside of strings, quoted atoms, character literals and comments
+struct Scanner<'a> {
+    chars: std::str::CharIndices<'a>,
+    offset: usize,
+}
+impl<'a> Scanner<'a> {
+    fn new(source: &'a str, from: usize) -> Self {
+        Scanner {
+            chars: source[from..].char_indices(),
+            offset: from,
+        }
+    }
+
+    fn skip_quoted(&mut self, quote: char) {
+        while let Some((_, c)) = self.chars.next() {
+            match c {
+                '\\' => {
+                    self.chars.next();
+                }
+                c if c == quote => return,
+                _ => (),
+            }
+        }
+    }
+}
+impl<'a> Iterator for Scanner<'a> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (i, c) = self.chars.next()?;
+            match c {
+                '"' | '\'' => self.skip_quoted(c),
+                '%' => {
+                    while let Some((_, c)) = self.chars.next() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                '$' => {
+                    if let Some((_, '\\')) = self.chars.next() {
+                        self.chars.next();
+                    }
+                }
+                c => return Some((self.offset + i, c)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::set_modules;
+
+    fn modules(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn set_modules_inserts_missing_key() {
+        let app_src = "{application, foo,\n [{vsn, \"1.0\"}]}.\n";
+        let app = set_modules(app_src, &modules(&["foo", "foo_sup"])).unwrap();
+        assert_eq!(
+            app,
+            "{application, foo,\n [{modules, [foo, foo_sup]},\n  {vsn, \"1.0\"}]}.\n"
+        );
+    }
+
+    #[test]
+    fn set_modules_replaces_existing_key() {
+        let app_src =
+            "%% {modules, []}\n{application, foo, [{description, \"{modules}\"}, {modules, []}]}.";
+        let app = set_modules(app_src, &modules(&["foo"])).unwrap();
+        assert_eq!(
+            app,
+            "%% {modules, []}\n{application, foo, [{description, \"{modules}\"}, {modules, [foo]}]}."
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use super::config::CompilerSettings;
+
+/// The name of the cache file, which is stored in the output directory
+const CACHE_FILE: &str = ".lumen_cache";
+
+/// Tracks the content hash of each source file as of its last successful
+/// compilation, so that unchanged modules can be skipped on rebuild.
+///
+/// The cache is stored as a plain text file with one line per source file,
+/// containing the hash, the module name and the path of the source file.
+#[derive(Debug)]
+pub struct BuildCache {
+    path: PathBuf,
+    output_dir: PathBuf,
+    entries: HashMap<PathBuf, CacheEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    hash: String,
+    module: String,
+}
+
+impl BuildCache {
+    /// Creates an empty cache for the given output directory, ignoring any
+    /// existing cache file
+    pub fn empty(output_dir: &Path) -> Self {
+        BuildCache {
+            path: output_dir.join(CACHE_FILE),
+            output_dir: output_dir.to_path_buf(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Loads the cache for the given output directory, if one exists
+    pub fn load(output_dir: &Path) -> io::Result<Self> {
+        let mut cache = Self::empty(output_dir);
+        let contents = match fs::read_to_string(&cache.path) {
+            Ok(contents) => contents,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(cache),
+            Err(err) => return Err(err),
+        };
+        for line in contents.lines() {
+            let mut parts = line.splitn(3, ' ');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(hash), Some(module), Some(source)) => {
+                    let entry = CacheEntry {
+                        hash: hash.to_string(),
+                        module: module.to_string(),
+                    };
+                    cache.entries.insert(PathBuf::from(source), entry);
+                }
+                // A corrupt entry simply causes that module to be recompiled
+                _ => continue,
+            }
+        }
+        Ok(cache)
+    }
+
+    /// Returns true if the given source file was compiled with the same hash,
+    /// and its artifacts with the given extensions are still present in the
+    /// output directory
+    pub fn is_fresh(&self, source: &Path, hash: &str, extensions: &[&str]) -> bool {
+        match self.entries.get(source) {
+            Some(entry) if entry.hash == hash => extensions.iter().all(|ext| {
+                self.output_dir
+                    .join(format!("{}.{}", entry.module, ext))
+                    .is_file()
+            }),
+            _ => false,
+        }
+    }
+
+    /// Returns the name of the module last compiled from the given source file
+    pub fn module(&self, source: &Path) -> Option<&str> {
+        self.entries.get(source).map(|entry| entry.module.as_str())
+    }
+
+    /// Records a successful compilation of the given source file
+    pub fn insert(&mut self, source: PathBuf, hash: String, module: String) {
+        self.entries.insert(source, CacheEntry { hash, module });
+    }
+
+    /// Writes the cache back out to the output directory
+    pub fn save(&self) -> io::Result<()> {
+        let mut file = fs::File::create(&self.path)?;
+        for (source, entry) in self.entries.iter() {
+            writeln!(file, "{} {} {}", entry.hash, entry.module, source.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes the content hash of a source file.
+///
+/// The hash covers the contents of the file, the contents of all files it
+/// includes (transitively), and the settings which affect how it is parsed.
+pub fn hash_source(config: &CompilerSettings, source: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+
+    hasher.input(format!("{:?}", config.mode).as_bytes());
+    // A module which only compiled with warnings must be rebuilt under -Werror
+    hasher.input(&[config.warnings_as_errors as u8]);
+    hasher.input(format!("{:?}", config.emit).as_bytes());
+    let mut defines = config
+        .defines
+        .iter()
+        .map(|(name, def)| format!("{}={:?}", name, def))
+        .collect::<Vec<_>>();
+    defines.sort();
+    for define in defines.iter() {
+        hasher.input(define.as_bytes());
+    }
+
+    let mut visited = HashSet::new();
+    hash_file(config, source, &mut hasher, &mut visited)?;
+
+    Ok(format!("{:x}", hasher.result()))
+}
+
+fn hash_file(
+    config: &CompilerSettings,
+    file: &Path,
+    hasher: &mut Sha256,
+    visited: &mut HashSet<PathBuf>,
+) -> io::Result<()> {
+    if !visited.insert(file.to_path_buf()) {
+        return Ok(());
+    }
+    let contents = fs::read(file)?;
+    hasher.input(&contents);
+
+    // Binary files (i.e. .beam) have no include dependencies
+    let text = match std::str::from_utf8(&contents) {
+        Ok(text) => text,
+        Err(_) => return Ok(()),
+    };
+    for include in includes(text) {
+        // Includes which cannot be found will fail to compile anyway
+        if let Some(path) = resolve_include(config, file, &include) {
+            hash_file(config, &path, hasher, visited)?;
+        }
+    }
+    Ok(())
+}
+
+enum Include {
+    File(String),
+    Lib(String),
+}
+
+/// Scans Erlang source for `-include` and `-include_lib` attributes
+fn includes(source: &str) -> Vec<Include> {
+    let mut includes = Vec::new();
+    for line in source.lines() {
+        let line = line.trim_start();
+        let (is_lib, rest) = if line.starts_with("-include_lib") {
+            (true, &line["-include_lib".len()..])
+        } else if line.starts_with("-include") {
+            (false, &line["-include".len()..])
+        } else {
+            continue;
+        };
+        let mut quoted = rest.splitn(3, '"');
+        match (quoted.next(), quoted.next(), quoted.next()) {
+            (Some(_), Some(path), Some(_)) if is_lib => includes.push(Include::Lib(path.into())),
+            (Some(_), Some(path), Some(_)) => includes.push(Include::File(path.into())),
+            _ => continue,
+        }
+    }
+    includes
+}
+
+fn resolve_include(config: &CompilerSettings, file: &Path, include: &Include) -> Option<PathBuf> {
+    let path = match *include {
+        Include::File(ref path) => path,
+        Include::Lib(ref path) => {
+            // The first path component of an `-include_lib` is the application name
+            let found = config
+                .code_path
+                .iter()
+                .map(|dir| dir.join(path))
+                .find(|p| p.is_file());
+            if found.is_some() {
+                return found;
+            }
+            path
+        }
+    };
+    file.parent()
+        .into_iter()
+        .chain(config.include_path.iter().map(|p| p.as_path()))
+        .map(|dir| dir.join(path))
+        .find(|p| p.is_file())
+}
@@ -1,46 +1,119 @@
+use std::cmp;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use failure::{format_err, Error};
 
 use libeir_diagnostics::emitter::{cyan, green, green_bold, white, yellow, yellow_bold};
-use libeir_diagnostics::{ColorSpec, Emitter, NullEmitter, StandardStreamEmitter};
+use libeir_diagnostics::{CodeMap, ColorSpec, Emitter, NullEmitter, StandardStreamEmitter};
 use libeir_diagnostics::{Diagnostic, Severity};
 
-use libeir_intern::Ident;
 use libeir_ir::Module;
 
-use libeir_passes::PassManager;
-use libeir_syntax_erl::{ParseConfig, Parser};
+use libeir_passes::{CompilePatternPass, NaiveInlineClosuresPass, SimplifyCfgPass};
+use libeir_passes::{FunctionPass, PassManager};
+use libeir_syntax_erl::{Parser, ParserError};
 
-pub use super::config::{CompilerMode, CompilerSettings, Verbosity};
+use liblumen_beam::syntax::ast::AST;
+
+use super::app::Application;
+use super::cache::{self, BuildCache};
+pub use super::config::{parse_define, CompilerMode, CompilerSettings, Emit, Verbosity};
 pub use super::errors::CompilerError;
+use super::timing::{self, PhaseTimings};
+use super::warnings::{DiagnosticCounts, Suppressions};
+
+#[cfg(test)]
+mod test;
 
 /// The result produced by compiler functions
 pub type CompileResult = Result<(), Error>;
 
+/// Summary information about a completed compilation
+#[derive(Debug, Clone)]
 pub struct CompilationInfo {
     num_modules: usize,
-    compilation_time: usize,
+    num_cached: usize,
+    num_errors: usize,
+    num_warnings: usize,
+    compilation_time: Duration,
+    diagnostics: Vec<(PathBuf, Vec<Diagnostic>)>,
+    timings: Vec<(String, PhaseTimings)>,
 }
 impl CompilationInfo {
     pub fn new() -> Self {
         CompilationInfo {
             num_modules: 0,
-            compilation_time: 0,
+            num_cached: 0,
+            num_errors: 0,
+            num_warnings: 0,
+            compilation_time: Duration::from_secs(0),
+            diagnostics: Vec::new(),
+            timings: Vec::new(),
         }
     }
+
+    /// The number of modules which were compiled
+    pub fn num_modules(&self) -> usize {
+        self.num_modules
+    }
+
+    /// The number of modules which were up to date, and so not recompiled
+    pub fn num_cached(&self) -> usize {
+        self.num_cached
+    }
+
+    /// The number of errors reported
+    pub fn num_errors(&self) -> usize {
+        self.num_errors
+    }
+
+    /// The number of warnings reported, excluding suppressed warnings
+    pub fn num_warnings(&self) -> usize {
+        self.num_warnings
+    }
+
+    /// The diagnostics reported for each compiled source file
+    pub fn diagnostics(&self) -> &[(PathBuf, Vec<Diagnostic>)] {
+        &self.diagnostics
+    }
+
+    /// The time spent in each phase of compilation, for each compiled module
+    pub fn timings(&self) -> &[(String, PhaseTimings)] {
+        &self.timings
+    }
+
+    /// The wall-clock time spent compiling
+    pub fn compilation_time(&self) -> Duration {
+        self.compilation_time
+    }
 }
 
 pub struct Compiler {
     config: CompilerSettings,
     info: CompilationInfo,
     emitter: Arc<dyn Emitter>,
+    app: Option<Application>,
 }
 impl Compiler {
-    pub fn new(config: CompilerSettings) -> Self {
+    /// Creates a new compiler for the given settings.
+    ///
+    /// If the source directory is the root of an OTP application, i.e. it
+    /// contains `src/<app>.app.src`, then only `src/` is compiled, `include/`
+    /// is added to the include path, and `<app>.app` is generated in the output
+    /// directory on successful compilation.
+    pub fn new(mut config: CompilerSettings) -> Result<Self, Error> {
+        let app = Application::detect(&config.source_dir).map_err(CompilerError::from)?;
+        if let Some(ref app) = app {
+            config.source_dir = app.src_dir();
+            config.include_path.push_front(app.include_dir());
+        }
+
         let emitter: Arc<dyn Emitter> = match config.verbosity {
             Verbosity::Silent => Arc::new(NullEmitter::new()),
             v => Arc::new(
@@ -51,25 +124,102 @@ impl Compiler {
         };
         let info = CompilationInfo::new();
 
-        Compiler {
+        Ok(Compiler {
             config,
             info,
             emitter,
+            app,
+        })
+    }
+
+    /// Compiles all modules found in the source directory, writing the
+    /// artifacts selected by `emit` for each module to the output directory.
+    ///
+    /// Modules whose sources (and included files) are unchanged since the last
+    /// compilation are skipped, unless `force` is set.
+    pub fn compile(&mut self) -> Result<CompilationInfo, Error> {
+        let start = Instant::now();
+
+        let output_dir = self.output_dir();
+        fs::create_dir_all(&output_dir).map_err(CompilerError::from)?;
+
+        let mut cache = if self.config.force {
+            BuildCache::empty(&output_dir)
+        } else {
+            BuildCache::load(&output_dir).map_err(CompilerError::from)?
+        };
+
+        let extensions = self
+            .config
+            .emit
+            .iter()
+            .filter_map(Emit::extension)
+            .collect::<Vec<_>>();
+        let mut stale = Vec::new();
+        let mut hashes = HashMap::new();
+        let sources = self.find_sources()?;
+        for file in sources.iter() {
+            let hash = cache::hash_source(&self.config, file).map_err(CompilerError::from)?;
+            if cache.is_fresh(file, &hash, &extensions) {
+                self.info.num_cached += 1;
+            } else {
+                hashes.insert(file.clone(), hash);
+                stale.push(file.clone());
+            }
         }
+
+        // Modules which compiled successfully are written and cached even if
+        // others failed, so they are not rebuilt once the errors are fixed
+        let (modules, result) = self.compile_files(stale);
+        for (file, (module, artifacts)) in modules.iter() {
+            self.write_artifacts(&output_dir, module, artifacts)?;
+            let hash = hashes.remove(file).unwrap();
+            cache.insert(file.clone(), hash, module.name.to_string());
+        }
+        cache.save().map_err(CompilerError::from)?;
+        result?;
+
+        if let Some(ref app) = self.app {
+            let mut modules = sources
+                .iter()
+                .filter_map(|file| cache.module(file))
+                .map(|module| module.to_string())
+                .collect::<Vec<_>>();
+            modules.sort();
+            app.write_app_file(&output_dir, &modules)
+                .map_err(CompilerError::from)?;
+        }
+
+        self.info.num_modules = modules.len();
+        self.info.compilation_time = start.elapsed();
+
+        Ok(self.info.clone())
     }
 
-    pub fn compile(&mut self) -> Result<(), Error> {
-        unimplemented!()
+    /// Parses, lowers and runs the passes over all modules found in the source
+    /// directory, without writing any artifacts or consulting the build cache.
+    ///
+    /// The diagnostics for each module are available from the returned
+    /// info, or from `compilation_info` if checking failed.
+    pub fn check(&mut self) -> Result<CompilationInfo, Error> {
+        let start = Instant::now();
+
+        let files = self.find_sources()?;
+        let (modules, result) = self.compile_files(files);
+        result?;
+
+        self.info.num_modules = modules.len();
+        self.info.compilation_time = start.elapsed();
+
+        Ok(self.info.clone())
     }
 
-    // Parses all modules into a map. The map uses the module name symbol
-    // as the key, and the AST for the module as the value.
-    fn parse_modules(&mut self) -> Result<HashMap<Ident, Module>, Error> {
+    // Finds all source files for the current compiler mode
+    fn find_sources(&self) -> Result<Vec<PathBuf>, Error> {
         use walkdir::{DirEntry, WalkDir};
 
-        let mut parser = Parser::new(self.config.clone().into());
-
         let extension = match self.config.mode {
+            CompilerMode::Beam => "beam",
             CompilerMode::Erlang => "erl",
         };
 
@@ -95,48 +245,120 @@ impl Compiler {
             .follow_links(true)
             .into_iter();
 
-        let mut modules = HashMap::new();
-        let config = ParseConfig::default();
-        let mut parser = Parser::new(config);
-
-        for entry in walker.filter_entry(|e| !is_hidden(e) && is_source_file(e, extension)) {
-            let entry = entry.unwrap();
-            let file = entry.path();
-
-            let mut module = match self.config.mode {
-                CompilerMode::Erlang => self.parse_erl(&mut parser, file)?,
-            };
-
-            modules.insert(module.name.clone(), module);
+        let mut files = Vec::new();
+        for entry in walker.filter_entry(|e| !is_hidden(e)) {
+            let entry = entry.map_err(|e| format_err!("{}", e))?;
+            if is_source_file(&entry, extension) {
+                files.push(entry.into_path());
+            }
         }
 
-        Ok(modules)
+        Ok(files)
     }
 
-    // Compiles a .erl file to Erlang AST
-    fn parse_erl(&self, parser: &mut Parser, file: &Path) -> Result<Module, Error> {
-        use libeir_syntax_erl::ast;
-        match parser.parse_file::<&Path, ast::Module>(file) {
-            Ok(ast) => {
-                let (res, messages) = libeir_syntax_erl::lower_module(&ast);
-                for msg in messages.iter() {
-                    self.diagnostic(&msg.to_diagnostic());
+    // Compiles the given files concurrently, using one worker thread per CPU.
+    //
+    // Progress is reported as each file starts, and diagnostics as each file
+    // completes. All modules which compiled successfully are returned, along
+    // with the first error encountered, if any
+    fn compile_files(
+        &mut self,
+        files: Vec<PathBuf>,
+    ) -> (Vec<(PathBuf, (Module, Vec<Artifact>))>, CompileResult) {
+        let num_workers = cmp::min(num_cpus::get(), files.len());
+        let queue = Arc::new(Mutex::new(files));
+        let (sender, receiver) = mpsc::channel();
+
+        let workers = (0..num_workers)
+            .map(|_| {
+                let queue = queue.clone();
+                let sender = sender.clone();
+                let config = self.config.clone();
+                thread::spawn(move || {
+                    let mut parser = Parser::new(config.clone().into());
+                    loop {
+                        let file = match queue.lock().unwrap().pop() {
+                            None => break,
+                            Some(file) => file,
+                        };
+                        if sender.send(WorkerEvent::Started(file.clone())).is_err() {
+                            break;
+                        }
+                        let mut timings = PhaseTimings::default();
+                        let (result, diagnostics) = match config.mode {
+                            CompilerMode::Beam => {
+                                parse_beam(&mut parser, &file, &config.emit, &mut timings)
+                            }
+                            CompilerMode::Erlang => {
+                                parse_erl(&mut parser, &file, &config.emit, &mut timings)
+                            }
+                        };
+                        let event = WorkerEvent::Finished(file, (result, diagnostics), timings);
+                        if sender.send(event).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(sender);
+
+        let mut modules = Vec::new();
+        let mut result = Ok(());
+        let mut timings = Vec::new();
+        for event in receiver {
+            let (file, (module, mut diagnostics), file_timings) = match event {
+                WorkerEvent::Started(file) => {
+                    self.info(format!("Compiling {}", file.display()));
+                    continue;
                 }
-                match res.ok() {
-                    Some(mut ir) => {
-                        let mut pass_manager = PassManager::default();
-                        pass_manager.run(&mut ir);
-                        Ok(ir)
+                WorkerEvent::Finished(file, result, file_timings) => (file, result, file_timings),
+            };
+            let (module, counts) = apply_warning_settings(&self.config, module, &mut diagnostics);
+            for diagnostic in diagnostics.iter() {
+                self.diagnostic(diagnostic);
+            }
+            self.info.num_errors += counts.errors;
+            self.info.num_warnings += counts.warnings;
+            self.info.diagnostics.push((file.clone(), diagnostics));
+            let name = file.file_stem().unwrap().to_string_lossy().into_owned();
+            timings.push((name, file_timings));
+
+            match module {
+                Ok(module) => modules.push((file, module)),
+                Err(err) => {
+                    if result.is_ok() {
+                        result = Err(err);
                     }
-                    None => Err(CompilerError::Failed.into()),
                 }
             }
-            Err(errs) => Err(CompilerError::Parser {
-                codemap: self.config.codemap.clone(),
-                errs,
+        }
+        for worker in workers {
+            if worker.join().is_err() && result.is_ok() {
+                result = Err(format_err!("compiler worker thread panicked"));
             }
-            .into()),
         }
+
+        if !timings.is_empty() {
+            self.debug(timing::format_table(&timings));
+        }
+        self.info.timings.extend(timings);
+
+        (modules, result)
+    }
+
+    // Writes the textual EIR dumps for a module to `<output_dir>/<module>.<extension>`
+    fn write_artifacts(
+        &self,
+        output_dir: &Path,
+        module: &Module,
+        artifacts: &[Artifact],
+    ) -> CompileResult {
+        for artifact in artifacts.iter() {
+            let path = output_dir.join(format!("{}.{}", module.name, artifact.extension));
+            fs::write(&path, &artifact.text).map_err(CompilerError::from)?;
+        }
+        Ok(())
     }
 
     #[inline]
@@ -161,6 +383,12 @@ impl Compiler {
             .unwrap();
     }
 
+    /// Summary information about the compilation so far, which is
+    /// available even if compilation failed
+    pub fn compilation_info(&self) -> &CompilationInfo {
+        &self.info
+    }
+
     pub fn warnings_as_errors(&self) -> bool {
         self.config.warnings_as_errors
     }
@@ -173,17 +401,27 @@ impl Compiler {
         self.config.output_dir.clone()
     }
 
+    pub fn codemap(&self) -> Arc<Mutex<CodeMap>> {
+        self.config.codemap.clone()
+    }
+
     pub fn warn<M: Display>(&self, message: M) {
         self.write_warning(yellow_bold(), "WARN: ");
         self.write_warning(yellow(), &message.to_string());
     }
 
+    /// Reports progress, shown with `-v` or higher
     pub fn info<M: Display>(&self, message: M) {
-        self.write_info(cyan(), &message.to_string());
+        if self.config.verbosity <= Verbosity::Info {
+            self.write_info(cyan(), &message.to_string());
+        }
     }
 
+    /// Reports detailed progress, shown with `-vv` or higher
     pub fn debug<M: Display>(&self, message: M) {
-        self.write_info(white(), &message.to_string());
+        if self.config.verbosity <= Verbosity::Debug {
+            self.write_info(white(), &message.to_string());
+        }
     }
 
     pub fn diagnostic(&self, diagnostic: &Diagnostic) {
@@ -191,12 +429,232 @@ impl Compiler {
     }
 }
 
+/// The output of compiling a set of in-memory sources with `compile_sources`
+#[derive(Debug)]
+pub struct CompiledSources {
+    /// The modules which compiled successfully
+    pub modules: Vec<Module>,
+    /// The diagnostics reported for each source, keyed by the name it was given
+    pub diagnostics: Vec<(String, Vec<Diagnostic>)>,
+    /// The number of errors reported
+    pub num_errors: usize,
+    /// The number of warnings reported, excluding suppressed warnings
+    pub num_warnings: usize,
+    /// The code map used to resolve the spans of the diagnostics
+    pub codemap: Arc<Mutex<CodeMap>>,
+    failed: bool,
+}
+impl CompiledSources {
+    /// Returns true if every source compiled successfully
+    pub fn is_success(&self) -> bool {
+        !self.failed
+    }
+}
+
+/// Compiles a set of in-memory `(name, source)` pairs of Erlang source code.
+///
+/// This is the entry point for tools which embed the compiler: nothing is read
+/// from or written to disk, nothing is printed, and all diagnostics are returned
+/// to the caller rather than emitted. The `source_dir`, `output_dir`, `mode`,
+/// `emit` and `verbosity` settings are ignored.
+pub fn compile_sources<N, S>(config: &CompilerSettings, sources: &[(N, S)]) -> CompiledSources
+where
+    N: AsRef<str>,
+    S: AsRef<str>,
+{
+    use libeir_syntax_erl::ast;
+
+    let parser = Parser::new(config.clone().into());
+    let mut output = CompiledSources {
+        modules: Vec::new(),
+        diagnostics: Vec::new(),
+        num_errors: 0,
+        num_warnings: 0,
+        codemap: config.codemap.clone(),
+        failed: false,
+    };
+    for (name, source) in sources.iter() {
+        let source = source.as_ref();
+        let result = parser.parse_string::<&str, ast::Module>(source);
+        let mut timings = PhaseTimings::default();
+        let (result, mut diagnostics) = lower(result, &[], &mut timings);
+        let (result, counts) = apply_warning_settings(config, result, &mut diagnostics);
+        match result {
+            Ok((module, _)) => output.modules.push(module),
+            Err(_) => output.failed = true,
+        }
+        output.num_errors += counts.errors;
+        output.num_warnings += counts.warnings;
+        output
+            .diagnostics
+            .push((name.as_ref().to_string(), diagnostics));
+    }
+    output
+}
+
+/// A textual dump of a module's EIR, written to `<output_dir>/<module>.<extension>`
+struct Artifact {
+    extension: String,
+    text: String,
+}
+
+/// The outcome of compiling a single file, along with any diagnostics produced
+type FileResult = (Result<(Module, Vec<Artifact>), Error>, Vec<Diagnostic>);
+
+/// What a worker thread in `Compiler::compile_files` reports back as it works
+enum WorkerEvent {
+    Started(PathBuf),
+    Finished(PathBuf, FileResult, PhaseTimings),
+}
+
+// Compiles a .erl file to Erlang AST
+fn parse_erl(
+    parser: &mut Parser,
+    file: &Path,
+    emit: &[Emit],
+    timings: &mut PhaseTimings,
+) -> FileResult {
+    use libeir_syntax_erl::ast;
+    let result = timings.time("parse", || parser.parse_file::<&Path, ast::Module>(file));
+    lower(result, emit, timings)
+}
+
+// Compiles the abstract code of a .beam file to Erlang AST
+//
+// The `raw_abstract_v1` forms are printed back out as Erlang source, which is
+// then handled by the same front-end used for .erl files. The forms are printed
+// on their original lines, so the lines in diagnostics refer to the original
+// source named by the `-file` attribute at the top of the printed source.
+//
+// Forms which cannot be printed as source fail the module with an error for
+// each, rather than being left out of it
+fn parse_beam(
+    parser: &mut Parser,
+    file: &Path,
+    emit: &[Emit],
+    timings: &mut PhaseTimings,
+) -> FileResult {
+    use libeir_syntax_erl::ast;
+    let abstract_code = match timings.time("decode", || AST::from_beam_file(file)) {
+        Ok(abstract_code) => abstract_code,
+        Err(err) => return (Err(err.into()), Vec::new()),
+    };
+    let source = match abstract_code.module.to_source_with_original_lines() {
+        Ok(source) => source,
+        Err(forms) => {
+            let diagnostics = forms
+                .iter()
+                .map(|form| Diagnostic::new_error(format!("{}: {}", file.display(), form)))
+                .collect();
+            return (Err(CompilerError::Failed.into()), diagnostics);
+        }
+    };
+    let result = timings.time("parse", || {
+        parser.parse_string::<&str, ast::Module>(&source)
+    });
+    lower(result, emit, timings)
+}
+
+// Lowers a parsed module to EIR and runs the passes over it, dumping the EIR
+// at each of the stages selected by `emit`
+//
+// Warnings the module suppresses with `-compile(nowarn_...)` are dropped
+fn lower(
+    result: Result<libeir_syntax_erl::ast::Module, Vec<ParserError>>,
+    emit: &[Emit],
+    timings: &mut PhaseTimings,
+) -> FileResult {
+    match result {
+        Ok(ast) => {
+            let (res, messages) = timings.time("lower", || libeir_syntax_erl::lower_module(&ast));
+            let suppressions = Suppressions::from_module(&ast);
+            let diagnostics = messages
+                .iter()
+                .map(|msg| (msg, msg.to_diagnostic()))
+                .filter(|(msg, diagnostic)| !suppressions.is_suppressed(msg, diagnostic))
+                .map(|(_, diagnostic)| diagnostic)
+                .collect();
+            match res.ok() {
+                Some(mut ir) => {
+                    let mut artifacts = Vec::new();
+                    let mut dump = |kind: Emit, extension: String, ir: &Module| {
+                        if emit.contains(&kind) {
+                            let text = ir.to_text();
+                            artifacts.push(Artifact { extension, text });
+                        }
+                    };
+                    dump(Emit::Eir, "eir".to_string(), &ir);
+                    for (i, (name, mut pass_manager)) in passes().into_iter().enumerate() {
+                        timings.time(name, || pass_manager.run(&mut ir));
+                        dump(Emit::EirPasses, format!("{:02}.{}.eir", i + 1, name), &ir);
+                    }
+                    dump(Emit::EirOpt, "opt.eir".to_string(), &ir);
+                    (Ok((ir, artifacts)), diagnostics)
+                }
+                None => (Err(CompilerError::Failed.into()), diagnostics),
+            }
+        }
+        Err(errs) => {
+            let diagnostics = errs.iter().map(|err| err.to_diagnostic()).collect();
+            (Err(CompilerError::Failed.into()), diagnostics)
+        }
+    }
+}
+
+// Applies the warning settings to the outcome of compiling a single file:
+// warnings are dropped under `no_warn`, and fail the module under
+// `warnings_as_errors`. Returns the counts of the remaining diagnostics
+fn apply_warning_settings<T>(
+    config: &CompilerSettings,
+    result: Result<T, Error>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> (Result<T, Error>, DiagnosticCounts) {
+    if config.no_warn {
+        diagnostics.retain(|d| d.severity != Severity::Warning);
+    }
+    let mut counts = DiagnosticCounts::default();
+    for diagnostic in diagnostics.iter() {
+        counts.count(diagnostic);
+    }
+    match result {
+        Ok(_) if config.warnings_as_errors && counts.warnings > 0 => {
+            (Err(CompilerError::Failed.into()), counts)
+        }
+        result => (result, counts),
+    }
+}
+
+// The passes run over each module, in order. These are the same passes run by
+// `PassManager::default()`, but each has its own manager so that the EIR can be
+// dumped between them
+fn passes() -> Vec<(&'static str, PassManager)> {
+    fn pass<P: FunctionPass + 'static>(pass: P) -> PassManager {
+        let mut pass_manager = PassManager::new();
+        pass_manager.push_function_pass(pass);
+        pass_manager
+    }
+
+    vec![
+        ("simplify_cfg", pass(SimplifyCfgPass::new())),
+        (
+            "naive_inline_closures",
+            pass(NaiveInlineClosuresPass::new()),
+        ),
+        ("compile_pattern", pass(CompilePatternPass::new())),
+        (
+            "naive_inline_closures",
+            pass(NaiveInlineClosuresPass::new()),
+        ),
+        ("simplify_cfg", pass(SimplifyCfgPass::new())),
+    ]
+}
+
 fn verbosity_to_severity(v: Verbosity) -> Severity {
     match v {
         Verbosity::Silent => Severity::Bug,
         Verbosity::Error => Severity::Error,
         Verbosity::Warning => Severity::Warning,
         Verbosity::Info => Severity::Note,
-        Verbosity::Debug => Severity::Note,
+        Verbosity::Debug => Severity::Help,
     }
 }
@@ -0,0 +1,246 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use libeir_diagnostics::{CodeMap, ColorChoice, Severity};
+use libeir_ir::Module;
+
+use crate::compiler::*;
+
+fn testdata(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/testdata")
+        .join(path)
+}
+
+fn settings(source: &str, defines: &[&str]) -> CompilerSettings {
+    let source_dir = testdata(source);
+    let mut include_path = VecDeque::new();
+    include_path.push_back(source_dir.parent().unwrap().to_path_buf());
+    let codemap = Arc::new(Mutex::new(CodeMap::new()));
+    CompilerSettings {
+        mode: CompilerMode::Erlang,
+        color: ColorChoice::Never,
+        source_dir,
+        output_dir: std::env::temp_dir(),
+        emit: vec![Emit::EirOpt],
+        defines: defines
+            .iter()
+            .map(|d| parse_define(d, &codemap).unwrap())
+            .collect::<HashMap<_, _>>(),
+        warnings_as_errors: false,
+        no_warn: false,
+        force: false,
+        verbosity: Verbosity::Silent,
+        code_path: Vec::new(),
+        include_path,
+        codemap,
+    }
+}
+
+fn parse_module(config: CompilerSettings, name: &str) -> Module {
+    let mut compiler = Compiler::new(config).unwrap();
+    let files = compiler.find_sources().unwrap();
+    let (modules, result) = compiler.compile_files(files);
+    result.unwrap();
+    modules
+        .into_iter()
+        .map(|(_, (module, _))| module)
+        .find(|module| &*module.name.name.as_str() == name)
+        .unwrap()
+}
+
+fn has_function(module: &Module, name: &str) -> bool {
+    module
+        .functions
+        .keys()
+        .any(|ident| &*ident.name.as_str() == name)
+}
+
+#[test]
+fn ifdef_with_define() {
+    let config = settings("defines/ifdef.erl", &["TEST", "VSN=1"]);
+    let module = parse_module(config, "ifdef");
+
+    assert!(has_function(&module, "test_only"));
+    assert!(!has_function(&module, "release_only"));
+}
+
+#[test]
+fn ifdef_without_define() {
+    let config = settings("defines/ifdef.erl", &["VSN=1"]);
+    let module = parse_module(config, "ifdef");
+
+    assert!(!has_function(&module, "test_only"));
+    assert!(has_function(&module, "release_only"));
+}
+
+#[test]
+fn include_lib_from_code_path() {
+    let mut config = settings("include_lib/src/uses_dep.erl", &[]);
+    config.code_path.push(testdata("include_lib/deps"));
+    let module = parse_module(config, "uses_dep");
+
+    assert!(has_function(&module, "from_dep"));
+}
+
+#[test]
+fn include_lib_without_code_path() {
+    let config = settings("include_lib/src/uses_dep.erl", &[]);
+    let mut compiler = Compiler::new(config).unwrap();
+    let files = compiler.find_sources().unwrap();
+
+    assert!(compiler.compile_files(files).1.is_err());
+}
+
+#[test]
+fn defined_values_are_terms() {
+    let config = settings("defines/term.erl", &["MODE=debug", "VSN=1"]);
+    let module = parse_module(config, "term");
+
+    assert!(has_function(&module, "debug"));
+    assert!(has_function(&module, "run"));
+}
+
+#[test]
+fn parse_define_with_and_without_value() {
+    let codemap = Arc::new(Mutex::new(CodeMap::new()));
+
+    assert!(parse_define("TEST", &codemap).is_ok());
+    assert!(parse_define("VSN=1.0", &codemap).is_ok());
+    assert!(parse_define("MODE=debug", &codemap).is_ok());
+    assert!(parse_define("=1", &codemap).is_err());
+    assert!(parse_define("VSN=", &codemap).is_err());
+}
+
+#[test]
+fn unchanged_modules_are_not_recompiled() {
+    let output_dir = std::env::temp_dir().join(format!("lumen_cache_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&output_dir);
+
+    let mut config = settings("defines/ifdef.erl", &["VSN=1"]);
+    config.output_dir = output_dir.clone();
+
+    let info = Compiler::new(config.clone()).unwrap().compile().unwrap();
+    assert_eq!(info.num_modules(), 1);
+    assert_eq!(info.num_cached(), 0);
+
+    let info = Compiler::new(config.clone()).unwrap().compile().unwrap();
+    assert_eq!(info.num_modules(), 0);
+    assert_eq!(info.num_cached(), 1);
+
+    // Changing the defines invalidates the cached module
+    config.defines = settings("defines/ifdef.erl", &["TEST", "VSN=1"]).defines;
+    let info = Compiler::new(config.clone()).unwrap().compile().unwrap();
+    assert_eq!(info.num_modules(), 1);
+
+    config.force = true;
+    let info = Compiler::new(config).unwrap().compile().unwrap();
+    assert_eq!(info.num_modules(), 1);
+    assert_eq!(info.num_cached(), 0);
+
+    std::fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[test]
+fn nowarn_compile_options_suppress_warnings() {
+    // The fun's argument shadows `X`
+    let body = "-export([f/1]).\nf(X) -> fun(X) -> X end.\n";
+    let sources = [
+        ("warn.erl", format!("-module(warn).\n{}", body)),
+        (
+            "nowarn_shadow.erl",
+            format!(
+                "-module(nowarn_shadow).\n-compile([nowarn_shadow_vars]).\n{}",
+                body
+            ),
+        ),
+        (
+            "nowarn_other.erl",
+            format!(
+                "-module(nowarn_other).\n-compile([nowarn_unused_vars]).\n{}",
+                body
+            ),
+        ),
+    ];
+    let output = compile_sources(&CompilerSettings::default(), &sources);
+    let num_warnings = |i: usize| {
+        output.diagnostics[i]
+            .1
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+            .count()
+    };
+
+    assert!(output.is_success());
+    assert!(num_warnings(0) > 0);
+    assert_eq!(num_warnings(1), 0);
+    assert_eq!(num_warnings(2), num_warnings(0));
+}
+
+#[test]
+fn compile_abstract_code_from_beam() {
+    let mut config = settings("../../../liblumen_beam/tests/testdata/ast/test.beam", &[]);
+    config.mode = CompilerMode::Beam;
+    let module = parse_module(config, "test");
+
+    for name in &["literals", "hello", "map_fun", "guard", "sum", "op"] {
+        assert!(has_function(&module, name));
+    }
+}
+
+#[test]
+fn emit_writes_eir_for_each_stage() {
+    let output_dir = std::env::temp_dir().join(format!("lumen_emit_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&output_dir);
+
+    let mut config = settings("defines/ifdef.erl", &["VSN=1"]);
+    config.output_dir = output_dir.clone();
+    config.emit = vec![Emit::Eir, Emit::EirOpt, Emit::EirPasses];
+    Compiler::new(config).unwrap().compile().unwrap();
+
+    assert!(output_dir.join("ifdef.eir").is_file());
+    assert!(output_dir.join("ifdef.opt.eir").is_file());
+    assert!(output_dir.join("ifdef.01.simplify_cfg.eir").is_file());
+
+    std::fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[test]
+fn compile_sources_from_memory() {
+    let sources = [
+        (
+            "good.erl",
+            "-module(good).\n-export([run/0]).\nrun() -> ok.\n",
+        ),
+        ("bad.erl", "-module(bad).\nrun() -> .\n"),
+    ];
+    let output = compile_sources(&CompilerSettings::default(), &sources);
+
+    assert!(!output.is_success());
+    assert_eq!(output.modules.len(), 1);
+    assert!(has_function(&output.modules[0], "run"));
+    assert!(output.num_errors > 0);
+
+    let (name, diagnostics) = &output.diagnostics[1];
+    assert_eq!(name, "bad.erl");
+    assert!(!diagnostics.is_empty());
+}
+
+#[test]
+fn compile_application_layout() {
+    let output_dir = std::env::temp_dir().join(format!("lumen_app_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&output_dir);
+
+    let mut config = settings("app/myapp", &[]);
+    config.include_path.clear();
+    config.output_dir = output_dir.clone();
+    let info = Compiler::new(config).unwrap().compile().unwrap();
+    assert_eq!(info.num_modules(), 2);
+
+    let app = std::fs::read_to_string(output_dir.join("myapp.app")).unwrap();
+    assert!(app.contains("{modules, [myapp, myapp_util]}"));
+    assert!(app.contains("{vsn, \"0.1.0\"}"));
+
+    std::fs::remove_dir_all(&output_dir).unwrap();
+}
@@ -6,7 +6,10 @@ use std::sync::{Arc, Mutex};
 
 use failure::{format_err, Error};
 
-use libeir_diagnostics::{CodeMap, ColorChoice};
+use libeir_diagnostics::{CodeMap, ColorChoice, FileName};
+use libeir_intern::Symbol;
+use libeir_syntax_erl::lexer::{FileMapSource, Lexer, LexicalToken, Scanner};
+use libeir_syntax_erl::preprocessor::MacroDef;
 use libeir_syntax_erl::ParseConfig;
 
 /// Determines which type of compilation to perform,
@@ -14,6 +17,7 @@ use libeir_syntax_erl::ParseConfig;
 /// parsing modules from Erlang source code.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
 pub enum CompilerMode {
+    Beam,
     Erlang,
 }
 impl FromStr for CompilerMode {
@@ -21,12 +25,46 @@ impl FromStr for CompilerMode {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
+            "beam" => Ok(CompilerMode::Beam),
             "erl" => Ok(CompilerMode::Erlang),
             _ => Err(format_err!("invalid file type {}", s)),
         }
     }
 }
 
+/// Determines which textual EIR artifacts are written for each module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Emit {
+    /// The EIR produced by lowering, before any passes are run
+    Eir,
+    /// The EIR after all passes have run
+    EirOpt,
+    /// The EIR after each individual pass
+    EirPasses,
+}
+impl Emit {
+    /// The file extension of the artifact, for kinds which produce a single file
+    pub fn extension(&self) -> Option<&'static str> {
+        match *self {
+            Emit::Eir => Some("eir"),
+            Emit::EirOpt => Some("opt.eir"),
+            Emit::EirPasses => None,
+        }
+    }
+}
+impl FromStr for Emit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eir" => Ok(Emit::Eir),
+            "eir-opt" => Ok(Emit::EirOpt),
+            "eir-passes" => Ok(Emit::EirPasses),
+            _ => Err(format_err!("invalid emit type {}", s)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Verbosity {
     Debug,
@@ -58,14 +96,35 @@ pub struct CompilerSettings {
     pub color: ColorChoice,
     pub source_dir: PathBuf,
     pub output_dir: PathBuf,
-    //pub defines: HashMap<Symbol, MacroDef>,
+    pub emit: Vec<Emit>,
+    pub defines: HashMap<Symbol, MacroDef>,
     pub warnings_as_errors: bool,
     pub no_warn: bool,
+    pub force: bool,
     pub verbosity: Verbosity,
     pub code_path: Vec<PathBuf>,
     pub include_path: VecDeque<PathBuf>,
     pub codemap: Arc<Mutex<CodeMap>>,
 }
+impl Default for CompilerSettings {
+    fn default() -> Self {
+        CompilerSettings {
+            mode: CompilerMode::Erlang,
+            color: ColorChoice::Auto,
+            source_dir: PathBuf::new(),
+            output_dir: PathBuf::new(),
+            emit: vec![Emit::EirOpt],
+            defines: HashMap::new(),
+            warnings_as_errors: false,
+            no_warn: false,
+            force: false,
+            verbosity: Verbosity::Warning,
+            code_path: Vec::new(),
+            include_path: VecDeque::new(),
+            codemap: Arc::new(Mutex::new(CodeMap::new())),
+        }
+    }
+}
 impl Into<ParseConfig> for CompilerSettings {
     fn into(self) -> ParseConfig {
         ParseConfig {
@@ -74,7 +133,40 @@ impl Into<ParseConfig> for CompilerSettings {
             no_warn: self.no_warn,
             code_paths: self.code_path.clone().into(),
             include_paths: self.include_path.clone(),
-            macros: None,
+            macros: Some(self.defines.clone()),
         }
     }
 }
+
+/// Parses a macro definition given on the command line, i.e. `NAME` or `NAME=VALUE`.
+///
+/// A macro without a value is defined as `true`, as is done by `erlc`. The value
+/// is tokenized as Erlang, so `-DVSN=1` defines an integer and `-DMODE=debug` an
+/// atom; it is added to the given codemap so diagnostics can refer to it.
+pub fn parse_define(
+    define: &str,
+    codemap: &Arc<Mutex<CodeMap>>,
+) -> Result<(Symbol, MacroDef), Error> {
+    let mut parts = define.splitn(2, '=');
+    let name = match parts.next() {
+        Some(name) if !name.is_empty() => Symbol::intern(name),
+        _ => return Err(format_err!("invalid macro definition '{}'", define)),
+    };
+    let def = match parts.next() {
+        None => MacroDef::Boolean(true),
+        Some(value) => {
+            let filemap = codemap.lock().unwrap().add_filemap(
+                FileName::Virtual(format!("-D{}", define).into()),
+                value.to_string(),
+            );
+            let tokens = Lexer::new(Scanner::new(FileMapSource::new(filemap)))
+                .collect::<Result<Vec<LexicalToken>, _>>()
+                .map_err(|_| format_err!("invalid value in macro definition '{}'", define))?;
+            if tokens.is_empty() {
+                return Err(format_err!("invalid macro definition '{}'", define));
+            }
+            MacroDef::Dynamic(tokens)
+        }
+    };
+    Ok((name, def))
+}
@@ -1,5 +1,10 @@
+mod app;
+mod cache;
 mod compiler;
 mod config;
 mod errors;
+mod timing;
+mod warnings;
 
 pub use self::compiler::*;
+pub use self::timing::PhaseTimings;
@@ -0,0 +1,133 @@
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// The time spent in each phase of compiling a single module, in the order
+/// the phases ran
+#[derive(Debug, Default, Clone)]
+pub struct PhaseTimings {
+    phases: Vec<(&'static str, Duration)>,
+}
+impl PhaseTimings {
+    /// Runs `f`, recording the time it took under the given phase name
+    pub fn time<T, F: FnOnce() -> T>(&mut self, phase: &'static str, f: F) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.phases.push((phase, start.elapsed()));
+        result
+    }
+
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, time)| *time).sum()
+    }
+}
+
+/// Renders the phase timings of a set of modules as a table, with one row per
+/// module and one column per phase, e.g.:
+///
+/// ```text
+/// module     parse   lower   simplify_cfg   total
+/// foo       1.20ms  0.31ms         0.05ms  1.56ms
+/// ```
+///
+/// Modules are sorted by total time, slowest first. Modules which did not
+/// reach a phase have no entry in that column.
+pub fn format_table(rows: &[(String, PhaseTimings)]) -> String {
+    // The columns are those of the module which got furthest through compilation
+    let phases = rows
+        .iter()
+        .map(|(_, timings)| timings.phases())
+        .max_by_key(|phases| phases.len())
+        .unwrap_or(&[]);
+
+    let mut rows = rows.iter().collect::<Vec<_>>();
+    rows.sort_by(|(_, a), (_, b)| b.total().cmp(&a.total()));
+
+    let cells = rows
+        .iter()
+        .map(|(module, timings)| {
+            let mut cells = vec![module.clone()];
+            for i in 0..phases.len() {
+                cells.push(
+                    timings
+                        .phases()
+                        .get(i)
+                        .map(|(_, time)| format_duration(*time))
+                        .unwrap_or_default(),
+                );
+            }
+            cells.push(format_duration(timings.total()));
+            cells
+        })
+        .collect::<Vec<_>>();
+
+    let mut header = vec!["module"];
+    header.extend(phases.iter().map(|(phase, _)| *phase));
+    header.push("total");
+
+    let widths = (0..header.len())
+        .map(|i| {
+            cells
+                .iter()
+                .map(|row| row[i].len())
+                .chain(Some(header[i].len()))
+                .max()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let mut table = String::new();
+    let lines = Some(header.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        .into_iter()
+        .chain(cells.into_iter());
+    for line in lines {
+        for (i, cell) in line.iter().enumerate() {
+            if i == 0 {
+                write!(table, "{:<width$}", cell, width = widths[i]).unwrap();
+            } else {
+                write!(table, "  {:>width$}", cell, width = widths[i]).unwrap();
+            }
+        }
+        writeln!(table).unwrap();
+    }
+    table
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn timings(phases: &[(&'static str, u64)]) -> PhaseTimings {
+        PhaseTimings {
+            phases: phases
+                .iter()
+                .map(|(phase, ms)| (*phase, Duration::from_millis(*ms)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn format_table_sorts_slowest_first() {
+        let rows = vec![
+            ("fast".to_string(), timings(&[("parse", 1)])),
+            ("slow".to_string(), timings(&[("parse", 2), ("lower", 10)])),
+        ];
+        let table = format_table(&rows);
+        let lines = table.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("module"));
+        assert!(lines[0].contains("parse") && lines[0].contains("lower"));
+        assert!(lines[1].starts_with("slow") && lines[1].ends_with("12.00ms"));
+        assert!(lines[2].starts_with("fast") && lines[2].ends_with("1.00ms"));
+    }
+}
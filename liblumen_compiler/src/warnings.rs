@@ -0,0 +1,77 @@
+use libeir_diagnostics::{Diagnostic, Severity};
+use libeir_syntax_erl::ast::Module;
+use libeir_syntax_erl::LowerError;
+
+/// The kinds of warning produced when lowering which a module can suppress
+/// with a `nowarn_*` compile option
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    /// `nowarn_unused_vars`
+    UnusedVars,
+    /// `nowarn_shadow_vars`
+    ShadowVars,
+}
+impl WarningKind {
+    /// Returns the kind of warning reported by the given message, if it is one
+    /// which can be suppressed
+    pub fn of(message: &LowerError) -> Option<Self> {
+        match message {
+            LowerError::UnusedVariable { .. } => Some(WarningKind::UnusedVars),
+            LowerError::ShadowingBind { .. } => Some(WarningKind::ShadowVars),
+            _ => None,
+        }
+    }
+}
+
+/// The set of warnings suppressed by a module via `-compile(nowarn_...)`
+///
+/// None of the warnings reported when lowering concern a single function, so
+/// options which name functions, e.g. `{nowarn_unused_function, [{f, 1}]}`,
+/// suppress nothing here.
+#[derive(Debug, Default, Clone)]
+pub struct Suppressions {
+    kinds: Vec<WarningKind>,
+}
+impl Suppressions {
+    /// Collects the `nowarn_*` options from the `-compile` attributes of the
+    /// parsed module
+    pub fn from_module(module: &Module) -> Self {
+        let mut kinds = Vec::new();
+        if let Some(ref options) = module.compile {
+            if options.no_warn_unused_vars {
+                kinds.push(WarningKind::UnusedVars);
+            }
+            if options.no_warn_shadow_vars {
+                kinds.push(WarningKind::ShadowVars);
+            }
+        }
+        Suppressions { kinds }
+    }
+
+    /// Returns true if the given message is a warning suppressed by this module
+    pub fn is_suppressed(&self, message: &LowerError, diagnostic: &Diagnostic) -> bool {
+        if diagnostic.severity != Severity::Warning {
+            return false;
+        }
+        match WarningKind::of(message) {
+            Some(kind) => self.kinds.contains(&kind),
+            None => false,
+        }
+    }
+}
+
+/// Counts of the diagnostics produced during compilation, by severity
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiagnosticCounts {
+    pub errors: usize,
+    pub warnings: usize,
+}
+impl DiagnosticCounts {
+    pub fn count(&mut self, diagnostic: &Diagnostic) {
+        match diagnostic.severity {
+            Severity::Bug | Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+            _ => (),
+        }
+    }
+}
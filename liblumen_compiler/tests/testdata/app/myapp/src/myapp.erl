@@ -0,0 +1,7 @@
+-module(myapp).
+
+-include("myapp.hrl").
+
+-export([greet/0]).
+
+greet() -> ?GREETING.
@@ -0,0 +1,17 @@
+-module(ifdef).
+
+-export([run/0]).
+
+-ifdef(TEST).
+-export([test_only/0]).
+
+test_only() -> ok.
+-endif.
+
+-ifndef(TEST).
+-export([release_only/0]).
+
+release_only() -> ok.
+-endif.
+
+run() -> ?VSN.
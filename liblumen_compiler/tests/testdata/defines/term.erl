@@ -0,0 +1,12 @@
+-module(term).
+
+%% Only valid Erlang if MODE is defined as an atom and VSN as an integer
+-export([run/?VSN]).
+
+-ifdef(MODE).
+-export([?MODE/0]).
+
+?MODE() -> ok.
+-endif.
+
+run(_) -> ?VSN.
@@ -0,0 +1,13 @@
+-module(uses_dep).
+
+-include_lib("dep/include/dep.hrl").
+
+-export([run/0]).
+
+-ifdef(DEP_INCLUDED).
+-export([from_dep/0]).
+
+from_dep() -> ok.
+-endif.
+
+run() -> ok.
@@ -1,4 +1,8 @@
+use std::convert::TryInto;
+use std::fs::File;
 use std::path::Path;
+use std::process;
+use std::sync::Arc;
 
 use clap::{App, Arg};
 
@@ -12,9 +16,13 @@ use libeir_syntax_erl::ast::Module as ErlAstModule;
 use libeir_syntax_erl::lower_module;
 use libeir_syntax_erl::{Parse, ParseConfig, Parser};
 
-use liblumen_eir_interpreter::{call_erlang, VM};
+use liblumen_eir_interpreter::literal::parse_term;
+use liblumen_eir_interpreter::shell::Shell;
+use liblumen_eir_interpreter::trace::{Filter, TraceSink, WriteSink};
+use liblumen_eir_interpreter::{call_erlang, CallError, VM};
 
-use liblumen_alloc::erts::term::Atom;
+use liblumen_alloc::erts::exception::runtime;
+use liblumen_alloc::erts::term::{Atom, Boxed, Cons};
 
 use lumen_runtime::scheduler::Scheduler;
 
@@ -52,8 +60,8 @@ where
     res
 }
 
-fn main() {
-    let matches = App::new("Lumen Eir Interpreter CLI")
+fn app() -> App<'static, 'static> {
+    App::new("Lumen Eir Interpreter CLI")
         .version("alpha")
         .arg(
             Arg::from_usage("<LOAD_ERL_FILES> 'load files into the interpreter'")
@@ -62,22 +70,72 @@ fn main() {
         )
         .arg(
             Arg::from_usage("<FUN_IDENT> -i,--ident <IDENT> 'select single function'")
-                .required(true),
+                .required_unless("shell"),
+        )
+        .arg(Arg::from_usage(
+            "--shell 'start an interactive shell instead of running a function'",
+        ))
+        .arg(
+            Arg::from_usage(
+                "-a, --arg [TERM]... 'pass an Erlang term literal as the next argument'",
+            )
+            .number_of_values(1)
+            // Negative numbers are terms, not flags
+            .allow_hyphen_values(true),
         )
-        .get_matches();
+        .arg(Arg::from_usage(
+            "--trace 'trace calls, returns and exceptions of all processes to stderr'",
+        ))
+        .arg(
+            Arg::from_usage(
+                "--trace-filter [FILTER]... 'only trace functions matching MODULE[:FUNCTION[/ARITY]]'",
+            )
+            .number_of_values(1),
+        )
+        .arg(Arg::from_usage(
+            "--trace-blocks 'also trace the blocks executed in traced functions'",
+        ))
+        .arg(Arg::from_usage(
+            "--trace-file [PATH] 'write the trace to a file instead of stderr'",
+        ))
+}
 
-    let ident = FunctionIdent::parse(matches.value_of("FUN_IDENT").unwrap()).unwrap();
+fn main() {
+    let matches = app().get_matches();
+
+    if matches.is_present("trace")
+        || matches.is_present("trace-filter")
+        || matches.is_present("trace-file")
+    {
+        let sink: Arc<dyn TraceSink> = match matches.value_of("trace-file") {
+            Some(path) => match File::create(path) {
+                Ok(file) => Arc::new(WriteSink::new(file)),
+                Err(error) => {
+                    eprintln!("cannot create trace file `{}`: {}", path, error);
+                    process::exit(2);
+                }
+            },
+            None => Arc::new(WriteSink::new(std::io::stderr())),
+        };
+
+        let mut tracer = VM.tracer.write().unwrap();
+        tracer.set_sink(Some(sink));
+        tracer.trace_all_processes(true);
+        tracer.trace_blocks(matches.is_present("trace-blocks"));
+        for spec in matches.values_of("trace-filter").into_iter().flatten() {
+            match Filter::parse(spec) {
+                Ok(filter) => tracer.add_filter(filter),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    process::exit(2);
+                }
+            }
+        }
+    }
 
     &*VM;
 
-    let arc_scheduler = Scheduler::current();
-    let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
-
-    let module = Atom::try_from_str(&ident.module.as_str()).unwrap();
-    let function = Atom::try_from_str(&ident.name.as_str()).unwrap();
-    assert!(ident.arity == 0);
-
-    for file in matches.values_of("LOAD_ERL_FILES").unwrap() {
+    for file in matches.values_of("LOAD_ERL_FILES").into_iter().flatten() {
         let config = ParseConfig::default();
         let mut eir_mod = lower_file(file, config).unwrap();
 
@@ -88,11 +146,89 @@ fn main() {
         let mut pass_manager = PassManager::default();
         pass_manager.run(&mut eir_mod);
 
-        VM.modules.write().unwrap().register_erlang_module(eir_mod);
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
     }
 
-    //let int = init_arc_process.integer(5).unwrap();
-    call_erlang(init_arc_process, module, function, &[])
-        .ok()
-        .unwrap();
+    if matches.is_present("shell") {
+        Shell::new().run();
+        return;
+    }
+
+    let ident = FunctionIdent::parse(matches.value_of("FUN_IDENT").unwrap()).unwrap();
+
+    let arc_scheduler = Scheduler::current();
+    let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+    let module = Atom::try_from_str(&ident.module.as_str()).unwrap();
+    let function = Atom::try_from_str(&ident.name.as_str()).unwrap();
+
+    let mut args = Vec::new();
+    for arg in matches.values_of("arg").into_iter().flatten() {
+        match parse_term(&init_arc_process, arg) {
+            Ok(term) => args.push(term),
+            Err(error) => {
+                eprintln!("invalid argument `{}`: {}", arg, error);
+                process::exit(2);
+            }
+        }
+    }
+    if args.len() != ident.arity {
+        eprintln!(
+            "{} takes {} arguments, but {} were given",
+            ident,
+            ident.arity,
+            args.len()
+        );
+        process::exit(2);
+    }
+
+    match call_erlang(init_arc_process, module, function, &args) {
+        Ok(value) => println!("{}", value),
+        Err(CallError::Exception(exception)) => {
+            print_exception(&exception);
+            process::exit(1);
+        }
+        Err(deadlock @ CallError::Deadlock { .. }) => {
+            eprintln!("** {}", deadlock);
+            process::exit(1);
+        }
+    }
+}
+
+fn print_exception(exception: &runtime::Exception) {
+    let class = match exception.class {
+        runtime::Class::Error { .. } => "error",
+        runtime::Class::Exit => "exit",
+        runtime::Class::Throw => "throw",
+    };
+    eprintln!("** exception {}: {}", class, exception.reason);
+
+    let stacktrace: Option<Boxed<Cons>> = exception
+        .stacktrace
+        .and_then(|stacktrace| stacktrace.try_into().ok());
+    if let Some(stacktrace) = stacktrace {
+        for entry in stacktrace.into_iter().flatten() {
+            eprintln!("     in {}", entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::app;
+
+    #[test]
+    fn negative_arguments_are_values() {
+        let matches = app()
+            .get_matches_from_safe(vec!["run_file", "-i", "m:f/2", "--arg", "-1", "-a", "-2.5"])
+            .unwrap();
+        assert_eq!(
+            vec!["-1", "-2.5"],
+            matches.values_of("arg").unwrap().collect::<Vec<_>>()
+        );
+    }
 }
@@ -4,41 +4,95 @@ use std::sync::Arc;
 use cranelift_entity::EntityRef;
 use libeir_ir::Block;
 
+use liblumen_alloc::erts::exception::runtime::Class;
+use liblumen_alloc::erts::exception::system::Alloc;
 use liblumen_alloc::erts::process::code::stack::frame::Frame;
-use liblumen_alloc::erts::process::code::Result;
+use liblumen_alloc::erts::process::code::{result_from_exception, Result};
 use liblumen_alloc::erts::process::Process;
-use liblumen_alloc::erts::term::{Atom, Term, TypedTerm};
+use liblumen_alloc::erts::term::{atom_unchecked, Atom, Term, TypedTerm};
 use liblumen_alloc::erts::ModuleFunctionArity;
+use liblumen_alloc::raise;
 
 use crate::exec::CallExecutor;
 
+/// The throw continuation at the bottom of a process' call chain.
+///
+/// Expects the `[Class, Reason, Stacktrace]` argument list on the stack, and
+/// exits the process with the corresponding exception. As in BEAM, an uncaught
+/// throw becomes an error with the reason `{nocatch, Reason}`.
 pub fn return_throw(arc_process: &Arc<Process>) -> Result {
     let argument_list = arc_process.stack_pop().unwrap();
+    let arguments = list_to_vec(argument_list);
+    assert!(arguments.len() == 3);
+
+    let class: Class = arguments[0].try_into().unwrap();
+    let (class, reason) = match class {
+        Class::Throw => {
+            let nocatch = atom_unchecked("nocatch");
+            let reason = arc_process.tuple_from_slice(&[nocatch, arguments[1]])?;
+            (Class::Error { arguments: None }, reason)
+        }
+        class => (class, arguments[1]),
+    };
 
-    panic!("{:?}", argument_list);
-
-    //let class: Atom = class_term.try_into().unwrap();
-    //let class = match class.name() {
-    //    "EXIT" => Class::Exit,
-    //    k => unreachable!("{:?}", k),
-    //};
-
-    //let exc = Exception {
-    //    class,
-    //    reason: reason_term,
-    //    stacktrace: Some(trace_term),
-    //    file: "",
-    //    line: 0,
-    //    column: 0,
-    //};
-    //result_from_exception(arc_process, exc.into())
+    let exception = raise!(class, reason, Some(arguments[2]));
+    result_from_exception(arc_process, exception.into())
 }
 
-pub fn return_ok(arc_process: &Arc<Process>) -> Result {
-    let argument_list = arc_process.stack_pop().unwrap();
+/// Converts an exception class to the atom used for it by `catch` clauses
+pub fn class_term(class: &Class) -> Term {
+    match class {
+        Class::Error { .. } => atom_unchecked("error"),
+        Class::Exit => atom_unchecked("exit"),
+        Class::Throw => atom_unchecked("throw"),
+    }
+}
 
-    println!("PROCESS EXIT NORMAL WITH: {:?}", argument_list);
+/// Builds an Erlang stacktrace, i.e. a list of `{Module, Function, Arity, Location}`
+/// tuples, from the frames on the process' code stack, skipping the top `skip` frames.
+///
+/// Frames belonging to the interpreter's own continuations are left out.
+pub fn stacktrace(process: &Process, skip: usize) -> std::result::Result<Term, Alloc> {
+    let intrinsics = Atom::try_from_str("lumen_eir_interpreter_intrinsics").unwrap();
+
+    let mut entries = Vec::new();
+    for mfa in process.stacktrace().iter().skip(skip) {
+        if mfa.module == intrinsics {
+            continue;
+        }
+        let module = atom_unchecked(mfa.module.name());
+        let function = atom_unchecked(mfa.function.name());
+        let arity = process.integer(mfa.arity)?;
+        entries.push(process.tuple_from_slice(&[module, function, arity, Term::NIL])?);
+    }
 
+    process.list_from_slice(&entries)
+}
+
+fn list_to_vec(list: Term) -> Vec<Term> {
+    let mut vec = Vec::new();
+    match list.to_typed_term().unwrap() {
+        TypedTerm::Nil => (),
+        TypedTerm::List(cons) => {
+            for result in cons.into_iter() {
+                vec.push(result.unwrap());
+            }
+        }
+        _ => panic!(),
+    }
+    vec
+}
+
+/// The return continuation at the bottom of the call chain started by
+/// `call_erlang`.
+///
+/// Hands the returned value back to `call_erlang`, which takes it after the
+/// process has exited.
+pub fn return_ok(arc_process: &Arc<Process>) -> Result {
+    let argument_list = arc_process.stack_pop().unwrap();
+    if let [value] = list_to_vec(argument_list).as_slice() {
+        crate::VM.reply(arc_process, *value);
+    }
     arc_process.return_from_call(argument_list)?;
     Process::call_code(arc_process)
 }
@@ -87,11 +141,13 @@ pub fn interpreter_mfa_code(arc_process: &Arc<Process>) -> Result {
 /// * arity integer
 /// * argument list
 /// * block id integer
+/// * module version integer
 /// * environment list
 pub fn interpreter_closure_code(arc_process: &Arc<Process>) -> Result {
     let arity_term = arc_process.stack_pop().unwrap();
     let argument_list = arc_process.stack_pop().unwrap();
     let block_id_term = arc_process.stack_pop().unwrap();
+    let version_term = arc_process.stack_pop().unwrap();
     let environment_list = arc_process.stack_pop().unwrap();
 
     let mfa = arc_process.current_module_function_arity().unwrap();
@@ -99,6 +155,7 @@ pub fn interpreter_closure_code(arc_process: &Arc<Process>) -> Result {
     let arity: usize = arity_term.try_into().unwrap();
 
     let block_id: usize = block_id_term.try_into().unwrap();
+    let version: usize = version_term.try_into().unwrap();
     let block = Block::new(block_id);
 
     let mut argument_vec: Vec<Term> = Vec::new();
@@ -134,6 +191,7 @@ pub fn interpreter_closure_code(arc_process: &Arc<Process>) -> Result {
         mfa.module,
         mfa.function,
         arity,
+        version,
         &argument_vec,
         block,
         &environment_vec,
@@ -0,0 +1,499 @@
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use num_bigint::{BigInt, Sign};
+use num_traits::{One, ToPrimitive, Zero};
+
+use libeir_ir::{BinaryEntrySpecifier, Block, Endianness};
+
+use liblumen_alloc::erts::exception::system;
+use liblumen_alloc::erts::process::Process;
+use liblumen_alloc::erts::term::binary::aligned_binary::AlignedBinary;
+use liblumen_alloc::erts::term::binary::maybe_aligned_maybe_binary::MaybeAlignedMaybeBinary;
+use liblumen_alloc::erts::term::binary::{Bitstring, IterableBitstring, MaybePartialByte};
+use liblumen_alloc::erts::term::{MatchContext, SubBinary, Term, TypedTerm};
+
+use super::{CallExecutor, OpResult};
+use crate::module::ErlangFunction;
+
+/// A growable sequence of bits, stored most significant bit first
+#[derive(Debug, Default, Clone)]
+pub struct Bits {
+    bytes: Vec<u8>,
+    bit_len: usize,
+}
+impl Bits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Bits {
+            bytes: bytes.to_vec(),
+            bit_len: bytes.len() * 8,
+        }
+    }
+
+    /// Reads the bits of a binary or bitstring term, returning `None` if the
+    /// term is not a bitstring
+    pub fn from_term(term: Term) -> Option<Self> {
+        match term.to_typed_term().unwrap() {
+            TypedTerm::Boxed(boxed) => match boxed.to_typed_term().unwrap() {
+                TypedTerm::HeapBinary(heap_binary) => {
+                    Some(Self::from_bytes(heap_binary.as_bytes()))
+                }
+                TypedTerm::ProcBin(proc_bin) => Some(Self::from_bytes(proc_bin.as_bytes())),
+                TypedTerm::SubBinary(subbinary) => Some(Self::from_subbinary(&subbinary)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn from_subbinary(subbinary: &SubBinary) -> Self {
+        let mut bits = Self::new();
+        for byte in subbinary.full_byte_iter() {
+            bits.push_bits(byte as u64, 8);
+        }
+        for bit in subbinary.partial_byte_bit_iter() {
+            bits.push_bit(bit != 0);
+        }
+        bits
+    }
+
+    pub fn bit_len(&self) -> usize {
+        self.bit_len
+    }
+
+    pub fn bit(&self, index: usize) -> bool {
+        (self.bytes[index / 8] >> (7 - (index % 8))) & 1 == 1
+    }
+
+    pub fn push_bit(&mut self, bit: bool) {
+        if self.bit_len % 8 == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            let last = self.bytes.len() - 1;
+            self.bytes[last] |= 1 << (7 - (self.bit_len % 8));
+        }
+        self.bit_len += 1;
+    }
+
+    /// Pushes the low `count` bits of `value`, most significant first
+    pub fn push_bits(&mut self, value: u64, count: usize) {
+        for i in (0..count).rev() {
+            self.push_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    /// Pushes `count` bits of `other`, starting at bit `start`
+    pub fn push_slice(&mut self, other: &Bits, start: usize, count: usize) {
+        for i in start..(start + count) {
+            self.push_bit(other.bit(i));
+        }
+    }
+
+    /// Pushes an integer as a `size` bit two's complement value
+    pub fn push_integer(&mut self, value: &BigInt, size: usize, endianness: &Endianness) {
+        let modulus = BigInt::one() << size;
+        let value = ((value % &modulus) + &modulus) % &modulus;
+        let (_, mut bytes) = value.to_bytes_le();
+        bytes.resize((size + 7) / 8, 0);
+
+        if is_little(endianness) {
+            // The least significant byte comes first, with the remaining
+            // high-order bits in a final partial byte
+            for byte in bytes.iter().take(size / 8) {
+                self.push_bits(*byte as u64, 8);
+            }
+            if size % 8 != 0 {
+                self.push_bits(bytes[size / 8] as u64, size % 8);
+            }
+        } else {
+            for i in (0..size).rev() {
+                self.push_bit((bytes[i / 8] >> (i % 8)) & 1 == 1);
+            }
+        }
+    }
+
+    /// Reads `size` bits starting at bit `start` as an integer
+    pub fn read_integer(
+        &self,
+        start: usize,
+        size: usize,
+        signed: bool,
+        endianness: &Endianness,
+    ) -> BigInt {
+        let mut bytes = vec![0u8; (size + 7) / 8];
+        if is_little(endianness) {
+            for i in 0..size {
+                let (byte, bit) = (i / 8, i % 8);
+                // Full bytes are stored most significant bit first, as is the
+                // final partial byte, which holds the high-order bits
+                let width = if byte == size / 8 { size % 8 } else { 8 };
+                if self.bit(start + i) {
+                    bytes[byte] |= 1 << (width - 1 - bit);
+                }
+            }
+        } else {
+            for i in 0..size {
+                if self.bit(start + i) {
+                    let n = size - 1 - i;
+                    bytes[n / 8] |= 1 << (n % 8);
+                }
+            }
+        }
+
+        let value = BigInt::from_bytes_le(Sign::Plus, &bytes);
+        if signed && size > 0 && value.bit(size as u64 - 1) {
+            value - (BigInt::one() << size)
+        } else {
+            value
+        }
+    }
+
+    /// Allocates the bits as a binary, or as a sub-binary of a binary if the
+    /// bit length is not a multiple of 8
+    pub fn to_term(&self, proc: &Arc<Process>) -> Result<Term, system::Exception> {
+        let binary = proc.binary_from_bytes(&self.bytes)?;
+        if self.bit_len % 8 == 0 {
+            Ok(binary)
+        } else {
+            Ok(proc.subbinary_from_original(
+                binary,
+                0,
+                0,
+                self.bit_len / 8,
+                (self.bit_len % 8) as u8,
+            )?)
+        }
+    }
+}
+
+// num-bigint 0.2 has no `BigInt::bit`
+trait Bit {
+    fn bit(&self, n: u64) -> bool;
+}
+impl Bit for BigInt {
+    fn bit(&self, n: u64) -> bool {
+        !((self >> n as usize) & BigInt::one()).is_zero()
+    }
+}
+
+fn is_little(endianness: &Endianness) -> bool {
+    match endianness {
+        Endianness::Big => false,
+        Endianness::Little => true,
+        Endianness::Native => cfg!(target_endian = "little"),
+    }
+}
+
+fn term_to_bigint(term: Term) -> Option<BigInt> {
+    match term.to_typed_term().unwrap() {
+        TypedTerm::SmallInteger(small) => {
+            let value: isize = small.into();
+            Some(value.into())
+        }
+        TypedTerm::Boxed(boxed) => match boxed.to_typed_term().unwrap() {
+            TypedTerm::BigInteger(big) => {
+                let value: &BigInt = big.as_ref().into();
+                Some(value.clone())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn term_to_f64(term: Term) -> Option<f64> {
+    match term.to_typed_term().unwrap() {
+        TypedTerm::Float(float) => Some(float.into()),
+        TypedTerm::Boxed(boxed) => match boxed.to_typed_term().unwrap() {
+            TypedTerm::Float(float) => Some(float.into()),
+            _ => None,
+        },
+        _ => term_to_bigint(term).and_then(|int| int.to_f64()),
+    }
+}
+
+fn term_to_usize(term: Term) -> Option<usize> {
+    term.try_into().ok()
+}
+
+/// Appends a single segment to a binary, i.e. one element of `<<...>>`.
+///
+/// Expects the reads `[ok, err, bin, value]` or `[ok, err, bin, value, size]`.
+/// On success `ok` is called with the new binary, otherwise `err` is called
+/// with no arguments.
+pub fn binary_push(
+    exec: &mut CallExecutor,
+    proc: &Arc<Process>,
+    fun: &ErlangFunction,
+    specifier: &BinaryEntrySpecifier,
+    block: Block,
+) -> Result<OpResult, system::Exception> {
+    let reads = fun.fun.block_reads(block);
+    assert!(reads.len() == 4 || reads.len() == 5);
+
+    let bin_term = exec.make_term(proc, fun, reads[2])?;
+    let val_term = exec.make_term(proc, fun, reads[3])?;
+    let size = match reads.get(4) {
+        None => None,
+        Some(read) => match term_to_usize(exec.make_term(proc, fun, *read)?) {
+            None => return exec.val_call(proc, fun, reads[1]),
+            size => size,
+        },
+    };
+
+    let mut bits = Bits::from_term(bin_term).expect("binary push onto a non-binary");
+    if push_segment(&mut bits, specifier, val_term, size).is_none() {
+        return exec.val_call(proc, fun, reads[1]);
+    }
+
+    exec.next_args.push(bits.to_term(proc)?);
+    exec.val_call(proc, fun, reads[0])
+}
+
+// Encodes `value` according to the specifier, returning `None` if the value
+// is not valid for the segment type (i.e. `badarg`)
+fn push_segment(
+    bits: &mut Bits,
+    specifier: &BinaryEntrySpecifier,
+    value: Term,
+    size: Option<usize>,
+) -> Option<()> {
+    match specifier {
+        BinaryEntrySpecifier::Integer {
+            unit, endianness, ..
+        } => {
+            let value = term_to_bigint(value)?;
+            let size = size.unwrap_or(8) * (*unit as usize);
+            bits.push_integer(&value, size, endianness);
+        }
+        BinaryEntrySpecifier::Float { unit, endianness } => {
+            let value = term_to_f64(value)?;
+            let size = size.unwrap_or(64) * (*unit as usize);
+            let raw = match size {
+                64 => BigInt::from(value.to_bits()),
+                32 => BigInt::from((value as f32).to_bits()),
+                _ => return None,
+            };
+            bits.push_integer(&raw, size, endianness);
+        }
+        BinaryEntrySpecifier::Bytes { unit } | BinaryEntrySpecifier::Bits { unit } => {
+            let value = Bits::from_term(value)?;
+            let size = match size {
+                Some(size) => size * (*unit as usize),
+                None => value.bit_len(),
+            };
+            if size > value.bit_len() {
+                return None;
+            }
+            if let BinaryEntrySpecifier::Bytes { .. } = specifier {
+                if size % 8 != 0 {
+                    return None;
+                }
+            }
+            bits.push_slice(&value, 0, size);
+        }
+        BinaryEntrySpecifier::Utf8 => {
+            let c = term_to_char(value)?;
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                bits.push_bits(byte as u64, 8);
+            }
+        }
+        BinaryEntrySpecifier::Utf16 { endianness } => {
+            let c = term_to_char(value)?;
+            let mut buf = [0; 2];
+            for unit in c.encode_utf16(&mut buf).iter() {
+                bits.push_integer(&BigInt::from(*unit), 16, endianness);
+            }
+        }
+        BinaryEntrySpecifier::Utf32 { endianness } => {
+            let c = term_to_char(value)?;
+            bits.push_integer(&BigInt::from(c as u32), 32, endianness);
+        }
+    }
+    Some(())
+}
+
+fn term_to_char(term: Term) -> Option<char> {
+    let codepoint: u32 = term_to_usize(term)?.try_into().ok()?;
+    std::char::from_u32(codepoint)
+}
+
+/// Attempts to match a single segment at the start of a binary, as in
+/// `<<Value:Size/Specifier, Rest/bits>>`.
+///
+/// The binary is matched through a `MatchContext`, and on success the matched
+/// value and the rest of the binary, as a `SubBinary`, are pushed as the
+/// arguments of the branch. Returns `false` if the segment does not match.
+pub fn binary_match(
+    exec: &mut CallExecutor,
+    proc: &Arc<Process>,
+    specifier: &BinaryEntrySpecifier,
+    bin_term: Term,
+    size: Option<Term>,
+) -> Result<bool, system::Exception> {
+    let bits = match Bits::from_term(bin_term) {
+        None => return Ok(false),
+        Some(bits) => bits,
+    };
+    let size = match size {
+        None => None,
+        Some(term) => match term_to_usize(term) {
+            None => return Ok(false),
+            size => size,
+        },
+    };
+
+    let (value, len) = match read_segment(proc, &bits, specifier, size)? {
+        None => return Ok(false),
+        Some(result) => result,
+    };
+
+    // Skip past the matched segment, leaving the rest of the binary
+    let mut ctx = MatchContext::new(bin_term);
+    SubBinary::from_match(&mut ctx, len);
+    let rest = SubBinary::from_match(&mut ctx, bits.bit_len() - len);
+    let rest = proc.subbinary_from_original(
+        rest.original(),
+        rest.byte_offset(),
+        rest.bit_offset(),
+        rest.full_byte_len(),
+        rest.partial_byte_bit_len(),
+    )?;
+
+    exec.next_args.push(value);
+    exec.next_args.push(rest);
+    Ok(true)
+}
+
+// Decodes a segment from the start of `bits`, returning the value and the
+// number of bits it occupied, or `None` if it does not match
+fn read_segment(
+    proc: &Arc<Process>,
+    bits: &Bits,
+    specifier: &BinaryEntrySpecifier,
+    size: Option<usize>,
+) -> Result<Option<(Term, usize)>, system::Exception> {
+    let available = bits.bit_len();
+    match specifier {
+        BinaryEntrySpecifier::Integer {
+            signed,
+            unit,
+            endianness,
+        } => {
+            let size = size.unwrap_or(8) * (*unit as usize);
+            if size > available {
+                return Ok(None);
+            }
+            let value = bits.read_integer(0, size, *signed, endianness);
+            Ok(Some((proc.integer(value)?, size)))
+        }
+        BinaryEntrySpecifier::Float { unit, endianness } => {
+            // Only 32 and 64 bit floats can match, so other sizes, such as
+            // `<<F:128/float>>`, fail before anything is read
+            let size = match size.unwrap_or(64).checked_mul(*unit as usize) {
+                Some(size @ 32) | Some(size @ 64) if size <= available => size,
+                _ => return Ok(None),
+            };
+            let raw = bits.read_integer(0, size, false, endianness);
+            let raw = raw.to_u64().unwrap();
+            let value = match size {
+                64 => f64::from_bits(raw),
+                _ => f32::from_bits(raw as u32) as f64,
+            };
+            if !value.is_finite() {
+                return Ok(None);
+            }
+            Ok(Some((proc.float(value)?, size)))
+        }
+        BinaryEntrySpecifier::Bytes { unit } | BinaryEntrySpecifier::Bits { unit } => {
+            let size = match size {
+                Some(size) => size * (*unit as usize),
+                None => available,
+            };
+            if size > available {
+                return Ok(None);
+            }
+            if let BinaryEntrySpecifier::Bytes { .. } = specifier {
+                if size % 8 != 0 {
+                    return Ok(None);
+                }
+            }
+            let mut value = Bits::new();
+            value.push_slice(bits, 0, size);
+            Ok(Some((value.to_term(proc)?, size)))
+        }
+        BinaryEntrySpecifier::Utf8 => {
+            let bytes = (0..available / 8)
+                .take(4)
+                .map(|i| bits.read_integer(i * 8, 8, false, &Endianness::Big))
+                .map(|byte| byte.to_u8().unwrap())
+                .collect::<Vec<u8>>();
+            let len = match bytes.first() {
+                None => return Ok(None),
+                Some(b) if *b < 0x80 => 1,
+                Some(b) if *b >> 5 == 0b110 => 2,
+                Some(b) if *b >> 4 == 0b1110 => 3,
+                Some(b) if *b >> 3 == 0b11110 => 4,
+                Some(_) => return Ok(None),
+            };
+            match bytes.get(..len).map(std::str::from_utf8) {
+                Some(Ok(s)) => {
+                    let c = s.chars().next().unwrap();
+                    Ok(Some((proc.integer(c as u32 as usize)?, len * 8)))
+                }
+                _ => Ok(None),
+            }
+        }
+        BinaryEntrySpecifier::Utf16 { endianness } => {
+            if available < 16 {
+                return Ok(None);
+            }
+            let unit = |i: usize| {
+                let value = bits.read_integer(i * 16, 16, false, endianness);
+                value.to_u16().unwrap()
+            };
+            let first = unit(0);
+            let units = if (0xD800..0xDC00).contains(&first) {
+                if available < 32 {
+                    return Ok(None);
+                }
+                vec![first, unit(1)]
+            } else {
+                vec![first]
+            };
+            match std::char::decode_utf16(units.iter().cloned()).next() {
+                Some(Ok(c)) => Ok(Some((proc.integer(c as u32 as usize)?, units.len() * 16))),
+                _ => Ok(None),
+            }
+        }
+        BinaryEntrySpecifier::Utf32 { endianness } => {
+            if available < 32 {
+                return Ok(None);
+            }
+            let value = bits.read_integer(0, 32, false, endianness);
+            match value.to_u32().and_then(std::char::from_u32) {
+                Some(c) => Ok(Some((proc.integer(c as u32 as usize)?, 32))),
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+/// Creates the term for a constant binary
+pub fn binary_from_const(
+    proc: &Arc<Process>,
+    bytes: &[u8],
+    bit_len: usize,
+) -> Result<Term, system::Exception> {
+    let mut bits = Bits::new();
+    for i in 0..bit_len {
+        bits.push_bit((bytes[i / 8] >> (7 - (i % 8))) & 1 == 1);
+    }
+    bits.to_term(proc)
+}
@@ -1,12 +1,13 @@
+use std::convert::TryInto;
 use std::sync::Arc;
 
 use libeir_ir::{BasicType, Block, MatchKind, PrimOpKind};
 
 use liblumen_alloc::erts::exception::system;
 use liblumen_alloc::erts::process::Process;
-use liblumen_alloc::erts::term::TypedTerm;
+use liblumen_alloc::erts::term::{Boxed, Cons, Term, Tuple, TypedTerm};
 
-use super::{CallExecutor, OpResult};
+use super::{binary, CallExecutor, OpResult};
 use crate::module::ErlangFunction;
 
 pub fn match_op(
@@ -38,9 +39,28 @@ pub fn match_op(
                     return exec.val_call(proc, fun, *branch);
                 }
             }
-            MatchKind::Type(BasicType::Map) => {
+            MatchKind::Type(typ) => {
                 assert!(branch_args.len() == 0);
-                if unpack_term.is_map() {
+                if is_type(unpack_term, typ) {
+                    return exec.val_call(proc, fun, *branch);
+                }
+            }
+            MatchKind::Tuple(arity) => {
+                assert!(branch_args.len() == 0);
+                let tuple: Result<Boxed<Tuple>, _> = unpack_term.try_into();
+                if let Ok(tuple) = tuple {
+                    if tuple.len() == *arity {
+                        exec.next_args.extend(tuple.iter());
+                        return exec.val_call(proc, fun, *branch);
+                    }
+                }
+            }
+            MatchKind::ListCell => {
+                assert!(branch_args.len() == 0);
+                let cons: Result<Boxed<Cons>, _> = unpack_term.try_into();
+                if let Ok(cons) = cons {
+                    exec.next_args.push(cons.head);
+                    exec.next_args.push(cons.tail);
                     return exec.val_call(proc, fun, *branch);
                 }
             }
@@ -61,13 +81,39 @@ pub fn match_op(
                     _ => unreachable!(),
                 }
             }
+            MatchKind::Binary(specifier) => {
+                let size = match branch_args.get(0) {
+                    Some(size) => Some(exec.make_term(proc, fun, *size)?),
+                    None => None,
+                };
+                if binary::binary_match(exec, proc, specifier, unpack_term, size)? {
+                    return exec.val_call(proc, fun, *branch);
+                }
+            }
             MatchKind::Wildcard => {
                 assert!(branch_args.len() == 0);
                 return exec.val_call(proc, fun, *branch);
             }
-            kind => unimplemented!("{:?}", kind),
         }
     }
 
     panic!()
 }
+
+fn is_type(term: Term, typ: &BasicType) -> bool {
+    match typ {
+        BasicType::List => term.is_list(),
+        BasicType::ListCell => term.is_non_empty_list(),
+        BasicType::Nil => term.is_nil(),
+        BasicType::Tuple(arity) => {
+            let tuple: Result<Boxed<Tuple>, _> = term.try_into();
+            tuple.map(|tuple| tuple.len() == *arity).unwrap_or(false)
+        }
+        BasicType::Map => term.is_map(),
+        BasicType::Number => term.is_number(),
+        BasicType::Float => term.is_float(),
+        BasicType::Integer => term.is_integer(),
+        BasicType::SmallInteger => term.is_smallint(),
+        BasicType::BigInteger => term.is_bigint(),
+    }
+}
@@ -7,20 +7,26 @@ use libeir_intern::Symbol;
 use libeir_ir::constant::{AtomicTerm, Const, ConstKind};
 use libeir_ir::{Block, OpKind, PrimOpKind, Value, ValueKind};
 
-use liblumen_alloc::erts::exception::system;
+use liblumen_alloc::erts::exception::{runtime, system, Exception};
 use liblumen_alloc::erts::process::code::Result;
 use liblumen_alloc::erts::process::Process;
 use liblumen_alloc::erts::term::{atom_unchecked, Atom, Term, TypedTerm};
 use liblumen_alloc::erts::ModuleFunctionArity;
+use liblumen_alloc::undef;
 
 use crate::module::{ErlangFunction, NativeFunctionKind, ResolvedFunction};
+use crate::trace::Event;
 use crate::vm::VMState;
 
+mod binary;
 mod r#match;
+mod receive;
 
 pub struct CallExecutor {
     binds: HashMap<Value, Term>,
     next_args: Vec<Term>,
+    // The value called by the last operation, used to trace returns
+    continuation: Option<Value>,
 }
 
 pub enum OpResult {
@@ -34,6 +40,7 @@ impl CallExecutor {
         CallExecutor {
             binds: HashMap::new(),
             next_args: Vec::new(),
+            continuation: None,
         }
     }
 
@@ -47,13 +54,24 @@ impl CallExecutor {
         args: &[Term],
     ) -> Result {
         let modules = vm.modules.read().unwrap();
-        match modules.lookup_function(module, function, arity) {
-            None => self.fun_not_found(proc, args[1]),
-            Some(ResolvedFunction::Native(native)) => self.run_native(vm, proc, native, args),
+        vm.tracer
+            .read()
+            .unwrap()
+            .call(proc, module, function, arity, &args[2..]);
+        let native = match modules.lookup_function(module, function, arity) {
+            None => None,
+            Some(ResolvedFunction::Native(native)) => Some(native),
             Some(ResolvedFunction::Erlang(fun)) => {
                 let entry = fun.fun.block_entry();
-                self.run_erlang(vm, proc, fun, entry, args)
+                return self.run_erlang(vm, proc, fun, entry, args);
             }
+        };
+
+        // Natives may load or purge code, which needs the registry unlocked
+        std::mem::drop(modules);
+        match native {
+            None => self.fun_not_found(proc, module, function, args),
+            Some(native) => self.run_native(vm, proc, module, function, native, args),
         }
     }
 
@@ -64,16 +82,17 @@ impl CallExecutor {
         module: Atom,
         function: Atom,
         arity: usize,
+        version: usize,
         args: &[Term],
         block: Block,
         env: &[Term],
     ) -> Result {
+        // Continuations run in the version of the module that created them,
+        // even if it has since become old code
         let modules = vm.modules.read().unwrap();
-        println!("ARGS {:?}", args);
-        match modules.lookup_function(module, function, arity) {
-            None => self.fun_not_found(proc, args[1]),
-            Some(ResolvedFunction::Native(_ptr)) => unreachable!(),
-            Some(ResolvedFunction::Erlang(fun)) => {
+        match modules.lookup_erlang_function(module, function, arity, version) {
+            None => self.fun_not_found(proc, module, function, args),
+            Some(fun) => {
                 let live = &fun.live.live[&block];
                 assert!(live.size(&fun.live.pool) == env.len());
 
@@ -86,11 +105,42 @@ impl CallExecutor {
         }
     }
 
-    fn fun_not_found(&self, proc: &Arc<Process>, throw_cont: Term) -> Result {
-        let exit_atom = atom_unchecked("EXIT");
-        let undef_atom = atom_unchecked("undef");
-        let trace_atom = atom_unchecked("trace");
-        self.call_closure(proc, throw_cont, &[exit_atom, undef_atom, trace_atom])
+    fn fun_not_found(
+        &self,
+        proc: &Arc<Process>,
+        module: Atom,
+        function: Atom,
+        args: &[Term],
+    ) -> Result {
+        let arguments = proc.list_from_slice(&args[2..])?;
+        // The top frame is that of the missing function, which `undef!` adds itself
+        let stacktrace_tail = crate::code::stacktrace(proc, 1)?;
+        let exception = undef!(
+            proc,
+            atom_unchecked(module.name()),
+            atom_unchecked(function.name()),
+            arguments,
+            stacktrace_tail
+        );
+        self.raise(proc, args[1], exception)
+    }
+
+    /// Raises an exception in interpreted code by calling the given throw
+    /// continuation with `Class, Reason, Stacktrace`.
+    ///
+    /// System exceptions can't be caught, and are returned directly.
+    fn raise(&self, proc: &Arc<Process>, throw_cont: Term, exception: Exception) -> Result {
+        match exception {
+            Exception::Runtime(exception) => {
+                let class = crate::code::class_term(&exception.class);
+                let stacktrace = match exception.stacktrace {
+                    Some(stacktrace) => stacktrace,
+                    None => crate::code::stacktrace(proc, 0)?,
+                };
+                self.call_closure(proc, throw_cont, &[class, exception.reason, stacktrace])
+            }
+            Exception::System(exception) => Err(exception),
+        }
     }
 
     fn call_closure(&self, proc: &Arc<Process>, closure: Term, args: &[Term]) -> Result {
@@ -99,9 +149,12 @@ impl CallExecutor {
                 TypedTerm::Closure(closure) => {
                     //assert!(closure.env_hack.len() != 1);
                     if closure.env.len() > 0 {
-                        let env_list = proc.list_from_slice(&closure.env[1..]).unwrap();
+                        let env_list = proc.list_from_slice(&closure.env[2..]).unwrap();
                         proc.stack_push(env_list)?;
 
+                        let version = closure.env[1];
+                        proc.stack_push(version)?;
+
                         let block_id = closure.env[0];
                         proc.stack_push(block_id)?;
                     }
@@ -126,15 +179,36 @@ impl CallExecutor {
 
     fn run_native(
         &mut self,
-        _vm: &VMState,
+        vm: &VMState,
         proc: &Arc<Process>,
+        module: Atom,
+        function: Atom,
         native: NativeFunctionKind,
         args: &[Term],
     ) -> Result {
+        let arity = args.len() - 2;
         match native {
             NativeFunctionKind::Simple(ptr) => match ptr(proc, &args[2..]) {
-                Ok(ret) => self.call_closure(proc, args[0], &[ret]),
-                Err(()) => panic!(),
+                Ok(ret) => {
+                    vm.tracer
+                        .read()
+                        .unwrap()
+                        .ret(proc, module, function, arity, ret);
+                    self.call_closure(proc, args[0], &[ret])
+                }
+                Err(exception) => {
+                    if let Exception::Runtime(ref exception) = exception {
+                        vm.tracer.read().unwrap().exception(
+                            proc,
+                            module,
+                            function,
+                            arity,
+                            crate::code::class_term(&exception.class),
+                            exception.reason,
+                        );
+                    }
+                    self.raise(proc, args[1], exception)
+                }
             },
             NativeFunctionKind::Yielding(ptr) => ptr(proc, args),
         }
@@ -157,15 +231,46 @@ impl CallExecutor {
                 self.binds.insert(*v, t.clone());
             }
             self.next_args.clear();
+            self.continuation = None;
+
+            vm.set_executing(proc, fun);
+
+            vm.tracer
+                .read()
+                .unwrap()
+                .block(proc, fun.fun.ident(), block);
 
             match self.run_erlang_op(vm, proc, fun, block).unwrap() {
                 OpResult::Block(b) => block = b,
-                OpResult::Term(t) => break self.call_closure(proc, t, &self.next_args),
+                OpResult::Term(t) => {
+                    self.trace_exit(vm, proc, fun);
+                    break self.call_closure(proc, t, &self.next_args);
+                }
                 OpResult::TermYield(t) => break self.call_closure(proc, t, &self.next_args),
             }
         }
     }
 
+    /// Records a return or exception if the last operation called one of the
+    /// continuations the function was entered with.
+    fn trace_exit(&self, vm: &VMState, proc: &Arc<Process>, fun: &ErlangFunction) {
+        let entry_args = fun.fun.block_args(fun.fun.block_entry());
+        let ident = fun.fun.ident();
+        let tracer = vm.tracer.read().unwrap();
+
+        match self.continuation {
+            Some(value) if value == entry_args[0] && self.next_args.len() == 1 => {
+                tracer.erlang(proc, ident, || Event::Return(self.next_args[0]))
+            }
+            Some(value) if value == entry_args[1] && self.next_args.len() == 3 => {
+                tracer.erlang(proc, ident, || {
+                    Event::Exception(self.next_args[0], self.next_args[1])
+                })
+            }
+            _ => (),
+        }
+    }
+
     fn make_const_term(
         &self,
         proc: &Arc<Process>,
@@ -181,38 +286,37 @@ impl CallExecutor {
             //ConstKind::Atomic(AtomicTerm::Float(flt)) => {
             //    Term::Float(flt.0.into()).into()
             //}
-            //ConstKind::Atomic(AtomicTerm::Binary(bin)) => {
-            //    Term::Binary(Rc::new(bin.0.clone().into())).into()
-            //}
+            ConstKind::Atomic(AtomicTerm::Binary(bin)) => {
+                let bytes = bin.0.try_as_byte_aligned_slice().unwrap();
+                self::binary::binary_from_const(proc, bytes, bytes.len() * 8)
+            }
             ConstKind::Atomic(AtomicTerm::Nil) => Ok(Term::NIL),
-            //ConstKind::ListCell { head, tail } => {
-            //    Term::ListCell(
-            //        self.make_const_term(fun, *head),
-            //        self.make_const_term(fun, *tail),
-            //    ).into()
-            //}
-            //ConstKind::Tuple { entries } => {
-            //    let vec = entries.as_slice(&fun.fun.cons().const_pool)
-            //        .iter()
-            //        .map(|e| self.make_const_term(fun, *e))
-            //        .collect::<Vec<_>>();
-            //    Term::Tuple(vec).into()
-            //}
-            //ConstKind::Map { keys, values } => {
-            //    assert!(keys.len(&fun.fun.cons().const_pool)
-            //            == values.len(&fun.fun.cons().const_pool));
-
-            //    let mut map = MapTerm::new();
-            //    for (key, val) in keys.as_slice(&fun.fun.cons().const_pool).iter()
-            //        .zip(values.as_slice(&fun.fun.cons().const_pool).iter())
-            //    {
-            //        let key_v = self.make_const_term(fun, *key);
-            //        let val_v = self.make_const_term(fun, *val);
-            //        map.insert(key_v, val_v);
-            //    }
-
-            //    Term::Map(map).into()
-            //}
+            ConstKind::ListCell { head, tail } => {
+                let head = self.make_const_term(proc, fun, *head)?;
+                let tail = self.make_const_term(proc, fun, *tail)?;
+                Ok(proc.cons(head, tail)?)
+            }
+            ConstKind::Tuple { entries } => {
+                let vec = entries
+                    .as_slice(&fun.fun.cons().const_pool)
+                    .iter()
+                    .map(|e| self.make_const_term(proc, fun, *e))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                Ok(proc.tuple_from_slice(&vec)?)
+            }
+            ConstKind::Map { keys, values } => {
+                let pool = &fun.fun.cons().const_pool;
+                assert!(keys.len(pool) == values.len(pool));
+
+                let mut vec = Vec::new();
+                for (key, val) in keys.as_slice(pool).iter().zip(values.as_slice(pool).iter()) {
+                    let key_v = self.make_const_term(proc, fun, *key)?;
+                    let val_v = self.make_const_term(proc, fun, *val)?;
+                    vec.push((key_v, val_v));
+                }
+
+                Ok(proc.map_from_slice(&vec)?)
+            }
             kind => unimplemented!("{:?}", kind),
         }
     }
@@ -227,6 +331,7 @@ impl CallExecutor {
 
         let mut env = Vec::new();
         env.push(proc.integer(block.index())?);
+        env.push(proc.integer(fun.version)?);
         for v in live.iter(&fun.live.pool) {
             assert!(fun.fun.value_argument(v).is_some());
             env.push(self.make_term(proc, fun, v)?);
@@ -297,6 +402,7 @@ impl CallExecutor {
         fun: &ErlangFunction,
         value: Value,
     ) -> std::result::Result<OpResult, system::Exception> {
+        self.continuation = Some(value);
         if let ValueKind::Block(block) = fun.fun.value_kind(value) {
             Ok(OpResult::Block(block))
         } else {
@@ -305,6 +411,24 @@ impl CallExecutor {
         }
     }
 
+    /// Raises an exception from an operation that has no throw continuation of
+    /// its own, through the one the function was entered with. A `try` in the
+    /// same function does not catch it, but one in a caller does.
+    fn raise_from_op(
+        &mut self,
+        proc: &Arc<Process>,
+        fun: &ErlangFunction,
+        exception: runtime::Exception,
+    ) -> std::result::Result<OpResult, system::Exception> {
+        let class = crate::code::class_term(&exception.class);
+        let stacktrace = crate::code::stacktrace(proc, 0)?;
+        self.next_args
+            .extend_from_slice(&[class, exception.reason, stacktrace]);
+
+        let throw_cont = fun.fun.block_args(fun.fun.block_entry())[1];
+        self.val_call(proc, fun, throw_cont)
+    }
+
     fn run_erlang_op(
         &mut self,
         _vm: &VMState,
@@ -314,7 +438,6 @@ impl CallExecutor {
     ) -> std::result::Result<OpResult, system::Exception> {
         let reads = fun.fun.block_reads(block);
         let kind = fun.fun.block_kind(block).unwrap();
-        println!("OP: {:?}", kind);
         match kind {
             OpKind::Call => {
                 for read in reads.iter().skip(1) {
@@ -412,69 +535,15 @@ impl CallExecutor {
 
                 self.val_call(proc, fun, reads[call_n])
             }
-            //OpKind::TraceCaptureRaw => {
-            //    TermCall {
-            //        fun: self.make_term(fun, reads[0]),
-            //        args: vec![Term::Nil.into()],
-            //    }
-            //}
+            OpKind::TraceCaptureRaw => {
+                let stacktrace = crate::code::stacktrace(proc, 0)?;
+                self.next_args.push(stacktrace);
+                self.val_call(proc, fun, reads[0])
+            }
             OpKind::Match { branches } => self::r#match::match_op(self, proc, fun, branches, block),
-            //OpKind::BinaryPush { specifier } => {
-            //    let bin_term = self.make_term(fun, reads[2]);
-            //    let mut bin = match &*bin_term {
-            //        Term::Binary(bin) => (**bin).clone(),
-            //        Term::BinarySlice { buf, bit_offset, bit_length } => {
-            //            let slice = BitSlice::with_offset_length(
-            //                &**buf, *bit_offset, *bit_length);
-            //            let mut new = BitVec::new();
-            //            new.push(slice);
-            //            new
-            //        }
-            //        _ => panic!(),
-            //    };
-
-            //    let val_term = self.make_term(fun, reads[3]);
-
-            //    assert!(reads.len() == 4 || reads.len() == 5);
-            //    let size_term = reads.get(4).map(|r| self.make_term(fun, *r));
-
-            //    match specifier {
-            //        BinaryEntrySpecifier::Integer {
-            //            signed, unit, endianness } =>
-            //        {
-            //            let size = size_term.unwrap().as_usize().unwrap();
-            //            let bit_size = *unit as usize * size;
-
-            //            let endian = match *endianness {
-            //                Endianness::Big => Endian::Big,
-            //                Endianness::Little => Endian::Little,
-            //                Endianness::Native => Endian::Big,
-            //            };
-
-            //            let val = val_term.as_integer().unwrap().clone();
-            //            let carrier = integer_to_carrier(
-            //                val, bit_size, endian);
-
-            //            bin.push(carrier);
-            //        }
-            //        BinaryEntrySpecifier::Bytes { unit: 1 } => {
-            //            let binary = val_term.as_binary().unwrap();
-
-            //            if let Some(size_term) = size_term {
-            //                dbg!(&size_term, &binary);
-            //                assert!(size_term.as_usize().unwrap() == binary.len());
-            //            }
-
-            //            bin.push(binary);
-            //        }
-            //        k => unimplemented!("{:?}", k),
-            //    }
-
-            //    return TermCall {
-            //        fun: self.make_term(fun, reads[0]),
-            //        args: vec![Term::Binary(bin.into()).into()],
-            //    };
-            //}
+            OpKind::BinaryPush { specifier } => {
+                self::binary::binary_push(self, proc, fun, specifier, block)
+            }
             OpKind::MapPut { action } => {
                 let map_read = reads[2];
                 if let Some(constant) = fun.fun.value_const(map_read) {
@@ -500,58 +569,13 @@ impl CallExecutor {
                 unimplemented!()
             }
             OpKind::Intrinsic(name) if *name == Symbol::intern("receive_start") => {
-                assert!(reads.len() == 2);
-
-                let timeout = self.make_term(proc, fun, reads[1])?;
-                // Only infinity supported
-                assert!(timeout == atom_unchecked("infinity"));
-
-                proc.mailbox.lock().borrow_mut().recv_start();
-
-                self.next_args.push(Term::NIL);
-                self.val_call(proc, fun, reads[0])
+                self::receive::receive_start(self, proc, fun, block)
             }
             OpKind::Intrinsic(name) if *name == Symbol::intern("receive_wait") => {
-                assert!(reads.len() == 2);
-
-                let mailbox_lock = proc.mailbox.lock();
-                let mut mailbox = mailbox_lock.borrow_mut();
-                if let Some(msg_term) = mailbox.recv_peek() {
-                    mailbox.recv_increment();
-
-                    std::mem::drop(mailbox);
-                    std::mem::drop(mailbox_lock);
-
-                    self.next_args.push(msg_term);
-                    self.val_call(proc, fun, reads[1])
-                } else {
-                    // If there are no messages, schedule a call
-                    // to the current block for later.
-                    let curr_cont = self.make_closure(proc, fun, block).unwrap();
-                    self.next_args.push(Term::NIL);
-                    proc.wait();
-                    Ok(OpResult::TermYield(curr_cont))
-                }
+                self::receive::receive_wait(self, proc, fun, block)
             }
             OpKind::Intrinsic(name) if *name == Symbol::intern("receive_done") => {
-                assert!(reads.len() >= 1);
-
-                let mailbox_lock = proc.mailbox.lock();
-                let mut mailbox = mailbox_lock.borrow_mut();
-
-                if mailbox.recv_last_off_heap() {
-                    // Copy to process heap
-                    unimplemented!()
-                } else {
-                    for n in 0..(reads.len() - 1) {
-                        let term = self.make_term(proc, fun, reads[n + 1]).unwrap();
-                        self.next_args.push(term);
-                    }
-                }
-
-                mailbox.recv_finish(proc);
-
-                self.val_call(proc, fun, reads[0])
+                self::receive::receive_done(self, proc, fun, block)
             }
             //OpKind::Unreachable => {
             //    println!("==== Reached OpKind::Unreachable! ====");
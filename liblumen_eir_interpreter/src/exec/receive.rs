@@ -0,0 +1,183 @@
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use libeir_ir::Block;
+
+use liblumen_alloc::error;
+use liblumen_alloc::erts::exception::system;
+use liblumen_alloc::erts::message::{self, Message};
+use liblumen_alloc::erts::process::{Process, RecvTimeout};
+use liblumen_alloc::erts::term::{atom_unchecked, Boxed, Reference, Term, Tuple};
+
+use lumen_runtime::time::monotonic::{self, Milliseconds};
+use lumen_runtime::timer::{self, Destination, Timeout};
+
+use super::{CallExecutor, OpResult};
+use crate::module::ErlangFunction;
+
+/// Reads: `[continuation, timeout]`
+///
+/// Starts a receive. A timeout other than `infinity` or `0` arms a timer which
+/// sends the process `{timeout, Reference, receive_timeout}` on expiry, and one
+/// that is not a non-negative integer raises `error:timeout_value` from the
+/// function.
+pub fn receive_start(
+    exec: &mut CallExecutor,
+    proc: &Arc<Process>,
+    fun: &ErlangFunction,
+    block: Block,
+) -> Result<OpResult, system::Exception> {
+    let reads = fun.fun.block_reads(block);
+    assert!(reads.len() == 2);
+
+    let timeout_term = exec.make_term(proc, fun, reads[1])?;
+    let timeout = if timeout_term == atom_unchecked("infinity") {
+        RecvTimeout::Infinity
+    } else {
+        let milliseconds: Milliseconds = match timeout_term.try_into() {
+            Ok(milliseconds) => milliseconds,
+            Err(_) => {
+                let exception = error!(atom_unchecked("timeout_value"));
+                return exec.raise_from_op(proc, fun, exception);
+            }
+        };
+        if milliseconds == 0 {
+            RecvTimeout::Immediate
+        } else {
+            let reference_term = timer::start(
+                monotonic::time_in_milliseconds() + milliseconds,
+                Destination::Process(Arc::downgrade(proc)),
+                Timeout::TimeoutTuple,
+                atom_unchecked("receive_timeout"),
+                proc,
+            )?;
+            let reference: Boxed<Reference> = reference_term.try_into().unwrap();
+            RecvTimeout::Timer(*reference)
+        }
+    };
+
+    proc.mailbox.lock().borrow_mut().recv_start(timeout);
+
+    exec.next_args.push(Term::NIL);
+    exec.val_call(proc, fun, reads[0])
+}
+
+/// Reads: `[timeout_continuation, message_continuation]`
+///
+/// Passes the next message in the mailbox to the message continuation, or
+/// takes the timeout continuation if the receive timed out. When there are no
+/// more messages to try, the process waits, and the receive is resumed when a
+/// message, possibly that of the timer, arrives.
+pub fn receive_wait(
+    exec: &mut CallExecutor,
+    proc: &Arc<Process>,
+    fun: &ErlangFunction,
+    block: Block,
+) -> Result<OpResult, system::Exception> {
+    let reads = fun.fun.block_reads(block);
+    assert!(reads.len() == 2);
+
+    let mailbox_lock = proc.mailbox.lock();
+    let mut mailbox = mailbox_lock.borrow_mut();
+    let timeout = mailbox.recv_timeout();
+
+    match mailbox.recv_peek() {
+        Some(msg_term) if !is_timer_message(timeout, msg_term) => {
+            mailbox.recv_increment();
+
+            std::mem::drop(mailbox);
+            std::mem::drop(mailbox_lock);
+
+            exec.next_args.push(msg_term);
+            exec.val_call(proc, fun, reads[1])
+        }
+        Some(_) => {
+            mailbox.recv_finish_timeout(proc);
+            exec.val_call(proc, fun, reads[0])
+        }
+        None => {
+            if let RecvTimeout::Immediate = timeout {
+                mailbox.recv_finish_timeout(proc);
+                return exec.val_call(proc, fun, reads[0]);
+            }
+
+            // If there are no messages, schedule a call
+            // to the current block for later.
+            let curr_cont = exec.make_closure(proc, fun, block).unwrap();
+            exec.next_args.push(Term::NIL);
+            proc.wait();
+            Ok(OpResult::TermYield(curr_cont))
+        }
+    }
+}
+
+/// Reads: `[continuation, values...]`
+///
+/// Removes the matched message from the mailbox, cancelling the receive's
+/// timer if it has one.
+pub fn receive_done(
+    exec: &mut CallExecutor,
+    proc: &Arc<Process>,
+    fun: &ErlangFunction,
+    block: Block,
+) -> Result<OpResult, system::Exception> {
+    let reads = fun.fun.block_reads(block);
+    assert!(reads.len() >= 1);
+
+    let mailbox_lock = proc.mailbox.lock();
+    let mut mailbox = mailbox_lock.borrow_mut();
+
+    if mailbox.recv_last_off_heap() {
+        // Copy to process heap
+        unimplemented!()
+    } else {
+        for n in 0..(reads.len() - 1) {
+            let term = exec.make_term(proc, fun, reads[n + 1]).unwrap();
+            exec.next_args.push(term);
+        }
+    }
+
+    let timeout = mailbox.recv_timeout();
+    mailbox.recv_finish(proc);
+
+    if let RecvTimeout::Timer(reference) = timeout {
+        // If the timer already expired its message is in the mailbox, where
+        // it must not be seen by later receives.
+        if timer::cancel(&reference).is_none() {
+            mailbox.flush(
+                |message| match message {
+                    Message::Process(message::Process { data }) => is_timer_message(timeout, *data),
+                    Message::HeapFragment(message::HeapFragment { data, .. }) => {
+                        is_timer_message(timeout, *data)
+                    }
+                },
+                proc,
+            );
+        }
+    }
+
+    std::mem::drop(mailbox);
+    std::mem::drop(mailbox_lock);
+
+    exec.val_call(proc, fun, reads[0])
+}
+
+// Returns true if `message` is the one sent by the timer of a receive
+fn is_timer_message(timeout: RecvTimeout, message: Term) -> bool {
+    let reference = match timeout {
+        RecvTimeout::Timer(reference) => reference,
+        _ => return false,
+    };
+
+    let tuple: Result<Boxed<Tuple>, _> = message.try_into();
+    match tuple {
+        Ok(tuple) if tuple.len() == 3 && tuple[0] == atom_unchecked("timeout") => {
+            let message_reference: Result<Boxed<Reference>, _> = tuple[1].try_into();
+            match message_reference {
+                Ok(message_reference) => *message_reference == reference,
+                Err(_) => false,
+            }
+        }
+        _ => false,
+    }
+}
@@ -1,20 +1,24 @@
 #![deny(warnings)]
 
+use std::fmt::{self, Display};
 use std::sync::Arc;
 
-use liblumen_alloc::erts::exception;
-use liblumen_alloc::erts::process::{Process, Status};
-use liblumen_alloc::erts::term::{atom_unchecked, Atom, Term};
+use liblumen_alloc::erts::exception::runtime;
+use liblumen_alloc::erts::process::{Process, RecvTimeout, Status};
+use liblumen_alloc::erts::term::{atom_unchecked, Atom, Pid, Term};
 use liblumen_alloc::erts::ModuleFunctionArity;
+use liblumen_alloc::{exit, CloneToProcess};
 
 use lumen_runtime::process::spawn::options::Options;
 use lumen_runtime::scheduler::Scheduler;
-use lumen_runtime::system;
 
 mod code;
 mod exec;
+pub mod literal;
 mod module;
 mod native;
+pub mod shell;
+pub mod trace;
 mod vm;
 
 use self::vm::VMState;
@@ -24,12 +28,51 @@ lazy_static! {
     pub static ref VM: VMState = VMState::new();
 }
 
+/// Why a function run by `call_erlang` did not return a value
+#[derive(Debug)]
+pub enum CallError {
+    /// The process exited with an exception, copied to the heap of the caller
+    Exception(runtime::Exception),
+    /// The process is waiting for a message that can never arrive, because no
+    /// other process can run and no timer is pending. The pids are those of
+    /// the process and of the waiting processes it spawned or linked to.
+    Deadlock { waiting_processes: Vec<Pid> },
+}
+
+impl Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallError::Exception(exception) => write!(f, "{:?}", exception),
+            CallError::Deadlock { waiting_processes } => {
+                write!(f, "deadlock with waiting processes")?;
+                for pid in waiting_processes {
+                    write!(f, " {}", pid)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<runtime::Exception> for CallError {
+    fn from(exception: runtime::Exception) -> Self {
+        CallError::Exception(exception)
+    }
+}
+
+/// Runs `module:function` with `args` in a new process, and returns the
+/// value it returns, copied to the heap of `proc`, or the exception it exits
+/// with.
+///
+/// Other processes on the scheduler keep running while the process waits, so
+/// a deadlock is only reported once none of them can run. The deadlocked
+/// process and the waiting processes it spawned or linked to are then killed.
 pub fn call_erlang(
     proc: Arc<Process>,
     module: Atom,
     function: Atom,
     args: &[Term],
-) -> std::result::Result<(), ()> {
+) -> std::result::Result<Term, CallError> {
     let return_ok = {
         let mfa = ModuleFunctionArity {
             module: Atom::try_from_str("lumen_eir_interpreter_intrinsics").unwrap(),
@@ -75,65 +118,135 @@ pub fn call_erlang(
     // if this fails increase heap size
     .unwrap();
 
+    VM.expect_reply(run_arc_process.pid(), proc.clone());
+    let result = run_to_completion(&run_arc_process, &proc);
+    VM.forget_reply(run_arc_process.pid());
+
+    result
+}
+
+// Drives the scheduler until `run_arc_process` exits or deadlocks
+fn run_to_completion(
+    run_arc_process: &Arc<Process>,
+    proc: &Process,
+) -> std::result::Result<Term, CallError> {
+    let arc_scheduler = Scheduler::current();
+
     loop {
-        let ran = Scheduler::current().run_through(&run_arc_process);
+        // Runs every other runnable process until `run_arc_process` runs, so
+        // `false` means nothing on the scheduler is runnable.
+        let ran = arc_scheduler.run_through(run_arc_process);
 
         match *run_arc_process.status.read() {
-            Status::Exiting(ref exception) => match exception {
-                exception::runtime::Exception {
-                    class: exception::runtime::Class::Exit,
-                    reason,
-                    ..
-                } => {
-                    if *reason != atom_unchecked("normal") {
-                        return Err(());
-                    //panic!("Process exited: {:?}", reason);
-                    } else {
-                        return Ok(());
-                    }
-                }
-                _ => {
-                    return Err(());
-                    //panic!(
-                    //    "Process exception: {:?}\n{:?}",
-                    //    exception,
-                    //    run_arc_process.stacktrace()
-                    //);
-                }
-            },
-            Status::Waiting => {
-                if ran {
-                    system::io::puts(&format!(
-                        "WAITING Run queues len = {:?}",
-                        Scheduler::current().run_queues_len()
-                    ));
+            Status::Exiting(ref exception) => {
+                let normal = exception.class == runtime::Class::Exit
+                    && exception.reason == atom_unchecked("normal");
+
+                return match VM.take_reply(run_arc_process.pid()) {
+                    Some(value) if normal => Ok(value),
+                    _ => Err(clone_exception_to_process(exception, proc).into()),
+                };
+            }
+            Status::Waiting if !ran => {
+                if waiting_on_timer(run_arc_process) {
+                    // A timer timing out can still wake a process
+                    std::thread::yield_now();
                 } else {
-                    panic!(
-                        "{:?} did not run.  Deadlock likely in {:#?}",
-                        run_arc_process,
-                        Scheduler::current()
-                    );
+                    break;
                 }
             }
-            Status::Runnable => {
-                system::io::puts(&format!(
-                    "RUNNABLE Run queues len = {:?}",
-                    Scheduler::current().run_queues_len()
-                ));
-            }
-            Status::Running => {
-                system::io::puts(&format!(
-                    "RUNNING Run queues len = {:?}",
-                    Scheduler::current().run_queues_len()
-                ));
-            }
+            Status::Waiting | Status::Runnable | Status::Running => (),
+        }
+    }
+
+    let deadlocked = related_processes(run_arc_process, arc_scheduler.waiting_processes());
+    for arc_process in deadlocked.iter() {
+        arc_process.exception(exit!(atom_unchecked("killed")));
+        arc_scheduler.stop_waiting(arc_process);
+    }
+
+    let mut waiting_processes: Vec<Pid> = deadlocked
+        .iter()
+        .map(|arc_process| arc_process.pid())
+        .collect();
+    waiting_processes.sort();
+
+    Err(CallError::Deadlock { waiting_processes })
+}
+
+// `run_arc_process` and the processes in `waiting` that it spawned or linked
+// to, directly or through processes that it did
+fn related_processes(
+    run_arc_process: &Arc<Process>,
+    waiting: Vec<Arc<Process>>,
+) -> Vec<Arc<Process>> {
+    let mut related = vec![run_arc_process.clone()];
+    let mut others: Vec<Arc<Process>> = waiting
+        .into_iter()
+        .filter(|arc_process| arc_process.pid() != run_arc_process.pid())
+        .collect();
+
+    loop {
+        let related_pids: Vec<Pid> = related
+            .iter()
+            .map(|arc_process| arc_process.pid())
+            .collect();
+        let (new, rest): (Vec<_>, Vec<_>) = others.into_iter().partition(|arc_process| {
+            arc_process
+                .parent_pid()
+                .map_or(false, |parent_pid| related_pids.contains(&parent_pid))
+                || arc_process
+                    .linked_pid_set
+                    .lock()
+                    .iter()
+                    .any(|pid| related_pids.contains(pid))
+        });
+        others = rest;
+
+        if new.is_empty() {
+            break related;
         }
+        related.extend(new);
+    }
+}
+
+// Copies an exception from the heap of the process that raised it
+fn clone_exception_to_process(
+    exception: &runtime::Exception,
+    process: &Process,
+) -> runtime::Exception {
+    let class = match exception.class {
+        runtime::Class::Error { arguments } => runtime::Class::Error {
+            arguments: arguments.map(|arguments| arguments.clone_to_process(process)),
+        },
+        ref class => class.clone(),
+    };
+
+    runtime::Exception {
+        class,
+        reason: exception.reason.clone_to_process(process),
+        stacktrace: exception
+            .stacktrace
+            .map(|stacktrace| stacktrace.clone_to_process(process)),
+        file: exception.file,
+        line: exception.line,
+        column: exception.column,
+    }
+}
+
+// Whether a waiting process may still be woken by its receive timing out or
+// by a process a pending timer wakes
+fn waiting_on_timer(process: &Process) -> bool {
+    match process.mailbox.lock().borrow().recv_timeout() {
+        RecvTimeout::Timer(_) => true,
+        _ => Scheduler::current().has_pending_timers(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::call_erlang;
+    use super::CallError;
     use super::VM;
 
     use libeir_diagnostics::{ColorChoice, Emitter, StandardStreamEmitter};
@@ -146,8 +259,10 @@ mod tests {
     use libeir_syntax_erl::lower_module;
     use libeir_syntax_erl::{Parse, ParseConfig, Parser};
 
-    use liblumen_alloc::erts::term::Atom;
+    use liblumen_alloc::erts::term::{atom_unchecked, Atom};
 
+    use lumen_runtime::otp::erlang;
+    use lumen_runtime::registry::atom_to_process;
     use lumen_runtime::scheduler::Scheduler;
 
     fn parse<T>(input: &str, config: ParseConfig) -> (T, Parser)
@@ -224,7 +339,11 @@ run() -> yay.
         let mut pass_manager = PassManager::default();
         pass_manager.run(&mut eir_mod);
 
-        VM.modules.write().unwrap().register_erlang_module(eir_mod);
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
 
         call_erlang(init_arc_process, module, function, &[])
             .ok()
@@ -260,11 +379,780 @@ fib(X) -> fib(X - 1) + fib(X-2).
         let mut pass_manager = PassManager::default();
         pass_manager.run(&mut eir_mod);
 
-        VM.modules.write().unwrap().register_erlang_module(eir_mod);
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
 
         let int = init_arc_process.integer(5).unwrap();
         call_erlang(init_arc_process, module, function, &[int])
             .ok()
             .unwrap();
     }
+
+    #[test]
+    fn binary_construct_and_match() {
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("binary_test").unwrap();
+        let function = Atom::try_from_str("run").unwrap();
+
+        let config = ParseConfig::default();
+        let mut eir_mod = lower(
+            "
+-module(binary_test).
+
+run() ->
+    Bin = <<1, 258:16/little, \"abc\", -1:4/signed, 3:4>>,
+    <<1, 258:16/little, Rest/binary>> = Bin,
+    <<\"abc\", -1:4/signed, Low:4>> = Rest,
+    3 = Low,
+    ok.
+",
+            config,
+        )
+        .unwrap();
+
+        for fun in eir_mod.functions.values() {
+            fun.graph_validate_global();
+        }
+
+        let mut pass_manager = PassManager::default();
+        pass_manager.run(&mut eir_mod);
+
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
+
+        call_erlang(init_arc_process, module, function, &[])
+            .ok()
+            .unwrap();
+    }
+
+    #[test]
+    fn tuple_and_list_patterns() {
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("pattern_test").unwrap();
+        let function = Atom::try_from_str("run").unwrap();
+
+        let config = ParseConfig::default();
+        let mut eir_mod = lower(
+            "
+-module(pattern_test).
+
+run() ->
+    6 = sum([1, 2, 3]),
+    {ok, 2} = first({ok, [2, 3]}),
+    tuple = kind({a, b}),
+    list = kind([a]),
+    nil = kind([]),
+    integer = kind(1),
+    map = kind(#{}),
+    ok.
+
+sum([]) -> 0;
+sum([H | T]) -> H + sum(T).
+
+first({ok, [H | _]}) -> {ok, H};
+first({error, _} = Error) -> Error.
+
+kind(Term) ->
+    case Term of
+        {_, _} -> tuple;
+        [_ | _] -> list;
+        [] -> nil;
+        I when is_integer(I) -> integer;
+        M when is_map(M) -> map
+    end.
+",
+            config,
+        )
+        .unwrap();
+
+        for fun in eir_mod.functions.values() {
+            fun.graph_validate_global();
+        }
+
+        let mut pass_manager = PassManager::default();
+        pass_manager.run(&mut eir_mod);
+
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
+
+        call_erlang(init_arc_process, module, function, &[])
+            .ok()
+            .unwrap();
+    }
+
+    #[test]
+    fn try_catch() {
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("try_catch_test").unwrap();
+
+        let config = ParseConfig::default();
+        let mut eir_mod = lower(
+            "
+-module(try_catch_test).
+
+run() ->
+    caught = try throw(oops) catch throw:oops -> caught end,
+    {error, badarith} = try add(1, a) catch error:R1 -> {error, R1} end,
+    {exit, bye} = try exit(bye) catch exit:R2 -> {exit, R2} end,
+    [_ | _] = try throw(oops) catch _:_:Stacktrace -> Stacktrace end,
+    ok.
+
+add(A, B) -> A + B.
+
+uncaught() -> throw(oops).
+",
+            config,
+        )
+        .unwrap();
+
+        for fun in eir_mod.functions.values() {
+            fun.graph_validate_global();
+        }
+
+        let mut pass_manager = PassManager::default();
+        pass_manager.run(&mut eir_mod);
+
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
+
+        let run = Atom::try_from_str("run").unwrap();
+        call_erlang(init_arc_process.clone(), module, run, &[])
+            .ok()
+            .unwrap();
+
+        let uncaught = Atom::try_from_str("uncaught").unwrap();
+        call_erlang(init_arc_process, module, uncaught, &[])
+            .err()
+            .unwrap();
+    }
+
+    #[test]
+    fn receive_after() {
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("receive_after_test").unwrap();
+        let function = Atom::try_from_str("run").unwrap();
+
+        let config = ParseConfig::default();
+        let mut eir_mod = lower(
+            "
+-module(receive_after_test).
+
+run() ->
+    timeout = receive _ -> message after 0 -> timeout end,
+    timeout = receive _ -> message after 10 -> timeout end,
+    erlang:send(self(), hello),
+    hello = receive hello -> hello after 1000 -> timeout end,
+    erlang:send(self(), hello),
+    hello = receive hello -> hello after infinity -> timeout end,
+    timeout = receive _ -> message after 0 -> timeout end,
+    timeout_value = try wait(foo) catch error:Reason -> Reason end,
+    timeout_value = try wait(-1) catch error:Negative -> Negative end,
+    ok.
+
+wait(Timeout) ->
+    receive _ -> message after Timeout -> timeout end.
+",
+            config,
+        )
+        .unwrap();
+
+        for fun in eir_mod.functions.values() {
+            fun.graph_validate_global();
+        }
+
+        let mut pass_manager = PassManager::default();
+        pass_manager.run(&mut eir_mod);
+
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
+
+        call_erlang(init_arc_process, module, function, &[])
+            .ok()
+            .unwrap();
+    }
+
+    #[test]
+    fn runtime_bifs() {
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("runtime_bifs_test").unwrap();
+        let function = Atom::try_from_str("run").unwrap();
+
+        let config = ParseConfig::default();
+        let mut eir_mod = lower(
+            "
+-module(runtime_bifs_test).
+
+run() ->
+    b = element(2, {a, b}),
+    {a, c} = setelement(2, {a, b}, c),
+    3 = length([a, b, c]),
+    <<\"abc\">> = list_to_binary(\"abc\"),
+    [3, 2, 1] = lists:reverse([1, 2, 3]),
+    true = lists:member(b, [a, b]),
+    [a] = maps:keys(#{a => 1}),
+    ok.
+",
+            config,
+        )
+        .unwrap();
+
+        for fun in eir_mod.functions.values() {
+            fun.graph_validate_global();
+        }
+
+        let mut pass_manager = PassManager::default();
+        pass_manager.run(&mut eir_mod);
+
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
+
+        call_erlang(init_arc_process, module, function, &[])
+            .ok()
+            .unwrap();
+    }
+
+    #[test]
+    fn trace_filtered_function() {
+        use std::sync::{Arc, Mutex};
+
+        use super::trace::{Filter, Trace, TraceSink};
+
+        struct Lines(Mutex<Vec<String>>);
+        impl TraceSink for Lines {
+            fn record(&self, trace: &Trace) {
+                self.0.lock().unwrap().push(trace.to_string());
+            }
+        }
+
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("trace_test").unwrap();
+        let function = Atom::try_from_str("run").unwrap();
+
+        let config = ParseConfig::default();
+        let mut eir_mod = lower(
+            "
+-module(trace_test).
+
+run() ->
+    {ok, 42} = double(21),
+    ok.
+
+double(X) ->
+    Y = X * 2,
+    {ok, Y}.
+",
+            config,
+        )
+        .unwrap();
+
+        for fun in eir_mod.functions.values() {
+            fun.graph_validate_global();
+        }
+
+        let mut pass_manager = PassManager::default();
+        pass_manager.run(&mut eir_mod);
+
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
+
+        let lines = Arc::new(Lines(Mutex::new(Vec::new())));
+        {
+            let mut tracer = VM.tracer.write().unwrap();
+            tracer.set_sink(Some(lines.clone()));
+            tracer.trace_all_processes(true);
+            tracer.add_filter(Filter::parse("trace_test:double/1").unwrap());
+        }
+
+        let result = call_erlang(init_arc_process, module, function, &[]);
+        VM.tracer.write().unwrap().reset();
+        result.ok().unwrap();
+
+        let lines = lines.0.lock().unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("trace_test:double/1 call (21)"));
+        assert!(lines[1].ends_with("trace_test:double/1 returned {:'ok', 42}"));
+    }
+
+    #[test]
+    fn hot_code_loading() {
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("hot_test").unwrap();
+
+        let load = |version: &str| {
+            let config = ParseConfig::default();
+            let mut eir_mod = lower(
+                &format!(
+                    "
+-module(hot_test).
+
+start() ->
+    Pid = spawn(hot_test, waiter, [self()]),
+    receive ready -> ok end,
+    register(hot_waiter, Pid),
+    ok.
+
+waiter(Parent) ->
+    erlang:send(Parent, ready),
+    receive stop -> ok end.
+
+version() -> {}.
+
+check() ->
+    2 = hot_test:version(),
+    Waiter = whereis(hot_waiter),
+    true = erlang:check_old_code(hot_test),
+    true = erlang:check_process_code(Waiter, hot_test),
+    false = erlang:check_process_code(self(), hot_test),
+    false = code:soft_purge(hot_test),
+    true = code:purge(hot_test),
+    false = erlang:check_old_code(hot_test),
+    ok.
+",
+                    version
+                ),
+                config,
+            )
+            .unwrap();
+
+            for fun in eir_mod.functions.values() {
+                fun.graph_validate_global();
+            }
+
+            let mut pass_manager = PassManager::default();
+            pass_manager.run(&mut eir_mod);
+
+            VM.modules.write().unwrap().register_erlang_module(eir_mod)
+        };
+
+        load("1").unwrap();
+        call_erlang(
+            init_arc_process.clone(),
+            module,
+            Atom::try_from_str("start").unwrap(),
+            &[],
+        )
+        .ok()
+        .unwrap();
+
+        load("2").unwrap();
+        // The first version is still old code
+        assert!(load("3").is_err());
+
+        call_erlang(
+            init_arc_process.clone(),
+            module,
+            Atom::try_from_str("check").unwrap(),
+            &[],
+        )
+        .ok()
+        .unwrap();
+
+        load("3").unwrap();
+    }
+
+    #[test]
+    fn old_continuation_is_old_code() {
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("stack_test").unwrap();
+
+        let load = |version: &str| {
+            let config = ParseConfig::default();
+            let mut eir_mod = lower(
+                &format!(
+                    "
+-module(stack_test).
+
+start() ->
+    Pid = spawn(stack_test, outer, [self()]),
+    receive waiting -> ok end,
+    register(stack_waiter, Pid),
+    ok.
+
+outer(Parent) ->
+    erlang:send(Parent, waiting),
+    receive go -> ok end,
+    Result = stack_test:inner(Parent),
+    {{done, Result}}.
+
+inner(Parent) ->
+    erlang:send(Parent, ready),
+    receive stop -> ok end.
+
+version() -> {}.
+
+check() ->
+    2 = stack_test:version(),
+    Waiter = whereis(stack_waiter),
+    erlang:send(Waiter, go),
+    receive ready -> ok end,
+    true = erlang:check_process_code(Waiter, stack_test),
+    false = code:soft_purge(stack_test),
+    true = code:purge(stack_test),
+    ok.
+",
+                    version
+                ),
+                config,
+            )
+            .unwrap();
+
+            for fun in eir_mod.functions.values() {
+                fun.graph_validate_global();
+            }
+
+            let mut pass_manager = PassManager::default();
+            pass_manager.run(&mut eir_mod);
+
+            VM.modules.write().unwrap().register_erlang_module(eir_mod)
+        };
+
+        load("1").unwrap();
+        call_erlang(
+            init_arc_process.clone(),
+            module,
+            Atom::try_from_str("start").unwrap(),
+            &[],
+        )
+        .ok()
+        .unwrap();
+
+        // The waiter calls `inner` in the new version, while the continuation
+        // of `outer` in the old version waits for it to return
+        load("2").unwrap();
+        call_erlang(
+            init_arc_process.clone(),
+            module,
+            Atom::try_from_str("check").unwrap(),
+            &[],
+        )
+        .ok()
+        .unwrap();
+    }
+
+    #[test]
+    fn call_with_literal_arguments() {
+        use super::literal::parse_term;
+
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("literal_arguments_test").unwrap();
+        let function = Atom::try_from_str("run").unwrap();
+
+        let config = ParseConfig::default();
+        let mut eir_mod = lower(
+            "
+-module(literal_arguments_test).
+
+run({Tag, [A | _]}, B) -> {Tag, A + B};
+run(_, _) -> erlang:error(badarg).
+",
+            config,
+        )
+        .unwrap();
+
+        for fun in eir_mod.functions.values() {
+            fun.graph_validate_global();
+        }
+
+        let mut pass_manager = PassManager::default();
+        pass_manager.run(&mut eir_mod);
+
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
+
+        let args = [
+            parse_term(&init_arc_process, "{sum, [1, 2 | 3]}").unwrap(),
+            parse_term(&init_arc_process, "41").unwrap(),
+        ];
+        let result = call_erlang(init_arc_process.clone(), module, function, &args).unwrap();
+        let expected = parse_term(&init_arc_process, "{sum, 42}").unwrap();
+        assert_eq!(result, expected);
+
+        let args = [
+            parse_term(&init_arc_process, "<<\"bin\">>").unwrap(),
+            parse_term(&init_arc_process, "#{}").unwrap(),
+        ];
+        match call_erlang(init_arc_process.clone(), module, function, &args) {
+            Err(CallError::Exception(exception)) => {
+                assert_eq!(exception.reason, atom_unchecked("badarg"))
+            }
+            result => panic!("expected badarg, got {:?}", result),
+        }
+
+        let args = [
+            parse_term(&init_arc_process, "{difference, [1]}").unwrap(),
+            parse_term(&init_arc_process, "-3").unwrap(),
+        ];
+        let result = call_erlang(init_arc_process.clone(), module, function, &args).unwrap();
+        let expected = parse_term(&init_arc_process, "{difference, -2}").unwrap();
+        assert_eq!(result, expected);
+
+        assert!(parse_term(&init_arc_process, "{unclosed").is_err());
+    }
+
+    #[test]
+    fn shell_keeps_bindings() {
+        use super::literal::parse_term;
+        use super::shell::Shell;
+
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let mut shell = Shell::new();
+
+        let x = shell.eval("X = 20 + 1").unwrap();
+        assert_eq!(x, parse_term(&init_arc_process, "21").unwrap());
+
+        let pair = shell.eval("Y = X * 2, {X, Y}").unwrap();
+        assert_eq!(pair, parse_term(&init_arc_process, "{21, 42}").unwrap());
+
+        assert!(shell.eval("erlang:error(oops)").is_err());
+        assert!(shell.eval("X = 22").is_err());
+
+        let self_pid = shell.eval("self()").unwrap();
+        assert_eq!(shell.eval("self()").unwrap(), self_pid);
+
+        let sum = shell.eval("lists:reverse([X, Y])").unwrap();
+        assert_eq!(sum, parse_term(&init_arc_process, "[42, 21]").unwrap());
+    }
+
+    #[test]
+    fn deadlock_is_reported() {
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("deadlock_test").unwrap();
+        let function = Atom::try_from_str("run").unwrap();
+
+        let config = ParseConfig::default();
+        let mut eir_mod = lower(
+            "
+-module(deadlock_test).
+
+bystander() ->
+    register(deadlock_bystander, spawn(deadlock_test, wait, [])),
+    ok.
+
+run() ->
+    register(deadlock_caller, self()),
+    spawn(deadlock_test, stuck, [self()]),
+    receive ready -> ok end,
+    receive never -> ok end.
+
+stuck(Parent) ->
+    register(deadlock_stuck, self()),
+    erlang:send(Parent, ready),
+    receive never -> ok end.
+
+wait() ->
+    receive stop -> ok end.
+",
+            config,
+        )
+        .unwrap();
+
+        for fun in eir_mod.functions.values() {
+            fun.graph_validate_global();
+        }
+
+        let mut pass_manager = PassManager::default();
+        pass_manager.run(&mut eir_mod);
+
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
+
+        // A waiting process that has nothing to do with the deadlock
+        call_erlang(
+            init_arc_process.clone(),
+            module,
+            Atom::try_from_str("bystander").unwrap(),
+            &[],
+        )
+        .unwrap();
+
+        let result = call_erlang(init_arc_process.clone(), module, function, &[]);
+
+        let registered = |name: &str| atom_to_process(&Atom::try_from_str(name).unwrap()).unwrap();
+        let caller = registered("deadlock_caller");
+        let stuck = registered("deadlock_stuck");
+        let bystander = registered("deadlock_bystander");
+
+        match result {
+            Err(CallError::Deadlock { waiting_processes }) => {
+                let mut expected = vec![caller.pid(), stuck.pid()];
+                expected.sort();
+                assert_eq!(waiting_processes, expected);
+            }
+            result => panic!("expected a deadlock, got {:?}", result),
+        }
+
+        // The deadlocked processes are killed, but not the bystander
+        assert!(caller.is_exiting());
+        assert!(stuck.is_exiting());
+        assert!(!bystander.is_exiting());
+
+        erlang::send_2(
+            bystander.pid_term(),
+            atom_unchecked("stop"),
+            &init_arc_process,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn binary_float_match() {
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("binary_float_test").unwrap();
+        let function = Atom::try_from_str("run").unwrap();
+
+        let config = ParseConfig::default();
+        let mut eir_mod = lower(
+            "
+-module(binary_float_test).
+
+run() ->
+    Bin = <<1.5/float, 2.5:32/float, 0:32>>,
+    <<A/float, B:32/float, _:32>> = Bin,
+    1.5 = A,
+    2.5 = B,
+    nomatch = case Bin of
+                  <<F:128/float>> -> F;
+                  _ -> nomatch
+              end,
+    ok.
+",
+            config,
+        )
+        .unwrap();
+
+        for fun in eir_mod.functions.values() {
+            fun.graph_validate_global();
+        }
+
+        let mut pass_manager = PassManager::default();
+        pass_manager.run(&mut eir_mod);
+
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
+
+        call_erlang(init_arc_process, module, function, &[])
+            .ok()
+            .unwrap();
+    }
+
+    #[test]
+    fn maps_get() {
+        &*VM;
+
+        let arc_scheduler = Scheduler::current();
+        let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
+
+        let module = Atom::try_from_str("maps_get_test").unwrap();
+        let function = Atom::try_from_str("run").unwrap();
+
+        let config = ParseConfig::default();
+        let mut eir_mod = lower(
+            "
+-module(maps_get_test).
+
+run() ->
+    1 = maps:get(a, #{a => 1}),
+    {badkey, b} = try maps:get(b, #{a => 1}) catch error:R1 -> R1 end,
+    {badmap, nil} = try maps:get(a, nil) catch error:R2 -> R2 end,
+    ok.
+",
+            config,
+        )
+        .unwrap();
+
+        for fun in eir_mod.functions.values() {
+            fun.graph_validate_global();
+        }
+
+        let mut pass_manager = PassManager::default();
+        pass_manager.run(&mut eir_mod);
+
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_mod)
+            .unwrap();
+
+        call_erlang(init_arc_process, module, function, &[])
+            .ok()
+            .unwrap();
+    }
 }
@@ -0,0 +1,375 @@
+//! Parses Erlang term literals, such as the arguments given to the
+//! interpreter on the command line, into process terms.
+
+use std::fmt::{self, Display};
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use num_bigint::BigInt;
+
+use liblumen_alloc::erts::exception::system::Alloc;
+use liblumen_alloc::erts::process::Process;
+use liblumen_alloc::erts::term::{atom_unchecked, Atom, Term};
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// The input is not a valid term literal
+    Syntax {
+        position: usize,
+        message: String,
+    },
+    Alloc(Alloc),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Syntax { position, message } => {
+                write!(f, "{} at position {}", message, position)
+            }
+            ParseError::Alloc(alloc) => write!(f, "{:?}", alloc),
+        }
+    }
+}
+
+impl From<Alloc> for ParseError {
+    fn from(alloc: Alloc) -> Self {
+        ParseError::Alloc(alloc)
+    }
+}
+
+/// Parses a single term literal, allocating it on the process heap.
+///
+/// Supports integers (including `Base#Digits` and `$c`), floats, atoms,
+/// strings, binaries of bytes and strings, tuples, proper and improper lists,
+/// and maps.
+pub fn parse_term(process: &Process, input: &str) -> Result<Term, ParseError> {
+    let mut parser = TermParser {
+        process,
+        input,
+        chars: input.char_indices().peekable(),
+    };
+
+    let term = parser.term()?;
+    parser.skip_whitespace();
+    match parser.chars.peek() {
+        None => Ok(term),
+        Some(_) => Err(parser.error("unexpected input after term")),
+    }
+}
+
+struct TermParser<'a> {
+    process: &'a Process,
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> TermParser<'a> {
+    fn term(&mut self) -> Result<Term, ParseError> {
+        self.skip_whitespace();
+
+        match self.peek_char() {
+            Some('{') => {
+                self.next_char();
+                let elements = self.sequence('}')?;
+                Ok(self.process.tuple_from_slice(&elements)?)
+            }
+            Some('[') => self.list(),
+            Some('#') => self.map(),
+            Some('<') => self.binary(),
+            Some('"') => {
+                let string = self.quoted('"')?;
+                Ok(self.process.charlist_from_str(&string)?)
+            }
+            Some('\'') => {
+                let name = self.quoted('\'')?;
+                self.atom(&name)
+            }
+            Some('$') => {
+                self.next_char();
+                match self.next_char() {
+                    Some('\\') => {
+                        let c = self.escape()?;
+                        Ok(self.process.integer(c)?)
+                    }
+                    Some(c) => Ok(self.process.integer(c)?),
+                    None => Err(self.error("expected character after `$`")),
+                }
+            }
+            Some(c) if c == '-' || c == '+' || c.is_ascii_digit() => self.number(),
+            Some(c) if c.is_ascii_lowercase() => {
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_' || c == '@');
+                self.atom(&name)
+            }
+            Some(_) => Err(self.error("expected a term")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn list(&mut self) -> Result<Term, ParseError> {
+        self.expect('[')?;
+        self.skip_whitespace();
+        if self.peek_char() == Some(']') {
+            self.next_char();
+            return Ok(Term::NIL);
+        }
+
+        let mut elements = Vec::new();
+        loop {
+            elements.push(self.term()?);
+            self.skip_whitespace();
+            match self.next_char() {
+                Some(',') => continue,
+                Some(']') => return Ok(self.process.list_from_slice(&elements)?),
+                Some('|') => {
+                    let tail = self.term()?;
+                    self.skip_whitespace();
+                    self.expect(']')?;
+                    return Ok(self.process.improper_list_from_slice(&elements, tail)?);
+                }
+                _ => return Err(self.error("expected `,`, `|` or `]`")),
+            }
+        }
+    }
+
+    fn map(&mut self) -> Result<Term, ParseError> {
+        self.expect('#')?;
+        self.expect('{')?;
+        self.skip_whitespace();
+
+        let mut entries = Vec::new();
+        if self.peek_char() == Some('}') {
+            self.next_char();
+        } else {
+            loop {
+                let key = self.term()?;
+                self.skip_whitespace();
+                self.expect('=')?;
+                self.expect('>')?;
+                let value = self.term()?;
+                entries.push((key, value));
+
+                self.skip_whitespace();
+                match self.next_char() {
+                    Some(',') => continue,
+                    Some('}') => break,
+                    _ => return Err(self.error("expected `,` or `}`")),
+                }
+            }
+        }
+
+        Ok(self.process.map_from_slice(&entries)?)
+    }
+
+    fn binary(&mut self) -> Result<Term, ParseError> {
+        self.expect('<')?;
+        self.expect('<')?;
+        self.skip_whitespace();
+
+        let mut bytes = Vec::new();
+        if self.peek_char() == Some('>') {
+            self.expect('>')?;
+            self.expect('>')?;
+            return Ok(self.process.binary_from_bytes(&bytes)?);
+        }
+
+        loop {
+            self.skip_whitespace();
+            match self.peek_char() {
+                Some('"') => bytes.extend(self.quoted('"')?.into_bytes()),
+                Some(c) if c.is_ascii_digit() => {
+                    let position = self.position();
+                    let digits = self.take_while(|c| c.is_ascii_digit());
+                    match digits.parse::<u8>() {
+                        Ok(byte) => bytes.push(byte),
+                        Err(_) => {
+                            return Err(ParseError::Syntax {
+                                position,
+                                message: "binary segments must be bytes".to_string(),
+                            })
+                        }
+                    }
+                }
+                _ => return Err(self.error("expected a byte or string")),
+            }
+
+            self.skip_whitespace();
+            match self.next_char() {
+                Some(',') => continue,
+                Some('>') => {
+                    self.expect('>')?;
+                    return Ok(self.process.binary_from_bytes(&bytes)?);
+                }
+                _ => return Err(self.error("expected `,` or `>>`")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Term, ParseError> {
+        let start = self.position();
+        let negative = match self.peek_char() {
+            Some('-') => {
+                self.next_char();
+                true
+            }
+            Some('+') => {
+                self.next_char();
+                false
+            }
+            _ => false,
+        };
+
+        let mut digits = self.take_while(|c| c.is_ascii_digit() || c == '_');
+        digits.retain(|c| c != '_');
+        if digits.is_empty() {
+            return Err(self.error("expected digits"));
+        }
+
+        let (radix, digits) = if self.peek_char() == Some('#') {
+            self.next_char();
+            let radix: u32 = digits.parse().unwrap_or(0);
+            if radix < 2 || radix > 36 {
+                return Err(ParseError::Syntax {
+                    position: start,
+                    message: format!("invalid base {}", digits),
+                });
+            }
+            (radix, self.take_while(|c| c.is_ascii_alphanumeric()))
+        } else if self.peek_char() == Some('.') && self.peek_second_char_is_digit() {
+            self.next_char();
+            let fraction = self.take_while(|c| c.is_ascii_digit());
+            let mut float = format!("{}.{}", digits, fraction);
+            if let Some(e) = self.peek_char().filter(|c| *c == 'e' || *c == 'E') {
+                self.next_char();
+                float.push(e);
+                if let Some(sign) = self.peek_char().filter(|c| *c == '-' || *c == '+') {
+                    self.next_char();
+                    float.push(sign);
+                }
+                float.push_str(&self.take_while(|c| c.is_ascii_digit()));
+            }
+
+            let value: f64 = float.parse().map_err(|_| self.error("invalid float"))?;
+            let value = if negative { -value } else { value };
+            return Ok(self.process.float(value)?);
+        } else {
+            (10, digits)
+        };
+
+        match BigInt::parse_bytes(digits.as_bytes(), radix) {
+            Some(integer) => {
+                let integer = if negative { -integer } else { integer };
+                Ok(self.process.integer(integer)?)
+            }
+            None => Err(ParseError::Syntax {
+                position: start,
+                message: "invalid integer".to_string(),
+            }),
+        }
+    }
+
+    fn atom(&mut self, name: &str) -> Result<Term, ParseError> {
+        match Atom::try_from_str(name) {
+            Ok(_) => Ok(atom_unchecked(name)),
+            Err(error) => Err(self.error(&error.to_string())),
+        }
+    }
+
+    // Parses the contents of a string or quoted atom, handling escapes
+    fn quoted(&mut self, quote: char) -> Result<String, ParseError> {
+        self.expect(quote)?;
+
+        let mut string = String::new();
+        loop {
+            match self.next_char() {
+                Some(c) if c == quote => return Ok(string),
+                Some('\\') => string.push(self.escape()?),
+                Some(c) => string.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, ParseError> {
+        match self.next_char() {
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some('s') => Ok(' '),
+            Some('e') => Ok('\x1b'),
+            Some('0') => Ok('\0'),
+            Some(c) => Ok(c),
+            None => Err(self.error("unterminated escape")),
+        }
+    }
+
+    // Parses comma separated terms up to the closing `end`
+    fn sequence(&mut self, end: char) -> Result<Vec<Term>, ParseError> {
+        let mut terms = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek_char() == Some(end) {
+            self.next_char();
+            return Ok(terms);
+        }
+
+        loop {
+            terms.push(self.term()?);
+            self.skip_whitespace();
+            match self.next_char() {
+                Some(',') => continue,
+                Some(c) if c == end => return Ok(terms),
+                _ => return Err(self.error(&format!("expected `,` or `{}`", end))),
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        match self.peek_char() {
+            Some(c) if c == expected => {
+                self.next_char();
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected `{}`", expected))),
+        }
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut taken = String::new();
+        while let Some(c) = self.peek_char().filter(|c| predicate(*c)) {
+            self.next_char();
+            taken.push(c);
+        }
+        taken
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn peek_second_char_is_digit(&self) -> bool {
+        let mut chars = self.chars.clone();
+        chars.next();
+        chars.next().map_or(false, |(_, c)| c.is_ascii_digit())
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        self.chars.next().map(|(_, c)| c)
+    }
+
+    fn position(&mut self) -> usize {
+        let end = self.input.len();
+        self.chars.peek().map_or(end, |(position, _)| *position)
+    }
+
+    fn error(&mut self, message: &str) -> ParseError {
+        ParseError::Syntax {
+            position: self.position(),
+            message: message.to_string(),
+        }
+    }
+}
@@ -3,6 +3,7 @@ use std::sync::Arc;
 
 use libeir_ir::{Function, LiveValues, Module};
 
+use liblumen_alloc::erts::exception;
 use liblumen_alloc::erts::process::code::Result;
 use liblumen_alloc::erts::process::Process;
 use liblumen_alloc::erts::term::{Atom, Term};
@@ -14,26 +15,54 @@ pub enum ResolvedFunction<'a> {
 
 pub struct ModuleRegistry {
     map: HashMap<Atom, ModuleType>,
+    /// The previous version of a module, still used by processes that were
+    /// running it when the current version was loaded
+    old: HashMap<Atom, ErlangModule>,
+    next_version: usize,
 }
 
+/// Returned when loading a module while its old code is still in use
+#[derive(Debug)]
+pub struct NotPurged(pub Atom);
+
 impl ModuleRegistry {
     pub fn new() -> Self {
         ModuleRegistry {
             map: HashMap::new(),
+            old: HashMap::new(),
+            next_version: 0,
         }
     }
 
-    pub fn register_erlang_module(&mut self, module: Module) {
-        let erl_module = ErlangModule::from_eir(module);
-        match self.map.remove(&erl_module.name) {
-            None => self
-                .map
-                .insert(erl_module.name, ModuleType::Erlang(erl_module)),
+    /// Loads a module. If the module is already loaded, that version becomes
+    /// old code, which fails if the module already has old code that has not
+    /// been purged.
+    pub fn register_erlang_module(&mut self, module: Module) -> Result<(), NotPurged> {
+        let name = Atom::try_from_str(module.name.as_str()).unwrap();
+        if self.old.contains_key(&name) && self.current_version(name).is_some() {
+            return Err(NotPurged(name));
+        }
+
+        let erl_module = ErlangModule::from_eir(module, self.next_version);
+        self.next_version += 1;
+
+        match self.map.remove(&name) {
+            None => self.map.insert(name, ModuleType::Erlang(erl_module)),
             Some(ModuleType::Native(native)) => self
                 .map
-                .insert(erl_module.name, ModuleType::Overlayed(erl_module, native)),
-            _ => panic!(),
+                .insert(name, ModuleType::Overlayed(erl_module, native)),
+            Some(ModuleType::Erlang(current)) => {
+                self.old.insert(name, current);
+                self.map.insert(name, ModuleType::Erlang(erl_module))
+            }
+            Some(ModuleType::Overlayed(current, native)) => {
+                self.old.insert(name, current);
+                self.map
+                    .insert(name, ModuleType::Overlayed(erl_module, native))
+            }
         };
+
+        Ok(())
     }
 
     pub fn register_native_module(&mut self, native: NativeModule) {
@@ -46,13 +75,55 @@ impl ModuleRegistry {
         };
     }
 
+    /// Makes the current version of an Erlang module old code, so that calls
+    /// to it no longer resolve. Returns `Err` if the module already has old
+    /// code and `Ok(false)` if it has no current version.
+    pub fn delete_erlang_module(&mut self, module: Atom) -> Result<bool, NotPurged> {
+        if self.current_version(module).is_none() {
+            return Ok(false);
+        }
+        if self.old.contains_key(&module) {
+            return Err(NotPurged(module));
+        }
+
+        let current = match self.map.remove(&module).unwrap() {
+            ModuleType::Erlang(erl) => erl,
+            ModuleType::Overlayed(erl, native) => {
+                self.map.insert(module, ModuleType::Native(native));
+                erl
+            }
+            ModuleType::Native(_) => unreachable!(),
+        };
+        self.old.insert(module, current);
+
+        Ok(true)
+    }
+
+    /// Removes the old code of a module, returning its version
+    pub fn purge_erlang_module(&mut self, module: Atom) -> Option<usize> {
+        self.old.remove(&module).map(|old| old.version)
+    }
+
+    pub fn current_version(&self, module: Atom) -> Option<usize> {
+        match self.map.get(&module) {
+            Some(ModuleType::Erlang(erl)) | Some(ModuleType::Overlayed(erl, _)) => {
+                Some(erl.version)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn old_version(&self, module: Atom) -> Option<usize> {
+        self.old.get(&module).map(|old| old.version)
+    }
+
+    /// Looks up a function in the newest version of a module
     pub fn lookup_function(
         &self,
         module: Atom,
         function: Atom,
         arity: usize,
     ) -> Option<ResolvedFunction> {
-        println!("LOOKUP {:?}:{:?}/{}", module, function, arity,);
         match self.map.get(&module) {
             None => None,
             Some(ModuleType::Erlang(erl)) => erl
@@ -75,11 +146,32 @@ impl ModuleRegistry {
             }
         }
     }
+
+    /// Looks up an interpreted function in a specific version of a module,
+    /// which may be old code
+    pub fn lookup_erlang_function(
+        &self,
+        module: Atom,
+        function: Atom,
+        arity: usize,
+        version: usize,
+    ) -> Option<&ErlangFunction> {
+        let erl = match self.map.get(&module) {
+            Some(ModuleType::Erlang(erl)) | Some(ModuleType::Overlayed(erl, _))
+                if erl.version == version =>
+            {
+                erl
+            }
+            _ => self.old.get(&module).filter(|old| old.version == version)?,
+        };
+
+        erl.functions.get(&(function, arity))
+    }
 }
 
 #[derive(Copy, Clone)]
 pub enum NativeFunctionKind {
-    Simple(fn(&Arc<Process>, &[Term]) -> std::result::Result<Term, ()>),
+    Simple(fn(&Arc<Process>, &[Term]) -> exception::Result),
     Yielding(fn(&Arc<Process>, &[Term]) -> Result),
 }
 
@@ -99,7 +191,7 @@ impl NativeModule {
         &mut self,
         name: Atom,
         arity: usize,
-        fun: fn(&Arc<Process>, &[Term]) -> std::result::Result<Term, ()>,
+        fun: fn(&Arc<Process>, &[Term]) -> exception::Result,
     ) {
         self.functions
             .insert((name, arity), NativeFunctionKind::Simple(fun));
@@ -119,15 +211,18 @@ impl NativeModule {
 pub struct ErlangFunction {
     pub fun: Function,
     pub live: LiveValues,
+    pub module: Atom,
+    pub version: usize,
 }
 
 pub struct ErlangModule {
     pub name: Atom,
+    pub version: usize,
     pub functions: HashMap<(Atom, usize), ErlangFunction>,
 }
 
 impl ErlangModule {
-    pub fn from_eir(module: Module) -> Self {
+    pub fn from_eir(module: Module, version: usize) -> Self {
         let name_atom = Atom::try_from_str(module.name.as_str()).unwrap();
         let functions = module
             .functions
@@ -136,6 +231,8 @@ impl ErlangModule {
                 let nfun = ErlangFunction {
                     live: fun.live_values(),
                     fun: fun.clone(),
+                    module: name_atom,
+                    version,
                 };
                 let name = Atom::try_from_str(fun.ident().name.as_str()).unwrap();
                 ((name, fun.ident().arity), nfun)
@@ -143,6 +240,7 @@ impl ErlangModule {
             .collect();
         ErlangModule {
             name: name_atom,
+            version,
             functions,
         }
     }
@@ -0,0 +1,90 @@
+use std::convert::TryInto;
+
+use liblumen_alloc::badarg;
+use liblumen_alloc::erts::exception;
+use liblumen_alloc::erts::term::{atom_unchecked, Atom, Pid, Term};
+
+use crate::module::NativeModule;
+use crate::VM;
+
+pub fn make_code() -> NativeModule {
+    let mut native = NativeModule::new(Atom::try_from_str("code").unwrap());
+
+    native.add_simple(Atom::try_from_str("delete").unwrap(), 1, |_, args| {
+        assert!(args.len() == 1);
+        delete_1(args[0])
+    });
+
+    native.add_simple(Atom::try_from_str("purge").unwrap(), 1, |_, args| {
+        assert!(args.len() == 1);
+        purge_1(args[0])
+    });
+
+    native.add_simple(Atom::try_from_str("soft_purge").unwrap(), 1, |_, args| {
+        assert!(args.len() == 1);
+        soft_purge_1(args[0])
+    });
+
+    native
+}
+
+fn delete_1(module: Term) -> exception::Result {
+    let module: Atom = module.try_into()?;
+    let deleted = VM
+        .modules
+        .write()
+        .unwrap()
+        .delete_erlang_module(module)
+        .unwrap_or(false);
+
+    Ok(deleted.into())
+}
+
+fn purge_1(module: Term) -> exception::Result {
+    let module: Atom = module.try_into()?;
+
+    Ok(VM.purge_module(module).into())
+}
+
+fn soft_purge_1(module: Term) -> exception::Result {
+    let module: Atom = module.try_into()?;
+
+    Ok(VM.soft_purge_module(module).into())
+}
+
+// The code loading BIFs in `erlang`
+
+pub fn check_old_code_1(module: Term) -> exception::Result {
+    let module: Atom = module.try_into()?;
+    let has_old_code = VM.modules.read().unwrap().old_version(module).is_some();
+
+    Ok(has_old_code.into())
+}
+
+pub fn check_process_code_2(pid: Term, module: Term) -> exception::Result {
+    let pid: Pid = pid.try_into()?;
+    let module: Atom = module.try_into()?;
+
+    Ok(VM.check_process_code(pid, module).into())
+}
+
+pub fn delete_module_1(module: Term) -> exception::Result {
+    let module: Atom = module.try_into()?;
+
+    match VM.modules.write().unwrap().delete_erlang_module(module) {
+        Ok(true) => Ok(true.into()),
+        Ok(false) => Ok(atom_unchecked("undefined")),
+        Err(_) => Err(badarg!().into()),
+    }
+}
+
+/// Removes old code without checking for processes executing it, which is
+/// left to `code:purge/1`
+pub fn purge_module_1(module: Term) -> exception::Result {
+    let module: Atom = module.try_into()?;
+
+    match VM.modules.write().unwrap().purge_erlang_module(module) {
+        Some(_) => Ok(true.into()),
+        None => Err(badarg!().into()),
+    }
+}
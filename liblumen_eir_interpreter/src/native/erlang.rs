@@ -1,85 +1,74 @@
-use liblumen_alloc::erts::term::Atom;
+use std::sync::Arc;
+
+use liblumen_alloc::erts::exception::system::Alloc;
+use liblumen_alloc::erts::process::Process;
+use liblumen_alloc::erts::term::{Atom, Term};
 use liblumen_alloc::erts::ModuleFunctionArity;
 use lumen_runtime::otp::erlang;
 
+use super::code;
 use crate::module::NativeModule;
 
 pub fn make_erlang() -> NativeModule {
-    let mut native = NativeModule::new(Atom::try_from_str("erlang").unwrap());
-
-    native.add_simple(Atom::try_from_str("<").unwrap(), 2, |_proc, args| {
-        assert!(args.len() == 2);
-        Ok(erlang::is_less_than_2(args[0], args[1]))
-    });
-    native.add_simple(Atom::try_from_str("=<").unwrap(), 2, |_proc, args| {
-        assert!(args.len() == 2);
-        Ok(erlang::is_equal_or_less_than_2(args[0], args[1]))
-    });
-    native.add_simple(Atom::try_from_str("=:=").unwrap(), 2, |_proc, args| {
-        assert!(args.len() == 2);
-        Ok(erlang::are_exactly_equal_2(args[0], args[1]))
-    });
-
-    native.add_simple(Atom::try_from_str("spawn").unwrap(), 3, |proc, args| {
-        assert!(args.len() == 3);
-
-        let ret = {
-            let mfa = ModuleFunctionArity {
-                module: Atom::try_from_str("lumen_eir_interpreter_intrinsics").unwrap(),
-                function: Atom::try_from_str("return_clean").unwrap(),
-                arity: 1,
-            };
-            proc.closure(
-                proc.pid_term(),
-                mfa.into(),
-                crate::code::return_clean,
-                vec![],
-            )
-            .unwrap()
-        };
+    let mut native = super::otp_module("erlang");
 
-        let inner_args = proc.cons(ret, proc.cons(ret, args[2]).unwrap()).unwrap();
-        Ok(erlang::spawn_3::native(proc, args[0], args[1], inner_args).unwrap())
-    });
-
-    native.add_simple(Atom::try_from_str("send").unwrap(), 2, |proc, args| {
-        assert!(args.len() == 2);
-        Ok(erlang::send_2(args[0], args[1], proc).unwrap())
-    });
-
-    native.add_simple(Atom::try_from_str("-").unwrap(), 2, |proc, args| {
-        assert!(args.len() == 2);
-        Ok(erlang::subtract_2::native(proc, args[0], args[1]).unwrap())
-    });
-
-    native.add_simple(Atom::try_from_str("+").unwrap(), 2, |proc, args| {
-        assert!(args.len() == 2);
-        Ok(erlang::add_2::native(proc, args[0], args[1]).unwrap())
-    });
+    native.add_simple(
+        Atom::try_from_str("check_old_code").unwrap(),
+        1,
+        |_, args| {
+            assert!(args.len() == 1);
+            code::check_old_code_1(args[0])
+        },
+    );
 
-    native.add_simple(Atom::try_from_str("self").unwrap(), 0, |proc, args| {
-        assert!(args.len() == 0);
-        Ok(proc.pid_term())
-    });
+    native.add_simple(
+        Atom::try_from_str("check_process_code").unwrap(),
+        2,
+        |_, args| {
+            assert!(args.len() == 2);
+            code::check_process_code_2(args[0], args[1])
+        },
+    );
 
     native.add_simple(
-        Atom::try_from_str("is_integer").unwrap(),
+        Atom::try_from_str("delete_module").unwrap(),
         1,
-        |_proc, args| {
+        |_, args| {
             assert!(args.len() == 1);
-            Ok(erlang::is_integer_1(args[0]))
+            code::delete_module_1(args[0])
         },
     );
 
+    native.add_simple(Atom::try_from_str("purge_module").unwrap(), 1, |_, args| {
+        assert!(args.len() == 1);
+        code::purge_module_1(args[0])
+    });
+
+    // Spawned interpreted functions take return and throw continuations
+    // before their arguments, which have to be added here, so these replace
+    // the `spawn` functions from the table.
+    native.add_simple(Atom::try_from_str("spawn").unwrap(), 3, |proc, args| {
+        assert!(args.len() == 3);
+        let inner_args = spawn_arguments(proc, args[2])?;
+        erlang::spawn_3::native(proc, args[0], args[1], inner_args)
+    });
+
     native.add_simple(
-        Atom::try_from_str("monotonic_time").unwrap(),
-        0,
+        Atom::try_from_str("spawn_link").unwrap(),
+        3,
         |proc, args| {
-            assert!(args.len() == 0);
-            Ok(erlang::monotonic_time_0::native(proc).unwrap())
+            assert!(args.len() == 3);
+            let inner_args = spawn_arguments(proc, args[2])?;
+            erlang::spawn_link_3::native(proc, args[0], args[1], inner_args)
         },
     );
 
+    native.add_simple(Atom::try_from_str("spawn_opt").unwrap(), 4, |proc, args| {
+        assert!(args.len() == 4);
+        let inner_args = spawn_arguments(proc, args[2])?;
+        erlang::spawn_opt_4::native(proc, args[0], args[1], inner_args, args[3])
+    });
+
     native.add_yielding(Atom::try_from_str("apply").unwrap(), 3, |proc, args| {
         assert!(args.len() == 5);
 
@@ -94,3 +83,36 @@ pub fn make_erlang() -> NativeModule {
 
     native
 }
+
+// Prepends the continuations for the top of a spawned process' call chain to
+// the argument list of the function it runs
+pub fn spawn_arguments(proc: &Arc<Process>, arguments: Term) -> Result<Term, Alloc> {
+    let ret = {
+        let mfa = ModuleFunctionArity {
+            module: Atom::try_from_str("lumen_eir_interpreter_intrinsics").unwrap(),
+            function: Atom::try_from_str("return_clean").unwrap(),
+            arity: 1,
+        };
+        proc.closure(
+            proc.pid_term(),
+            mfa.into(),
+            crate::code::return_clean,
+            vec![],
+        )?
+    };
+    let throw = {
+        let mfa = ModuleFunctionArity {
+            module: Atom::try_from_str("lumen_eir_interpreter_intrinsics").unwrap(),
+            function: Atom::try_from_str("return_throw").unwrap(),
+            arity: 3,
+        };
+        proc.closure(
+            proc.pid_term(),
+            mfa.into(),
+            crate::code::return_throw,
+            vec![],
+        )?
+    };
+
+    proc.cons(ret, proc.cons(throw, arguments)?)
+}
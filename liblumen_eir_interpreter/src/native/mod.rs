@@ -1,5 +1,45 @@
+//! The native modules the interpreter starts with, which expose the BIFs of
+//! `lumen_runtime::otp`.
+//!
+//! The functions come from the table in `lumen_runtime::otp::natives`, which
+//! the `.beam` loader shares, and which leaves out functions that run as stack
+//! frames and call back into Erlang, such as `timer:tc/3`. `spawn` and `apply`
+//! are wrapped by hand in `erlang.rs`, and the code loading BIFs, which need
+//! the interpreter's module registry, are defined in `code.rs`.
+
+use liblumen_alloc::erts::term::Atom;
+
+use crate::module::NativeModule;
+
+mod code;
 mod erlang;
-mod maps;
 
-pub use erlang::make_erlang;
-pub use maps::make_maps;
+pub use code::make_code;
+pub use erlang::{make_erlang, spawn_arguments};
+
+pub fn make_binary() -> NativeModule {
+    otp_module("binary")
+}
+
+pub fn make_lists() -> NativeModule {
+    otp_module("lists")
+}
+
+pub fn make_maps() -> NativeModule {
+    otp_module("maps")
+}
+
+// A native module with the functions of `lumen_runtime::otp` for the Erlang
+// module `name`
+fn otp_module(name: &str) -> NativeModule {
+    let name = Atom::try_from_str(name).unwrap();
+    let mut native = NativeModule::new(name);
+
+    for (&(module, function, arity), &fun) in lumen_runtime::otp::natives().iter() {
+        if module == name {
+            native.add_simple(function, arity as usize, fun);
+        }
+    }
+
+    native
+}
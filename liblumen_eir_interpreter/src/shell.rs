@@ -0,0 +1,535 @@
+//! An interactive shell, which evaluates Erlang expressions in a long-lived
+//! process.
+//!
+//! Each expression is lowered as the `eval` function of a throwaway module,
+//! which takes the values of the bound variables it uses as arguments and
+//! returns its value along with the values of the variables it binds. The
+//! function is applied by the shell process, which hands the result back
+//! with `lumen_eir_shell:reply/1`. The module is unloaded once no binding
+//! holds one of its funs and no process is running it.
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Once};
+
+use libeir_diagnostics::{ColorChoice, Emitter, StandardStreamEmitter};
+use libeir_ir::Module;
+use libeir_passes::PassManager;
+use libeir_syntax_erl::ast::Module as ErlAstModule;
+use libeir_syntax_erl::lower_module;
+use libeir_syntax_erl::{ParseConfig, Parser};
+
+use liblumen_alloc::erts::exception;
+use liblumen_alloc::erts::process::{Process, Status};
+use liblumen_alloc::erts::term::{atom_unchecked, Atom, Boxed, Term, Tuple, TypedTerm};
+
+use lumen_runtime::otp::erlang;
+use lumen_runtime::process::spawn::options::Options;
+use lumen_runtime::scheduler::Scheduler;
+
+use crate::module::NativeModule;
+use crate::vm::uses_code;
+use crate::VM;
+
+const SHELL_MODULE: &str = "lumen_eir_shell";
+
+const SHELL_SOURCE: &str = "
+-module(lumen_eir_shell).
+
+loop() ->
+    receive
+        {eval, Module, Arguments} ->
+            Reply = try
+                {ok, erlang:apply(Module, eval, Arguments)}
+            catch
+                Class:Reason:Stacktrace -> {error, Class, Reason, Stacktrace}
+            end,
+            lumen_eir_shell:reply(Reply),
+            loop()
+    end.
+";
+
+// Bound to the value of the expression in the generated `eval` function
+const VALUE_VARIABLE: &str = "LumenEirShellValue__";
+
+static LOAD_SHELL_MODULE: Once = Once::new();
+
+// Numbers the `eval` modules of all shells, so their names never clash
+static EVALS: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Shell {
+    driver: Arc<Process>,
+    shell: Arc<Process>,
+    bindings: BTreeMap<String, Term>,
+    expressions: usize,
+    /// The `eval` modules of earlier expressions that are still in use, by
+    /// funs in the bindings or by processes the expressions spawned
+    eval_modules: Vec<Atom>,
+}
+
+impl Shell {
+    /// Loads the shell's own module, if no shell has yet, and starts the
+    /// shell process
+    pub fn new() -> Self {
+        LOAD_SHELL_MODULE.call_once(|| {
+            let mut native = NativeModule::new(Atom::try_from_str(SHELL_MODULE).unwrap());
+            native.add_simple(Atom::try_from_str("reply").unwrap(), 1, |process, args| {
+                reply_1(process, args[0])
+            });
+
+            let eir_module = lower_string(SHELL_SOURCE, true).unwrap();
+            let mut modules = VM.modules.write().unwrap();
+            modules.register_native_module(native);
+            modules.register_erlang_module(eir_module).unwrap();
+        });
+
+        let driver = Scheduler::current().spawn_init(100_000).unwrap();
+        let shell = spawn_shell(&driver);
+
+        Shell {
+            driver,
+            shell,
+            bindings: BTreeMap::new(),
+            expressions: 0,
+            eval_modules: Vec::new(),
+        }
+    }
+
+    /// Reads expressions from stdin until it is closed or `q().` is entered
+    pub fn run(&mut self) {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+
+        loop {
+            print!("{}> ", self.expressions + 1);
+            io::stdout().flush().unwrap();
+
+            let mut input = String::new();
+            loop {
+                match lines.next() {
+                    Some(Ok(line)) => {
+                        input.push_str(&line);
+                        input.push('\n');
+                        if line.trim_end().ends_with('.') {
+                            break;
+                        }
+                    }
+                    _ => return,
+                }
+            }
+
+            let input = input.trim().trim_end_matches('.').trim();
+            match input {
+                "" => continue,
+                "q()" => return,
+                _ => self.eval_line(input),
+            }
+        }
+    }
+
+    /// Evaluates one line of input, without the terminating `.`
+    pub fn eval_line(&mut self, input: &str) {
+        self.expressions += 1;
+
+        if input == "f()" {
+            self.bindings.clear();
+            println!("ok");
+        } else if input == "b()" {
+            for (name, value) in self.bindings.iter() {
+                println!("{} = {}", name, value);
+            }
+        } else if input.starts_with("c(") && input.ends_with(')') {
+            let file = input[2..input.len() - 1]
+                .trim()
+                .trim_matches(|c| c == '"' || c == '\'');
+            match compile_file(file) {
+                Ok(module) => println!("{{ok,{}}}", module.name()),
+                Err(()) => println!("error"),
+            }
+        } else {
+            match self.eval(input) {
+                Ok(value) => println!("{}", value),
+                Err(message) => println!("{}", message),
+            }
+        }
+    }
+
+    /// Evaluates an expression, keeping the variables it binds
+    pub fn eval(&mut self, expression: &str) -> Result<Term, String> {
+        let variables = variables(expression);
+        let (bound, unbound): (Vec<_>, Vec<_>) = variables
+            .into_iter()
+            .partition(|variable| self.bindings.contains_key(variable));
+        let unbound: Vec<_> = unbound
+            .into_iter()
+            .filter(|variable| !variable.starts_with('_'))
+            .collect();
+
+        let module_name = format!(
+            "lumen_eir_shell_{}",
+            EVALS.fetch_add(1, Ordering::SeqCst) + 1
+        );
+        // Variables that are only bound inside funs or unsafely in branches
+        // can't be returned, in which case the expression is evaluated
+        // without keeping its bindings
+        let eir_module = if unbound.is_empty() {
+            lower_string(&eval_source(&module_name, expression, &bound, &[]), true)
+        } else {
+            lower_string(
+                &eval_source(&module_name, expression, &bound, &unbound),
+                false,
+            )
+            .or_else(|()| lower_string(&eval_source(&module_name, expression, &bound, &[]), true))
+        }
+        .map_err(|()| "error".to_string())?;
+        VM.modules
+            .write()
+            .unwrap()
+            .register_erlang_module(eir_module)
+            .unwrap();
+
+        self.eval_modules
+            .push(Atom::try_from_str(&module_name).unwrap());
+
+        let arguments: Vec<Term> = bound.iter().map(|name| self.bindings[name]).collect();
+        let result = self
+            .request(&module_name, &arguments)
+            .and_then(|reply| self.take_reply(reply, unbound));
+        self.purge_eval_modules();
+
+        result
+    }
+
+    // Binds the variables bound by an expression, returning its value or
+    // the exception it raised
+    fn take_reply(&mut self, reply: Term, unbound: Vec<String>) -> Result<Term, String> {
+        let reply: Boxed<Tuple> = reply.try_into().unwrap();
+        if reply[0] == atom_unchecked("ok") {
+            let result: Boxed<Tuple> = reply[1].try_into().unwrap();
+            let values: Vec<Term> = match result[1].to_typed_term().unwrap() {
+                TypedTerm::List(cons) => cons.into_iter().map(|value| value.unwrap()).collect(),
+                _ => Vec::new(),
+            };
+            for (name, value) in unbound.into_iter().zip(values.into_iter()) {
+                self.bindings.insert(name, value);
+            }
+
+            Ok(result[0])
+        } else {
+            let class: Atom = reply[1].try_into().unwrap();
+            Err(format!(
+                "** exception {}: {}\n     in {}",
+                class.name(),
+                reply[2],
+                reply[3]
+            ))
+        }
+    }
+
+    // Unloads the `eval` modules that nothing uses anymore
+    fn purge_eval_modules(&mut self) {
+        let bindings = &self.bindings;
+        self.eval_modules.retain(|&module| {
+            let version = {
+                let modules = VM.modules.read().unwrap();
+                modules
+                    .current_version(module)
+                    .or_else(|| modules.old_version(module))
+            };
+            let version = match version {
+                Some(version) => version,
+                None => return false,
+            };
+            if bindings
+                .values()
+                .any(|value| uses_code(*value, module, version))
+            {
+                return true;
+            }
+
+            // Each expression has its own module, so there is never old code
+            // to purge before deleting it
+            VM.modules
+                .write()
+                .unwrap()
+                .delete_erlang_module(module)
+                .unwrap();
+            !VM.soft_purge_module(module)
+        });
+    }
+
+    // Has the shell process evaluate `module:eval(arguments...)`, and returns
+    // its reply on the heap of the driver
+    fn request(&mut self, module: &str, arguments: &[Term]) -> Result<Term, String> {
+        let message = {
+            let arguments = self
+                .driver
+                .list_from_slice(arguments)
+                .map_err(|alloc| format!("{:?}", alloc))?;
+            self.driver
+                .tuple_from_slice(&[atom_unchecked("eval"), atom_unchecked(module), arguments])
+                .map_err(|alloc| format!("{:?}", alloc))?
+        };
+
+        VM.expect_reply(self.shell.pid(), self.driver.clone());
+        erlang::send_2(self.shell.pid_term(), message, &self.driver)
+            .map_err(|exception| format!("{:?}", exception))?;
+
+        loop {
+            let ran = Scheduler::current().run_through(&self.shell);
+
+            if let Some(reply) = VM.take_reply(self.shell.pid()) {
+                return Ok(reply);
+            }
+
+            let error = match *self.shell.status.read() {
+                Status::Exiting(ref exception) => {
+                    Some(format!("** shell process exited: {}", exception.reason))
+                }
+                // Nothing will wake the shell up, so give up on the expression
+                // rather than hanging
+                Status::Waiting if !ran && !crate::waiting_on_timer(&self.shell) => {
+                    Some("** the expression is blocked waiting for a message".to_string())
+                }
+                _ => None,
+            };
+            if let Some(error) = error {
+                VM.forget_reply(self.shell.pid());
+                self.restart();
+                return Err(error);
+            }
+        }
+    }
+
+    fn restart(&mut self) {
+        if !self.shell.is_exiting() {
+            self.shell
+                .exception(liblumen_alloc::exit!(atom_unchecked("killed")));
+        }
+        self.shell = spawn_shell(&self.driver);
+    }
+}
+
+fn spawn_shell(driver: &Arc<Process>) -> Arc<Process> {
+    let arguments = crate::native::spawn_arguments(driver, Term::NIL).unwrap();
+
+    let mut options: Options = Default::default();
+    options.min_heap_size = Some(50_000);
+
+    Scheduler::spawn_apply_3(
+        driver,
+        options,
+        Atom::try_from_str(SHELL_MODULE).unwrap(),
+        Atom::try_from_str("loop").unwrap(),
+        arguments,
+    )
+    .unwrap()
+}
+
+/// Loads a `.erl` file, replacing the current version of its module. Any
+/// processes still running the version before that are killed.
+pub fn compile_file(file: &str) -> Result<Atom, ()> {
+    let path = if Path::new(file).extension().is_some() {
+        file.to_string()
+    } else {
+        format!("{}.erl", file)
+    };
+
+    let parser = Parser::new(ParseConfig::default());
+    let parsed: ErlAstModule = match parser.parse_file::<_, ErlAstModule>(&path) {
+        Ok(parsed) => parsed,
+        Err(errors) => {
+            let emitter = StandardStreamEmitter::new(ColorChoice::Auto)
+                .set_codemap(parser.config.codemap.clone());
+            for error in errors.iter() {
+                emitter.diagnostic(&error.to_diagnostic()).unwrap();
+            }
+            return Err(());
+        }
+    };
+    let eir_module = lower(&parser, &parsed, true)?;
+
+    let name = Atom::try_from_str(eir_module.name.as_str()).unwrap();
+    VM.purge_module(name);
+    VM.modules
+        .write()
+        .unwrap()
+        .register_erlang_module(eir_module)
+        .unwrap();
+
+    Ok(name)
+}
+
+fn lower_string(source: &str, emit_diagnostics: bool) -> Result<Module, ()> {
+    let parser = Parser::new(ParseConfig::default());
+    match parser.parse_string::<&str, ErlAstModule>(source) {
+        Ok(parsed) => lower(&parser, &parsed, emit_diagnostics),
+        Err(errors) => {
+            if emit_diagnostics {
+                let emitter = StandardStreamEmitter::new(ColorChoice::Auto)
+                    .set_codemap(parser.config.codemap.clone());
+                for error in errors.iter() {
+                    emitter.diagnostic(&error.to_diagnostic()).unwrap();
+                }
+            }
+            Err(())
+        }
+    }
+}
+
+fn lower(parser: &Parser, parsed: &ErlAstModule, emit_diagnostics: bool) -> Result<Module, ()> {
+    let (result, messages) = lower_module(parsed);
+
+    if emit_diagnostics {
+        let emitter = StandardStreamEmitter::new(ColorChoice::Auto)
+            .set_codemap(parser.config.codemap.clone());
+        for message in messages.iter() {
+            emitter.diagnostic(&message.to_diagnostic()).unwrap();
+        }
+    }
+
+    let mut eir_module = result?;
+    for fun in eir_module.functions.values() {
+        fun.graph_validate_global();
+    }
+
+    let mut pass_manager = PassManager::default();
+    pass_manager.run(&mut eir_module);
+
+    Ok(eir_module)
+}
+
+// The module evaluating `expression`, whose `eval` function takes the values
+// of the `bound` variables and returns `{Value, [Unbound...]}`
+fn eval_source(module: &str, expression: &str, bound: &[String], unbound: &[String]) -> String {
+    format!(
+        "-module('{module}').\n\neval({bound}) ->\n    {value} = begin\n{expression}\n    end,\n    {{{value}, [{unbound}]}}.\n",
+        module = module,
+        bound = bound.join(", "),
+        value = VALUE_VARIABLE,
+        expression = expression,
+        unbound = unbound.join(", "),
+    )
+}
+
+/// The names of the variables in an expression, in order of first use
+fn variables(expression: &str) -> Vec<String> {
+    let mut variables: Vec<String> = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // Skip strings and quoted atoms
+            '"' | '\'' => {
+                while let Some(next) = chars.next() {
+                    match next {
+                        '\\' => {
+                            chars.next();
+                        }
+                        _ if next == c => break,
+                        _ => (),
+                    }
+                }
+            }
+            '$' => {
+                if chars.next() == Some('\\') {
+                    chars.next();
+                }
+            }
+            // Skip numbers, which may have digits in any base
+            _ if c.is_ascii_digit() => {
+                while let Some(&next) = chars.peek() {
+                    if next.is_alphanumeric() || next == '#' || next == '_' {
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+            }
+            // Skip macros
+            '?' => {
+                while let Some(&next) = chars.peek() {
+                    if next.is_alphanumeric() || next == '_' || next == '?' {
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+            }
+            '%' => {
+                while let Some(next) = chars.next() {
+                    if next == '\n' {
+                        break;
+                    }
+                }
+            }
+            _ if c.is_alphanumeric() || c == '_' => {
+                let mut name = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_alphanumeric() || next == '_' || next == '@' {
+                        name.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                let is_variable = c.is_uppercase() || (c == '_' && name.len() > 1);
+                if is_variable && !variables.contains(&name) {
+                    variables.push(name);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    variables
+}
+
+// Used by the shell process to hand the result of an expression back
+fn reply_1(process: &Process, reply: Term) -> exception::Result {
+    VM.reply(process, reply);
+    Ok(atom_unchecked("ok"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Shell;
+
+    use liblumen_alloc::erts::term::atom_unchecked;
+
+    use crate::VM;
+
+    #[test]
+    fn eval_modules_are_purged() {
+        let mut shell = Shell::new();
+        // Loading the shell module again would panic
+        let mut other = Shell::new();
+
+        shell.eval("X = 1 + 1").unwrap();
+        assert!(shell.eval_modules.is_empty());
+
+        // The fun's module stays loaded as long as the fun is bound
+        shell.eval("F = fun(_) -> X + 1 end").unwrap();
+        assert_eq!(1, shell.eval_modules.len());
+        let module = shell.eval_modules[0];
+
+        other.eval("ok").unwrap();
+        assert!(other.eval_modules.is_empty());
+        assert_eq!(1, shell.eval_modules.len());
+
+        shell.eval("3 = F(0)").unwrap();
+        assert_eq!(1, shell.eval_modules.len());
+
+        shell.eval_line("f()");
+        assert_eq!(atom_unchecked("ok"), shell.eval("ok").unwrap());
+        assert!(shell.eval_modules.is_empty());
+
+        let modules = VM.modules.read().unwrap();
+        assert_eq!(None, modules.current_version(module));
+        assert_eq!(None, modules.old_version(module));
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use libeir_ir::{Block, FunctionIdent};
+
+use liblumen_alloc::erts::process::Process;
+use liblumen_alloc::erts::term::{Atom, Pid, Term};
+
+/// What happened in a traced function
+pub enum Event<'a> {
+    /// The function was entered with the given arguments
+    Call(&'a [Term]),
+    /// The function returned the given value to its caller
+    Return(Term),
+    /// The function raised an exception with the given class and reason
+    Exception(Term, Term),
+    /// Execution moved to the given block of the function
+    Block(Block),
+}
+
+/// An event in a traced function, as passed to a `TraceSink`
+pub struct Trace<'a> {
+    pub pid: Pid,
+    pub module: Atom,
+    pub function: Atom,
+    pub arity: usize,
+    pub event: Event<'a>,
+}
+
+impl Display for Trace<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}:{}/{} ",
+            self.pid,
+            self.module.name(),
+            self.function.name(),
+            self.arity
+        )?;
+
+        match self.event {
+            Event::Call(arguments) => {
+                f.write_str("call (")?;
+                for (index, argument) in arguments.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", argument)?;
+                }
+                f.write_str(")")
+            }
+            Event::Return(value) => write!(f, "returned {}", value),
+            Event::Exception(class, reason) => write!(f, "raised {}:{}", class, reason),
+            Event::Block(block) => write!(f, "block {}", block),
+        }
+    }
+}
+
+/// Receives the events recorded by a `Tracer`
+pub trait TraceSink: Send + Sync {
+    fn record(&self, trace: &Trace);
+}
+
+/// Writes each event as a line to a writer, such as `std::io::stderr()`
+pub struct WriteSink<W: Write + Send>(Mutex<W>);
+
+impl<W: Write + Send> WriteSink<W> {
+    pub fn new(writer: W) -> Self {
+        WriteSink(Mutex::new(writer))
+    }
+}
+
+impl<W: Write + Send> TraceSink for WriteSink<W> {
+    fn record(&self, trace: &Trace) {
+        let mut writer = self.0.lock().unwrap();
+        // Tracing is best effort, and must not bring down the traced code
+        let _ = writeln!(writer, "{}", trace);
+    }
+}
+
+/// Selects traced functions by module and, optionally, function and arity.
+///
+/// Parsed from `module`, `module:function` or `module:function/arity`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+    pub module: Atom,
+    pub function: Option<Atom>,
+    pub arity: Option<usize>,
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (module, rest) = match spec.find(':') {
+            Some(index) => (&spec[..index], Some(&spec[index + 1..])),
+            None => (spec, None),
+        };
+        let (function, arity) = match rest {
+            Some(rest) => match rest.find('/') {
+                Some(index) => (Some(&rest[..index]), Some(&rest[index + 1..])),
+                None => (Some(rest), None),
+            },
+            None => (None, None),
+        };
+
+        if module.is_empty() || function.map_or(false, str::is_empty) {
+            return Err(format!("invalid trace filter `{}`", spec));
+        }
+        let arity = match arity {
+            Some(arity) => Some(
+                arity
+                    .parse()
+                    .map_err(|_| format!("invalid arity in trace filter `{}`", spec))?,
+            ),
+            None => None,
+        };
+
+        Ok(Filter {
+            module: Atom::try_from_str(module).map_err(|error| error.to_string())?,
+            function: match function {
+                Some(function) => {
+                    Some(Atom::try_from_str(function).map_err(|error| error.to_string())?)
+                }
+                None => None,
+            },
+            arity,
+        })
+    }
+
+    pub fn matches(&self, module: Atom, function: Atom, arity: usize) -> bool {
+        self.module == module
+            && self.function.map_or(true, |f| f == function)
+            && self.arity.map_or(true, |a| a == arity)
+    }
+}
+
+/// Records function calls, returns and exceptions in interpreted code.
+///
+/// Nothing is recorded until a sink is set and tracing is enabled, either for
+/// all processes or for individual ones. When filters are added, only the
+/// functions matching at least one of them are traced.
+#[derive(Default)]
+pub struct Tracer {
+    sink: Option<Arc<dyn TraceSink>>,
+    all_processes: bool,
+    processes: HashSet<Pid>,
+    filters: Vec<Filter>,
+    blocks: bool,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sink(&mut self, sink: Option<Arc<dyn TraceSink>>) {
+        self.sink = sink;
+    }
+
+    /// Traces every process, including those spawned later
+    pub fn trace_all_processes(&mut self, enable: bool) {
+        self.all_processes = enable;
+    }
+
+    pub fn trace_process(&mut self, pid: Pid, enable: bool) {
+        if enable {
+            self.processes.insert(pid);
+        } else {
+            self.processes.remove(&pid);
+        }
+    }
+
+    pub fn add_filter(&mut self, filter: Filter) {
+        self.filters.push(filter);
+    }
+
+    pub fn clear_filters(&mut self) {
+        self.filters.clear();
+    }
+
+    /// Also record every block executed in traced functions
+    pub fn trace_blocks(&mut self, enable: bool) {
+        self.blocks = enable;
+    }
+
+    /// Disables tracing and removes the sink and all filters
+    pub fn reset(&mut self) {
+        *self = Default::default();
+    }
+
+    fn is_active(&self) -> bool {
+        self.sink.is_some() && (self.all_processes || !self.processes.is_empty())
+    }
+
+    fn is_traced(&self, pid: Pid, module: Atom, function: Atom, arity: usize) -> bool {
+        (self.all_processes || self.processes.contains(&pid))
+            && (self.filters.is_empty()
+                || self
+                    .filters
+                    .iter()
+                    .any(|filter| filter.matches(module, function, arity)))
+    }
+
+    pub(crate) fn call(
+        &self,
+        process: &Process,
+        module: Atom,
+        function: Atom,
+        arity: usize,
+        arguments: &[Term],
+    ) {
+        self.record(process, module, function, arity, || Event::Call(arguments));
+    }
+
+    pub(crate) fn ret(
+        &self,
+        process: &Process,
+        module: Atom,
+        function: Atom,
+        arity: usize,
+        value: Term,
+    ) {
+        self.record(process, module, function, arity, || Event::Return(value));
+    }
+
+    pub(crate) fn exception(
+        &self,
+        process: &Process,
+        module: Atom,
+        function: Atom,
+        arity: usize,
+        class: Term,
+        reason: Term,
+    ) {
+        self.record(process, module, function, arity, || {
+            Event::Exception(class, reason)
+        });
+    }
+
+    /// Records an event in an interpreted function, identified by its `ident`
+    pub(crate) fn erlang<'a>(
+        &self,
+        process: &Process,
+        ident: &FunctionIdent,
+        event: impl FnOnce() -> Event<'a>,
+    ) {
+        if !self.is_active() {
+            return;
+        }
+        let module = Atom::try_from_str(ident.module.as_str()).unwrap();
+        let function = Atom::try_from_str(ident.name.as_str()).unwrap();
+        self.record(process, module, function, ident.arity, event);
+    }
+
+    pub(crate) fn block(&self, process: &Process, ident: &FunctionIdent, block: Block) {
+        if self.blocks {
+            self.erlang(process, ident, || Event::Block(block));
+        }
+    }
+
+    fn record<'a>(
+        &self,
+        process: &Process,
+        module: Atom,
+        function: Atom,
+        arity: usize,
+        event: impl FnOnce() -> Event<'a>,
+    ) {
+        if !self.is_active() {
+            return;
+        }
+        let pid = process.pid();
+        if !self.is_traced(pid, module, function, arity) {
+            return;
+        }
+
+        self.sink.as_ref().unwrap().record(&Trace {
+            pid,
+            module,
+            function,
+            arity,
+            event: event(),
+        });
+    }
+}
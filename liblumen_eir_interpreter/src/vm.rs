@@ -1,21 +1,36 @@
-use std::rc::Rc;
-use std::sync::RwLock;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex, RwLock};
 
 use libeir_ir::FunctionIdent;
 
-use liblumen_alloc::erts::exception;
-use liblumen_alloc::erts::process::Status;
-use liblumen_alloc::erts::term::{atom_unchecked, Atom, Term};
+use liblumen_alloc::erts::process::Process;
+use liblumen_alloc::erts::term::{atom_unchecked, Atom, Pid, Term, TypedTerm};
+use liblumen_alloc::{exit, CloneToProcess};
 
-use lumen_runtime::process::spawn::options::Options;
+use lumen_runtime::registry::pid_to_process;
 use lumen_runtime::scheduler::Scheduler;
-use lumen_runtime::system;
 
-use super::module::ModuleRegistry;
+use super::module::{ErlangFunction, ModuleRegistry};
+use super::trace::Tracer;
+use super::CallError;
 
 pub struct VMState {
     pub modules: RwLock<ModuleRegistry>,
     pub closure_hack: RwLock<Vec<Vec<Term>>>,
+    pub tracer: RwLock<Tracer>,
+    /// The module and version of the code each process last executed. The
+    /// continuations on its stack may be of other versions.
+    executing: Mutex<HashMap<Pid, (Atom, usize)>>,
+    /// Values that processes hand back to the Rust code waiting on them
+    replies: Mutex<HashMap<Pid, Reply>>,
+}
+
+// A value expected from a process, which is copied to the heap of the
+// process it is for
+struct Reply {
+    to: Arc<Process>,
+    value: Option<Term>,
 }
 
 impl VMState {
@@ -23,93 +38,183 @@ impl VMState {
         lumen_runtime::otp::erlang::apply_3::set_code(crate::code::apply);
 
         let mut modules = ModuleRegistry::new();
+        modules.register_native_module(crate::native::make_binary());
+        modules.register_native_module(crate::native::make_code());
         modules.register_native_module(crate::native::make_erlang());
+        modules.register_native_module(crate::native::make_lists());
         modules.register_native_module(crate::native::make_maps());
 
         VMState {
             modules: RwLock::new(modules),
             closure_hack: RwLock::new(Vec::new()),
+            tracer: RwLock::new(Tracer::new()),
+            executing: Mutex::new(HashMap::new()),
+            replies: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn set_executing(&self, process: &Process, fun: &ErlangFunction) {
+        self.executing
+            .lock()
+            .unwrap()
+            .insert(process.pid(), (fun.module, fun.version));
+    }
+
+    /// Expects a reply from the process `from`, replacing any earlier reply
+    /// that was not taken
+    pub(crate) fn expect_reply(&self, from: Pid, to: Arc<Process>) {
+        self.replies
+            .lock()
+            .unwrap()
+            .insert(from, Reply { to, value: None });
+    }
+
+    /// Hands a value back from `from`, if a reply is expected from it
+    pub(crate) fn reply(&self, from: &Process, value: Term) {
+        if let Some(reply) = self.replies.lock().unwrap().get_mut(&from.pid()) {
+            reply.value = Some(value.clone_to_process(&reply.to));
+        }
+    }
+
+    /// Takes the reply of `from`, if it has replied
+    pub(crate) fn take_reply(&self, from: Pid) -> Option<Term> {
+        let mut replies = self.replies.lock().unwrap();
+        match replies.get(&from) {
+            Some(Reply { value: Some(_), .. }) => replies.remove(&from)?.value,
+            _ => None,
         }
     }
 
-    pub fn call(
-        &mut self,
-        fun: &FunctionIdent,
-        args: &[Term],
-    ) -> Result<Rc<Term>, (Rc<Term>, Rc<Term>, Rc<Term>)> {
+    /// Stops expecting a reply from `from`
+    pub(crate) fn forget_reply(&self, from: Pid) {
+        self.replies.lock().unwrap().remove(&from);
+    }
+
+    /// Returns `true` if the process is executing the old code of `module`, or
+    /// will return to it
+    pub fn check_process_code(&self, pid: Pid, module: Atom) -> bool {
+        let old_version = match self.modules.read().unwrap().old_version(module) {
+            Some(version) => version,
+            None => return false,
+        };
+
+        self.processes_executing(module, old_version)
+            .iter()
+            .any(|arc_process| arc_process.pid() == pid)
+    }
+
+    /// Removes the old code of `module`, killing any processes still
+    /// executing it. Returns `true` if any processes were killed.
+    pub fn purge_module(&self, module: Atom) -> bool {
+        let mut modules = self.modules.write().unwrap();
+        let old_version = match modules.purge_erlang_module(module) {
+            Some(version) => version,
+            None => return false,
+        };
+
+        let lingering = self.processes_executing(module, old_version);
+        for arc_process in lingering.iter() {
+            arc_process.exception(exit!(atom_unchecked("killed")));
+
+            // Waiting processes have to run to exit
+            if let Some(scheduler_id) = arc_process.scheduler_id() {
+                if let Some(arc_scheduler) = Scheduler::from_id(&scheduler_id) {
+                    arc_scheduler.stop_waiting(arc_process);
+                }
+            }
+        }
+
+        !lingering.is_empty()
+    }
+
+    /// Removes the old code of `module` unless a process is still executing
+    /// it, in which case `false` is returned.
+    pub fn soft_purge_module(&self, module: Atom) -> bool {
+        let mut modules = self.modules.write().unwrap();
+        if let Some(old_version) = modules.old_version(module) {
+            if !self.processes_executing(module, old_version).is_empty() {
+                return false;
+            }
+            modules.purge_erlang_module(module);
+        }
+
+        true
+    }
+
+    // The live processes that last executed the given version of `module`, or
+    // have a continuation or fun of it on their stack
+    fn processes_executing(&self, module: Atom, version: usize) -> Vec<Arc<Process>> {
+        let mut executing = self.executing.lock().unwrap();
+        // Forget processes that have exited since they last ran interpreted code
+        executing.retain(|pid, _| pid_to_process(pid).is_some());
+
+        executing
+            .iter()
+            .filter_map(|(pid, code)| pid_to_process(pid).map(|arc_process| (arc_process, code)))
+            .filter(|(arc_process, _)| !arc_process.is_exiting())
+            .filter(|(arc_process, code)| {
+                **code == (module, version) || stack_uses_code(arc_process, module, version)
+            })
+            .map(|(arc_process, _)| arc_process)
+            .collect()
+    }
+
+    /// Runs `fun` with `args` in a new process spawned from a new init
+    /// process, returning the value it returns on the init process's heap.
+    pub fn call(&self, fun: &FunctionIdent, args: &[Term]) -> Result<Term, CallError> {
         let arc_scheduler = Scheduler::current();
         let init_arc_process = arc_scheduler.spawn_init(0).unwrap();
 
         let module = Atom::try_from_str(&fun.module.as_str()).unwrap();
         let function = Atom::try_from_str(&fun.name.as_str()).unwrap();
-        let arguments = init_arc_process
-            .list_from_slice(args)
-            // if not enough memory here, resize `spawn_init` heap
-            .unwrap();
-
-        let mut options: Options = Default::default();
-        options.min_heap_size = Some(4 + 1000 * 2);
-
-        let run_arc_process = Scheduler::spawn_apply_3(
-            &init_arc_process,
-            options,
-            module,
-            function,
-            arguments)
-            // if this fails  a bigger sized heap
-            .unwrap();
-
-        loop {
-            let ran = Scheduler::current().run_through(&run_arc_process);
-
-            match *run_arc_process.status.read() {
-                Status::Exiting(ref exception) => match exception {
-                    exception::runtime::Exception {
-                        class: exception::runtime::Class::Exit,
-                        reason,
-                        ..
-                    } => {
-                        if *reason != atom_unchecked("normal") {
-                            panic!("Process exited: {:?}", reason);
-                        } else {
-                            panic!("yay!");
-                        }
-                    }
-                    _ => {
-                        panic!(
-                            "Process exception: {:?}\n{:?}",
-                            exception,
-                            run_arc_process.stacktrace()
-                        );
-                    }
-                },
-                Status::Waiting => {
-                    if ran {
-                        system::io::puts(&format!(
-                            "WAITING Run queues len = {:?}",
-                            Scheduler::current().run_queues_len()
-                        ));
-                    } else {
-                        panic!(
-                            "{:?} did not run.  Deadlock likely in {:#?}",
-                            run_arc_process,
-                            Scheduler::current()
-                        );
-                    }
-                }
-                Status::Runnable => {
-                    system::io::puts(&format!(
-                        "RUNNABLE Run queues len = {:?}",
-                        Scheduler::current().run_queues_len()
-                    ));
-                }
-                Status::Running => {
-                    system::io::puts(&format!(
-                        "RUNNING Run queues len = {:?}",
-                        Scheduler::current().run_queues_len()
-                    ));
-                }
-            }
+
+        crate::call_erlang(init_arc_process, module, function, args)
+    }
+}
+
+/// Whether `term` is or contains a continuation or fun of the given version of
+/// `module`
+pub(crate) fn uses_code(term: Term, module: Atom, version: usize) -> bool {
+    let typed_term = match term.to_typed_term().unwrap() {
+        TypedTerm::Boxed(boxed) => boxed.to_typed_term().unwrap(),
+        typed_term => typed_term,
+    };
+
+    match typed_term {
+        // Interpreted closures start their environment with the block they
+        // enter and the version of the module it is in
+        TypedTerm::Closure(closure) => {
+            let is_of_version = closure.module_function_arity().module == module
+                && closure.env.len() >= 2
+                && closure.env[1].try_into().ok() == Some(version);
+
+            is_of_version
+                || closure
+                    .env
+                    .iter()
+                    .any(|value| uses_code(*value, module, version))
         }
+        TypedTerm::Tuple(tuple) => tuple
+            .iter()
+            .any(|element| uses_code(element, module, version)),
+        TypedTerm::List(cons) => cons
+            .into_iter()
+            .any(|element| element.map_or(false, |element| uses_code(element, module, version))),
+        TypedTerm::Map(map) => map.keys().into_iter().any(|key| {
+            uses_code(key, module, version)
+                || map
+                    .get(key)
+                    .map_or(false, |value| uses_code(value, module, version))
+        }),
+        _ => false,
     }
 }
+
+// Whether a process will return to, or may call, the given version of `module`
+// once it continues. The continuations of a process that is not executing are
+// in the environments of the closures on its stack.
+fn stack_uses_code(process: &Process, module: Atom, version: usize) -> bool {
+    (1..=process.stack_used())
+        .filter_map(|slot| process.stack_slot(slot))
+        .any(|term| uses_code(term, module, version))
+}
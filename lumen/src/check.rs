@@ -0,0 +1,125 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use clap::ArgMatches;
+use failure::Error;
+use serde_json::{json, Value};
+
+use libeir_diagnostics::{ByteIndex, ByteSpan, CodeMap, Diagnostic, LabelStyle, Severity};
+use liblumen_compiler::Compiler;
+
+use crate::compiler::configure;
+
+#[cfg(test)]
+mod test;
+
+/// Dispatches command-line arguments to the compiler backend, checking the
+/// sources without producing artifacts.
+///
+/// Diagnostics are reported via the usual emitter, and additionally written as
+/// JSON, one object per line, to the file given by `--json-output` (or stdout)
+pub fn dispatch<'a>(args: &'a ArgMatches) -> Result<(), Error> {
+    let config = configure(args)?;
+    let mut compiler = Compiler::new(config)?;
+
+    let result = compiler.check();
+
+    let mut out: Box<dyn Write> = match args.value_of_os("json-output") {
+        Some(path) if path != OsStr::new("-") => Box::new(File::create(path)?),
+        _ => Box::new(io::stdout()),
+    };
+    let codemap = compiler.codemap();
+    let codemap = codemap.lock().unwrap();
+    for (file, diagnostics) in compiler.compilation_info().diagnostics() {
+        for diagnostic in diagnostics.iter() {
+            writeln!(out, "{}", to_json(&codemap, file, diagnostic))?;
+        }
+    }
+    out.flush()?;
+
+    result.map(|_| ())
+}
+
+// Renders a diagnostic as a single-line JSON object of the form:
+//
+//     {"file": "src/foo.erl", "severity": "warning", "message": "...",
+//      "span": {"start": 10, "end": 14}, "start": {"line": 1, "column": 11},
+//      "end": {"line": 1, "column": 15}, "notes": [...]}
+//
+// The location fields are taken from the primary label, and are `null` when the
+// diagnostic has none. Byte offsets are relative to the start of the file, and
+// lines and columns are 1-based. Secondary labels become notes with the same
+// location fields, plus their own message
+fn to_json(codemap: &CodeMap, source: &Path, diagnostic: &Diagnostic) -> String {
+    let primary = diagnostic
+        .labels
+        .iter()
+        .find(|label| label.style == LabelStyle::Primary);
+    let file = primary
+        .and_then(|label| file_name(codemap, label.span))
+        .unwrap_or_else(|| source.display().to_string());
+    let mut json = json!({
+        "file": file,
+        "severity": severity(diagnostic.severity),
+        "message": diagnostic.message,
+    });
+    location(&mut json, codemap, primary.map(|label| label.span));
+
+    let notes = diagnostic
+        .labels
+        .iter()
+        .filter(|label| label.style != LabelStyle::Primary)
+        .map(|label| {
+            let mut note = json!({
+                "message": label.message.as_ref().map(|s| s.as_str()).unwrap_or(""),
+            });
+            location(&mut note, codemap, Some(label.span));
+            note
+        })
+        .collect::<Vec<_>>();
+    json["notes"] = Value::Array(notes);
+
+    json.to_string()
+}
+
+// Adds the `span`, `start` and `end` fields for the given span to a JSON object
+fn location(json: &mut Value, codemap: &CodeMap, span: Option<ByteSpan>) {
+    let file = span.and_then(|span| codemap.find_file(span.start()).map(|file| (span, file)));
+    let (span, file) = match file {
+        None => {
+            json["span"] = Value::Null;
+            json["start"] = Value::Null;
+            json["end"] = Value::Null;
+            return;
+        }
+        Some(found) => found,
+    };
+    let offset = |index: ByteIndex| index.to_usize() - file.span().start().to_usize();
+    json["span"] = json!({ "start": offset(span.start()), "end": offset(span.end()) });
+    for (key, index) in &[("start", span.start()), ("end", span.end())] {
+        json[*key] = match file.location(*index) {
+            Ok((line, column)) => {
+                json!({ "line": line.to_usize() + 1, "column": column.to_usize() + 1 })
+            }
+            Err(_) => Value::Null,
+        };
+    }
+}
+
+fn file_name(codemap: &CodeMap, span: ByteSpan) -> Option<String> {
+    codemap
+        .find_file(span.start())
+        .map(|file| file.name().to_string())
+}
+
+fn severity(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use libeir_diagnostics::{ByteOffset, ByteSpan, CodeMap, Diagnostic, FileName, Label};
+
+use crate::check::to_json;
+
+const SOURCE: &str = "-module(foo).\nbar(X) ->\n    ok.\n";
+
+fn codemap() -> (CodeMap, ByteSpan) {
+    let mut codemap = CodeMap::new();
+    let span = codemap
+        .add_filemap(FileName::Virtual("src/foo.erl".into()), SOURCE.to_string())
+        .span();
+    (codemap, span)
+}
+
+// The span of `len` bytes at `offset` in the source
+fn span(file: ByteSpan, offset: i64, len: i64) -> ByteSpan {
+    let start = file.start() + ByteOffset(offset);
+    ByteSpan::new(start, start + ByteOffset(len))
+}
+
+fn parse(json: &str) -> Value {
+    assert!(!json.contains('\n'));
+    serde_json::from_str(json).unwrap()
+}
+
+#[test]
+fn primary_label_gives_the_file_and_location() {
+    let (codemap, file) = codemap();
+    // `X` in `bar(X)`
+    let diagnostic = Diagnostic::new_warning("variable 'X' is unused")
+        .with_label(Label::new_primary(span(file, 18, 1)));
+
+    assert_eq!(
+        parse(&to_json(&codemap, Path::new("other.erl"), &diagnostic)),
+        json!({
+            "file": "src/foo.erl",
+            "severity": "warning",
+            "message": "variable 'X' is unused",
+            "span": {"start": 18, "end": 19},
+            "start": {"line": 2, "column": 5},
+            "end": {"line": 2, "column": 6},
+            "notes": [],
+        })
+    );
+}
+
+#[test]
+fn secondary_labels_are_notes() {
+    let (codemap, file) = codemap();
+    let diagnostic = Diagnostic::new_error("bad")
+        .with_label(Label::new_primary(span(file, 28, 2)))
+        .with_label(Label::new_secondary(span(file, 0, 13)).with_message("module here"));
+
+    let json = parse(&to_json(&codemap, Path::new("src/foo.erl"), &diagnostic));
+
+    assert_eq!(json["severity"], "error");
+    assert_eq!(json["start"], json!({"line": 3, "column": 5}));
+    assert_eq!(
+        json["notes"],
+        json!([{
+            "message": "module here",
+            "span": {"start": 0, "end": 13},
+            "start": {"line": 1, "column": 1},
+            "end": {"line": 1, "column": 14},
+        }])
+    );
+}
+
+#[test]
+fn without_a_primary_label_the_location_is_null() {
+    let (codemap, _) = codemap();
+    let diagnostic = Diagnostic::new_error("parsing failed");
+
+    assert_eq!(
+        parse(&to_json(&codemap, Path::new("src/foo.erl"), &diagnostic)),
+        json!({
+            "file": "src/foo.erl",
+            "severity": "error",
+            "message": "parsing failed",
+            "span": null,
+            "start": null,
+            "end": null,
+            "notes": [],
+        })
+    );
+}
+
+#[test]
+fn messages_are_escaped() {
+    let (codemap, _) = codemap();
+    let diagnostic = Diagnostic::new_error("\"quoted\" \\ back\nslash\t\u{1}");
+    let json = to_json(&codemap, Path::new("src/foo.erl"), &diagnostic);
+
+    assert!(json.contains(r#""message":"\"quoted\" \\ back\nslash\t\u0001""#));
+    assert_eq!(parse(&json)["message"], "\"quoted\" \\ back\nslash\t\u{1}");
+}
+
+#[test]
+fn fields_are_in_a_fixed_order() {
+    let (codemap, _) = codemap();
+    let json = to_json(&codemap, Path::new("a.erl"), &Diagnostic::new_error("e"));
+
+    assert_eq!(
+        json,
+        r#"{"file":"a.erl","severity":"error","message":"e","span":null,"start":null,"end":null,"notes":[]}"#
+    );
+}
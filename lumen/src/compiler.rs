@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::convert::From;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
@@ -7,31 +7,80 @@ use clap::{value_t, ArgMatches};
 use failure::Error;
 
 use libeir_diagnostics::{CodeMap, ColorChoice};
-use liblumen_compiler::{Compiler, CompilerMode, CompilerSettings, Verbosity};
+use liblumen_compiler::{parse_define, Compiler, CompilerMode, CompilerSettings, Emit, Verbosity};
 
 /// Dispatches command-line arguments to the compiler backend
 pub fn dispatch<'a>(args: &'a ArgMatches) -> Result<(), Error> {
     let config = configure(args)?;
-    let mut compiler = Compiler::new(config);
+    let mut compiler = Compiler::new(config)?;
 
-    compiler.compile()?;
+    let info = match compiler.compile() {
+        Ok(info) => info,
+        Err(err) => {
+            let info = compiler.compilation_info();
+            compiler.info(format!(
+                "{} errors, {} warnings",
+                info.num_errors(),
+                info.num_warnings()
+            ));
+            if compiler.warnings_as_errors() && info.num_warnings() > 0 {
+                compiler.warn("warnings are being treated as errors");
+            }
+            return Err(err);
+        }
+    };
+    compiler.info(format!(
+        "Compiled {} modules in {:.2}s ({} up to date, {} warnings)",
+        info.num_modules(),
+        info.compilation_time().as_secs_f64(),
+        info.num_cached(),
+        info.num_warnings()
+    ));
 
     Ok(())
 }
 
 /// Create a CompilerSettings struct from ArgMatches produced by clap
-fn configure<'a>(args: &'a ArgMatches) -> Result<CompilerSettings, Error> {
+pub fn configure<'a>(args: &'a ArgMatches) -> Result<CompilerSettings, Error> {
     let codemap = Arc::new(Mutex::new(CodeMap::new()));
     let mode = value_t!(args, "compiler", CompilerMode).unwrap_or_else(|e| e.exit());
     let source_dir = args.value_of_os("path").map(PathBuf::from).unwrap();
-    let output_dir = args.value_of_os("output").map(PathBuf::from).unwrap();
+    // Subcommands which produce no artifacts have no output directory
+    let output_dir = args
+        .value_of_os("output")
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    let emit = match args.values_of("emit") {
+        None => Vec::new(),
+        Some(values) => values
+            .map(|value| value.parse())
+            .collect::<Result<Vec<Emit>, _>>()?,
+    };
     let warnings_as_errors = args.is_present("warnings-as-errors");
-    let no_warn = args.is_present("no_warn");
+    let no_warn = args.is_present("no-warnings");
+    let force = args.is_present("force");
     let verbosity = Verbosity::from_level(args.occurrences_of("verbose") as isize);
-    let include_path = VecDeque::new();
+    let mut defines = HashMap::new();
+    if let Some(values) = args.values_of("define") {
+        for value in values {
+            let (name, def) = parse_define(value, &codemap)?;
+            defines.insert(name, def);
+        }
+    }
+    let mut include_path = match args.values_of_os("include-path") {
+        None => VecDeque::new(),
+        Some(values) => values.map(PathBuf::from).collect(),
+    };
+    // Like erlc, headers next to the sources are always visible to them
+    if source_dir.is_dir() {
+        include_path.push_back(source_dir.clone());
+    } else if let Some(parent) = source_dir.parent() {
+        include_path.push_back(parent.to_path_buf());
+    }
+    // Paths given with --pa are searched in the reverse of the order they were given
     let mut code_path = match args.values_of_os("prepend-path") {
         None => Vec::new(),
-        Some(values) => values.map(PathBuf::from).collect(),
+        Some(values) => values.map(PathBuf::from).rev().collect(),
     };
     let mut append_dirs = match args.values_of_os("append-path") {
         None => Vec::new(),
@@ -43,8 +92,11 @@ fn configure<'a>(args: &'a ArgMatches) -> Result<CompilerSettings, Error> {
         color: ColorChoice::Auto,
         source_dir,
         output_dir,
+        emit,
+        defines,
         warnings_as_errors,
         no_warn,
+        force,
         verbosity,
         code_path,
         include_path,
@@ -1,5 +1,9 @@
+mod check;
 mod compiler;
+#[cfg(test)]
+mod test;
 
+use std::ffi::OsStr;
 use std::process;
 
 use clap::{crate_description, crate_name, crate_version};
@@ -26,88 +30,12 @@ fn main() {
     let output_dir = cwd.join("_build/target");
 
     // Build argument parser
-    let matches = App::new(crate_name!())
-        .version(crate_version!())
-        .about(crate_description!())
-        .subcommand(
-            SubCommand::with_name("compile")
-                .about("Compiles Erlang to an executable or shared library")
-                .arg(
-                    Arg::with_name("path")
-                        .help("The path to the file or directory of files you wish to compile")
-                        .index(1)
-                        .takes_value(true)
-                        .value_name("FILE_OR_DIR")
-                        .default_value_os(cwd.as_os_str())
-                        .required(true),
-                )
-                .arg(
-                    Arg::with_name("compiler")
-                        .help("The type of compiler to use")
-                        .takes_value(true)
-                        .value_name("TYPE")
-                        .possible_values(&["beam", "erl"])
-                        .default_value("erl")
-                        .required(true),
-                )
-                .arg(
-                    Arg::with_name("output")
-                        .help("The directory to place compiler output")
-                        .short("o")
-                        .long("output")
-                        .value_name("DIR")
-                        .default_value_os(output_dir.as_os_str()),
-                )
-                .arg(
-                    Arg::with_name("define")
-                        .help("Define a macro, e.g. -DTEST")
-                        .short("D")
-                        .long("define")
-                        .value_name("NAME")
-                        .takes_value(true)
-                        .multiple(true),
-                )
-                .arg(
-                    Arg::with_name("warnings-as-errors")
-                        .help("Causes the compiler to treat all warnings as errors")
-                        .long("warnings-as-errors"),
-                )
-                .arg(
-                    Arg::with_name("no-warnings")
-                        .help("Disable warnings")
-                        .long("no-warnings")
-                        .conflicts_with("warnings-as-errors"),
-                )
-                .arg(
-                    Arg::with_name("verbose")
-                        .help("Set verbosity level")
-                        .short("v")
-                        .multiple(true),
-                )
-                .arg(
-                    Arg::with_name("append-path")
-                        .help("Appends a path to the code path")
-                        .short("pz")
-                        .long("append-path")
-                        .value_name("PATH")
-                        .takes_value(true)
-                        .multiple(true),
-                )
-                .arg(
-                    Arg::with_name("prepend-path")
-                        .help("Prepends a path to the code path")
-                        .short("pa")
-                        .long("prepend-path")
-                        .value_name("PATH")
-                        .takes_value(true)
-                        .multiple(true),
-                ),
-        )
-        .get_matches();
+    let matches = app(cwd.as_os_str(), output_dir.as_os_str()).get_matches();
 
     // Dispatch commands
     let result: Result<(), Error> = match matches.subcommand() {
         ("compile", Some(args)) => compiler::dispatch(&args),
+        ("check", Some(args)) => check::dispatch(&args),
         _ => Ok(()),
     };
 
@@ -135,3 +63,129 @@ fn main() {
         _ => return,
     };
 }
+
+/// Builds the argument parser
+fn app<'a, 'b>(cwd: &'a OsStr, output_dir: &'a OsStr) -> App<'a, 'b> {
+    App::new(crate_name!())
+        .version(crate_version!())
+        .about(crate_description!())
+        .subcommand(
+            source_args(
+                SubCommand::with_name("compile")
+                    .about("Compiles Erlang to an executable or shared library"),
+                cwd,
+            )
+            .arg(
+                Arg::with_name("output")
+                    .help("The directory to place compiler output")
+                    .short("o")
+                    .long("output")
+                    .value_name("DIR")
+                    .default_value_os(output_dir),
+            )
+            .arg(
+                Arg::with_name("emit")
+                    .help("The kinds of EIR to write for each module")
+                    .long("emit")
+                    .value_name("KIND")
+                    .possible_values(&["eir", "eir-opt", "eir-passes"])
+                    .default_value("eir-opt")
+                    .use_delimiter(true)
+                    .multiple(true),
+            )
+            .arg(
+                Arg::with_name("force")
+                    .help("Recompile all modules, even if they are up to date")
+                    .long("force"),
+            ),
+        )
+        .subcommand(
+            source_args(
+                SubCommand::with_name("check")
+                    .about("Checks Erlang sources for errors without producing any output"),
+                cwd,
+            )
+            .arg(
+                Arg::with_name("json-output")
+                    .help("The file to write diagnostics to as JSON lines, or - for stdout")
+                    .long("json-output")
+                    .value_name("FILE")
+                    .default_value("-"),
+            ),
+        )
+}
+
+/// Adds the arguments shared by all subcommands which operate on Erlang sources
+fn source_args<'a, 'b>(cmd: App<'a, 'b>, cwd: &'a OsStr) -> App<'a, 'b> {
+    cmd.arg(
+        Arg::with_name("path")
+            .help("The path to the file or directory of files you wish to compile")
+            .index(1)
+            .takes_value(true)
+            .value_name("FILE_OR_DIR")
+            .default_value_os(cwd)
+            .required(true),
+    )
+    .arg(
+        Arg::with_name("compiler")
+            .help("The type of compiler to use")
+            .takes_value(true)
+            .value_name("TYPE")
+            .possible_values(&["beam", "erl"])
+            .default_value("erl")
+            .required(true),
+    )
+    .arg(
+        Arg::with_name("define")
+            .help("Define a macro, e.g. -DTEST or -DVSN=1")
+            .short("D")
+            .long("define")
+            .value_name("NAME")
+            .takes_value(true)
+            .multiple(true),
+    )
+    .arg(
+        Arg::with_name("include-path")
+            .help("Adds a directory to search for included files")
+            .short("I")
+            .long("include")
+            .value_name("DIR")
+            .takes_value(true)
+            .multiple(true),
+    )
+    .arg(
+        Arg::with_name("warnings-as-errors")
+            .help("Causes the compiler to treat all warnings as errors")
+            .long("warnings-as-errors"),
+    )
+    .arg(
+        Arg::with_name("no-warnings")
+            .help("Disable warnings")
+            .long("no-warnings")
+            .conflicts_with("warnings-as-errors"),
+    )
+    .arg(
+        Arg::with_name("verbose")
+            .help("Set verbosity level, -v reports progress and -vv phase timings")
+            .short("v")
+            .multiple(true),
+    )
+    .arg(
+        Arg::with_name("append-path")
+            .help("Appends a path to the code path")
+            .long("append-path")
+            .visible_alias("pz")
+            .value_name("PATH")
+            .takes_value(true)
+            .multiple(true),
+    )
+    .arg(
+        Arg::with_name("prepend-path")
+            .help("Prepends a path to the code path")
+            .long("prepend-path")
+            .visible_alias("pa")
+            .value_name("PATH")
+            .takes_value(true)
+            .multiple(true),
+    )
+}
@@ -0,0 +1,45 @@
+use std::ffi::OsStr;
+
+use clap::ArgMatches;
+
+use crate::app;
+
+fn parse(args: &[&str]) -> ArgMatches<'static> {
+    let matches = app(OsStr::new("."), OsStr::new("_build/target"))
+        .get_matches_from_safe(args)
+        .unwrap();
+    matches.subcommand_matches("compile").unwrap().clone()
+}
+
+fn values<'a>(matches: &'a ArgMatches, name: &str) -> Vec<&'a str> {
+    matches
+        .values_of(name)
+        .map(|values| values.collect())
+        .unwrap_or_default()
+}
+
+#[test]
+fn pa_and_pz_are_separate_paths() {
+    let matches = parse(&[
+        "lumen", "compile", "src", "--pa", "a", "--pz", "b", "--pa", "c",
+    ]);
+
+    assert_eq!(vec!["a", "c"], values(&matches, "prepend-path"));
+    assert_eq!(vec!["b"], values(&matches, "append-path"));
+    assert_eq!(Some("src"), matches.value_of("path"));
+}
+
+#[test]
+fn pa_and_pz_are_aliases_of_the_long_names() {
+    let matches = parse(&[
+        "lumen",
+        "compile",
+        "--prepend-path",
+        "a",
+        "--append-path",
+        "b",
+    ]);
+
+    assert_eq!(vec!["a"], values(&matches, "prepend-path"));
+    assert_eq!(vec!["b"], values(&matches, "append-path"));
+}
@@ -0,0 +1,244 @@
+//! Loads compiled `.beam` files, so that modules precompiled by `erlc` or `elixirc` can run in the
+//! runtime.
+//!
+//! Loading a module decodes the instructions of its `Code` chunk, resolves its imports against the
+//! natives in `crate::otp`, and copies the terms in its `LitT` chunk into a literal area owned by
+//! the module.  The instructions are then translated into threaded code: each instruction carries
+//! the function that executes it and operands that are already resolved to registers, terms and
+//! code indices, so running a function is a loop calling one handler after another.
+//!
+//! Each call into a BEAM function gets its own `Frame` on the process' code stack, so loaded code
+//! is interleaved with natives and other `Code` just like the rest of the runtime, and a process
+//! running loaded code can be rescheduled between any two calls.
+//!
+//! # Supported instructions
+//!
+//! Only a subset of the generic instructions has a handler:
+//!
+//! * local, external and dynamic calls, `apply`, funs and `return`
+//! * `bif0` to `bif2` and `gc_bif1` to `gc_bif3`, for the BIFs in `crate::otp::natives`
+//! * stack frames: `allocate*`, `test_heap`, `init`, `init_yregs`, `trim` and `deallocate`
+//! * `send` and `receive`, including `after` and the receive markers
+//! * the type and comparison tests, `select_val` and `select_tuple_arity`
+//! * moving and building terms: lists, tuples and maps
+//! * `catch`, `try` and the `badmatch`, `case_end`, `if_end` family of errors
+//!
+//! No binary instruction (`bs_*`) or float register instruction (`fmove`, `fadd`, ...) has a
+//! handler, nor do the instructions that modern compilers no longer emit, such as `put_string`,
+//! `make_fun` and `raise`, or `on_load` and `update_record`.  A module using any of them fails to
+//! load with `LoadError::UnsupportedInstruction`, and one whose operands are not what the handlers
+//! expect with `LoadError::UnsupportedOperand`.
+//!
+//! Binaries and float arithmetic are out of scope for now.  Many of OTP's own modules use them, and
+//! loading is all or nothing, so such a module is rejected even if the functions that would be
+//! called do not use them.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let module = lumen_runtime::beam::load_file("ebin/Elixir.Unicode.beam")?;
+//! lumen_runtime::otp::erlang::apply_3::set_code(lumen_runtime::beam::apply_3);
+//! lumen_runtime::beam::spawn(&parent_process, Default::default(), module, function, arguments)?;
+//! ```
+
+mod interpreter;
+mod literal;
+mod load;
+mod module;
+#[cfg(test)]
+mod test;
+
+use std::fmt::{self, Display};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+use liblumen_alloc::erts::exception::system::Alloc;
+use liblumen_alloc::erts::process::code::stack::frame::{Frame, Placement};
+use liblumen_alloc::erts::process::code::Code;
+use liblumen_alloc::erts::process::Process;
+use liblumen_alloc::erts::term::{Atom, Term};
+
+use liblumen_beam::beam::reader::StandardBeamFile;
+use liblumen_beam::serialization::etf;
+use liblumen_beam::ReadError;
+
+use crate::process::spawn::options::Options;
+use crate::scheduler::Scheduler;
+
+pub use self::module::purge;
+
+/// Loads the module in `beam` and makes it the current version of the module.
+///
+/// If the module was already loaded, the version it replaces becomes its old version, which
+/// processes can finish running until it is `purge`d.
+pub fn load(beam: &StandardBeamFile) -> Result<Atom, LoadError> {
+    let module = load::module(beam)?;
+    let name = module.name;
+
+    module::register(module)?;
+
+    Ok(name)
+}
+
+/// Loads the module in the contents of a `.beam` file.
+pub fn load_binary(bytes: &[u8]) -> Result<Atom, LoadError> {
+    let beam = StandardBeamFile::from_reader(Cursor::new(bytes))?;
+
+    load(&beam)
+}
+
+/// Loads the module in the `.beam` file at `path`.
+pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Atom, LoadError> {
+    let beam = StandardBeamFile::from_file(path)?;
+
+    load(&beam)
+}
+
+/// Returns `true` if the exported `module:function/arity` is in a loaded module.
+pub fn is_exported(module: Atom, function: Atom, arity: u8) -> bool {
+    module::get(module)
+        .and_then(|module| module.exported(function, arity))
+        .is_some()
+}
+
+/// Places a `Frame` that calls the exported `module:function/arity` with `arguments`.
+///
+/// Like the other `place_frame_with_arguments`, the result is pushed on the stack when the
+/// function returns.
+pub fn place_frame_with_arguments(
+    process: &Process,
+    placement: Placement,
+    module: Atom,
+    function: Atom,
+    arguments: Vec<Term>,
+) -> Result<(), Alloc> {
+    for argument in arguments.iter().rev() {
+        process.stack_push(*argument)?;
+    }
+
+    let module_function_arity = Arc::new(liblumen_alloc::ModuleFunctionArity {
+        module,
+        function,
+        arity: arguments.len() as u8,
+    });
+    process.place_frame(Frame::new(module_function_arity, code()), placement);
+
+    Ok(())
+}
+
+/// Spawns a process that runs the exported `module:function/arity` with `arguments`.
+pub fn spawn(
+    parent_process: &Process,
+    options: Options,
+    module: Atom,
+    function: Atom,
+    arguments: Vec<Term>,
+) -> Result<Arc<Process>, Alloc> {
+    Scheduler::spawn_code(parent_process, options, module, function, arguments, code())
+}
+
+/// The `Code` that runs an exported function in a loaded module, with the arguments on the stack
+/// and the `ModuleFunctionArity` of the function in its `Frame`.
+pub fn code() -> Code {
+    interpreter::enter
+}
+
+/// The `Code` to use for `otp::erlang::apply_3::set_code`, so that `apply/3` and `spawn/3` can call
+/// loaded modules and the natives they can import.
+pub fn apply_3(arc_process: &Arc<Process>) -> liblumen_alloc::erts::process::code::Result {
+    interpreter::apply_3(arc_process)
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Read(ReadError),
+    /// A chunk that every module needs is not in the `.beam` file
+    MissingChunk(&'static str),
+    InvalidAtom(String),
+    Literal(etf::DecodeError),
+    /// A literal is of a type that cannot be put in a literal area, such as a pid
+    UnsupportedLiteral(String),
+    /// The interpreter does not implement the instruction, so the module cannot be loaded
+    UnsupportedInstruction {
+        instruction: &'static str,
+        function: Option<String>,
+    },
+    /// An operand is not what the handler of its instruction expects, such as a register where a
+    /// label should be or an index past the end of the `LitT` or `ImpT` chunk
+    UnsupportedOperand {
+        instruction: &'static str,
+        operand: String,
+    },
+    UndefinedLabel(u64),
+    /// A `FunT` entry captures more variables than its function has arguments, or leaves more
+    /// than 255 arguments to the caller
+    InvalidFun {
+        function: String,
+        arity: u32,
+        num_free: u32,
+    },
+    /// The module already has old code, which has to be purged before loading another version
+    NotPurged(Atom),
+    Alloc(Alloc),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::LoadError::*;
+
+        match self {
+            Read(error) => error.fmt(f),
+            MissingChunk(id) => write!(f, "missing {} chunk", id),
+            InvalidAtom(name) => write!(f, "invalid atom {:?}", name),
+            Literal(error) => write!(f, "invalid literal: {}", error),
+            UnsupportedLiteral(literal) => write!(f, "unsupported literal {}", literal),
+            UnsupportedInstruction {
+                instruction,
+                function: Some(function),
+            } => write!(f, "unsupported instruction {} in {}", instruction, function),
+            UnsupportedInstruction {
+                instruction,
+                function: None,
+            } => write!(f, "unsupported instruction {}", instruction),
+            UnsupportedOperand {
+                instruction,
+                operand,
+            } => write!(f, "unsupported operand {} of {}", operand, instruction),
+            UndefinedLabel(label) => write!(f, "undefined label {}", label),
+            InvalidFun {
+                function,
+                arity,
+                num_free,
+            } => write!(
+                f,
+                "invalid fun {}/{} with {} free variables",
+                function, arity, num_free
+            ),
+            NotPurged(module) => write!(
+                f,
+                "module {} has old code, which must be purged first",
+                module.name()
+            ),
+            Alloc(_) => write!(f, "out of memory for literals"),
+        }
+    }
+}
+
+impl From<Alloc> for LoadError {
+    fn from(alloc: Alloc) -> Self {
+        LoadError::Alloc(alloc)
+    }
+}
+
+impl From<etf::DecodeError> for LoadError {
+    fn from(decode_error: etf::DecodeError) -> Self {
+        LoadError::Literal(decode_error)
+    }
+}
+
+impl From<ReadError> for LoadError {
+    fn from(read_error: ReadError) -> Self {
+        LoadError::Read(read_error)
+    }
+}
@@ -0,0 +1,1851 @@
+//! Runs the threaded code of loaded modules.
+//!
+//! A `Machine` holds the registers of the function a process is running.  Calls between loaded
+//! functions stay in the `Machine`'s loop while the process has reductions left: the caller's `y`
+//! registers and catches are pushed on the process' stack and its `Frame` is replaced by one that
+//! runs `resume`, so that `return` can restore them without leaving the loop.  When the process is
+//! out of reductions, or calls code that is not loaded, the `Machine` returns to the scheduler and
+//! the `Frame`s on the code stack say where to continue.
+
+use std::convert::{TryFrom, TryInto};
+use std::sync::Arc;
+
+use liblumen_alloc::erts::exception::runtime::{self, Class};
+use liblumen_alloc::erts::exception::system::{self, Alloc};
+use liblumen_alloc::erts::exception::Exception;
+use liblumen_alloc::erts::message::{self, Message};
+use liblumen_alloc::erts::process::code::stack::frame::{Frame, Placement};
+use liblumen_alloc::erts::process::code::{self, result_from_exception};
+use liblumen_alloc::erts::process::{Process, RecvTimeout};
+use liblumen_alloc::erts::term::{
+    atom_unchecked, AsTerm, Atom, Boxed, Closure, Cons, Map, Reference, Term, Tuple, TypedTerm,
+};
+use liblumen_alloc::{badarity, badfun, badkey, badmap, error, exit, raise, undef};
+use liblumen_alloc::{CloneToProcess, ModuleFunctionArity};
+
+use liblumen_beam::beam::reader::chunk::code::Opcode;
+
+use crate::otp::{self, erlang, Native};
+use crate::time::monotonic::{self, Milliseconds};
+use crate::timer::{self, Destination, Timeout};
+
+use super::module::{self, Module, Operand};
+
+/// Executes an instruction.  `Machine.ip` is already at the next instruction, so jumps only need
+/// to set it.  The operands have the `Shape`s given by `handler`.
+pub type Handler = fn(&mut Machine, &[Operand]) -> Result<Step, Exception>;
+
+pub enum Step {
+    /// Run the instruction at `Machine.ip`
+    Next,
+    /// Return to the scheduler, which runs the top `Frame` of the code stack next
+    Stop,
+}
+
+/// The handler for `opcode` and the shapes of its operands, or `None` if the interpreter does not
+/// implement it
+pub fn handler(opcode: Opcode) -> Option<(Handler, &'static [Shape])> {
+    use self::Shape::*;
+
+    let handler: (Handler, &'static [Shape]) = match opcode {
+        Opcode::FuncInfo => (func_info, &[Source, Source, Unsigned]),
+        Opcode::Call => (call, &[Unsigned, Function]),
+        Opcode::CallLast => (call_last, &[Unsigned, Function, Unsigned]),
+        Opcode::CallOnly => (call_only, &[Unsigned, Function]),
+        Opcode::CallExt => (call_ext, &[Unsigned, Import]),
+        Opcode::CallExtLast => (call_ext_last, &[Unsigned, Import, Unsigned]),
+        Opcode::CallExtOnly => (call_ext_only, &[Unsigned, Import]),
+        Opcode::Bif0 => (bif0, &[Import, Destination]),
+        Opcode::Bif1 => (bif1, &[OptionalLabel, Import, Source, Destination]),
+        Opcode::Bif2 => (bif2, &[OptionalLabel, Import, Source, Source, Destination]),
+        Opcode::GcBif1 => (
+            gc_bif1,
+            &[OptionalLabel, Unsigned, Import, Source, Destination],
+        ),
+        Opcode::GcBif2 => (
+            gc_bif2,
+            &[OptionalLabel, Unsigned, Import, Source, Source, Destination],
+        ),
+        Opcode::GcBif3 => (
+            gc_bif3,
+            &[
+                OptionalLabel,
+                Unsigned,
+                Import,
+                Source,
+                Source,
+                Source,
+                Destination,
+            ],
+        ),
+        Opcode::Allocate | Opcode::AllocateZero => (allocate, &[Unsigned, Unsigned]),
+        Opcode::AllocateHeap | Opcode::AllocateHeapZero => {
+            (allocate_heap, &[Unsigned, Unsigned, Unsigned])
+        }
+        Opcode::TestHeap => (no_op, &[Unsigned, Unsigned]),
+        Opcode::Init => (init, &[Destination]),
+        Opcode::InitYregs => (init_yregs, &[List(&[Destination])]),
+        Opcode::Trim => (trim, &[Unsigned, Unsigned]),
+        Opcode::Deallocate => (deallocate, &[Unsigned]),
+        Opcode::Return => (return_, &[]),
+        Opcode::Send => (send, &[]),
+        Opcode::RemoveMessage => (remove_message, &[]),
+        Opcode::Timeout => (no_op, &[]),
+        Opcode::LoopRec => (loop_rec, &[Label, Destination]),
+        Opcode::LoopRecEnd => (jump, &[Label]),
+        Opcode::Wait => (wait, &[Label]),
+        Opcode::WaitTimeout => (wait_timeout, &[Label, Source]),
+        Opcode::RecvMark | Opcode::RecvSet => (no_op, &[Label]),
+        Opcode::RecvMarkerReserve => (recv_marker_reserve, &[Destination]),
+        Opcode::RecvMarkerBind => (no_op, &[Source, Source]),
+        Opcode::RecvMarkerClear | Opcode::RecvMarkerUse => (no_op, &[Source]),
+        Opcode::IsLt => (is_lt, &[Label, Source, Source]),
+        Opcode::IsGe => (is_ge, &[Label, Source, Source]),
+        Opcode::IsEq => (is_eq, &[Label, Source, Source]),
+        Opcode::IsNe => (is_ne, &[Label, Source, Source]),
+        Opcode::IsEqExact => (is_eq_exact, &[Label, Source, Source]),
+        Opcode::IsNeExact => (is_ne_exact, &[Label, Source, Source]),
+        Opcode::IsInteger => (is_integer, &[Label, Source]),
+        Opcode::IsFloat => (is_float, &[Label, Source]),
+        Opcode::IsNumber => (is_number, &[Label, Source]),
+        Opcode::IsAtom => (is_atom, &[Label, Source]),
+        Opcode::IsPid => (is_pid, &[Label, Source]),
+        Opcode::IsReference => (is_reference, &[Label, Source]),
+        Opcode::IsPort => (is_port, &[Label, Source]),
+        Opcode::IsNil => (is_nil, &[Label, Source]),
+        Opcode::IsBinary => (is_binary, &[Label, Source]),
+        Opcode::IsBitstr => (is_bitstr, &[Label, Source]),
+        Opcode::IsList => (is_list, &[Label, Source]),
+        Opcode::IsNonemptyList => (is_nonempty_list, &[Label, Source]),
+        Opcode::IsTuple => (is_tuple, &[Label, Source]),
+        Opcode::IsBoolean => (is_boolean, &[Label, Source]),
+        Opcode::IsFunction => (is_function, &[Label, Source]),
+        Opcode::IsFunction2 => (is_function2, &[Label, Source, Source]),
+        Opcode::IsMap => (is_map, &[Label, Source]),
+        Opcode::TestArity => (test_arity, &[Label, Source, Unsigned]),
+        Opcode::IsTaggedTuple => (is_tagged_tuple, &[Label, Source, Unsigned, Source]),
+        Opcode::SelectVal => (select_val, &[Source, Label, List(&[Source, Label])]),
+        Opcode::SelectTupleArity => (
+            select_tuple_arity,
+            &[Source, Label, List(&[Unsigned, Label])],
+        ),
+        Opcode::Jump => (jump, &[Label]),
+        Opcode::Move => (move_, &[Source, Destination]),
+        Opcode::Swap => (swap, &[Destination, Destination]),
+        Opcode::GetList => (get_list, &[Source, Destination, Destination]),
+        Opcode::GetHd => (get_hd, &[Source, Destination]),
+        Opcode::GetTl => (get_tl, &[Source, Destination]),
+        Opcode::GetTupleElement => (get_tuple_element, &[Source, Unsigned, Destination]),
+        Opcode::SetTupleElement => (set_tuple_element, &[Source, Source, Unsigned]),
+        Opcode::PutList => (put_list, &[Source, Source, Destination]),
+        Opcode::PutTuple => (put_tuple, &[Unsigned, Destination]),
+        Opcode::Put => (put, &[Source]),
+        Opcode::PutTuple2 => (put_tuple2, &[Destination, List(&[Source])]),
+        Opcode::Badmatch => (badmatch, &[Source]),
+        Opcode::IfEnd => (if_end, &[]),
+        Opcode::CaseEnd => (case_end, &[Source]),
+        Opcode::Badrecord => (badrecord, &[Source]),
+        Opcode::TryCaseEnd => (try_case_end, &[Source]),
+        Opcode::Catch => (catch, &[Y, Label]),
+        Opcode::CatchEnd => (catch_end, &[Y]),
+        Opcode::Try => (try_, &[Y, Label]),
+        Opcode::TryEnd => (try_end, &[Y]),
+        Opcode::TryCase => (try_case, &[Y]),
+        Opcode::BuildStacktrace => (no_op, &[]),
+        Opcode::RawRaise => (raw_raise, &[]),
+        Opcode::Apply => (apply, &[Unsigned]),
+        Opcode::ApplyLast => (apply_last, &[Unsigned, Unsigned]),
+        Opcode::CallFun => (call_fun, &[Unsigned]),
+        Opcode::CallFun2 => (call_fun2, &[Source, Unsigned, Source]),
+        Opcode::MakeFun2 => (make_fun2, &[Fun]),
+        Opcode::MakeFun3 => (make_fun3, &[Fun, Destination, List(&[Source])]),
+        Opcode::HasMapFields => (has_map_fields, &[Label, Source, List(&[Source])]),
+        Opcode::GetMapElements => (
+            get_map_elements,
+            &[Label, Source, List(&[Source, Destination])],
+        ),
+        Opcode::PutMapAssoc => (
+            put_map_assoc,
+            &[
+                OptionalLabel,
+                Source,
+                Destination,
+                Unsigned,
+                List(&[Source, Source]),
+            ],
+        ),
+        Opcode::PutMapExact => (
+            put_map_exact,
+            &[
+                OptionalLabel,
+                Source,
+                Destination,
+                Unsigned,
+                List(&[Source, Source]),
+            ],
+        ),
+        _ => return None,
+    };
+
+    Some(handler)
+}
+
+/// What an operand has to be for its handler, which `load::module` checks so that a malformed
+/// module is not loaded, rather than crashing the process that runs it
+#[derive(Debug)]
+pub enum Shape {
+    /// A term or a register to read
+    Source,
+    /// A register to write
+    Destination,
+    Y,
+    /// A label to jump to
+    Label,
+    /// A label, which may be `0`
+    OptionalLabel,
+    Unsigned,
+    /// A local function
+    Function,
+    /// An index into `Module.imports`
+    Import,
+    /// An index into `Module.funs`
+    Fun,
+    /// A list whose elements repeat the shapes
+    List(&'static [Shape]),
+}
+
+/// The `Code` for calls to an exported function from code that is not loaded, such as `spawn`.
+/// The arguments are on the stack.
+pub fn enter(arc_process: &Arc<Process>) -> code::Result {
+    let module_function_arity = arc_process.current_module_function_arity().unwrap();
+    let arguments = pop_arguments(arc_process, module_function_arity.arity as usize);
+    arc_process.reduce();
+
+    match find_exported(
+        module_function_arity.module,
+        module_function_arity.function,
+        module_function_arity.arity,
+    ) {
+        Some((module, function)) => Machine::new(arc_process, module, function, arguments).run(),
+        None => result_from_exception(
+            arc_process,
+            undefined(
+                arc_process,
+                module_function_arity.module,
+                module_function_arity.function,
+                &arguments,
+            ),
+        ),
+    }
+}
+
+/// The `Code` for `otp::erlang::apply_3::set_code`.
+///
+/// ## Stack
+///
+/// 1. module - atom `Term`
+/// 2. function - atom `Term`
+/// 3. arguments - list `Term`
+pub fn apply_3(arc_process: &Arc<Process>) -> code::Result {
+    let module = arc_process.stack_pop().unwrap();
+    let function = arc_process.stack_pop().unwrap();
+    let argument_list = arc_process.stack_pop().unwrap();
+    arc_process.reduce();
+
+    match apply_3_callee(arc_process, module, function, argument_list) {
+        Ok((Callee::Native(native), arguments)) => match native(arc_process, &arguments) {
+            Ok(value) => {
+                arc_process.return_from_call(value)?;
+
+                Process::call_code(arc_process)
+            }
+            Err(exception) => result_from_exception(arc_process, exception),
+        },
+        Ok((Callee::Loaded(module, function), arguments)) => {
+            let module_function_arity =
+                Arc::clone(&module.functions[function].module_function_arity);
+            arc_process.replace_frame(Frame::new(module_function_arity, enter));
+
+            Machine::new(arc_process, module, function, arguments).run()
+        }
+        Err(exception) => result_from_exception(arc_process, exception),
+    }
+}
+
+fn apply_3_callee(
+    process: &Process,
+    module: Term,
+    function: Term,
+    argument_list: Term,
+) -> Result<(Callee, Vec<Term>), Exception> {
+    let module_atom: Atom = module.try_into()?;
+    let function_atom: Atom = function.try_into()?;
+    let arguments = list_to_vec(argument_list)?;
+
+    match find(module_atom, function_atom, arguments.len() as u8) {
+        Some(callee) => Ok((callee, arguments)),
+        None => Err(undefined(process, module_atom, function_atom, &arguments)),
+    }
+}
+
+/// The `Code` for calls between loaded functions that were made when the process was out of
+/// reductions.  The version of the caller's module is on the stack above the arguments, so that
+/// local calls stay in the version of the module that made them.
+fn enter_local(arc_process: &Arc<Process>) -> code::Result {
+    let module_function_arity = arc_process.current_module_function_arity().unwrap();
+    let version = pop_usize(arc_process);
+    let arguments = pop_arguments(arc_process, module_function_arity.arity as usize);
+
+    match module::get_version(module_function_arity.module, version) {
+        Some(module) => {
+            let function = module
+                .local(module_function_arity.function, module_function_arity.arity)
+                .unwrap();
+
+            Machine::new(arc_process, module, function, arguments).run()
+        }
+        None => result_from_exception(arc_process, killed().into()),
+    }
+}
+
+/// The `Code` of the closures made by `make_fun2` and `make_fun3`, for when they are called by
+/// code that is not loaded.  The environment is on the stack above the arguments, starting with
+/// the version of the module that made the closure.
+fn enter_closure(arc_process: &Arc<Process>) -> code::Result {
+    let module_function_arity = arc_process.current_module_function_arity().unwrap();
+    let version = pop_usize(arc_process);
+    let option_module_fun =
+        module::get_version(module_function_arity.module, version).and_then(|module| {
+            module
+                .fun(module_function_arity.function)
+                .map(|fun| (module, fun))
+        });
+
+    match option_module_fun {
+        Some((module, fun)) => {
+            let function = module.funs[fun].function;
+            let env = pop_arguments(arc_process, module.funs[fun].num_free);
+            let mut arguments = pop_arguments(arc_process, module_function_arity.arity as usize);
+            arguments.extend(env);
+            arc_process.reduce();
+
+            Machine::new(arc_process, module, function, arguments).run()
+        }
+        // The version that made the closure has been purged.  Without it, the number of free
+        // variables on the stack is unknown, so the fun in the error only has the version.
+        None => {
+            let result_fun = arc_process.integer(version).and_then(|version| {
+                arc_process.closure(
+                    arc_process.pid_term(),
+                    module_function_arity,
+                    enter_closure,
+                    vec![version],
+                )
+            });
+            let exception = match result_fun {
+                Ok(fun) => badfun!(arc_process, fun),
+                Err(alloc) => alloc.into(),
+            };
+
+            result_from_exception(arc_process, exception)
+        }
+    }
+}
+
+/// The `Code` of a loaded function that called another function, with the returned value on the
+/// stack above the state of the function.
+fn resume(arc_process: &Arc<Process>) -> code::Result {
+    let value = arc_process.stack_pop().unwrap();
+
+    match Machine::resumed(arc_process) {
+        Ok(mut machine) => {
+            machine.x.push(value);
+
+            machine.run()
+        }
+        Err(exception) => result_from_exception(arc_process, exception.into()),
+    }
+}
+
+/// The `Code` of a loaded function that is waiting for a message in `wait` or `wait_timeout`.
+fn resume_wait(arc_process: &Arc<Process>) -> code::Result {
+    match Machine::resumed(arc_process) {
+        Ok(machine) => machine.run(),
+        Err(exception) => result_from_exception(arc_process, exception.into()),
+    }
+}
+
+pub struct Machine {
+    process: Arc<Process>,
+    module: Arc<Module>,
+    /// The index in `module.functions` of the running function
+    function: usize,
+    /// The index in `module.code` of the next instruction
+    ip: usize,
+    x: Vec<Term>,
+    y: Vec<Term>,
+    catches: Vec<Catch>,
+    put_tuple: Option<PutTuple>,
+}
+
+impl Machine {
+    fn new(
+        process: &Arc<Process>,
+        module: Arc<Module>,
+        function: usize,
+        arguments: Vec<Term>,
+    ) -> Self {
+        let ip = module.functions[function].entry;
+
+        Machine {
+            process: Arc::clone(process),
+            module,
+            function,
+            ip,
+            x: arguments,
+            y: Vec::new(),
+            catches: Vec::new(),
+            put_tuple: None,
+        }
+    }
+
+    /// Pops the state pushed by `push_state`.  The module is the one in the `ModuleFunctionArity`
+    /// of the top `Frame`, which is killed if its version has been purged.
+    fn resumed(process: &Arc<Process>) -> Result<Self, runtime::Exception> {
+        let name = process.current_module_function_arity().unwrap().module;
+        let version = pop_usize(process);
+        let ip = pop_usize(process);
+        let function = pop_usize(process);
+
+        let catch_count = pop_usize(process);
+        let mut catches = Vec::with_capacity(catch_count);
+
+        for _ in 0..catch_count {
+            let kind = if pop_usize(process) == 0 {
+                CatchKind::Catch
+            } else {
+                CatchKind::Try
+            };
+            let handler = pop_usize(process);
+            let y = pop_usize(process);
+
+            catches.push(Catch { y, handler, kind });
+        }
+
+        catches.reverse();
+
+        let y_count = pop_usize(process);
+        let mut y = pop_arguments(process, y_count);
+        y.reverse();
+
+        match module::get_version(name, version) {
+            Some(module) => Ok(Machine {
+                process: Arc::clone(process),
+                module,
+                function,
+                ip,
+                x: Vec::new(),
+                y,
+                catches,
+                put_tuple: None,
+            }),
+            None => Err(killed()),
+        }
+    }
+
+    /// Pushes the registers that survive a call on the process' stack, so that `resumed` can
+    /// restore them when the call returns.
+    fn push_state(&self) -> Result<(), Alloc> {
+        let process = &self.process;
+
+        for term in &self.y {
+            process.stack_push(*term)?;
+        }
+
+        process.stack_push(process.integer(self.y.len())?)?;
+
+        for catch in &self.catches {
+            process.stack_push(process.integer(catch.y)?)?;
+            process.stack_push(process.integer(catch.handler)?)?;
+            process.stack_push(process.integer(match catch.kind {
+                CatchKind::Catch => 0,
+                CatchKind::Try => 1,
+            })?)?;
+        }
+
+        process.stack_push(process.integer(self.catches.len())?)?;
+        process.stack_push(process.integer(self.function)?)?;
+        process.stack_push(process.integer(self.ip)?)?;
+        process.stack_push(process.integer(self.module.version)?)
+    }
+
+    fn run(mut self) -> code::Result {
+        loop {
+            let module = Arc::clone(&self.module);
+
+            // Calls and returns that switch modules break out of the inner loop, so that
+            // `module` is the module of `self.ip` again
+            let result = loop {
+                let instruction = &module.code[self.ip];
+                self.ip += 1;
+
+                match (instruction.handler)(&mut self, &instruction.operands) {
+                    Ok(Step::Next) if Arc::ptr_eq(&module, &self.module) => continue,
+                    result => break result,
+                }
+            };
+
+            let step = match result {
+                Ok(step) => step,
+                Err(Exception::Runtime(runtime_exception)) => {
+                    self.handle_exception(runtime_exception)?
+                }
+                Err(Exception::System(system_exception)) => return Err(system_exception),
+            };
+
+            if let Step::Stop = step {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Jumps to the innermost catch, unwinding the calls in this `Machine` until one has a catch.
+    fn handle_exception(
+        &mut self,
+        mut exception: runtime::Exception,
+    ) -> Result<Step, system::Exception> {
+        loop {
+            if exception.stacktrace.is_none() {
+                exception.stacktrace = Some(self.stacktrace(&exception.class)?);
+            }
+
+            if let Some(catch) = self.catches.last().cloned() {
+                self.catch(catch, exception)?;
+
+                return Ok(Step::Next);
+            }
+
+            self.process.pop_code_stack();
+
+            match self.process.current_code() {
+                Some(code) if code as usize == resume as usize => {
+                    match Machine::resumed(&self.process) {
+                        Ok(machine) => *self = machine,
+                        Err(killed) => {
+                            self.process.exception(killed);
+
+                            return Ok(Step::Stop);
+                        }
+                    }
+                }
+                _ => {
+                    self.process.exception(exception);
+
+                    return Ok(Step::Stop);
+                }
+            }
+        }
+    }
+
+    fn catch(&mut self, catch: Catch, exception: runtime::Exception) -> Result<(), Alloc> {
+        let process = &self.process;
+        let reason = exception.reason;
+        let stacktrace = exception.stacktrace.unwrap();
+
+        self.x = match catch.kind {
+            CatchKind::Try => {
+                let class = match exception.class {
+                    Class::Error { .. } => "error",
+                    Class::Exit => "exit",
+                    Class::Throw => "throw",
+                };
+
+                vec![atom_unchecked(class), reason, stacktrace]
+            }
+            CatchKind::Catch => {
+                let value = match exception.class {
+                    Class::Throw => reason,
+                    Class::Exit => process.tuple_from_slice(&[atom_unchecked("EXIT"), reason])?,
+                    Class::Error { .. } => {
+                        let reason_stacktrace = process.tuple_from_slice(&[reason, stacktrace])?;
+
+                        process.tuple_from_slice(&[atom_unchecked("EXIT"), reason_stacktrace])?
+                    }
+                };
+
+                vec![value]
+            }
+        };
+        self.ip = catch.handler;
+
+        Ok(())
+    }
+
+    fn stacktrace(&self, class: &Class) -> Result<Term, Alloc> {
+        let process = &self.process;
+        let module_function_arity = self.module_function_arity();
+        let arity_or_arguments = match class {
+            Class::Error {
+                arguments: Some(arguments),
+            } => *arguments,
+            _ => process.integer(module_function_arity.arity as usize)?,
+        };
+        let item = process.tuple_from_slice(&[
+            unsafe { module_function_arity.module.as_term() },
+            unsafe { module_function_arity.function.as_term() },
+            arity_or_arguments,
+            Term::NIL,
+        ])?;
+
+        process.list_from_slice(&[item])
+    }
+
+    fn module_function_arity(&self) -> Arc<ModuleFunctionArity> {
+        Arc::clone(&self.module.functions[self.function].module_function_arity)
+    }
+
+    /// Reading or writing a y register outside of the stack frame raises `badarg`, as the frame
+    /// depends on the path taken to the instruction, which loading does not follow.
+    fn read(&self, operand: &Operand) -> Result<Term, Exception> {
+        match operand {
+            Operand::Term(term) => Ok(*term),
+            Operand::Literal(index) => Ok(self
+                .module
+                .literals
+                .get(*index)
+                .clone_to_process(&self.process)),
+            Operand::X(register) => Ok(self.x.get(*register).cloned().unwrap_or(Term::NIL)),
+            Operand::Y(register) => match self.y.get(*register) {
+                Some(term) => Ok(*term),
+                None => Err(liblumen_alloc::badarg!().into()),
+            },
+            _ => unreachable!("{:?} is not a source, which loading checks", operand),
+        }
+    }
+
+    fn write(&mut self, operand: &Operand, term: Term) -> Result<(), Exception> {
+        match operand {
+            Operand::X(register) => {
+                if self.x.len() <= *register {
+                    self.x.resize(register + 1, Term::NIL);
+                }
+
+                self.x[*register] = term;
+
+                Ok(())
+            }
+            Operand::Y(register) => match self.y.get_mut(*register) {
+                Some(slot) => {
+                    *slot = term;
+
+                    Ok(())
+                }
+                None => Err(liblumen_alloc::badarg!().into()),
+            },
+            _ => unreachable!("{:?} is not a destination, which loading checks", operand),
+        }
+    }
+
+    /// `x(0)` to `x(arity - 1)`
+    fn arguments(&self, arity: usize) -> Vec<Term> {
+        (0..arity)
+            .map(|register| self.x.get(register).cloned().unwrap_or(Term::NIL))
+            .collect()
+    }
+
+    fn jump(&mut self, operand: &Operand) {
+        self.ip = label(operand).unwrap();
+    }
+
+    /// Continues with the next instruction if `passed`, otherwise at the `fail` label.
+    fn test(&mut self, fail: &Operand, passed: bool) -> Result<Step, Exception> {
+        if !passed {
+            self.jump(fail);
+        }
+
+        Ok(Step::Next)
+    }
+
+    /// Calls `function` with the arguments in the x registers.
+    fn call_function(
+        &mut self,
+        module: Arc<Module>,
+        function: usize,
+        call: Call,
+    ) -> Result<Step, Exception> {
+        let process = Arc::clone(&self.process);
+        let module_function_arity = Arc::clone(&module.functions[function].module_function_arity);
+        let arity = module_function_arity.arity as usize;
+        process.reduce();
+
+        let frame = Frame::new(module_function_arity, enter_local);
+
+        match call {
+            Call::Body => {
+                self.push_state()?;
+                process.replace_frame(Frame::new(self.module_function_arity(), resume));
+                process.push_frame(frame);
+            }
+            Call::Tail => process.replace_frame(frame),
+        }
+
+        if process.is_reduced() {
+            for argument in self.arguments(arity).iter().rev() {
+                process.stack_push(*argument)?;
+            }
+
+            process.stack_push(process.integer(module.version)?)?;
+
+            Ok(Step::Stop)
+        } else {
+            self.x.truncate(arity);
+            self.ip = module.functions[function].entry;
+            self.module = module;
+            self.function = function;
+            self.y.clear();
+            self.catches.clear();
+
+            Ok(Step::Next)
+        }
+    }
+
+    fn call_native(
+        &mut self,
+        native: Native,
+        arguments: &[Term],
+        call: Call,
+    ) -> Result<Step, Exception> {
+        self.process.reduce();
+
+        let value = native(&self.process, arguments)?;
+
+        match call {
+            Call::Body => {
+                self.write(&Operand::X(0), value)?;
+
+                Ok(Step::Next)
+            }
+            Call::Tail => self.return_value(value),
+        }
+    }
+
+    fn call_module_function(
+        &mut self,
+        module: Atom,
+        function: Atom,
+        arguments: Vec<Term>,
+        call: Call,
+    ) -> Result<Step, Exception> {
+        let arity = arguments.len();
+
+        match find(module, function, arity as u8) {
+            Some(Callee::Native(native)) => self.call_native(native, &arguments, call),
+            Some(Callee::Loaded(module, function)) => {
+                self.x = arguments;
+
+                self.call_function(module, function, call)
+            }
+            // `apply` is not a native, as it calls back into loaded code
+            None if module.name() == "erlang" && function.name() == "apply" && arity == 3 => {
+                let module: Atom = arguments[0].try_into()?;
+                let function: Atom = arguments[1].try_into()?;
+                let arguments = list_to_vec(arguments[2])?;
+
+                self.call_module_function(module, function, arguments, call)
+            }
+            None if module.name() == "erlang" && function.name() == "apply" && arity == 2 => {
+                let fun_arguments = list_to_vec(arguments[1])?;
+
+                self.call_fun(arguments[0], fun_arguments, call)
+            }
+            None => Err(undefined(&self.process, module, function, &arguments)),
+        }
+    }
+
+    fn call_fun(&mut self, fun: Term, arguments: Vec<Term>, call: Call) -> Result<Step, Exception> {
+        let process = Arc::clone(&self.process);
+        let result_closure: Result<Boxed<Closure>, _> = fun.try_into();
+        let closure = match result_closure {
+            Ok(closure) => closure,
+            Err(_) => return Err(badfun!(&process, fun)),
+        };
+
+        if closure.arity() as usize != arguments.len() {
+            let argument_list = process.list_from_slice(&arguments)?;
+
+            return Err(badarity!(&process, fun, argument_list));
+        }
+
+        if closure.frame().code() as usize == enter_closure as usize {
+            let module_function_arity = closure.module_function_arity();
+            let version: usize = closure.env[0].try_into().unwrap();
+            let option_module_fun = module::get_version(module_function_arity.module, version)
+                .and_then(|module| {
+                    module
+                        .fun(module_function_arity.function)
+                        .map(|fun| (module, fun))
+                });
+
+            match option_module_fun {
+                Some((module, fun)) => {
+                    let function = module.funs[fun].function;
+                    let mut arguments = arguments;
+                    arguments.extend(closure.env[1..].iter().cloned());
+                    self.x = arguments;
+
+                    self.call_function(module, function, call)
+                }
+                None => Err(badfun!(&process, fun)),
+            }
+        } else {
+            process.reduce();
+
+            match call {
+                Call::Body => {
+                    self.push_state()?;
+                    process.replace_frame(Frame::new(self.module_function_arity(), resume));
+                }
+                Call::Tail => process.pop_code_stack(),
+            }
+
+            closure.place_frame_with_arguments(&process, Placement::Push, arguments)?;
+            Process::call_code(&process).map_err(Exception::System)?;
+
+            Ok(Step::Stop)
+        }
+    }
+
+    fn return_value(&mut self, value: Term) -> Result<Step, Exception> {
+        let process = Arc::clone(&self.process);
+        process.reduce();
+        process.pop_code_stack();
+
+        match process.current_code() {
+            Some(code) if code as usize == resume as usize && !process.is_reduced() => {
+                match Machine::resumed(&process) {
+                    Ok(machine) => {
+                        *self = machine;
+                        self.write(&Operand::X(0), value)?;
+
+                        Ok(Step::Next)
+                    }
+                    Err(killed) => {
+                        process.exception(killed);
+
+                        Ok(Step::Stop)
+                    }
+                }
+            }
+            Some(_) => {
+                process.stack_push(value)?;
+                Process::call_code(&process).map_err(Exception::System)?;
+
+                Ok(Step::Stop)
+            }
+            // no caller, so the process exits normally
+            None => Ok(Step::Stop),
+        }
+    }
+
+    /// Waits for a message, continuing at `Machine.ip` when the process is run again.
+    fn wait(&mut self) -> Result<Step, Exception> {
+        self.push_state()?;
+        self.process
+            .replace_frame(Frame::new(self.module_function_arity(), resume_wait));
+        self.process.wait();
+
+        Ok(Step::Stop)
+    }
+
+    fn bif(
+        &mut self,
+        fail: &Operand,
+        import: &Operand,
+        arguments: &[Term],
+        destination: &Operand,
+    ) -> Result<Step, Exception> {
+        let import = &self.module.imports[unsigned(import)];
+        let result = match import.native {
+            Some(native) => native(&self.process, arguments),
+            None => {
+                let module_function_arity = &import.module_function_arity;
+
+                Err(undefined(
+                    &self.process,
+                    module_function_arity.module,
+                    module_function_arity.function,
+                    arguments,
+                ))
+            }
+        };
+
+        match result {
+            Ok(value) => {
+                self.write(destination, value)?;
+
+                Ok(Step::Next)
+            }
+            Err(Exception::Runtime(_)) if label(fail).is_some() => {
+                self.jump(fail);
+
+                Ok(Step::Next)
+            }
+            Err(exception) => Err(exception),
+        }
+    }
+
+    /// The environment of the closure starts with the version of the module, so that calling the
+    /// closure runs the version that made it, or raises `badfun` once that version is purged.
+    fn make_fun(&self, fun: usize, free: Vec<Term>) -> Result<Term, Alloc> {
+        let module_function_arity = Arc::clone(&self.module.funs[fun].module_function_arity);
+        let mut env = Vec::with_capacity(1 + free.len());
+        env.push(self.process.integer(self.module.version)?);
+        env.extend(free);
+
+        self.process.closure(
+            self.process.pid_term(),
+            module_function_arity,
+            enter_closure,
+            env,
+        )
+    }
+
+    fn put_map(&mut self, operands: &[Operand], exact: bool) -> Result<Step, Exception> {
+        let process = Arc::clone(&self.process);
+        let source = self.read(&operands[1])?;
+        let result_map: Result<Boxed<Map>, _> = source.try_into();
+        let map = match result_map {
+            Ok(map) => map,
+            Err(_) if label(&operands[0]).is_some() => {
+                self.jump(&operands[0]);
+
+                return Ok(Step::Next);
+            }
+            Err(_) => return Err(badmap!(&process, source)),
+        };
+        let mut hash_map = map.as_ref().clone();
+
+        for pair in list(&operands[4]).chunks(2) {
+            let key = self.read(&pair[0])?;
+
+            if exact && !hash_map.contains_key(&key) {
+                return match label(&operands[0]) {
+                    Some(_) => {
+                        self.jump(&operands[0]);
+
+                        Ok(Step::Next)
+                    }
+                    None => Err(badkey!(&process, key)),
+                };
+            }
+
+            hash_map.insert(key, self.read(&pair[1])?);
+        }
+
+        let map = process.map_from_hash_map(hash_map)?;
+        self.write(&operands[2], map)?;
+
+        Ok(Step::Next)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Catch {
+    /// The y register of the catch tag, which `catch_end`, `try_end` and `try_case` name
+    y: usize,
+    /// The index in `Module.code` of the `catch_end` or `try_case`
+    handler: usize,
+    kind: CatchKind,
+}
+
+#[derive(Clone, Copy)]
+enum CatchKind {
+    Catch,
+    Try,
+}
+
+/// A tuple being built by `put_tuple` and `put`
+struct PutTuple {
+    destination: Operand,
+    arity: usize,
+    elements: Vec<Term>,
+}
+
+enum Call {
+    /// The caller continues when the call returns
+    Body,
+    /// The call replaces the caller
+    Tail,
+}
+
+enum Callee {
+    Native(Native),
+    Loaded(Arc<Module>, usize),
+}
+
+fn find(module: Atom, function: Atom, arity: u8) -> Option<Callee> {
+    otp::natives()
+        .get(&(module, function, arity))
+        .cloned()
+        .map(Callee::Native)
+        .or_else(|| {
+            find_exported(module, function, arity)
+                .map(|(module, function)| Callee::Loaded(module, function))
+        })
+}
+
+fn find_exported(module: Atom, function: Atom, arity: u8) -> Option<(Arc<Module>, usize)> {
+    module::get(module).and_then(|module| {
+        module
+            .exported(function, arity)
+            .map(|function| (module, function))
+    })
+}
+
+fn undefined(process: &Process, module: Atom, function: Atom, arguments: &[Term]) -> Exception {
+    match process.list_from_slice(arguments) {
+        Ok(argument_list) => undef!(
+            process,
+            unsafe { module.as_term() },
+            unsafe { function.as_term() },
+            argument_list
+        ),
+        Err(alloc) => alloc.into(),
+    }
+}
+
+fn killed() -> runtime::Exception {
+    exit!(atom_unchecked("killed"))
+}
+
+// The first argument is on the top of the stack
+fn pop_arguments(process: &Process, arity: usize) -> Vec<Term> {
+    (0..arity).map(|_| process.stack_pop().unwrap()).collect()
+}
+
+fn pop_usize(process: &Process) -> usize {
+    process.stack_pop().unwrap().try_into().unwrap()
+}
+
+fn list_to_vec(list: Term) -> Result<Vec<Term>, Exception> {
+    match list.to_typed_term().unwrap() {
+        TypedTerm::Nil => Ok(Vec::new()),
+        TypedTerm::List(cons) => {
+            let mut vec = Vec::new();
+
+            for result in cons.into_iter() {
+                vec.push(result?);
+            }
+
+            Ok(vec)
+        }
+        _ => Err(liblumen_alloc::badarg!().into()),
+    }
+}
+
+fn unsigned(operand: &Operand) -> usize {
+    match operand {
+        Operand::Unsigned(unsigned) => *unsigned,
+        _ => unreachable!("{:?} is not unsigned, which loading checks", operand),
+    }
+}
+
+fn label(operand: &Operand) -> Option<usize> {
+    match operand {
+        Operand::Label(label) => *label,
+        _ => unreachable!("{:?} is not a label, which loading checks", operand),
+    }
+}
+
+fn list(operand: &Operand) -> &[Operand] {
+    match operand {
+        Operand::List(list) => list,
+        _ => unreachable!("{:?} is not a list, which loading checks", operand),
+    }
+}
+
+// Returns true if `message` is the one sent by the timer of a receive
+fn is_timer_message(timeout: RecvTimeout, message: Term) -> bool {
+    let reference = match timeout {
+        RecvTimeout::Timer(reference) => reference,
+        _ => return false,
+    };
+
+    let tuple: Result<Boxed<Tuple>, _> = message.try_into();
+    match tuple {
+        Ok(tuple) if tuple.len() == 3 && tuple[0] == atom_unchecked("timeout") => {
+            let message_reference: Result<Boxed<Reference>, _> = tuple[1].try_into();
+            match message_reference {
+                Ok(message_reference) => *message_reference == reference,
+                Err(_) => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+// Handlers
+
+fn no_op(_: &mut Machine, _: &[Operand]) -> Result<Step, Exception> {
+    Ok(Step::Next)
+}
+
+/// `func_info Module Function Arity`, which raises `function_clause` when no clause matched
+fn func_info(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let arguments = machine.arguments(unsigned(&operands[2]));
+    let argument_list = machine.process.list_from_slice(&arguments)?;
+
+    Err(error!(atom_unchecked("function_clause"), Some(argument_list)).into())
+}
+
+fn call(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    local_call(machine, &operands[1], Call::Body)
+}
+
+fn call_last(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    machine.y.clear();
+
+    local_call(machine, &operands[1], Call::Tail)
+}
+
+fn call_only(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    local_call(machine, &operands[1], Call::Tail)
+}
+
+fn local_call(machine: &mut Machine, function: &Operand, call: Call) -> Result<Step, Exception> {
+    let function = match function {
+        Operand::Function(function) => *function,
+        _ => unreachable!("{:?} is not a function, which loading checks", function),
+    };
+    let module = Arc::clone(&machine.module);
+
+    machine.call_function(module, function, call)
+}
+
+fn call_ext(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    external_call(machine, operands, Call::Body)
+}
+
+fn call_ext_last(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    machine.y.clear();
+
+    external_call(machine, operands, Call::Tail)
+}
+
+fn call_ext_only(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    external_call(machine, operands, Call::Tail)
+}
+
+/// `[Arity, Import, ...]`
+fn external_call(
+    machine: &mut Machine,
+    operands: &[Operand],
+    call: Call,
+) -> Result<Step, Exception> {
+    let arguments = machine.arguments(unsigned(&operands[0]));
+    let (native, module_function_arity) = {
+        let import = &machine.module.imports[unsigned(&operands[1])];
+
+        (import.native, Arc::clone(&import.module_function_arity))
+    };
+
+    match native {
+        Some(native) => machine.call_native(native, &arguments, call),
+        None => machine.call_module_function(
+            module_function_arity.module,
+            module_function_arity.function,
+            arguments,
+            call,
+        ),
+    }
+}
+
+fn bif0(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    machine.bif(&Operand::Label(None), &operands[0], &[], &operands[1])
+}
+
+fn bif1(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let arguments = [machine.read(&operands[2])?];
+
+    machine.bif(&operands[0], &operands[1], &arguments, &operands[3])
+}
+
+fn bif2(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let arguments = [machine.read(&operands[2])?, machine.read(&operands[3])?];
+
+    machine.bif(&operands[0], &operands[1], &arguments, &operands[4])
+}
+
+fn gc_bif1(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let arguments = [machine.read(&operands[3])?];
+
+    machine.bif(&operands[0], &operands[2], &arguments, &operands[4])
+}
+
+fn gc_bif2(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let arguments = [machine.read(&operands[3])?, machine.read(&operands[4])?];
+
+    machine.bif(&operands[0], &operands[2], &arguments, &operands[5])
+}
+
+fn gc_bif3(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let arguments = [
+        machine.read(&operands[3])?,
+        machine.read(&operands[4])?,
+        machine.read(&operands[5])?,
+    ];
+
+    machine.bif(&operands[0], &operands[2], &arguments, &operands[6])
+}
+
+fn allocate(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    machine.y = vec![Term::NIL; unsigned(&operands[0])];
+
+    Ok(Step::Next)
+}
+
+// The heap grows as terms are built, so only the stack needs allocating
+fn allocate_heap(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    allocate(machine, operands)
+}
+
+fn init(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    machine.write(&operands[0], Term::NIL)?;
+
+    Ok(Step::Next)
+}
+
+fn init_yregs(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    for register in list(&operands[0]) {
+        machine.write(register, Term::NIL)?;
+    }
+
+    Ok(Step::Next)
+}
+
+fn trim(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let count = unsigned(&operands[0]);
+
+    if machine.y.len() < count || machine.catches.iter().any(|catch| catch.y < count) {
+        return Err(liblumen_alloc::badarg!().into());
+    }
+
+    machine.y.drain(..count);
+
+    for catch in &mut machine.catches {
+        catch.y -= count;
+    }
+
+    Ok(Step::Next)
+}
+
+fn deallocate(machine: &mut Machine, _: &[Operand]) -> Result<Step, Exception> {
+    machine.y.clear();
+
+    Ok(Step::Next)
+}
+
+fn return_(machine: &mut Machine, _: &[Operand]) -> Result<Step, Exception> {
+    let value = machine.read(&Operand::X(0))?;
+
+    machine.return_value(value)
+}
+
+fn send(machine: &mut Machine, _: &[Operand]) -> Result<Step, Exception> {
+    let destination = machine.read(&Operand::X(0))?;
+    let message = machine.read(&Operand::X(1))?;
+    machine.process.reduce();
+
+    let sent = erlang::send_2(destination, message, &machine.process)?;
+    machine.write(&Operand::X(0), sent)?;
+
+    Ok(Step::Next)
+}
+
+/// `loop_rec Fail Destination` puts the next message in `Destination`, or jumps to `Fail`, which
+/// waits, if there are no more messages.
+fn loop_rec(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let option_message = {
+        let mailbox_guard = machine.process.mailbox.lock();
+        let mut mailbox = mailbox_guard.borrow_mut();
+        let timeout = mailbox.recv_timeout();
+
+        match mailbox.recv_peek() {
+            Some(message) if !is_timer_message(timeout, message) => {
+                mailbox.recv_increment();
+
+                Some((message, mailbox.recv_last_off_heap()))
+            }
+            // The timer message is left under the cursor for `wait_timeout`
+            _ => None,
+        }
+    };
+
+    match option_message {
+        Some((message, off_heap)) => {
+            let message = if off_heap {
+                message.clone_to_process(&machine.process)
+            } else {
+                message
+            };
+            machine.write(&operands[1], message)?;
+        }
+        None => machine.jump(&operands[0]),
+    }
+
+    Ok(Step::Next)
+}
+
+/// Removes the message that matched, cancelling the receive's timer if it has one.
+fn remove_message(machine: &mut Machine, _: &[Operand]) -> Result<Step, Exception> {
+    let process = &machine.process;
+    let mailbox_guard = process.mailbox.lock();
+    let mut mailbox = mailbox_guard.borrow_mut();
+    let timeout = mailbox.recv_timeout();
+    mailbox.recv_finish(process);
+
+    if let RecvTimeout::Timer(reference) = timeout {
+        // If the timer already expired its message is in the mailbox, where it must not be seen
+        // by later receives.
+        if timer::cancel(&reference).is_none() {
+            mailbox.flush(
+                |message| match message {
+                    Message::Process(message::Process { data }) => is_timer_message(timeout, *data),
+                    Message::HeapFragment(message::HeapFragment { data, .. }) => {
+                        is_timer_message(timeout, *data)
+                    }
+                },
+                process,
+            );
+        }
+    }
+
+    Ok(Step::Next)
+}
+
+fn wait(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    machine.jump(&operands[0]);
+
+    machine.wait()
+}
+
+/// `wait_timeout Label Time` starts the receive's timer the first time no message matched, and
+/// continues with the `after` clause once the timer's message arrives.
+fn wait_timeout(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let process = Arc::clone(&machine.process);
+    let time = machine.read(&operands[1])?;
+    let (recv_timeout, timed_out) = {
+        let mailbox_guard = process.mailbox.lock();
+        let mailbox = mailbox_guard.borrow();
+        let recv_timeout = mailbox.recv_timeout();
+        let timed_out = match mailbox.recv_peek() {
+            Some(message) => is_timer_message(recv_timeout, message),
+            None => false,
+        };
+
+        (recv_timeout, timed_out)
+    };
+
+    let option_milliseconds = if time == atom_unchecked("infinity") {
+        None
+    } else {
+        let milliseconds: Milliseconds = time
+            .try_into()
+            .map_err(|_| error!(atom_unchecked("timeout_value")))?;
+
+        Some(milliseconds)
+    };
+
+    if timed_out || option_milliseconds == Some(0) {
+        process
+            .mailbox
+            .lock()
+            .borrow_mut()
+            .recv_finish_timeout(&process);
+
+        return Ok(Step::Next);
+    }
+
+    if let (RecvTimeout::Infinity, Some(milliseconds)) = (recv_timeout, option_milliseconds) {
+        let reference_term = timer::start(
+            monotonic::time_in_milliseconds() + milliseconds,
+            Destination::Process(Arc::downgrade(&process)),
+            Timeout::TimeoutTuple,
+            atom_unchecked("receive_timeout"),
+            &process,
+        )?;
+        let reference: Boxed<Reference> = reference_term.try_into().unwrap();
+
+        process
+            .mailbox
+            .lock()
+            .borrow_mut()
+            .recv_set_timeout(RecvTimeout::Timer(*reference));
+    }
+
+    machine.jump(&operands[0]);
+
+    machine.wait()
+}
+
+fn recv_marker_reserve(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    machine.write(&operands[0], Term::NIL)?;
+
+    Ok(Step::Next)
+}
+
+/// Defines handlers for `test Fail Left Right`
+macro_rules! comparison_tests {
+    ($($name:ident($left:ident, $right:ident) => $passed:expr;)*) => {
+        $(
+            fn $name(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+                let $left = machine.read(&operands[1])?;
+                let $right = machine.read(&operands[2])?;
+
+                machine.test(&operands[0], $passed)
+            }
+        )*
+    };
+}
+
+comparison_tests! {
+    is_lt(left, right) => left < right;
+    is_ge(left, right) => left >= right;
+    is_eq(left, right) => left == right;
+    is_ne(left, right) => left != right;
+    is_eq_exact(left, right) => left.exactly_eq(&right);
+    is_ne_exact(left, right) => left.exactly_ne(&right);
+}
+
+/// Defines handlers for `test Fail Source`
+macro_rules! type_tests {
+    ($($name:ident => $predicate:ident;)*) => {
+        $(
+            fn $name(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+                let term = machine.read(&operands[1])?;
+
+                machine.test(&operands[0], term.$predicate())
+            }
+        )*
+    };
+}
+
+type_tests! {
+    is_integer => is_integer;
+    is_float => is_float;
+    is_number => is_number;
+    is_atom => is_atom;
+    is_pid => is_pid;
+    is_reference => is_reference;
+    is_port => is_port;
+    is_nil => is_nil;
+    is_binary => is_binary;
+    is_bitstr => is_bitstring;
+    is_list => is_list;
+    is_nonempty_list => is_non_empty_list;
+    is_tuple => is_tuple;
+    is_boolean => is_boolean;
+    is_function => is_function;
+    is_map => is_map;
+}
+
+fn is_function2(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let term = machine.read(&operands[1])?;
+    let arity: Result<usize, _> = machine.read(&operands[2])?.try_into();
+    let passed = match arity {
+        Ok(arity) => term.is_function_with_arity(arity),
+        Err(_) => false,
+    };
+
+    machine.test(&operands[0], passed)
+}
+
+fn test_arity(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let tuple: Result<Boxed<Tuple>, _> = machine.read(&operands[1])?.try_into();
+    let passed = match tuple {
+        Ok(tuple) => tuple.len() == unsigned(&operands[2]),
+        Err(_) => false,
+    };
+
+    machine.test(&operands[0], passed)
+}
+
+fn is_tagged_tuple(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let tuple: Result<Boxed<Tuple>, _> = machine.read(&operands[1])?.try_into();
+    let passed = match tuple {
+        Ok(tuple) => {
+            tuple.len() == unsigned(&operands[2])
+                && tuple[0].exactly_eq(&machine.read(&operands[3])?)
+        }
+        Err(_) => false,
+    };
+
+    machine.test(&operands[0], passed)
+}
+
+/// `select_val Source Fail [Value, Label, ...]`
+fn select_val(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let source = machine.read(&operands[0])?;
+    let mut option_label = None;
+
+    for pair in list(&operands[2]).chunks(2) {
+        if source.exactly_eq(&machine.read(&pair[0])?) {
+            option_label = Some(&pair[1]);
+
+            break;
+        }
+    }
+
+    machine.jump(option_label.unwrap_or(&operands[1]));
+
+    Ok(Step::Next)
+}
+
+/// `select_tuple_arity Source Fail [Arity, Label, ...]`
+fn select_tuple_arity(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let tuple: Result<Boxed<Tuple>, _> = machine.read(&operands[0])?.try_into();
+    let option_label = match tuple {
+        Ok(tuple) => list(&operands[2])
+            .chunks(2)
+            .find(|pair| unsigned(&pair[0]) == tuple.len())
+            .map(|pair| &pair[1]),
+        Err(_) => None,
+    };
+
+    machine.jump(option_label.unwrap_or(&operands[1]));
+
+    Ok(Step::Next)
+}
+
+fn jump(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    machine.jump(&operands[0]);
+
+    Ok(Step::Next)
+}
+
+fn move_(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let term = machine.read(&operands[0])?;
+    machine.write(&operands[1], term)?;
+
+    Ok(Step::Next)
+}
+
+fn swap(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let first = machine.read(&operands[0])?;
+    let second = machine.read(&operands[1])?;
+    machine.write(&operands[0], second)?;
+    machine.write(&operands[1], first)?;
+
+    Ok(Step::Next)
+}
+
+fn get_list(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let cons: Boxed<Cons> = machine.read(&operands[0])?.try_into()?;
+    machine.write(&operands[1], cons.head)?;
+    machine.write(&operands[2], cons.tail)?;
+
+    Ok(Step::Next)
+}
+
+fn get_hd(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let cons: Boxed<Cons> = machine.read(&operands[0])?.try_into()?;
+    machine.write(&operands[1], cons.head)?;
+
+    Ok(Step::Next)
+}
+
+fn get_tl(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let cons: Boxed<Cons> = machine.read(&operands[0])?.try_into()?;
+    machine.write(&operands[1], cons.tail)?;
+
+    Ok(Step::Next)
+}
+
+fn get_tuple_element(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let tuple: Boxed<Tuple> = machine.read(&operands[0])?.try_into()?;
+    let element = tuple.get_element_from_zero_based_usize_index(unsigned(&operands[1]))?;
+    machine.write(&operands[2], element)?;
+
+    Ok(Step::Next)
+}
+
+/// Updates a tuple that was just built, which is not yet visible to anything else.
+fn set_tuple_element(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let element = machine.read(&operands[0])?;
+    let mut tuple: Boxed<Tuple> = machine.read(&operands[1])?.try_into()?;
+    tuple.set_element_from_zero_based_usize_index(unsigned(&operands[2]), element)?;
+
+    Ok(Step::Next)
+}
+
+fn put_list(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let head = machine.read(&operands[0])?;
+    let tail = machine.read(&operands[1])?;
+    let list = machine.process.cons(head, tail)?;
+    machine.write(&operands[2], list)?;
+
+    Ok(Step::Next)
+}
+
+/// `put_tuple Arity Destination`, followed by a `put` for each element
+fn put_tuple(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let arity = unsigned(&operands[0]);
+
+    if arity == 0 {
+        let tuple = machine.process.tuple_from_slice(&[])?;
+        machine.write(&operands[1], tuple)?;
+    } else {
+        machine.put_tuple = Some(PutTuple {
+            destination: operands[1].clone(),
+            arity,
+            elements: Vec::with_capacity(arity),
+        });
+    }
+
+    Ok(Step::Next)
+}
+
+fn put(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let element = machine.read(&operands[0])?;
+    let mut put_tuple = match machine.put_tuple.take() {
+        Some(put_tuple) => put_tuple,
+        // a `put` that does not follow a `put_tuple`
+        None => return Err(liblumen_alloc::badarg!().into()),
+    };
+    put_tuple.elements.push(element);
+
+    if put_tuple.elements.len() == put_tuple.arity {
+        let tuple = machine.process.tuple_from_slice(&put_tuple.elements)?;
+        machine.write(&put_tuple.destination, tuple)?;
+    } else {
+        machine.put_tuple = Some(put_tuple);
+    }
+
+    Ok(Step::Next)
+}
+
+/// `put_tuple2 Destination [Element, ...]`
+fn put_tuple2(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let elements: Vec<Term> = list(&operands[1])
+        .iter()
+        .map(|element| machine.read(element))
+        .collect::<Result<_, _>>()?;
+    let tuple = machine.process.tuple_from_slice(&elements)?;
+    machine.write(&operands[0], tuple)?;
+
+    Ok(Step::Next)
+}
+
+fn tagged_error(machine: &Machine, tag: &str, value: Term) -> Result<Step, Exception> {
+    let reason = machine
+        .process
+        .tuple_from_slice(&[atom_unchecked(tag), value])?;
+
+    Err(error!(reason).into())
+}
+
+fn badmatch(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    tagged_error(machine, "badmatch", machine.read(&operands[0])?)
+}
+
+fn if_end(_: &mut Machine, _: &[Operand]) -> Result<Step, Exception> {
+    Err(error!(atom_unchecked("if_clause")).into())
+}
+
+fn case_end(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    tagged_error(machine, "case_clause", machine.read(&operands[0])?)
+}
+
+fn badrecord(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    tagged_error(machine, "badrecord", machine.read(&operands[0])?)
+}
+
+fn try_case_end(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    tagged_error(machine, "try_clause", machine.read(&operands[0])?)
+}
+
+/// `catch Y Label` and `try Y Label`
+fn push_catch(
+    machine: &mut Machine,
+    operands: &[Operand],
+    kind: CatchKind,
+) -> Result<Step, Exception> {
+    let y = match operands[0] {
+        Operand::Y(y) => y,
+        _ => unreachable!(
+            "{:?} is not a y register, which loading checks",
+            operands[0]
+        ),
+    };
+
+    machine.catches.push(Catch {
+        y,
+        handler: label(&operands[1]).unwrap(),
+        kind,
+    });
+
+    Ok(Step::Next)
+}
+
+/// `catch_end Y`, `try_end Y` and `try_case Y`, which end the catch whether or not there was an
+/// exception
+fn pop_catch(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let y = match operands[0] {
+        Operand::Y(y) => y,
+        _ => unreachable!(
+            "{:?} is not a y register, which loading checks",
+            operands[0]
+        ),
+    };
+
+    machine.catches.retain(|catch| catch.y != y);
+
+    Ok(Step::Next)
+}
+
+fn catch(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    push_catch(machine, operands, CatchKind::Catch)
+}
+
+fn catch_end(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    pop_catch(machine, operands)
+}
+
+fn try_(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    push_catch(machine, operands, CatchKind::Try)
+}
+
+fn try_end(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    pop_catch(machine, operands)
+}
+
+fn try_case(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    pop_catch(machine, operands)
+}
+
+/// Reraises the exception in `x(0)` to `x(2)`, which `try_case` left there.  The stacktrace
+/// is already a list, so `build_stacktrace` has nothing to do.
+fn raw_raise(machine: &mut Machine, _: &[Operand]) -> Result<Step, Exception> {
+    let class = machine.read(&Operand::X(0))?;
+    let reason = machine.read(&Operand::X(1))?;
+    let stacktrace = machine.read(&Operand::X(2))?;
+
+    match Class::try_from(class) {
+        Ok(class) => Err(raise!(class, reason, Some(stacktrace)).into()),
+        Err(_) => {
+            machine.write(&Operand::X(0), atom_unchecked("badarg"))?;
+
+            Ok(Step::Next)
+        }
+    }
+}
+
+/// `apply Arity` calls the module in `x(Arity)` and the function in `x(Arity + 1)`
+fn apply(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    dynamic_call(machine, unsigned(&operands[0]), Call::Body)
+}
+
+fn apply_last(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    machine.y.clear();
+
+    dynamic_call(machine, unsigned(&operands[0]), Call::Tail)
+}
+
+fn dynamic_call(machine: &mut Machine, arity: usize, call: Call) -> Result<Step, Exception> {
+    let arguments = machine.arguments(arity);
+    let module: Atom = machine.read(&Operand::X(arity))?.try_into()?;
+    let function: Atom = machine.read(&Operand::X(arity + 1))?.try_into()?;
+
+    machine.call_module_function(module, function, arguments, call)
+}
+
+/// `call_fun Arity` calls the fun in `x(Arity)`
+fn call_fun(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let arity = unsigned(&operands[0]);
+    let arguments = machine.arguments(arity);
+    let fun = machine.read(&Operand::X(arity))?;
+
+    machine.call_fun(fun, arguments, Call::Body)
+}
+
+/// `call_fun2 Tag Arity Fun`
+fn call_fun2(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let arguments = machine.arguments(unsigned(&operands[1]));
+    let fun = machine.read(&operands[2])?;
+
+    machine.call_fun(fun, arguments, Call::Body)
+}
+
+/// `make_fun2 Fun` makes a closure of the free variables in the first x registers
+fn make_fun2(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let fun = unsigned(&operands[0]);
+    let env = machine.arguments(machine.module.funs[fun].num_free);
+    let closure = machine.make_fun(fun, env)?;
+    machine.write(&Operand::X(0), closure)?;
+
+    Ok(Step::Next)
+}
+
+/// `make_fun3 Fun Destination [FreeVariable, ...]`
+fn make_fun3(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let env: Vec<Term> = list(&operands[2])
+        .iter()
+        .map(|free| machine.read(free))
+        .collect::<Result<_, _>>()?;
+    let closure = machine.make_fun(unsigned(&operands[0]), env)?;
+    machine.write(&operands[1], closure)?;
+
+    Ok(Step::Next)
+}
+
+/// `has_map_fields Fail Source [Key, ...]`
+fn has_map_fields(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let map: Result<Boxed<Map>, _> = machine.read(&operands[1])?.try_into();
+    let passed = match map {
+        Ok(map) => {
+            let mut has_keys = true;
+
+            for key in list(&operands[2]) {
+                if !map.is_key(machine.read(key)?) {
+                    has_keys = false;
+
+                    break;
+                }
+            }
+
+            has_keys
+        }
+        Err(_) => false,
+    };
+
+    machine.test(&operands[0], passed)
+}
+
+/// `get_map_elements Fail Source [Key, Destination, ...]`
+fn get_map_elements(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    let map: Result<Boxed<Map>, _> = machine.read(&operands[1])?.try_into();
+    let map = match map {
+        Ok(map) => map,
+        Err(_) => return machine.test(&operands[0], false),
+    };
+
+    for pair in list(&operands[2]).chunks(2) {
+        match map.get(machine.read(&pair[0])?) {
+            Some(value) => machine.write(&pair[1], value)?,
+            None => return machine.test(&operands[0], false),
+        }
+    }
+
+    Ok(Step::Next)
+}
+
+/// `put_map_assoc Fail Source Destination Live [Key, Value, ...]`
+fn put_map_assoc(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    machine.put_map(operands, false)
+}
+
+/// `put_map_exact Fail Source Destination Live [Key, Value, ...]`, which only updates keys that
+/// are already in the map
+fn put_map_exact(machine: &mut Machine, operands: &[Operand]) -> Result<Step, Exception> {
+    machine.put_map(operands, true)
+}
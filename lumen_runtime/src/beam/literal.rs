@@ -0,0 +1,230 @@
+//! The literal area of a loaded module.
+//!
+//! Literals are built once, when the module is loaded, in `HeapFragment`s owned by the module.
+//! Processes never point into the area: each use of a literal copies it to the process' heap, so
+//! the area can be freed with the module without garbage collecting the processes that ran it.
+
+use std::mem;
+use std::ptr::{self, NonNull};
+
+use num_bigint::BigInt;
+
+use liblumen_alloc::erts::process::alloc::heap_alloc::HeapAlloc;
+use liblumen_alloc::erts::term::{
+    AsTerm, Atom, BigInteger, Cons, Float, HeapBin, Integer, Map, Term, Tuple,
+};
+use liblumen_alloc::HeapFragment;
+
+use liblumen_beam::serialization::etf;
+
+use super::LoadError;
+
+pub struct Area {
+    literals: Vec<Literal>,
+}
+
+impl Area {
+    pub fn new(terms: &[etf::Term]) -> Result<Self, LoadError> {
+        let mut literals = Vec::with_capacity(terms.len());
+
+        for term in terms {
+            literals.push(Literal::new(term)?);
+        }
+
+        Ok(Area { literals })
+    }
+
+    /// The literal at `index`, which `load::module` checked is in the area
+    pub fn get(&self, index: usize) -> Term {
+        self.literals[index].term
+    }
+}
+
+// The terms in the fragments are never mutated after loading, and processes only copy them.
+unsafe impl Send for Area {}
+unsafe impl Sync for Area {}
+
+struct Literal {
+    term: Term,
+    // `None` for immediates
+    fragment: Option<NonNull<HeapFragment>>,
+}
+
+impl Literal {
+    fn new(etf_term: &etf::Term) -> Result<Self, LoadError> {
+        let need_in_words = need_in_words(etf_term)?;
+
+        if need_in_words == 0 {
+            let term = immediate(etf_term)?.unwrap();
+
+            Ok(Literal {
+                term,
+                fragment: None,
+            })
+        } else {
+            let mut fragment = unsafe { HeapFragment::new_from_word_size(need_in_words)? };
+            let result = from_etf(unsafe { fragment.as_mut() }, etf_term);
+
+            match result {
+                Ok(term) => Ok(Literal {
+                    term,
+                    fragment: Some(fragment),
+                }),
+                Err(error) => {
+                    unsafe { ptr::drop_in_place(fragment.as_ptr()) };
+
+                    Err(error)
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Literal {
+    fn drop(&mut self) {
+        if let Some(fragment) = self.fragment {
+            unsafe { ptr::drop_in_place(fragment.as_ptr()) };
+        }
+    }
+}
+
+/// Builds `etf_term` on `heap`, which must have at least `need_in_words(etf_term)` free.
+fn from_etf<A: HeapAlloc>(heap: &mut A, etf_term: &etf::Term) -> Result<Term, LoadError> {
+    if let Some(term) = immediate(etf_term)? {
+        return Ok(term);
+    }
+
+    let term = match etf_term {
+        etf::Term::FixInteger(fix_integer) => heap.integer(fix_integer.value)?,
+        etf::Term::BigInteger(big_integer) => {
+            heap.integer(big_int(&big_integer.value.to_signed_bytes_le()))?
+        }
+        etf::Term::Float(float) => heap.float(float.value)?,
+        etf::Term::Binary(binary) => heap.heapbin_from_bytes(&binary.bytes)?,
+        etf::Term::List(list) => {
+            let elements = from_etf_slice(heap, &list.elements)?;
+
+            heap.list_from_slice(&elements)?
+        }
+        etf::Term::ImproperList(improper_list) => {
+            let elements = from_etf_slice(heap, &improper_list.elements)?;
+            let last = from_etf(heap, &improper_list.last)?;
+
+            heap.improper_list_from_slice(&elements, last)?
+        }
+        etf::Term::Tuple(tuple) => {
+            let elements = from_etf_slice(heap, &tuple.elements)?;
+
+            heap.tuple_from_slice(&elements)?
+        }
+        etf::Term::Map(map) => {
+            let mut entries = Vec::with_capacity(map.entries.len());
+
+            for (key, value) in &map.entries {
+                entries.push((from_etf(heap, key)?, from_etf(heap, value)?));
+            }
+
+            heap.map_from_slice(&entries)?
+        }
+        other => return Err(LoadError::UnsupportedLiteral(other.to_string())),
+    };
+
+    Ok(term)
+}
+
+fn from_etf_slice<A: HeapAlloc>(
+    heap: &mut A,
+    etf_terms: &[etf::Term],
+) -> Result<Vec<Term>, LoadError> {
+    let mut terms = Vec::with_capacity(etf_terms.len());
+
+    for etf_term in etf_terms {
+        terms.push(from_etf(heap, etf_term)?);
+    }
+
+    Ok(terms)
+}
+
+/// The `Term` for atoms, small integers and `[]`, which need no heap.
+fn immediate(etf_term: &etf::Term) -> Result<Option<Term>, LoadError> {
+    let option_term = match etf_term {
+        etf::Term::Atom(atom) => {
+            let atom = Atom::try_from_str(&atom.name)
+                .map_err(|_| LoadError::InvalidAtom(atom.name.clone()))?;
+
+            Some(unsafe { atom.as_term() })
+        }
+        etf::Term::FixInteger(fix_integer) => match Integer::from(fix_integer.value) {
+            Integer::Small(small_integer) => Some(unsafe { small_integer.as_term() }),
+            Integer::Big(_) => None,
+        },
+        etf::Term::List(list) if list.elements.is_empty() => Some(Term::NIL),
+        _ => None,
+    };
+
+    Ok(option_term)
+}
+
+/// An upper bound of the words `from_etf` allocates for `etf_term`.
+fn need_in_words(etf_term: &etf::Term) -> Result<usize, LoadError> {
+    if immediate(etf_term)?.is_some() {
+        return Ok(0);
+    }
+
+    let words = match etf_term {
+        etf::Term::FixInteger(_) | etf::Term::BigInteger(_) => {
+            to_word_size(mem::size_of::<BigInteger>())
+        }
+        etf::Term::Float(_) => to_word_size(mem::size_of::<Float>()),
+        etf::Term::Binary(binary) => to_word_size(HeapBin::layout_bytes(&binary.bytes).size()),
+        etf::Term::List(list) => slice_need_in_words(&list.elements)?,
+        etf::Term::ImproperList(improper_list) => {
+            slice_need_in_words(&improper_list.elements)? + need_in_words(&improper_list.last)?
+        }
+        etf::Term::Tuple(tuple) => {
+            let mut words = Tuple::need_in_words_from_len(tuple.elements.len());
+
+            for element in &tuple.elements {
+                words += need_in_words(element)?;
+            }
+
+            words
+        }
+        etf::Term::Map(map) => {
+            // `map_from_slice` copies the entries after they are built
+            let mut words = to_word_size(mem::size_of::<Map>());
+
+            for (key, value) in &map.entries {
+                words += 2 * (need_in_words(key)? + need_in_words(value)?);
+            }
+
+            words
+        }
+        other => return Err(LoadError::UnsupportedLiteral(other.to_string())),
+    };
+
+    Ok(words)
+}
+
+// A `Cons` for each element
+fn slice_need_in_words(etf_terms: &[etf::Term]) -> Result<usize, LoadError> {
+    let mut words = etf_terms.len() * to_word_size(mem::size_of::<Cons>());
+
+    for etf_term in etf_terms {
+        words += need_in_words(etf_term)?;
+    }
+
+    Ok(words)
+}
+
+fn to_word_size(bytes: usize) -> usize {
+    let word = mem::size_of::<usize>();
+
+    (bytes + word - 1) / word
+}
+
+/// Converts the bytes of a big integer from the BEAM reader, which uses another version of `num`,
+/// to the `BigInt` used for terms.
+pub fn big_int(signed_bytes_le: &[u8]) -> BigInt {
+    BigInt::from_signed_bytes_le(signed_bytes_le)
+}
@@ -0,0 +1,369 @@
+//! Translates the chunks of a `.beam` file into a `Module`.
+
+use std::convert::TryFrom;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+
+use liblumen_alloc::erts::term::{AsTerm, Atom, Integer, Term};
+use liblumen_alloc::ModuleFunctionArity;
+
+use liblumen_beam::beam::reader::chunk::code::{self, Opcode};
+use liblumen_beam::beam::reader::chunk::StandardChunk;
+use liblumen_beam::beam::reader::parts::AtomId;
+use liblumen_beam::beam::reader::StandardBeamFile;
+use liblumen_beam::serialization::etf;
+
+use crate::otp;
+
+use super::interpreter::{self, Shape};
+use super::literal;
+use super::module::{Fun, Function, Import, Instruction, Module, Operand};
+use super::LoadError;
+
+pub fn module(beam: &StandardBeamFile) -> Result<Module, LoadError> {
+    let atoms = atoms(beam)?;
+    let name = atom(&atoms, 1)?;
+
+    let code_chunk = match beam.get_chunk(b"Code") {
+        Some(StandardChunk::Code(code_chunk)) => code_chunk,
+        _ => return Err(LoadError::MissingChunk("Code")),
+    };
+    let instructions = code_chunk.instructions()?;
+
+    let mut literals = Vec::new();
+
+    if let Some(StandardChunk::LitT(lit_t_chunk)) = beam.get_chunk(b"LitT") {
+        for literal in &lit_t_chunk.literals {
+            literals.push(etf::Term::decode(Cursor::new(literal))?);
+        }
+    }
+
+    let mut loader = Loader {
+        name,
+        atoms,
+        lit_t_count: literals.len(),
+        index_by_label: HashMap::new(),
+        function_by_entry: HashMap::new(),
+        functions: Vec::new(),
+        literals,
+    };
+
+    // Labels and line information are only needed to find code, so they are dropped and the labels
+    // are replaced by the index of the instruction that followed them.
+    let mut kept = Vec::with_capacity(instructions.len());
+
+    for instruction in &instructions {
+        match instruction.opcode {
+            Opcode::Label => {
+                let label = unsigned(instruction, 0)?;
+                loader.index_by_label.insert(label, kept.len());
+            }
+            Opcode::Line | Opcode::IntCodeEnd | Opcode::ExecutableLine | Opcode::NifStart => (),
+            Opcode::FuncInfo => {
+                kept.push(instruction);
+
+                let function = match &instruction.operands[1] {
+                    code::Operand::Atom(id) => loader.atom(*id)?,
+                    other => return Err(unsupported_operand(instruction, other)),
+                };
+                let arity = unsigned(instruction, 2)? as u8;
+                let entry = kept.len();
+
+                loader
+                    .function_by_entry
+                    .insert(entry, loader.functions.len());
+                loader.functions.push(Function {
+                    module_function_arity: Arc::new(ModuleFunctionArity {
+                        module: name,
+                        function,
+                        arity,
+                    }),
+                    entry,
+                    exported: false,
+                });
+            }
+            _ => kept.push(instruction),
+        }
+    }
+
+    if let Some(StandardChunk::ExpT(exp_t_chunk)) = beam.get_chunk(b"ExpT") {
+        for export in &exp_t_chunk.exports {
+            let index = loader.function_at_label(export.label as u64)?;
+            loader.functions[index].exported = true;
+        }
+    }
+
+    let mut funs = Vec::new();
+
+    if let Some(StandardChunk::FunT(fun_t_chunk)) = beam.get_chunk(b"FunT") {
+        for fun in &fun_t_chunk.functions {
+            let function = loader.function_at_label(fun.label as u64)?;
+            let fun_name = loader.atom(fun.function)?;
+            let arity = fun
+                .arity
+                .checked_sub(fun.num_free)
+                .and_then(|arity| u8::try_from(arity).ok())
+                .ok_or_else(|| LoadError::InvalidFun {
+                    function: fun_name.name().to_string(),
+                    arity: fun.arity,
+                    num_free: fun.num_free,
+                })?;
+
+            funs.push(Fun {
+                module_function_arity: Arc::new(ModuleFunctionArity {
+                    module: name,
+                    function: fun_name,
+                    arity,
+                }),
+                function,
+                num_free: fun.num_free as usize,
+            });
+        }
+    }
+
+    let mut imports = Vec::new();
+
+    if let Some(StandardChunk::ImpT(imp_t_chunk)) = beam.get_chunk(b"ImpT") {
+        for import in &imp_t_chunk.imports {
+            let module = loader.atom(import.module)?;
+            let function = loader.atom(import.function)?;
+            let arity = import.arity as u8;
+
+            imports.push(Import {
+                module_function_arity: Arc::new(ModuleFunctionArity {
+                    module,
+                    function,
+                    arity,
+                }),
+                native: otp::natives().get(&(module, function, arity)).cloned(),
+            });
+        }
+    }
+
+    let mut code = Vec::with_capacity(kept.len());
+
+    for (index, instruction) in kept.iter().enumerate() {
+        let (handler, shapes) = interpreter::handler(instruction.opcode).ok_or_else(|| {
+            LoadError::UnsupportedInstruction {
+                instruction: instruction.opcode.name(),
+                function: loader.function_name(index),
+            }
+        })?;
+        let mut operands = Vec::with_capacity(instruction.operands.len());
+
+        for (position, operand) in instruction.operands.iter().enumerate() {
+            let operand = match (instruction.opcode, position, operand) {
+                (Opcode::Call, 1, code::Operand::Label(label))
+                | (Opcode::CallLast, 1, code::Operand::Label(label))
+                | (Opcode::CallOnly, 1, code::Operand::Label(label)) => {
+                    Operand::Function(loader.function_at_label(*label)?)
+                }
+                _ => loader.operand(instruction, operand)?,
+            };
+
+            operands.push(operand);
+        }
+
+        if !has_shapes(&operands, shapes, imports.len(), funs.len()) {
+            return Err(LoadError::UnsupportedOperand {
+                instruction: instruction.opcode.name(),
+                operand: format!("{:?}", operands),
+            });
+        }
+
+        code.push(Instruction { handler, operands });
+    }
+
+    let literals = literal::Area::new(&loader.literals)?;
+
+    Ok(Module::new(
+        name,
+        code,
+        loader.functions,
+        funs,
+        imports,
+        literals,
+    ))
+}
+
+struct Loader {
+    name: Atom,
+    atoms: Vec<Atom>,
+    /// The number of literals in the `LitT` chunk, which literal operands index
+    lit_t_count: usize,
+    index_by_label: HashMap<u64, usize>,
+    /// The index in `functions` of the function whose entry is the key
+    function_by_entry: HashMap<usize, usize>,
+    functions: Vec<Function>,
+    /// The `LitT` literals, followed by the integers and floats that are too big for an operand
+    literals: Vec<etf::Term>,
+}
+
+impl Loader {
+    fn atom(&self, id: AtomId) -> Result<Atom, LoadError> {
+        atom(&self.atoms, id)
+    }
+
+    fn index(&self, label: u64) -> Result<usize, LoadError> {
+        self.index_by_label
+            .get(&label)
+            .cloned()
+            .ok_or(LoadError::UndefinedLabel(label))
+    }
+
+    fn function_at_label(&self, label: u64) -> Result<usize, LoadError> {
+        let index = self.index(label)?;
+
+        self.function_by_entry
+            .get(&index)
+            .cloned()
+            .ok_or(LoadError::UndefinedLabel(label))
+    }
+
+    /// `module:function/arity` of the function that contains the instruction at `index`
+    fn function_name(&self, index: usize) -> Option<String> {
+        self.functions
+            .iter()
+            .take_while(|function| function.entry <= index + 1)
+            .last()
+            .map(|function| {
+                let mfa = &function.module_function_arity;
+
+                format!("{}:{}/{}", self.name.name(), mfa.function.name(), mfa.arity)
+            })
+    }
+
+    fn literal(&mut self, term: etf::Term) -> Operand {
+        self.literals.push(term);
+
+        Operand::Literal(self.literals.len() - 1)
+    }
+
+    fn operand(
+        &mut self,
+        instruction: &code::Instruction,
+        operand: &code::Operand,
+    ) -> Result<Operand, LoadError> {
+        let operand = match operand {
+            code::Operand::Unsigned(value) => Operand::Unsigned(*value as usize),
+            code::Operand::Integer(value) => {
+                match Integer::from(literal::big_int(&value.to_signed_bytes_le())) {
+                    Integer::Small(small_integer) => {
+                        Operand::Term(unsafe { small_integer.as_term() })
+                    }
+                    Integer::Big(_) => self.literal(etf::Term::BigInteger(etf::BigInteger {
+                        value: value.clone(),
+                    })),
+                }
+            }
+            code::Operand::Atom(id) => Operand::Term(unsafe { self.atom(*id)?.as_term() }),
+            code::Operand::Nil => Operand::Term(Term::NIL),
+            code::Operand::X(register) => Operand::X(*register as usize),
+            code::Operand::Y(register) => Operand::Y(*register as usize),
+            code::Operand::Label(0) => Operand::Label(None),
+            code::Operand::Label(label) => Operand::Label(Some(self.index(*label)?)),
+            code::Operand::Character(character) => match Integer::from(*character) {
+                Integer::Small(small_integer) => Operand::Term(unsafe { small_integer.as_term() }),
+                Integer::Big(_) => return Err(unsupported_operand(instruction, operand)),
+            },
+            code::Operand::Float(value) => {
+                self.literal(etf::Term::Float(etf::Float { value: *value }))
+            }
+            code::Operand::List(operands) => {
+                let mut list = Vec::with_capacity(operands.len());
+
+                for operand in operands {
+                    list.push(self.operand(instruction, operand)?);
+                }
+
+                Operand::List(list)
+            }
+            code::Operand::Literal(index) if (*index as usize) < self.lit_t_count => {
+                Operand::Literal(*index as usize)
+            }
+            code::Operand::Literal(_) => return Err(unsupported_operand(instruction, operand)),
+            code::Operand::TypedRegister(register, _) => self.operand(instruction, register)?,
+            // Heap space is allocated as terms are built, so the sizes are not needed
+            code::Operand::AllocationList(_) => Operand::Unsigned(0),
+            code::Operand::FloatRegister(_) => {
+                return Err(unsupported_operand(instruction, operand))
+            }
+        };
+
+        Ok(operand)
+    }
+}
+
+fn atoms(beam: &StandardBeamFile) -> Result<Vec<Atom>, LoadError> {
+    match beam.atoms() {
+        Some(StandardChunk::Atom(atom_chunk)) => atom_chunk
+            .atoms
+            .iter()
+            .map(|atom| {
+                Atom::try_from_str(&atom.name)
+                    .map_err(|_| LoadError::InvalidAtom(atom.name.clone()))
+            })
+            .collect(),
+        _ => Err(LoadError::MissingChunk("Atom")),
+    }
+}
+
+// Atom ids start at `1`
+fn atom(atoms: &[Atom], id: AtomId) -> Result<Atom, LoadError> {
+    (id as usize)
+        .checked_sub(1)
+        .and_then(|index| atoms.get(index))
+        .cloned()
+        .ok_or_else(|| LoadError::InvalidAtom(format!("#{}", id)))
+}
+
+/// Whether `operands` are what the handler of their instruction expects, so that it can rely on them
+fn has_shapes(operands: &[Operand], shapes: &[Shape], imports: usize, funs: usize) -> bool {
+    operands.len() == shapes.len()
+        && operands
+            .iter()
+            .zip(shapes)
+            .all(|(operand, shape)| has_shape(operand, shape, imports, funs))
+}
+
+fn has_shape(operand: &Operand, shape: &Shape, imports: usize, funs: usize) -> bool {
+    match (shape, operand) {
+        (Shape::Source, Operand::Term(_))
+        | (Shape::Source, Operand::Literal(_))
+        | (Shape::Source, Operand::X(_))
+        | (Shape::Source, Operand::Y(_))
+        | (Shape::Destination, Operand::X(_))
+        | (Shape::Destination, Operand::Y(_))
+        | (Shape::Y, Operand::Y(_))
+        | (Shape::Label, Operand::Label(Some(_)))
+        | (Shape::OptionalLabel, Operand::Label(_))
+        | (Shape::Unsigned, Operand::Unsigned(_))
+        | (Shape::Function, Operand::Function(_)) => true,
+        (Shape::Import, Operand::Unsigned(index)) => *index < imports,
+        (Shape::Fun, Operand::Unsigned(index)) => *index < funs,
+        (Shape::List(shapes), Operand::List(list)) => {
+            list.len() % shapes.len() == 0
+                && list
+                    .iter()
+                    .zip(shapes.iter().cycle())
+                    .all(|(operand, shape)| has_shape(operand, shape, imports, funs))
+        }
+        _ => false,
+    }
+}
+
+fn unsigned(instruction: &code::Instruction, position: usize) -> Result<u64, LoadError> {
+    match &instruction.operands[position] {
+        code::Operand::Unsigned(value) => Ok(*value),
+        other => Err(unsupported_operand(instruction, other)),
+    }
+}
+
+fn unsupported_operand(instruction: &code::Instruction, operand: &code::Operand) -> LoadError {
+    LoadError::UnsupportedOperand {
+        instruction: instruction.opcode.name(),
+        operand: format!("{:?}", operand),
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+
+use liblumen_core::locks::RwLock;
+
+use liblumen_alloc::erts::term::{Atom, Term};
+use liblumen_alloc::ModuleFunctionArity;
+
+use crate::otp::Native;
+
+use super::interpreter::Handler;
+use super::literal;
+use super::LoadError;
+
+/// A loaded version of a module
+pub struct Module {
+    pub name: Atom,
+    /// Distinguishes the versions of the module, so that the code a process is running is not
+    /// confused with a version loaded while it was waiting.
+    pub version: usize,
+    pub code: Vec<Instruction>,
+    pub functions: Vec<Function>,
+    pub funs: Vec<Fun>,
+    pub imports: Vec<Import>,
+    pub literals: literal::Area,
+    function_by_function_arity: HashMap<(Atom, u8), usize>,
+    fun_by_function: HashMap<Atom, usize>,
+}
+
+impl Module {
+    pub fn new(
+        name: Atom,
+        code: Vec<Instruction>,
+        functions: Vec<Function>,
+        funs: Vec<Fun>,
+        imports: Vec<Import>,
+        literals: literal::Area,
+    ) -> Self {
+        let function_by_function_arity = functions
+            .iter()
+            .enumerate()
+            .map(|(index, function)| {
+                let mfa = &function.module_function_arity;
+
+                ((mfa.function, mfa.arity), index)
+            })
+            .collect();
+        let fun_by_function = funs
+            .iter()
+            .enumerate()
+            .map(|(index, fun)| (fun.module_function_arity.function, index))
+            .collect();
+
+        Module {
+            name,
+            version: VERSION_COUNT.fetch_add(1, Ordering::SeqCst),
+            code,
+            functions,
+            funs,
+            imports,
+            literals,
+            function_by_function_arity,
+            fun_by_function,
+        }
+    }
+
+    /// The index in `functions` of the exported `function/arity`
+    pub fn exported(&self, function: Atom, arity: u8) -> Option<usize> {
+        self.local(function, arity)
+            .filter(|index| self.functions[*index].exported)
+    }
+
+    /// The index in `functions` of `function/arity`, whether it is exported or not
+    pub fn local(&self, function: Atom, arity: u8) -> Option<usize> {
+        self.function_by_function_arity
+            .get(&(function, arity))
+            .cloned()
+    }
+
+    /// The index in `funs` of the fun whose code is the local `function`
+    pub fn fun(&self, function: Atom) -> Option<usize> {
+        self.fun_by_function.get(&function).cloned()
+    }
+}
+
+pub struct Function {
+    pub module_function_arity: Arc<ModuleFunctionArity>,
+    /// The index in `Module.code` of the first instruction after `func_info`
+    pub entry: usize,
+    pub exported: bool,
+}
+
+/// An entry of the `FunT` chunk
+pub struct Fun {
+    /// The `ModuleFunctionArity` of the closures, whose arity does not include the free variables
+    pub module_function_arity: Arc<ModuleFunctionArity>,
+    /// The index in `Module.functions` of the function that the fun runs
+    pub function: usize,
+    pub num_free: usize,
+}
+
+pub struct Import {
+    pub module_function_arity: Arc<ModuleFunctionArity>,
+    /// Imports that are not native are looked up in the loaded modules when they are called, so
+    /// that modules can be loaded in any order.
+    pub native: Option<Native>,
+}
+
+/// A threaded instruction
+pub struct Instruction {
+    pub handler: Handler,
+    pub operands: Vec<Operand>,
+}
+
+/// An operand of a threaded instruction, resolved when the module is loaded
+#[derive(Clone, Debug)]
+pub enum Operand {
+    /// An atom, small integer or `[]`
+    Term(Term),
+    /// An index into `Module.literals`
+    Literal(usize),
+    X(usize),
+    Y(usize),
+    /// An index into `Module.code`.  `None` is label `0`, which means a failed test or guard BIF
+    /// raises an exception.
+    Label(Option<usize>),
+    Unsigned(usize),
+    List(Vec<Operand>),
+    /// An index into `Module.functions`, for the labels of local calls
+    Function(usize),
+}
+
+/// Makes `module` the current version, with the previous current version, if any, becoming the
+/// old version.
+pub fn register(module: Module) -> Result<(), LoadError> {
+    let mut writable_versions_by_name = RW_LOCK_VERSIONS_BY_NAME.write();
+    let name = module.name;
+    let arc_module = Arc::new(module);
+
+    match writable_versions_by_name.get_mut(&name) {
+        Some(versions) => {
+            if versions.old.is_some() {
+                return Err(LoadError::NotPurged(name));
+            }
+
+            versions.old = Some(std::mem::replace(&mut versions.current, arc_module));
+        }
+        None => {
+            writable_versions_by_name.insert(
+                name,
+                Versions {
+                    current: arc_module,
+                    old: None,
+                },
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The current version of the module
+pub fn get(name: Atom) -> Option<Arc<Module>> {
+    RW_LOCK_VERSIONS_BY_NAME
+        .read()
+        .get(&name)
+        .map(|versions| Arc::clone(&versions.current))
+}
+
+/// The current or old version of the module with `version`, which is `None` once the version is
+/// purged.
+pub fn get_version(name: Atom, version: usize) -> Option<Arc<Module>> {
+    RW_LOCK_VERSIONS_BY_NAME
+        .read()
+        .get(&name)
+        .and_then(|versions| {
+            if versions.current.version == version {
+                Some(Arc::clone(&versions.current))
+            } else {
+                versions
+                    .old
+                    .as_ref()
+                    .filter(|old| old.version == version)
+                    .map(Arc::clone)
+            }
+        })
+}
+
+/// Removes the old code of `module`.  Processes that were running it are killed when they run
+/// again.  Returns `true` if the module had old code.
+pub fn purge(name: Atom) -> bool {
+    RW_LOCK_VERSIONS_BY_NAME
+        .write()
+        .get_mut(&name)
+        .and_then(|versions| versions.old.take())
+        .is_some()
+}
+
+struct Versions {
+    current: Arc<Module>,
+    old: Option<Arc<Module>>,
+}
+
+static VERSION_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref RW_LOCK_VERSIONS_BY_NAME: RwLock<HashMap<Atom, Versions>> =
+        RwLock::new(Default::default());
+}
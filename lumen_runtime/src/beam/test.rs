@@ -0,0 +1,732 @@
+use std::convert::TryInto;
+use std::sync::{Arc, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use num_bigint::BigInt;
+
+use liblumen_alloc::erts::process::code::stack::frame::{Frame, Placement};
+use liblumen_alloc::erts::process::code::{self, result_from_exception};
+use liblumen_alloc::erts::process::{Process, Status};
+use liblumen_alloc::erts::term::{atom_unchecked, Atom, Boxed, Term, Tuple};
+
+use liblumen_beam::beam::reader::chunk::code::{Instruction, Opcode, Operand};
+use liblumen_beam::beam::reader::chunk::{
+    AtomChunk, CodeChunk, ExpTChunk, FunTChunk, ImpTChunk, StandardChunk,
+};
+use liblumen_beam::beam::reader::parts;
+use liblumen_beam::beam::reader::StandardBeamFile;
+
+use crate::beam::{self, LoadError};
+use crate::otp::erlang;
+use crate::process;
+use crate::scheduler::Scheduler;
+use crate::test::receive_message;
+
+#[test]
+fn load_without_atom_chunk_errors_missing_chunk() {
+    match beam::load(&StandardBeamFile::new()) {
+        Err(LoadError::MissingChunk("Atom")) => (),
+        other => panic!(
+            "Loading returned {:?} instead of a missing Atom chunk",
+            other
+        ),
+    }
+}
+
+#[test]
+fn load_file_exports_the_exported_functions() {
+    let module = test_module();
+
+    assert!(beam::is_exported(module, atom("hello"), 1));
+    assert!(beam::is_exported(module, atom("module_info"), 0));
+    assert!(beam::is_exported(module, atom("module_info"), 1));
+    assert!(!beam::is_exported(module, atom("-hello/1-fun-0-"), 1));
+    assert!(!beam::is_exported(module, atom("hello"), 0));
+}
+
+#[test]
+fn spawn_runs_the_function_until_it_calls_a_module_that_is_not_loaded() {
+    let module = test_module();
+    let parent_arc_process = process::test_init();
+
+    // `hello/1` calls a fun that calls `io:format/2`
+    let child_arc_process = beam::spawn(
+        &parent_arc_process,
+        Default::default(),
+        module,
+        atom("hello"),
+        vec![atom_unchecked("world")],
+    )
+    .unwrap();
+
+    let scheduler = Scheduler::current();
+
+    while !child_arc_process.is_exiting() {
+        assert!(scheduler.run_through(&child_arc_process));
+    }
+
+    match *child_arc_process.status.read() {
+        Status::Exiting(ref runtime_exception) => {
+            assert_eq!(runtime_exception.reason, atom_unchecked("undef"));
+        }
+        ref status => panic!("Process status ({:?}) is not exiting.", status),
+    };
+}
+
+#[test]
+fn load_with_a_register_for_a_term_errors_unsupported_operand() {
+    // move {i,1} {i,2}
+    let beam = fixture(
+        "beam_bad_destination",
+        vec![],
+        vec![instruction(Opcode::Move, vec![i(1), i(2)])],
+    );
+
+    assert_unsupported_operand(beam::load(&beam.file), "move");
+}
+
+#[test]
+fn load_with_a_literal_that_is_not_in_the_lit_t_chunk_errors_unsupported_operand() {
+    // move {literal,3} {x,0}
+    let beam = fixture(
+        "beam_bad_literal",
+        vec![],
+        vec![instruction(Opcode::Move, vec![Operand::Literal(3), x(0)])],
+    );
+
+    assert_unsupported_operand(beam::load(&beam.file), "move");
+}
+
+#[test]
+fn load_with_an_import_that_is_not_in_the_imp_t_chunk_errors_unsupported_operand() {
+    // call_ext 1 7, with only erlang:'+'/2 and erlang:'*'/2 imported
+    let beam = fixture(
+        "beam_bad_import",
+        vec![],
+        vec![instruction(Opcode::CallExt, vec![u(1), u(7)])],
+    );
+
+    assert_unsupported_operand(beam::load(&beam.file), "call_ext");
+}
+
+#[test]
+fn load_with_a_jump_to_label_0_errors_unsupported_operand() {
+    let beam = fixture(
+        "beam_bad_label",
+        vec![],
+        vec![instruction(Opcode::Jump, vec![f(0)])],
+    );
+
+    assert_unsupported_operand(beam::load(&beam.file), "jump");
+}
+
+#[test]
+fn load_with_a_fun_with_more_free_variables_than_arguments_errors_invalid_fun() {
+    let beam = fixture(
+        "beam_bad_fun",
+        vec![parts::Function {
+            function: FUN,
+            arity: 1,
+            label: 2,
+            index: 0,
+            num_free: 2,
+            old_uniq: 0,
+        }],
+        vec![instruction(Opcode::Return, vec![])],
+    );
+
+    match beam::load(&beam.file) {
+        Err(LoadError::InvalidFun {
+            arity: 1,
+            num_free: 2,
+            ..
+        }) => (),
+        other => panic!("Loading returned {:?} instead of an invalid fun", other),
+    }
+}
+
+#[test]
+fn calls_return_their_values() {
+    // run(Parent) -> Parent ! double(add(1, 2)).
+    // add(A, B) -> A + B.
+    // double(X) -> X * 2.
+    let module = load(fixture(
+        "beam_calls",
+        vec![],
+        vec![
+            instruction(Opcode::Allocate, vec![u(1), u(1)]),
+            instruction(Opcode::Move, vec![x(0), y(0)]),
+            instruction(Opcode::Move, vec![i(1), x(0)]),
+            instruction(Opcode::Move, vec![i(2), x(1)]),
+            instruction(Opcode::Call, vec![u(2), f(4)]),
+            instruction(Opcode::Call, vec![u(1), f(6)]),
+            instruction(Opcode::Move, vec![x(0), x(1)]),
+            instruction(Opcode::Move, vec![y(0), x(0)]),
+            instruction(Opcode::Send, vec![]),
+            instruction(Opcode::Deallocate, vec![u(1)]),
+            instruction(Opcode::Return, vec![]),
+            instruction(Opcode::Label, vec![u(3)]),
+            instruction(Opcode::FuncInfo, vec![a(MODULE), a(ADD), u(2)]),
+            instruction(Opcode::Label, vec![u(4)]),
+            instruction(Opcode::GcBif2, vec![f(0), u(2), u(PLUS), x(0), x(1), x(0)]),
+            instruction(Opcode::Return, vec![]),
+            instruction(Opcode::Label, vec![u(5)]),
+            instruction(Opcode::FuncInfo, vec![a(MODULE), a(DOUBLE), u(1)]),
+            instruction(Opcode::Label, vec![u(6)]),
+            instruction(Opcode::Move, vec![i(2), x(1)]),
+            instruction(Opcode::CallExtOnly, vec![u(2), u(TIMES)]),
+        ],
+    ));
+    let (parent_arc_process, child_arc_process) = spawn_run(module);
+
+    run_until_exiting(&child_arc_process);
+
+    assert_eq!(
+        receive_message(&parent_arc_process),
+        Some(parent_arc_process.integer(6).unwrap())
+    );
+}
+
+#[test]
+fn receive_returns_the_matching_message_or_the_after_value_on_timeout() {
+    // run(Parent) ->
+    //     Parent ! receive
+    //                  {ping, X} -> X
+    //              after 10 -> timeout
+    //              end.
+    let module = load(fixture(
+        "beam_receive",
+        vec![],
+        vec![
+            instruction(Opcode::Allocate, vec![u(1), u(1)]),
+            instruction(Opcode::Move, vec![x(0), y(0)]),
+            instruction(Opcode::Label, vec![u(3)]),
+            instruction(Opcode::LoopRec, vec![f(5), x(0)]),
+            instruction(Opcode::IsTaggedTuple, vec![f(4), x(0), u(2), a(PING)]),
+            instruction(Opcode::GetTupleElement, vec![x(0), u(1), x(0)]),
+            instruction(Opcode::RemoveMessage, vec![]),
+            instruction(Opcode::Jump, vec![f(6)]),
+            instruction(Opcode::Label, vec![u(4)]),
+            instruction(Opcode::LoopRecEnd, vec![f(3)]),
+            instruction(Opcode::Label, vec![u(5)]),
+            instruction(Opcode::WaitTimeout, vec![f(3), i(10)]),
+            instruction(Opcode::Timeout, vec![]),
+            instruction(Opcode::Move, vec![a(TIMEOUT), x(0)]),
+            instruction(Opcode::Label, vec![u(6)]),
+            instruction(Opcode::Move, vec![x(0), x(1)]),
+            instruction(Opcode::Move, vec![y(0), x(0)]),
+            instruction(Opcode::Send, vec![]),
+            instruction(Opcode::Deallocate, vec![u(1)]),
+            instruction(Opcode::Return, vec![]),
+        ],
+    ));
+
+    let (parent_arc_process, child_arc_process) = spawn_run(module);
+    // Not matched, so it is skipped
+    erlang::send_2(
+        child_arc_process.pid_term(),
+        atom_unchecked("pong"),
+        &parent_arc_process,
+    )
+    .unwrap();
+    let ping = parent_arc_process
+        .tuple_from_slice(&[
+            atom_unchecked("ping"),
+            parent_arc_process.integer(7).unwrap(),
+        ])
+        .unwrap();
+    erlang::send_2(child_arc_process.pid_term(), ping, &parent_arc_process).unwrap();
+
+    run_until_exiting(&child_arc_process);
+
+    assert_eq!(
+        receive_message(&parent_arc_process),
+        Some(parent_arc_process.integer(7).unwrap())
+    );
+
+    let (parent_arc_process, child_arc_process) = spawn_run(module);
+
+    run_until_exiting(&child_arc_process);
+
+    assert_eq!(
+        receive_message(&parent_arc_process),
+        Some(atom_unchecked("timeout"))
+    );
+}
+
+#[test]
+fn try_catches_the_class_and_reason_of_an_exception() {
+    // run(Parent) ->
+    //     Parent ! try erlang:throw(oops) catch Class:Reason -> {Class, Reason} end.
+    let module = load(fixture(
+        "beam_try",
+        vec![],
+        vec![
+            instruction(Opcode::Allocate, vec![u(2), u(1)]),
+            instruction(Opcode::Move, vec![x(0), y(1)]),
+            instruction(Opcode::Try, vec![y(0), f(3)]),
+            instruction(Opcode::Move, vec![a(OOPS), x(0)]),
+            instruction(Opcode::CallExt, vec![u(1), u(THROW)]),
+            instruction(Opcode::TryEnd, vec![y(0)]),
+            instruction(Opcode::Jump, vec![f(4)]),
+            instruction(Opcode::Label, vec![u(3)]),
+            instruction(Opcode::TryCase, vec![y(0)]),
+            instruction(
+                Opcode::PutTuple2,
+                vec![x(0), Operand::List(vec![x(0), x(1)])],
+            ),
+            instruction(Opcode::Label, vec![u(4)]),
+            instruction(Opcode::Move, vec![x(0), x(1)]),
+            instruction(Opcode::Move, vec![y(1), x(0)]),
+            instruction(Opcode::Send, vec![]),
+            instruction(Opcode::Deallocate, vec![u(2)]),
+            instruction(Opcode::Return, vec![]),
+        ],
+    ));
+    let (parent_arc_process, child_arc_process) = spawn_run(module);
+
+    run_until_exiting(&child_arc_process);
+
+    assert_eq!(
+        receive_message(&parent_arc_process),
+        Some(
+            parent_arc_process
+                .tuple_from_slice(&[atom_unchecked("throw"), atom_unchecked("oops")])
+                .unwrap()
+        )
+    );
+}
+
+#[test]
+fn funs_are_called_with_their_free_variables() {
+    // run(Parent) ->
+    //     N = 10,
+    //     F = fun (X) -> X + N end,
+    //     Parent ! F(5).
+    let module = load(fixture(
+        "beam_funs",
+        vec![parts::Function {
+            function: FUN,
+            arity: 2,
+            label: 4,
+            index: 0,
+            num_free: 1,
+            old_uniq: 0,
+        }],
+        vec![
+            instruction(Opcode::Allocate, vec![u(1), u(1)]),
+            instruction(Opcode::Move, vec![x(0), y(0)]),
+            instruction(Opcode::Move, vec![i(10), x(0)]),
+            instruction(Opcode::MakeFun2, vec![u(0)]),
+            instruction(Opcode::Move, vec![x(0), x(1)]),
+            instruction(Opcode::Move, vec![i(5), x(0)]),
+            instruction(Opcode::CallFun, vec![u(1)]),
+            instruction(Opcode::Move, vec![x(0), x(1)]),
+            instruction(Opcode::Move, vec![y(0), x(0)]),
+            instruction(Opcode::Send, vec![]),
+            instruction(Opcode::Deallocate, vec![u(1)]),
+            instruction(Opcode::Return, vec![]),
+            instruction(Opcode::Label, vec![u(3)]),
+            instruction(Opcode::FuncInfo, vec![a(MODULE), a(FUN), u(2)]),
+            instruction(Opcode::Label, vec![u(4)]),
+            instruction(Opcode::GcBif2, vec![f(0), u(2), u(PLUS), x(0), x(1), x(0)]),
+            instruction(Opcode::Return, vec![]),
+        ],
+    ));
+    let (parent_arc_process, child_arc_process) = spawn_run(module);
+
+    run_until_exiting(&child_arc_process);
+
+    assert_eq!(
+        receive_message(&parent_arc_process),
+        Some(parent_arc_process.integer(15).unwrap())
+    );
+}
+
+#[test]
+fn funs_of_a_purged_version_raise_badfun() {
+    // run(Parent) -> Parent ! fun (X) -> X end.
+    let make = || {
+        fixture(
+            "beam_purged_fun",
+            vec![parts::Function {
+                function: FUN,
+                arity: 1,
+                label: 4,
+                index: 0,
+                num_free: 0,
+                old_uniq: 0,
+            }],
+            vec![
+                instruction(Opcode::Allocate, vec![u(1), u(1)]),
+                instruction(Opcode::Move, vec![x(0), y(0)]),
+                instruction(Opcode::MakeFun2, vec![u(0)]),
+                instruction(Opcode::Move, vec![x(0), x(1)]),
+                instruction(Opcode::Move, vec![y(0), x(0)]),
+                instruction(Opcode::Send, vec![]),
+                instruction(Opcode::Deallocate, vec![u(1)]),
+                instruction(Opcode::Return, vec![]),
+                instruction(Opcode::Label, vec![u(3)]),
+                instruction(Opcode::FuncInfo, vec![a(MODULE), a(FUN), u(1)]),
+                instruction(Opcode::Label, vec![u(4)]),
+                instruction(Opcode::Return, vec![]),
+            ],
+        )
+    };
+    let module = load(make());
+    let (parent_arc_process, child_arc_process) = spawn_run(module);
+
+    run_until_exiting(&child_arc_process);
+
+    let fun = receive_message(&parent_arc_process).unwrap();
+
+    load(make());
+    assert!(beam::purge(module));
+
+    // run(F) -> F(5).
+    let caller = load(fixture(
+        "beam_call_purged_fun",
+        vec![],
+        vec![
+            instruction(Opcode::Move, vec![x(0), x(1)]),
+            instruction(Opcode::Move, vec![i(5), x(0)]),
+            instruction(Opcode::CallFun, vec![u(1)]),
+            instruction(Opcode::Return, vec![]),
+        ],
+    ));
+    let caller_arc_process = beam::spawn(
+        &parent_arc_process,
+        Default::default(),
+        caller,
+        atom("run"),
+        vec![fun],
+    )
+    .unwrap();
+
+    run_until_exiting(&caller_arc_process);
+
+    match *caller_arc_process.status.read() {
+        Status::Exiting(ref runtime_exception) => {
+            let reason: Boxed<Tuple> = runtime_exception.reason.try_into().unwrap();
+
+            assert_eq!(reason.iter().next(), Some(atom_unchecked("badfun")));
+        }
+        ref status => panic!("Process status ({:?}) is not exiting.", status),
+    };
+}
+
+#[test]
+fn y_registers_outside_of_the_stack_frame_raise_badarg() {
+    // allocate 1 1, move {y,1} {x,0}
+    let module = load(fixture(
+        "beam_bad_y",
+        vec![],
+        vec![
+            instruction(Opcode::Allocate, vec![u(1), u(1)]),
+            instruction(Opcode::Move, vec![y(1), x(0)]),
+            instruction(Opcode::Deallocate, vec![u(1)]),
+            instruction(Opcode::Return, vec![]),
+        ],
+    ));
+    let (_, child_arc_process) = spawn_run(module);
+
+    run_until_exiting(&child_arc_process);
+
+    match *child_arc_process.status.read() {
+        Status::Exiting(ref runtime_exception) => {
+            assert_eq!(runtime_exception.reason, atom_unchecked("badarg"));
+        }
+        ref status => panic!("Process status ({:?}) is not exiting.", status),
+    };
+}
+
+#[test]
+fn load_file_runs_a_module_compiled_by_elixir() {
+    // `Elixir.Unicode` in `reader/unicode.ex`, which uses no binary or float instructions
+    let module = beam::load_file(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../liblumen_beam/tests/testdata/reader/Elixir.Unicode.beam"
+    ))
+    .unwrap();
+    assert_eq!(module, atom("Elixir.Unicode"));
+
+    let parent_arc_process = process::test_init();
+
+    let add1_arc_process = spawn_returning(
+        &parent_arc_process,
+        module,
+        "add1",
+        vec![parent_arc_process.integer(41).unwrap()],
+    );
+    run_until_exiting(&add1_arc_process);
+
+    assert_eq!(
+        receive_message(&parent_arc_process),
+        Some(parent_arc_process.integer(42).unwrap())
+    );
+
+    let utf8_atom_arc_process = spawn_returning(&parent_arc_process, module, "utf8_atom", vec![]);
+    run_until_exiting(&utf8_atom_arc_process);
+
+    assert_eq!(
+        receive_message(&parent_arc_process),
+        Some(atom_unchecked("åtom"))
+    );
+}
+
+fn atom(name: &str) -> Atom {
+    Atom::try_from_str(name).unwrap()
+}
+
+// Tests run in parallel and a module can only have one old version, so it is only loaded once
+fn test_module() -> Atom {
+    static LOAD: Once = Once::new();
+
+    LOAD.call_once(|| {
+        beam::load_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../liblumen_beam/tests/testdata/reader/test.beam"
+        ))
+        .unwrap();
+    });
+
+    atom("test")
+}
+
+// The atoms of the modules built by `fixture`, by their ids
+const MODULE: u32 = 1;
+const RUN: u32 = 2;
+const ADD: u32 = 3;
+const DOUBLE: u32 = 4;
+const FUN: u32 = 5;
+const ERLANG: u32 = 6;
+const PLUS_NAME: u32 = 7;
+const TIMES_NAME: u32 = 8;
+const THROW_NAME: u32 = 9;
+const PING: u32 = 10;
+const TIMEOUT: u32 = 11;
+const OOPS: u32 = 12;
+
+// The imports of the modules built by `fixture`, by their indices
+const PLUS: u64 = 0;
+const TIMES: u64 = 1;
+const THROW: u64 = 2;
+
+struct Fixture {
+    name: &'static str,
+    file: StandardBeamFile,
+}
+
+/// A module called `name` with the `funs`, whose code is the exported `run/1` followed by `body`.
+/// `body` can use the atoms and imports above and labels from `3`.
+fn fixture(name: &'static str, funs: Vec<parts::Function>, body: Vec<Instruction>) -> Fixture {
+    let mut instructions = vec![
+        instruction(Opcode::Label, vec![u(1)]),
+        instruction(Opcode::FuncInfo, vec![a(MODULE), a(RUN), u(1)]),
+        instruction(Opcode::Label, vec![u(2)]),
+    ];
+    instructions.extend(body);
+    instructions.push(instruction(Opcode::IntCodeEnd, vec![]));
+
+    // The loader does not use the counts in the header
+    let mut code_chunk = CodeChunk {
+        info_size: 16,
+        version: 0,
+        opcode_max: 0,
+        label_count: 0,
+        function_count: 0,
+        bytecode: Vec::new(),
+    };
+    code_chunk.set_instructions(&instructions).unwrap();
+
+    let atoms = [
+        name,
+        "run",
+        "add",
+        "double",
+        "-run/1-fun-0-",
+        "erlang",
+        "+",
+        "*",
+        "throw",
+        "ping",
+        "timeout",
+        "oops",
+    ];
+
+    let mut file = StandardBeamFile::new();
+    file.push_chunk(StandardChunk::Atom(AtomChunk {
+        is_unicode: true,
+        atoms: atoms
+            .iter()
+            .map(|name| parts::Atom {
+                name: name.to_string(),
+            })
+            .collect(),
+    }));
+    file.push_chunk(StandardChunk::Code(code_chunk));
+    file.push_chunk(StandardChunk::ExpT(ExpTChunk {
+        exports: vec![parts::Export {
+            function: RUN,
+            arity: 1,
+            label: 2,
+        }],
+    }));
+    file.push_chunk(StandardChunk::ImpT(ImpTChunk {
+        imports: vec![
+            import(PLUS_NAME, 2),
+            import(TIMES_NAME, 2),
+            import(THROW_NAME, 1),
+        ],
+    }));
+
+    file.push_chunk(StandardChunk::FunT(FunTChunk { functions: funs }));
+
+    Fixture { name, file }
+}
+
+fn import(function: u32, arity: u32) -> parts::Import {
+    parts::Import {
+        module: ERLANG,
+        function,
+        arity,
+    }
+}
+
+fn instruction(opcode: Opcode, operands: Vec<Operand>) -> Instruction {
+    Instruction { opcode, operands }
+}
+
+// The operands, named after their tags in BEAM assembly
+
+fn a(id: u32) -> Operand {
+    Operand::Atom(id)
+}
+
+fn f(label: u64) -> Operand {
+    Operand::Label(label)
+}
+
+fn i(integer: i64) -> Operand {
+    Operand::Integer(BigInt::from(integer))
+}
+
+fn u(unsigned: u64) -> Operand {
+    Operand::Unsigned(unsigned)
+}
+
+fn x(register: u64) -> Operand {
+    Operand::X(register)
+}
+
+fn y(register: u64) -> Operand {
+    Operand::Y(register)
+}
+
+fn load(fixture: Fixture) -> Atom {
+    let name = beam::load(&fixture.file).unwrap();
+    assert_eq!(name, atom(fixture.name));
+
+    name
+}
+
+fn assert_unsupported_operand(result: Result<Atom, LoadError>, expected_instruction: &str) {
+    match result {
+        Err(LoadError::UnsupportedOperand { instruction, .. }) => {
+            assert_eq!(instruction, expected_instruction)
+        }
+        other => panic!(
+            "Loading returned {:?} instead of an unsupported operand",
+            other
+        ),
+    }
+}
+
+/// Spawns `module:run(Parent)`
+fn spawn_run(module: Atom) -> (Arc<Process>, Arc<Process>) {
+    let parent_arc_process = process::test_init();
+    let child_arc_process = beam::spawn(
+        &parent_arc_process,
+        Default::default(),
+        module,
+        atom("run"),
+        vec![parent_arc_process.pid_term()],
+    )
+    .unwrap();
+
+    (parent_arc_process, child_arc_process)
+}
+
+/// Spawns `module:function(arguments)`, which sends the value it returns to `parent_arc_process`
+fn spawn_returning(
+    parent_arc_process: &Arc<Process>,
+    module: Atom,
+    function: &str,
+    mut arguments: Vec<Term>,
+) -> Arc<Process> {
+    arguments.insert(0, parent_arc_process.pid_term());
+
+    Scheduler::spawn_code(
+        parent_arc_process,
+        Default::default(),
+        module,
+        atom(function),
+        arguments,
+        call_and_send,
+    )
+    .unwrap()
+}
+
+/// Calls the loaded function with the arguments after the parent pid, below a `Frame` that sends
+/// the returned value to the parent
+fn call_and_send(arc_process: &Arc<Process>) -> code::Result {
+    let module_function_arity = arc_process.current_module_function_arity().unwrap();
+    let parent = arc_process.stack_pop().unwrap();
+    let arguments = (1..module_function_arity.arity)
+        .map(|_| arc_process.stack_pop().unwrap())
+        .collect();
+
+    arc_process.stack_push(parent)?;
+    arc_process.replace_frame(Frame::new(
+        Arc::clone(&module_function_arity),
+        send_returned,
+    ));
+    beam::place_frame_with_arguments(
+        arc_process,
+        Placement::Push,
+        module_function_arity.module,
+        module_function_arity.function,
+        arguments,
+    )?;
+
+    Process::call_code(arc_process)
+}
+
+fn send_returned(arc_process: &Arc<Process>) -> code::Result {
+    let value = arc_process.stack_pop().unwrap();
+    let parent = arc_process.stack_pop().unwrap();
+
+    match erlang::send_2(parent, value, arc_process) {
+        Ok(sent) => {
+            arc_process.return_from_call(sent)?;
+
+            Process::call_code(arc_process)
+        }
+        Err(exception) => result_from_exception(arc_process, exception),
+    }
+}
+
+fn run_until_exiting(arc_process: &Arc<Process>) {
+    let scheduler = Scheduler::current();
+    let deadline = Instant::now() + Duration::from_secs(5);
+
+    while !arc_process.is_exiting() {
+        assert!(Instant::now() < deadline, "{:?} did not exit", arc_process);
+
+        // Waiting for a timer
+        if !scheduler.run_through(arc_process) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
@@ -29,6 +29,9 @@ extern crate lazy_static;
 #[macro_use]
 mod macros;
 
+// `pub` so that compiled `.beam` files can be loaded and run
+#[cfg(not(target_arch = "wasm32"))]
+pub mod beam;
 mod binary;
 // `pub` or `examples/spawn-chain`
 pub mod code;
@@ -54,7 +57,7 @@ mod term;
 mod test;
 pub mod time;
 // Public so that external code can all `timer::expire` to expire timers
-mod timer;
+pub mod timer;
 mod tuple;
 
 use self::config::Config;
@@ -4,4 +4,7 @@ pub mod binary;
 pub mod erlang;
 pub mod lists;
 pub mod maps;
+pub mod native;
 pub mod timer;
+
+pub use self::native::{natives, Native};
@@ -55,6 +55,6 @@ fn module_function_arity() -> Arc<ModuleFunctionArity> {
     })
 }
 
-fn native(reason: Term) -> exception::Result {
+pub fn native(reason: Term) -> exception::Result {
     Err(exit!(reason).into())
 }
@@ -59,6 +59,6 @@ fn module_function_arity() -> Arc<ModuleFunctionArity> {
     })
 }
 
-fn native(term: Term) -> exception::Result {
+pub fn native(term: Term) -> exception::Result {
     Ok(term.is_function().into())
 }
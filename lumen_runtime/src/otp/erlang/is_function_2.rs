@@ -63,7 +63,7 @@ fn module_function_arity() -> Arc<ModuleFunctionArity> {
     })
 }
 
-fn native(term: Term, arity: Term) -> exception::Result {
+pub fn native(term: Term, arity: Term) -> exception::Result {
     let arity_arity: usize = arity.try_into()?;
 
     Ok(term.is_function_with_arity(arity_arity).into())
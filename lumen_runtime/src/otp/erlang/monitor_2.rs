@@ -158,11 +158,7 @@ fn monitor_process_tuple(
     }
 }
 
-pub(in crate::otp::erlang) fn native(
-    process: &Process,
-    r#type: Term,
-    item: Term,
-) -> exception::Result {
+pub fn native(process: &Process, r#type: Term, item: Term) -> exception::Result {
     let type_atom: Atom = r#type.try_into()?;
 
     match type_atom.name() {
@@ -61,7 +61,7 @@ fn module_function_arity() -> Arc<ModuleFunctionArity> {
     })
 }
 
-fn native(term: Term) -> exception::Result {
+pub fn native(term: Term) -> exception::Result {
     if term.is_number() {
         Ok(term)
     } else {
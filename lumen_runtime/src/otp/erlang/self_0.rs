@@ -39,6 +39,6 @@ fn module_function_arity() -> Arc<ModuleFunctionArity> {
     })
 }
 
-fn native(process: &Process) -> Term {
+pub fn native(process: &Process) -> Term {
     process.pid_term()
 }
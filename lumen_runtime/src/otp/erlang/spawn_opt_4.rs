@@ -72,7 +72,7 @@ fn module_function_arity() -> Arc<ModuleFunctionArity> {
     })
 }
 
-fn native(
+pub fn native(
     process: &Process,
     module: Term,
     function: Term,
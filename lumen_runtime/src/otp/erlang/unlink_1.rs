@@ -61,7 +61,7 @@ fn module_function_arity() -> Arc<ModuleFunctionArity> {
     })
 }
 
-fn native(process: &Process, pid_or_port: Term) -> exception::Result {
+pub fn native(process: &Process, pid_or_port: Term) -> exception::Result {
     match pid_or_port.to_typed_term().unwrap() {
         TypedTerm::Pid(pid) => {
             if pid == process.pid() {
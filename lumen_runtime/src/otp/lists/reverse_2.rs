@@ -62,7 +62,7 @@ fn module_function_arity() -> Arc<ModuleFunctionArity> {
     })
 }
 
-pub(super) fn native(process: &Process, list: Term, tail: Term) -> exception::Result {
+pub fn native(process: &Process, list: Term, tail: Term) -> exception::Result {
     match list.to_typed_term().unwrap() {
         TypedTerm::Nil => Ok(tail),
         TypedTerm::List(cons) => {
@@ -1,4 +1,5 @@
 pub mod find_2;
+pub mod get_2;
 pub mod get_3;
 pub mod is_key_2;
 pub mod keys_1;
@@ -0,0 +1,241 @@
+//! The table of native functions in `crate::otp`, by the Erlang `module:function/arity` they
+//! implement, shared by everything that runs Erlang code on the runtime: the `.beam` loader
+//! resolves imports against it and the EIR interpreter builds its native modules from it.
+//!
+//! Only functions that return their result directly are in the table.  Functions that run as
+//! stack frames and call back into Erlang through `erlang:apply/3` frames, such as `timer:tc/3`,
+//! are not: interpreted functions pass their result to a continuation rather than returning it to
+//! the frame that called them, so those frames would never resume.
+
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+
+use liblumen_alloc::erts::exception;
+use liblumen_alloc::erts::process::Process;
+use liblumen_alloc::erts::term::{Atom, Term};
+
+use super::{binary, erlang, lists, maps};
+
+/// A function in `crate::otp` that Erlang code can call
+pub type Native = fn(&Arc<Process>, &[Term]) -> exception::Result;
+
+/// The natives in `crate::otp` by `(module, function, arity)`
+pub fn natives() -> &'static HashMap<(Atom, Atom, u8), Native> {
+    &NATIVE_BY_MODULE_FUNCTION_ARITY
+}
+
+/// Adds native functions from `crate::otp` to `natives`, one line per Erlang function:
+///
+/// ```ignore
+/// natives! { natives, "erlang";
+///     "element"/2 => erlang::element_2(0, 1);
+///     "abs"/1 => erlang::abs_1(0, process);
+///     "register"/2 => erlang::register_2(0, 1, arc_process);
+/// }
+/// ```
+///
+/// The arguments describe how to call the Rust function: an integer is the index of an Erlang
+/// argument, `process` passes the calling `&Process` and `arc_process` an `Arc<Process>`. The
+/// function may return either a `Term` or an `exception::Result`.
+macro_rules! natives {
+    ($natives:ident, $module:literal; $($name:literal / $arity:literal => $($fun:ident)::+ ( $($arg:tt),* );)*) => {
+        $(
+            $natives.insert(
+                (
+                    Atom::try_from_str($module).unwrap(),
+                    Atom::try_from_str($name).unwrap(),
+                    $arity,
+                ),
+                |_process, arguments| {
+                    assert!(arguments.len() == $arity);
+                    IntoNativeResult::into_native_result(
+                        $($fun)::+($(native_argument!(_process, arguments, $arg)),*)
+                    )
+                },
+            );
+        )*
+    };
+}
+
+macro_rules! native_argument {
+    ($process:ident, $arguments:ident, process) => {
+        $process
+    };
+    ($process:ident, $arguments:ident, arc_process) => {
+        Arc::clone($process)
+    };
+    ($process:ident, $arguments:ident, $index:literal) => {
+        $arguments[$index]
+    };
+}
+
+fn new_natives() -> HashMap<(Atom, Atom, u8), Native> {
+    let mut natives: HashMap<(Atom, Atom, u8), Native> = HashMap::new();
+
+    natives! { natives, "binary";
+        "bin_to_list"/3 => binary::bin_to_list(0, 1, 2, process);
+    }
+
+    natives! { natives, "erlang";
+        "+"/1 => erlang::number_or_badarith_1::native(0);
+        "+"/2 => erlang::add_2::native(process, 0, 1);
+        "-"/1 => erlang::negate_1(0, process);
+        "-"/2 => erlang::subtract_2::native(process, 0, 1);
+        "*"/2 => erlang::multiply_2(0, 1, process);
+        "/"/2 => erlang::divide_2(0, 1, process);
+        "++"/2 => erlang::concatenate_2(0, 1, process);
+        "--"/2 => erlang::subtract_list_2(0, 1, process);
+        "=="/2 => erlang::are_equal_after_conversion_2(0, 1);
+        "/="/2 => erlang::are_not_equal_after_conversion_2(0, 1);
+        "=:="/2 => erlang::are_exactly_equal_2(0, 1);
+        "=/="/2 => erlang::are_exactly_not_equal_2(0, 1);
+        "<"/2 => erlang::is_less_than_2(0, 1);
+        "=<"/2 => erlang::is_equal_or_less_than_2(0, 1);
+        ">"/2 => erlang::is_greater_than_2(0, 1);
+        ">="/2 => erlang::is_greater_than_or_equal_2(0, 1);
+        "!"/2 => erlang::send_2(0, 1, process);
+        "abs"/1 => erlang::abs_1(0, process);
+        "and"/2 => erlang::and_2(0, 1);
+        "append_element"/2 => erlang::append_element_2(0, 1, process);
+        "atom_to_binary"/2 => erlang::atom_to_binary_2(0, 1, process);
+        "atom_to_list"/1 => erlang::atom_to_list_1(0, process);
+        "band"/2 => erlang::band_2(0, 1, process);
+        "binary_part"/2 => erlang::binary_part_2(0, 1, process);
+        "binary_part"/3 => erlang::binary_part_3(0, 1, 2, process);
+        "binary_to_atom"/2 => erlang::binary_to_atom_2(0, 1);
+        "binary_to_existing_atom"/2 => erlang::binary_to_existing_atom_2(0, 1);
+        "binary_to_float"/1 => erlang::binary_to_float_1(0, process);
+        "binary_to_integer"/1 => erlang::binary_to_integer_1(0, process);
+        "binary_to_integer"/2 => erlang::binary_to_integer_2(0, 1, process);
+        "binary_to_list"/1 => erlang::binary_to_list_1(0, process);
+        "binary_to_list"/3 => erlang::binary_to_list_3(0, 1, 2, process);
+        "binary_to_term"/1 => erlang::binary_to_term_1(0, process);
+        "binary_to_term"/2 => erlang::binary_to_term_2(0, 1, process);
+        "bit_size"/1 => erlang::bit_size_1(0, process);
+        "bitstring_to_list"/1 => erlang::bitstring_to_list_1(0, process);
+        "bnot"/1 => erlang::bnot_1(0, process);
+        "bor"/2 => erlang::bor_2(0, 1, process);
+        "bsl"/2 => erlang::bsl_2(0, 1, process);
+        "bsr"/2 => erlang::bsr_2(0, 1, process);
+        "bxor"/2 => erlang::bxor_2(0, 1, process);
+        "byte_size"/1 => erlang::byte_size_1(0, process);
+        "cancel_timer"/1 => erlang::cancel_timer_1(0, process);
+        "cancel_timer"/2 => erlang::cancel_timer_2(0, 1, process);
+        "ceil"/1 => erlang::ceil_1(0, process);
+        "convert_time_unit"/3 => erlang::convert_time_unit_3::native(process, 0, 1, 2);
+        "delete_element"/2 => erlang::delete_element_2(0, 1, process);
+        "demonitor"/2 => erlang::demonitor_2::native(process, 0, 1);
+        "div"/2 => erlang::div_2(0, 1, process);
+        "element"/2 => erlang::element_2(0, 1);
+        "error"/1 => erlang::error_1(0);
+        "error"/2 => erlang::error_2(0, 1);
+        "exit"/1 => erlang::exit_1::native(0);
+        "hd"/1 => erlang::hd_1(0);
+        "insert_element"/3 => erlang::insert_element_3(0, 1, 2, process);
+        "is_alive"/0 => erlang::is_alive_0();
+        "is_atom"/1 => erlang::is_atom_1(0);
+        "is_binary"/1 => erlang::is_binary_1(0);
+        "is_bitstring"/1 => erlang::is_bitstring_1(0);
+        "is_boolean"/1 => erlang::is_boolean_1(0);
+        "is_float"/1 => erlang::is_float_1(0);
+        "is_function"/1 => erlang::is_function_1::native(0);
+        "is_function"/2 => erlang::is_function_2::native(0, 1);
+        "is_integer"/1 => erlang::is_integer_1(0);
+        "is_list"/1 => erlang::is_list_1(0);
+        "is_map"/1 => erlang::is_map_1(0);
+        "is_map_key"/2 => maps::is_key_2::native(process, 0, 1);
+        "is_number"/1 => erlang::is_number_1(0);
+        "is_pid"/1 => erlang::is_pid_1(0);
+        "is_record"/2 => erlang::is_record_2(0, 1);
+        "is_record"/3 => erlang::is_record_3(0, 1, 2);
+        "is_reference"/1 => erlang::is_reference_1(0);
+        "is_tuple"/1 => erlang::is_tuple_1(0);
+        "length"/1 => erlang::length_1(0, process);
+        "link"/1 => erlang::link_1::native(process, 0);
+        "list_to_atom"/1 => erlang::list_to_atom_1(0);
+        "list_to_binary"/1 => erlang::list_to_binary_1(0, process);
+        "list_to_bitstring"/1 => erlang::list_to_bitstring_1(0, process);
+        "list_to_existing_atom"/1 => erlang::list_to_existing_atom_1(0);
+        "list_to_pid"/1 => erlang::list_to_pid_1(0, process);
+        "list_to_tuple"/1 => erlang::list_to_tuple_1(0, process);
+        "make_ref"/0 => erlang::make_ref_0(process);
+        "map_get"/2 => erlang::map_get_2(0, 1, process);
+        "map_size"/1 => erlang::map_size_1(0, process);
+        "max"/2 => erlang::max_2(0, 1);
+        "min"/2 => erlang::min_2(0, 1);
+        "monitor"/2 => erlang::monitor_2::native(process, 0, 1);
+        "monotonic_time"/0 => erlang::monotonic_time_0::native(process);
+        "monotonic_time"/1 => erlang::monotonic_time_1(0, process);
+        "node"/0 => erlang::node_0();
+        "not"/1 => erlang::not_1(0);
+        "or"/2 => erlang::or_2(0, 1);
+        "process_flag"/2 => erlang::process_flag_2::native(process, 0, 1);
+        "process_info"/2 => erlang::process_info_2::native(process, 0, 1);
+        "raise"/3 => erlang::raise_3(0, 1, 2);
+        "read_timer"/1 => erlang::read_timer_1(0, process);
+        "read_timer"/2 => erlang::read_timer_2(0, 1, process);
+        "register"/2 => erlang::register_2(0, 1, arc_process);
+        "registered"/0 => erlang::registered_0(process);
+        "rem"/2 => erlang::rem_2(0, 1, process);
+        "self"/0 => erlang::self_0::native(process);
+        "send"/2 => erlang::send_2(0, 1, process);
+        "send"/3 => erlang::send_3(0, 1, 2, process);
+        "send_after"/3 => erlang::send_after_3(0, 1, 2, arc_process);
+        "send_after"/4 => erlang::send_after_4(0, 1, 2, 3, arc_process);
+        "setelement"/3 => erlang::setelement_3(0, 1, 2, process);
+        "size"/1 => erlang::size_1(0, process);
+        "spawn"/3 => erlang::spawn_3::native(process, 0, 1, 2);
+        "spawn_link"/3 => erlang::spawn_link_3::native(process, 0, 1, 2);
+        "spawn_opt"/4 => erlang::spawn_opt_4::native(process, 0, 1, 2, 3);
+        "split_binary"/2 => erlang::split_binary_2(0, 1, process);
+        "start_timer"/3 => erlang::start_timer_3(0, 1, 2, arc_process);
+        "start_timer"/4 => erlang::start_timer_4(0, 1, 2, 3, arc_process);
+        "throw"/1 => erlang::throw_1(0);
+        "tl"/1 => erlang::tl_1(0);
+        "tuple_size"/1 => erlang::tuple_size_1(0, process);
+        "tuple_to_list"/1 => erlang::tuple_to_list_1(0, process);
+        "unlink"/1 => erlang::unlink_1::native(process, 0);
+        "unregister"/1 => erlang::unregister_1(0);
+        "whereis"/1 => erlang::whereis_1(0);
+        "xor"/2 => erlang::xor_2(0, 1);
+    }
+
+    natives! { natives, "lists";
+        "keyfind"/3 => lists::keyfind_3::native(0, 1, 2);
+        "keymember"/3 => lists::keymember_3::native(0, 1, 2);
+        "member"/2 => lists::member_2::native(0, 1);
+        "reverse"/1 => lists::reverse_1::native(process, 0);
+        "reverse"/2 => lists::reverse_2::native(process, 0, 1);
+    }
+
+    natives! { natives, "maps";
+        "find"/2 => maps::find_2::native(process, 0, 1);
+        "get"/2 => maps::get_2::native(process, 0, 1);
+        "get"/3 => maps::get_3::native(process, 0, 1, 2);
+        "is_key"/2 => maps::is_key_2::native(process, 0, 1);
+        "keys"/1 => maps::keys_1::native(process, 0);
+        "merge"/2 => maps::merge_2::native(process, 0, 1);
+    }
+
+    natives
+}
+
+/// The return types of the native functions in `crate::otp`
+trait IntoNativeResult {
+    fn into_native_result(self) -> exception::Result;
+}
+impl IntoNativeResult for Term {
+    fn into_native_result(self) -> exception::Result {
+        Ok(self)
+    }
+}
+impl IntoNativeResult for exception::Result {
+    fn into_native_result(self) -> exception::Result {
+        self
+    }
+}
+
+lazy_static! {
+    static ref NATIVE_BY_MODULE_FUNCTION_ARITY: HashMap<(Atom, Atom, u8), Native> = new_natives();
+}
@@ -89,6 +89,11 @@ impl Queues {
         }
     }
 
+    /// The processes waiting to receive a message or for a timer
+    pub fn waiting(&self) -> Vec<Arc<Process>> {
+        self.waiting.iter().cloned().collect()
+    }
+
     pub fn stop_waiting(&mut self, process: &Process) {
         match self.waiting.get(process) {
             Some(arc_process) => {
@@ -144,6 +149,10 @@ impl Waiting {
         self.0.insert(waiter)
     }
 
+    fn iter(&self) -> impl Iterator<Item = &Arc<Process>> {
+        self.0.iter()
+    }
+
     fn len(&self) -> usize {
         self.0.len()
     }
@@ -158,6 +158,16 @@ impl Scheduler {
         self.run_queues.read().len()
     }
 
+    /// Returns `true` if any timer started on this scheduler has yet to time out or be canceled.
+    pub fn has_pending_timers(&self) -> bool {
+        !self.hierarchy.read().is_empty()
+    }
+
+    /// The processes that can't run until they receive a message or a timer times out.
+    pub fn waiting_processes(&self) -> Vec<Arc<Process>> {
+        self.run_queues.read().waiting()
+    }
+
     #[cfg(test)]
     pub fn run_queue_len(&self, priority: Priority) -> usize {
         self.run_queues.read().run_queue_len(priority)
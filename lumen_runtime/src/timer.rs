@@ -182,6 +182,13 @@ impl Hierarchy {
         Ok(process_reference)
     }
 
+    /// Returns `true` if there are no timers left to time out
+    pub fn is_empty(&self) -> bool {
+        self.timer_by_reference_number
+            .values()
+            .all(|weak_timer| weak_timer.upgrade().is_none())
+    }
+
     pub fn timeout(&mut self) {
         self.timeout_at_once();
 